@@ -0,0 +1,132 @@
+//! The JSON endpoints, which describe kernel state that isn't exposed through the VFS.
+
+use crate::{Body, Response};
+use alloc::{format, string::String, sync::Arc};
+use core::fmt::Write;
+use fault_log::FaultEntry;
+use mod_mgmt::CrateNamespace;
+
+/// Returns the entries of the fault log, oldest first.
+///
+/// Each entry is an object whose fields mirror those of [`FaultEntry`].
+pub(crate) fn faults() -> Response {
+    let mut json = String::from("[");
+    for (i, entry) in fault_log::fault_entries().iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_fault(&mut json, entry);
+    }
+    json.push(']');
+    json_response(json)
+}
+
+fn write_fault(json: &mut String, entry: &FaultEntry) {
+    json.push_str("{\"fault_type\":");
+    write_string(json, &format!("{:?}", entry.fault_type));
+    json.push_str(",\"error_code\":");
+    write_option(json, entry.error_code.as_ref());
+    json.push_str(",\"core\":");
+    write_option(json, entry.core.as_ref());
+    json.push_str(",\"running_task\":");
+    write_string_option(json, entry.running_task.as_deref());
+    json.push_str(",\"running_app_crate\":");
+    write_string_option(json, entry.running_app_crate.as_deref());
+    json.push_str(",\"address_accessed\":");
+    write_string_option(json, entry.address_accessed.map(|a| format!("{:#X}", a.value())).as_deref());
+    json.push_str(",\"instruction_pointer\":");
+    write_string_option(json, entry.instruction_pointer.map(|a| format!("{:#X}", a.value())).as_deref());
+    json.push_str(",\"crate_error_occured\":");
+    write_string_option(json, entry.crate_error_occured.as_deref());
+    json.push_str(",\"replaced_crates\":[");
+    for (i, crate_name) in entry.replaced_crates.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_string(json, crate_name);
+    }
+    json.push_str("],\"action_taken\":");
+    write_string(json, &format!("{:?}", entry.action_taken));
+    json.push('}');
+}
+
+/// Returns the crates loaded into this task's crate namespace and each of its recursive namespaces.
+///
+/// The result is a list of objects with the namespace's `name` and its `crates`,
+/// each of which has a `name` and the path of the `object_file` it was loaded from.
+pub(crate) fn crates() -> Response {
+    let namespace = task::with_current_task(|t| t.get_namespace().clone())
+        .ok()
+        .or_else(|| mod_mgmt::get_initial_kernel_namespace().cloned());
+    let Some(namespace) = namespace else {
+        return Response::error(500);
+    };
+
+    let mut json = String::from("[");
+    let mut next: Option<&Arc<CrateNamespace>> = Some(&namespace);
+    while let Some(namespace) = next {
+        if json.len() > 1 {
+            json.push(',');
+        }
+        json.push_str("{\"name\":");
+        write_string(&mut json, namespace.name());
+        json.push_str(",\"crates\":[");
+        let mut first = true;
+        namespace.for_each_crate(false, |crate_name, crate_ref| {
+            if !first {
+                json.push(',');
+            }
+            first = false;
+            json.push_str("{\"name\":");
+            write_string(&mut json, crate_name);
+            json.push_str(",\"object_file\":");
+            let object_file = crate_ref.lock_as_ref().object_file.lock().get_absolute_path();
+            write_string(&mut json, &object_file);
+            json.push('}');
+            true
+        });
+        json.push_str("]}");
+        next = namespace.recursive_namespace();
+    }
+    json.push(']');
+    json_response(json)
+}
+
+fn json_response(json: String) -> Response {
+    Response::new(200, "application/json", Body::Bytes(json.into_bytes()))
+}
+
+fn write_option<T: core::fmt::Display>(json: &mut String, value: Option<&T>) {
+    match value {
+        Some(value) => {
+            let _ = write!(json, "{}", value);
+        }
+        None => json.push_str("null"),
+    }
+}
+
+fn write_string_option(json: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => write_string(json, value),
+        None => json.push_str("null"),
+    }
+}
+
+/// Writes `value` as a JSON string, escaping it as needed.
+fn write_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
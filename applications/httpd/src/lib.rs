@@ -0,0 +1,283 @@
+//! A minimal HTTP/1.1 server for inspecting a running Theseus system from another machine.
+//!
+//! All endpoints are read-only and only support `GET` and `HEAD` requests:
+//! * `/api/faults`: the entries in the `fault_log`, as JSON.
+//! * `/api/crates`: the crates loaded into each crate namespace, as JSON.
+//! * Any other path is looked up in the VFS, e.g., `/tasks` or `/namespaces`.
+//!   Files are served as-is and directories as HTML listings.
+//!
+//! When running in QEMU with `net=user`, the server can be reached from the host
+//! by forwarding a host port to it, e.g., `make run net=user hostfwd=tcp::8080-:80`
+//! followed by `httpd` in the Theseus shell makes it available at `http://localhost:8080`.
+
+#![no_std]
+
+extern crate alloc;
+
+mod api;
+mod vfs;
+
+use alloc::{format, string::String, vec, vec::Vec};
+use app_io::println;
+use core::time::Duration;
+use fs_node::FileRef;
+use getopts::{Matches, Options};
+use log::{debug, warn};
+use net::{
+    wire::{IpEndpoint, Ipv4Address},
+    IpAddress, TcpListener, TcpStream,
+};
+use percent_encoding::percent_decode;
+
+/// The port that the server listens on by default.
+const DEFAULT_PORT: u16 = 80;
+/// How long to wait for a client to send its request or accept the response.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+/// The maximum length of a request's line and headers.
+const MAX_REQUEST_LEN: usize = 8192;
+/// The maximum number of headers in a request.
+const MAX_HEADERS: usize = 32;
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "p",
+        "port",
+        "listen on <port> (default: 80)",
+        "<port>",
+    );
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(_f) => {
+            println!("{}", _f);
+            print_usage(&opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(&opts);
+        0
+    } else {
+        match _main(matches) {
+            Ok(_) => 0,
+            Err(e) => {
+                println!("{}", e);
+                -1
+            }
+        }
+    }
+}
+
+fn _main(matches: Matches) -> Result<(), &'static str> {
+    let port = matches
+        .opt_get_default("p", DEFAULT_PORT)
+        .map_err(|_| "invalid port")?;
+    let local = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), port);
+    let listener = TcpListener::bind(local).map_err(|_| "failed to listen on port")?;
+    println!("httpd: listening on port {}", port);
+
+    loop {
+        let (stream, remote) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // A failed handshake only affects that one client, so keep listening.
+                warn!("httpd: failed to accept connection: {:?}", e);
+                continue;
+            }
+        };
+        debug!("httpd: accepted connection from {}", remote);
+        // Serve each client from its own task so that a slow one can't hold up the others.
+        spawn::new_task_builder(serve_connection, stream)
+            .name(format!("httpd_{}", remote))
+            .spawn()?;
+    }
+}
+
+/// An HTTP response, whose body is either in memory or read from a file just before it's sent.
+pub(crate) struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+pub(crate) enum Body {
+    Bytes(Vec<u8>),
+    File(FileRef),
+}
+
+impl Response {
+    pub(crate) fn new(status: u16, content_type: &'static str, body: Body) -> Self {
+        Self {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    /// Returns a plain-text response with the given `status`, whose body is its reason phrase.
+    pub(crate) fn error(status: u16) -> Self {
+        let body = format!("{} {}\n", status, reason(status));
+        Self::new(status, "text/plain; charset=utf-8", Body::Bytes(body.into_bytes()))
+    }
+
+    pub(crate) fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+/// Returns the reason phrase for the given status code.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+/// Receives a single request from the client, responds to it, and closes the connection.
+fn serve_connection(stream: TcpStream) {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT));
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT));
+
+    let (response, head_only) = match read_request(&stream) {
+        Ok((method, path)) => {
+            let head_only = method == "HEAD";
+            let response = if method == "GET" || head_only {
+                route(&path)
+            } else {
+                Response::error(405).with_header("Allow", String::from("GET, HEAD"))
+            };
+            (response, head_only)
+        }
+        Err(Some(status)) => (Response::error(status), false),
+        // The client went away or never sent a full request.
+        Err(None) => return,
+    };
+
+    if let Err(e) = send_response(&stream, response, head_only) {
+        debug!("httpd: failed to send response: {:?}", e);
+    }
+    let _ = stream.flush();
+}
+
+/// Returns the response to a request for the given percent-decoded `path`.
+fn route(path: &str) -> Response {
+    match path {
+        "/api/faults" => api::faults(),
+        "/api/crates" => api::crates(),
+        _ => vfs::serve(path),
+    }
+}
+
+/// Receives a request and returns its method and percent-decoded path, without the query.
+///
+/// On failure, returns the status code of the error response to send, if any.
+fn read_request(stream: &TcpStream) -> Result<(String, String), Option<u16>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    loop {
+        let len = stream.read(&mut chunk).map_err(|_| None)?;
+        if len == 0 {
+            return Err(None);
+        }
+        buffer.extend_from_slice(&chunk[..len]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        match request.parse(&buffer) {
+            Ok(httparse::Status::Complete(_)) => {
+                let method = String::from(request.method.ok_or(Some(400))?);
+                let target = request.path.ok_or(Some(400))?;
+                let path = target.split('?').next().unwrap_or_default();
+                let path = percent_decode(path.as_bytes())
+                    .decode_utf8()
+                    .map_err(|_| Some(400))?;
+                if !path.starts_with('/') {
+                    return Err(Some(400));
+                }
+                return Ok((method, path.into()));
+            }
+            Ok(httparse::Status::Partial) if buffer.len() < MAX_REQUEST_LEN => {}
+            Ok(httparse::Status::Partial) => return Err(Some(431)),
+            Err(_) => return Err(Some(400)),
+        }
+    }
+}
+
+fn send_response(stream: &TcpStream, response: Response, head_only: bool) -> net::Result<()> {
+    // Files are read in full before the head is sent, so that the Content-Length
+    // matches the body even if the file's contents change while it's read, e.g., in the task_fs.
+    let body = match response.body {
+        Body::Bytes(bytes) => bytes,
+        Body::File(file) => read_file(&file),
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nServer: Theseus\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        body.len(),
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    write_all(stream, head.as_bytes())?;
+
+    if head_only {
+        return Ok(());
+    }
+    write_all(stream, &body)
+}
+
+/// Reads the contents of the given `file`, or as much of it as can be read.
+fn read_file(file: &FileRef) -> Vec<u8> {
+    let mut file = file.lock();
+    let mut contents = vec![0; file.len()];
+    let mut offset = 0;
+    // Some files return an error rather than 0 when reading at their end,
+    // so reading stops once the length they reported has been read.
+    while offset < contents.len() {
+        match file.read_at(&mut contents[offset..], offset) {
+            Ok(0) => break,
+            Ok(len) => offset += len,
+            Err(e) => {
+                warn!("httpd: failed to read file: {:?}", e);
+                break;
+            }
+        }
+    }
+    contents.truncate(offset);
+    contents
+}
+
+fn write_all(stream: &TcpStream, mut data: &[u8]) -> net::Result<()> {
+    while !data.is_empty() {
+        let written = stream.write(data)?;
+        data = &data[written..];
+    }
+    Ok(())
+}
+
+fn print_usage(opts: &Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: httpd [OPTIONS]
+Serves the file system, fault log, and loaded crates read-only over HTTP.
+
+Endpoints:
+    /api/faults    the fault log, as JSON
+    /api/crates    the crates loaded into each crate namespace, as JSON
+    /<path>        the file or directory at <path>, e.g., /tasks or /namespaces";
@@ -0,0 +1,89 @@
+//! Serving files and directories from the VFS.
+
+use crate::{Body, Response};
+use alloc::{format, string::String, vec::Vec};
+use fs_node::{DirRef, FileOrDir};
+use path::Path;
+use percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+/// Returns the file or directory at the given absolute `path`.
+pub(crate) fn serve(path: &str) -> Response {
+    match Path::get_absolute(&Path::new(String::from(path))) {
+        Some(FileOrDir::File(file)) => {
+            let content_type = content_type(path);
+            Response::new(200, content_type, Body::File(file))
+        }
+        // Links in a listing are relative, so they only work if the directory's path ends with a slash.
+        Some(FileOrDir::Dir(_)) if !path.ends_with('/') => {
+            Response::error(301).with_header("Location", format!("{}/", encode_path(path)))
+        }
+        Some(FileOrDir::Dir(dir)) => {
+            let listing = list_directory(path, &dir);
+            Response::new(200, "text/html; charset=utf-8", Body::Bytes(listing.into_bytes()))
+        }
+        None => Response::error(404),
+    }
+}
+
+/// Returns the content type of the file at `path`, based on its extension.
+///
+/// Most files in Theseus, e.g., those in `task_fs`, are text without an extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = path.rsplit('/').next().and_then(|name| name.rsplit_once('.')).map(|(_, e)| e);
+    match extension {
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("o" | "a" | "so" | "elf" | "bin" | "img" | "wasm" | "dbg") => "application/octet-stream",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// Renders an HTML page that links to each entry in the directory at `path`.
+fn list_directory(path: &str, dir: &DirRef) -> String {
+    let mut names = dir.lock().list();
+    names.sort();
+
+    let title = escape_html(path);
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html><head><title>{title}</title></head><body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if path != "/" {
+        page.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in names {
+        let suffix = match dir.lock().get(&name) {
+            Some(FileOrDir::Dir(_)) => "/",
+            _ => "",
+        };
+        page.push_str(&format!(
+            "<li><a href=\"{}{suffix}\">{}{suffix}</a></li>\n",
+            utf8_percent_encode(&name, PATH_SEGMENT_ENCODE_SET),
+            escape_html(&name),
+        ));
+    }
+    page.push_str("</ul>\n</body></html>\n");
+    page
+}
+
+/// Percent-encodes each component of `path`.
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|component| format!("{}", utf8_percent_encode(component, PATH_SEGMENT_ENCODE_SET)))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
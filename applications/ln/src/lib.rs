@@ -0,0 +1,107 @@
+#![no_std]
+#[macro_use] extern crate app_io;
+
+#[macro_use] extern crate alloc;
+extern crate task;
+extern crate getopts;
+extern crate path;
+extern crate fs_node;
+extern crate vfs_node;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use getopts::Options;
+use path::Path;
+use fs_node::FileOrDir;
+use vfs_node::{VFSHardLink, VFSSymlink};
+
+
+pub fn main(args: Vec<String>) -> isize {
+    match ln(args) {
+        Ok(_) => 0,
+        Err(err) => {
+            println!("{}", err);
+            -1
+        }
+    }
+}
+
+fn ln(args: Vec<String>) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("s", "symbolic", "make a symbolic link instead of a hard link");
+    opts.optflag("f", "force", "replace an existing node called LINK_NAME");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            print_usage(opts);
+            return Err(e.to_string());
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return Ok(());
+    }
+
+    let (target, link_name) = match matches.free.len() {
+        1 => (&matches.free[0], None),
+        2 => (&matches.free[0], Some(&matches.free[1])),
+        0 => {
+            print_usage(opts);
+            return Err("ln: missing file operand".into());
+        }
+        _ => {
+            print_usage(opts);
+            return Err("ln: too many arguments".into());
+        }
+    };
+
+    let Ok(working_dir) = task::with_current_task(|t|
+        t.get_env().lock().working_dir.clone()
+    ) else {
+        return Err("failed to get current task".into());
+    };
+
+    let target_basename = Path::new(target.clone()).basename().to_string();
+    // If LINK_NAME is omitted or is an existing directory, the link is created therein
+    // with the same name as the target.
+    let (parent_dir, name) = match link_name {
+        None => (working_dir.clone(), target_basename),
+        Some(link_name) => match Path::new(link_name.clone()).get(&working_dir) {
+            Some(FileOrDir::Dir(dir)) => (dir, target_basename),
+            _ => Path::new(link_name.clone()).get_parent_and_name(&working_dir)
+                .map_err(|e| format!("ln: invalid link name {link_name}: {e}"))?,
+        },
+    };
+
+    if parent_dir.lock().get(&name).is_some() && !matches.opt_present("f") {
+        return Err(format!("ln: failed to create link {name:?}: a node with that name already exists"));
+    }
+
+    if matches.opt_present("s") {
+        VFSSymlink::create(name, target.clone(), &parent_dir)?;
+    } else {
+        let file = match Path::new(target.clone()).get(&working_dir) {
+            Some(FileOrDir::File(file)) => file,
+            Some(FileOrDir::Dir(_)) => return Err(format!("ln: {target}: hard link not allowed for directory")),
+            None => return Err(format!("ln: couldn't find {target}")),
+        };
+        VFSHardLink::create(name, file, &parent_dir)?;
+    }
+    Ok(())
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+
+const USAGE: &str = "Usage: ln [-s] [-f] TARGET [LINK_NAME]
+  or:  ln [-s] [-f] TARGET DIRECTORY
+Create a link called LINK_NAME that refers to TARGET.
+By default, a hard link is created, which must refer to an existing file.
+Symbolic links store the path of TARGET, which is resolved each time the link is used.";
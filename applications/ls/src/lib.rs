@@ -2,10 +2,11 @@
 
 extern crate task;
 #[macro_use] extern crate app_io;
-extern crate alloc;
+#[macro_use] extern crate alloc;
 extern crate fs_node;
 extern crate getopts;
 extern crate path;
+extern crate time;
 
 use alloc::{
     string::String,
@@ -13,13 +14,14 @@ use alloc::{
     vec::Vec,
 };
 use core::fmt::Write;
-use fs_node::{FileOrDir, DirRef};
+use fs_node::{FileOrDir, DirRef, Metadata, NodeType};
 use getopts::Options;
 use path::Path;
 
 pub fn main(args: Vec<String>) -> isize {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
+    opts.optflag("l", "long", "use a long listing format that shows each node's metadata");
 
     let matches = match opts.parse(&args) {
         Ok(m) => m,
@@ -39,9 +41,10 @@ pub fn main(args: Vec<String>) -> isize {
         println!("failed to get current task");
         return -1;
     };
+    let long = matches.opt_present("l");
     // print children of working directory if no child is specified
     if matches.free.is_empty() {
-        print_children(&curr_wd);
+        print_children(&curr_wd, long);
         return 0;
     }
 
@@ -50,7 +53,12 @@ pub fn main(args: Vec<String>) -> isize {
     // Navigate to the path specified by first argument
     match path.get(&curr_wd) {
         Some(FileOrDir::Dir(dir)) => {
-            print_children(&dir);
+            print_children(&dir, long);
+            0
+        }
+        Some(FileOrDir::File(file)) if long => {
+            let file = file.lock();
+            println!("{}", format_long(&file.get_name(), &file.metadata(), file.symlink_target()));
             0
         }
         Some(FileOrDir::File(file)) => {
@@ -64,21 +72,50 @@ pub fn main(args: Vec<String>) -> isize {
     }
 }
 
-fn print_children(dir: &DirRef) {
+fn print_children(dir: &DirRef, long: bool) {
     let mut child_string = String::new();
     let mut child_list = dir.lock().list(); 
     child_list.reverse();
     for child in child_list.iter() {
-        writeln!(child_string, "{child}").expect("Failed to write child_string");
+        if long {
+            let Some(node) = dir.lock().get(child) else { continue };
+            writeln!(child_string, "{}", format_long(child, &node.metadata(), node.symlink_target())).expect("Failed to write child_string");
+        } else {
+            writeln!(child_string, "{child}").expect("Failed to write child_string");
+        }
     }
     println!("{}", child_string);
 }
 
+/// Formats a line of the long listing format, e.g., `drw- 0 2023-01-31 12:34 name`.
+/// Symbolic links are followed by the path that they point to, e.g., `name -> target`.
+fn format_long(name: &str, metadata: &Metadata, symlink_target: Option<String>) -> String {
+    let secs = metadata.modified.as_secs();
+    let (year, month, day) = time::civil_from_days((secs / 86400) as i64);
+    let (hour, minute) = ((secs % 86400) / 3600, (secs % 3600) / 60);
+    let mut line = format!("{}r{}{} {:>10} {:04}-{:02}-{:02} {:02}:{:02} {}",
+        match metadata.node_type {
+            NodeType::Directory => 'd',
+            NodeType::Symlink => 'l',
+            NodeType::File => '-',
+        },
+        if metadata.permissions.read_only { '-' } else { 'w' },
+        if metadata.permissions.executable { 'x' } else { '-' },
+        metadata.size,
+        year, month, day, hour, minute,
+        name,
+    );
+    if let Some(target) = symlink_target {
+        write!(line, " -> {target}").expect("Failed to write symlink target");
+    }
+    line
+}
+
 fn print_usage(opts: Options) {
     println!("{}", opts.usage(USAGE));
 }
 
 
-const USAGE: &str = "Usage: ls [DIR | FILE]
+const USAGE: &str = "Usage: ls [-l] [DIR | FILE]
 List the contents of the given directory or info about the given file.
 If no arguments are provided, it lists the contents of the current directory.";
\ No newline at end of file
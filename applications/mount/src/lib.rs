@@ -0,0 +1,98 @@
+#![no_std]
+#[macro_use] extern crate app_io;
+
+#[macro_use] extern crate alloc;
+extern crate task;
+extern crate getopts;
+extern crate path;
+extern crate fs_node;
+extern crate mount_table;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use getopts::Options;
+use path::Path;
+use fs_node::FileOrDir;
+
+
+pub fn main(args: Vec<String>) -> isize {
+    match mount(args) {
+        Ok(_) => 0,
+        Err(err) => {
+            println!("{}", err);
+            -1
+        }
+    }
+}
+
+fn mount(args: Vec<String>) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("l", "list-types", "list the filesystem types that can be mounted");
+    opts.optopt("t", "type", "the type of filesystem to mount (default: tmpfs)", "TYPE");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            print_usage(opts);
+            return Err(e.to_string());
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return Ok(());
+    }
+
+    if matches.opt_present("l") {
+        for fs_type in mount_table::filesystem_types() {
+            println!("{}", fs_type);
+        }
+        return Ok(());
+    }
+
+    let (source, target) = match matches.free.len() {
+        0 => {
+            for m in mount_table::mounts() {
+                println!("{:?}", m);
+            }
+            return Ok(());
+        }
+        1 => ("none", &matches.free[0]),
+        2 => (matches.free[0].as_str(), &matches.free[1]),
+        _ => {
+            print_usage(opts);
+            return Err("mount: too many arguments".into());
+        }
+    };
+    let fs_type = matches.opt_str("t").unwrap_or_else(|| mount_table::TMPFS.to_string());
+
+    let Ok(working_dir) = task::with_current_task(|t|
+        t.get_env().lock().working_dir.clone()
+    ) else {
+        return Err("failed to get current task".into());
+    };
+
+    let path = Path::new(target.clone());
+    let mount_point = match path.get(&working_dir) {
+        Some(FileOrDir::Dir(dir)) => dir,
+        Some(FileOrDir::File(_)) => return Err(format!("mount point {path} is not a directory")),
+        None => return Err(format!("mount point {path} does not exist")),
+    };
+    let name = mount_point.lock().get_name();
+
+    let fs_root = mount_table::create_filesystem(&fs_type, source, name)?;
+    mount_table::mount(fs_root, &mount_point, &fs_type, source)?;
+    Ok(())
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+
+const USAGE: &str = "Usage: mount [-t TYPE] [SOURCE] DIR
+Mount a new filesystem of the given TYPE from SOURCE onto the existing directory DIR.
+If no arguments are provided, it lists all currently-mounted filesystems.";
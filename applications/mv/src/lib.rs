@@ -0,0 +1,104 @@
+#![no_std]
+#[macro_use] extern crate app_io;
+
+#[macro_use] extern crate alloc;
+extern crate task;
+extern crate getopts;
+extern crate path;
+extern crate fs_node;
+extern crate mount_table;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use getopts::Options;
+use path::Path;
+use fs_node::{DirRef, FileOrDir, FsNode};
+
+
+pub fn main(args: Vec<String>) -> isize {
+    match mv(args) {
+        Ok(_) => 0,
+        Err(err) => {
+            println!("{}", err);
+            -1
+        }
+    }
+}
+
+fn mv(args: Vec<String>) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag("n", "no-clobber", "do not overwrite an existing file or directory");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            print_usage(opts);
+            return Err(e.to_string());
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return Ok(());
+    }
+
+    if matches.free.len() < 2 {
+        print_usage(opts);
+        return Err("mv: missing file operand".into());
+    }
+
+    let Ok(working_dir) = task::with_current_task(|t|
+        t.get_env().lock().working_dir.clone()
+    ) else {
+        return Err("failed to get current task".into());
+    };
+
+    let (sources, destination) = matches.free.split_at(matches.free.len() - 1);
+    let destination = &destination[0];
+    let dest_dir = match Path::new(destination.clone()).get(&working_dir) {
+        Some(FileOrDir::Dir(dir)) => Some(dir),
+        _ => None,
+    };
+    if sources.len() > 1 && dest_dir.is_none() {
+        return Err(format!("mv: target {destination} is not a directory"));
+    }
+
+    for source in sources {
+        // Moving a node into an existing directory keeps its name;
+        // otherwise, the destination path gives the node's new directory and name.
+        let (target_dir, target_name) = match dest_dir {
+            Some(ref dir) => (dir.clone(), Path::new(source.clone()).basename().to_string()),
+            None => Path::new(destination.clone()).get_parent_and_name(&working_dir)
+                .map_err(|e| format!("mv: invalid destination {destination}: {e}"))?,
+        };
+        if matches.opt_present("n") && target_dir.lock().get(&target_name).is_some() {
+            continue;
+        }
+        move_one(source, &working_dir, &target_dir, &target_name)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the node at the `source` path into `target_dir`, where it will be called `target_name`.
+fn move_one(source: &str, working_dir: &DirRef, target_dir: &DirRef, target_name: &str) -> Result<(), String> {
+    let path = Path::new(source.to_string());
+    let node = path.get_no_follow(working_dir).ok_or_else(|| format!("mv: couldn't find {path}"))?;
+
+    let source_dir = node.get_parent_dir().ok_or_else(|| format!("mv: couldn't find the parent directory of {path}"))?;
+    mount_table::move_node(&source_dir, &node.get_name(), target_dir, target_name)
+        .map(|_replaced| ())
+        .map_err(|e| format!("mv: couldn't move {path}: {e}"))
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+
+const USAGE: &str = "Usage: mv [-n] SOURCE DEST
+  or:  mv [-n] SOURCE... DIRECTORY
+Rename SOURCE to DEST, or move each SOURCE into DIRECTORY.";
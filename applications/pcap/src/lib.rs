@@ -0,0 +1,318 @@
+//! Captures the frames that network interfaces send and receive,
+//! and writes them in the libpcap format understood by Wireshark and tcpdump.
+//!
+//! Captured frames can either be saved into a file in the VFS, or streamed over UDP to
+//! another machine, where, e.g., `nc -u -l 5555 > capture.pcap` collects them into a file.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use app_io::println;
+use core::{str::FromStr, time::Duration};
+use fs_node::FileOrDir;
+use getopts::{Matches, Options};
+use memfs::MemFile;
+use net::{
+    capture::{CaptureConfig, CapturedFrame, Filter, Protocol, Side, DEFAULT_SNAP_LEN},
+    wire::{IpEndpoint, Ipv4Address},
+    IpAddress, NetworkInterface, UdpSocket,
+};
+use path::Path;
+
+/// The link type of Ethernet frames in a pcap file.
+const LINKTYPE_ETHERNET: u32 = 1;
+/// The magic number of a pcap file with microsecond timestamps.
+const PCAP_MAGIC: u32 = 0xA1B2_C3D4;
+/// The file that captured frames are saved to if none is given.
+const DEFAULT_FILE_NAME: &str = "capture.pcap";
+/// The largest frame that is sent in a single datagram when streaming.
+const MAX_STREAM_SNAP_LEN: usize = 8192;
+/// How often a stream checks whether the capture has been stopped.
+const STREAM_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "i",
+        "interface",
+        "capture on the interface with index <index> (default: 0)",
+        "<index>",
+    );
+    opts.optopt(
+        "s",
+        "snaplen",
+        "capture at most <snaplen> bytes of each frame (default: 65535)",
+        "<snaplen>",
+    );
+    opts.optopt(
+        "b",
+        "buffer",
+        "buffer at most <size> KiB of frames until they're saved (default: 1024)",
+        "<size>",
+    );
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(_f) => {
+            println!("{}", _f);
+            print_usage(&opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(&opts);
+        0
+    } else {
+        match _main(matches) {
+            Ok(_) => 0,
+            Err(e) => {
+                println!("{}", e);
+                -1
+            }
+        }
+    }
+}
+
+fn _main(matches: Matches) -> Result<(), String> {
+    let command = matches.free.first().ok_or("no command provided")?;
+    let rest = &matches.free[1..];
+
+    if command == "list" {
+        list();
+        return Ok(());
+    }
+
+    let index = matches
+        .opt_get_default("i", 0_usize)
+        .map_err(|_| "invalid interface index")?;
+    let interface = net::get_interfaces()
+        .lock()
+        .get(index)
+        .cloned()
+        .ok_or("no network interface with that index")?;
+
+    match command.as_str() {
+        "start" => {
+            let config = capture_config(&matches, parse_filter(rest)?)?;
+            interface.start_capture(config);
+            println!("Started capturing on interface {}", index);
+            Ok(())
+        }
+        "stop" => {
+            interface.stop_capture();
+            let stats = interface.capture_stats();
+            println!(
+                "Stopped capturing on interface {}: {} frames captured, {} dropped, {} not yet saved",
+                index, stats.captured, stats.dropped, stats.buffered,
+            );
+            Ok(())
+        }
+        "save" => {
+            let file_name = rest.first().map(String::as_str).unwrap_or(DEFAULT_FILE_NAME);
+            save(&interface, &matches, file_name)
+        }
+        "stream" => {
+            let destination = rest.first().ok_or("missing HOST:PORT destination")?;
+            stream(&interface, &matches, destination, &rest[1..])
+        }
+        other => Err(format!("unknown command {:?}", other)),
+    }
+}
+
+/// Prints each interface along with its capture status.
+fn list() {
+    let interfaces = net::get_interfaces().lock().clone();
+    for (index, interface) in interfaces.iter().enumerate() {
+        let mac = interface.mac_address();
+        let addresses: Vec<String> = interface.ip_addrs().iter().map(ToString::to_string).collect();
+        let stats = interface.capture_stats();
+        println!(
+            "{}: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x} [{}] {}: {} captured, {} dropped, {} buffered",
+            index,
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5],
+            addresses.join(", "),
+            if interface.is_capturing() { "capturing" } else { "not capturing" },
+            stats.captured,
+            stats.dropped,
+            stats.buffered,
+        );
+    }
+}
+
+/// Parses the remaining arguments as a filter expression, if there are any.
+fn parse_filter(args: &[String]) -> Result<Option<Filter>, &'static str> {
+    if args.is_empty() {
+        Ok(None)
+    } else {
+        Filter::from_str(&args.join(" ")).map(Some)
+    }
+}
+
+fn capture_config(matches: &Matches, filter: Option<Filter>) -> Result<CaptureConfig, &'static str> {
+    let default = CaptureConfig::default();
+    let snap_len = matches
+        .opt_get_default("s", DEFAULT_SNAP_LEN)
+        .map_err(|_| "invalid snaplen")?;
+    let buffer_size = matches
+        .opt_get_default("b", default.buffer_size / 1024)
+        .map_err(|_| "invalid buffer size")?
+        * 1024;
+    Ok(CaptureConfig {
+        filter,
+        snap_len,
+        buffer_size,
+    })
+}
+
+/// Writes the frames captured on `interface` so far into a new file at `file_path`.
+fn save(interface: &NetworkInterface, matches: &Matches, file_path: &str) -> Result<(), String> {
+    let (dir_path, file_name) = match file_path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((dir, name)) => (dir, name),
+        None => (".", file_path),
+    };
+    let curr_dir = task::with_current_task(|t| t.get_env().lock().working_dir.clone())
+        .map_err(|_| "failed to get current task's working directory")?;
+    let dir = Path::new(dir_path.to_string())
+        .get_dir(&curr_dir)
+        .ok_or_else(|| format!("couldn't find directory {:?}", dir_path))?;
+    if dir.lock().get(file_name).is_some() {
+        return Err(format!("{:?} already exists", file_path));
+    }
+
+    let frames = interface.take_captured_frames();
+    let snap_len = matches
+        .opt_get_default("s", DEFAULT_SNAP_LEN)
+        .map_err(|_| "invalid snaplen")?;
+    let mut content = file_header(snap_len);
+    for frame in &frames {
+        append_record(&mut content, frame);
+    }
+
+    let file = MemFile::create(file_name.to_string(), &dir)?;
+    file.lock()
+        .write_at(&content, 0)
+        .map_err(|_| "failed to write capture file")?;
+    let path = match dir.lock().get(file_name) {
+        Some(FileOrDir::File(f)) => f.lock().get_absolute_path(),
+        _ => file_path.to_string(),
+    };
+    println!("Saved {} frames to {}", frames.len(), path);
+    Ok(())
+}
+
+/// Captures frames on `interface` and sends them to `destination` over UDP
+/// until the capture is stopped, e.g., by `pcap stop` from another shell.
+fn stream(interface: &NetworkInterface, matches: &Matches, destination: &str, filter_args: &[String]) -> Result<(), String> {
+    let (host, port) = destination
+        .rsplit_once(':')
+        .ok_or("destination must be HOST:PORT")?;
+    let port = port.parse::<u16>().map_err(|_| "invalid destination port")?;
+    let address = dns::lookup_host(host)?
+        .into_iter()
+        .next()
+        .ok_or("destination host has no addresses")?;
+    let remote = IpEndpoint::new(address, port);
+
+    // The datagrams of the stream itself must not be captured, or each one would cause another.
+    let own_traffic = Filter::And(
+        Box::new(Filter::Protocol(Protocol::Udp)),
+        Box::new(Filter::And(
+            Box::new(Filter::Host(Side::Destination, address)),
+            Box::new(Filter::Port(Side::Destination, port)),
+        )),
+    );
+    let not_own_traffic = Filter::Not(Box::new(own_traffic));
+    let filter = match parse_filter(filter_args)? {
+        Some(filter) => Filter::And(Box::new(filter), Box::new(not_own_traffic)),
+        None => not_own_traffic,
+    };
+    let mut config = capture_config(matches, Some(filter))?;
+    config.snap_len = config.snap_len.min(MAX_STREAM_SNAP_LEN);
+
+    let local = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), 0);
+    let socket = UdpSocket::bind(local).map_err(|_| "couldn't bind a UDP socket")?;
+    let send = |datagram: &[u8]| {
+        socket
+            .send_to(datagram, remote)
+            .map_err(|_| "couldn't send captured frames")
+    };
+
+    send(&file_header(config.snap_len))?;
+    interface.start_capture(config);
+    println!("Streaming captured frames to {}; run `pcap stop` to finish", remote);
+
+    let mut sent = 0;
+    loop {
+        let frames = interface.take_captured_frames();
+        for frame in &frames {
+            let mut record = Vec::with_capacity(16 + frame.data.len());
+            append_record(&mut record, frame);
+            send(&record)?;
+        }
+        sent += frames.len();
+        if frames.is_empty() && !interface.is_capturing() {
+            break;
+        }
+        interface.wait_for_events(STREAM_POLL_INTERVAL);
+    }
+    println!("Streamed {} frames to {}", sent, remote);
+    Ok(())
+}
+
+/// Returns the global header of a pcap file.
+fn file_header(snap_len: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+    header.extend_from_slice(&2_u16.to_le_bytes()); // major version
+    header.extend_from_slice(&4_u16.to_le_bytes()); // minor version
+    header.extend_from_slice(&0_i32.to_le_bytes()); // time zone offset, always UTC
+    header.extend_from_slice(&0_u32.to_le_bytes()); // timestamp accuracy, always 0
+    header.extend_from_slice(&(snap_len as u32).to_le_bytes());
+    header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    header
+}
+
+/// Appends the pcap record of the given `frame` to `buffer`.
+fn append_record(buffer: &mut Vec<u8>, frame: &CapturedFrame) {
+    buffer.extend_from_slice(&(frame.timestamp.as_secs() as u32).to_le_bytes());
+    buffer.extend_from_slice(&frame.timestamp.subsec_micros().to_le_bytes());
+    buffer.extend_from_slice(&(frame.data.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&(frame.original_len as u32).to_le_bytes());
+    buffer.extend_from_slice(&frame.data);
+}
+
+fn print_usage(opts: &Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: pcap [OPTIONS] COMMAND
+Captures the frames sent and received by a network interface in the pcap format.
+
+Commands:
+    list
+        Lists the network interfaces and whether they're capturing frames.
+    start [FILTER]
+        Starts capturing frames that match the optional FILTER.
+    stop
+        Stops capturing frames.
+    save [FILE]
+        Saves the frames captured so far into a new FILE (default: capture.pcap).
+    stream HOST:PORT [FILTER]
+        Captures frames that match the optional FILTER and sends them to HOST:PORT over UDP
+        as a pcap stream until the capture is stopped.
+
+Filters use a subset of the pcap-filter syntax, e.g., `tcp port 80` or `udp and not host 10.0.2.3`:
+    arp, ip, ip6, icmp, icmp6, tcp, udp
+    [src|dst] host ADDRESS
+    [src|dst] port PORT
+    and, or, not, (, )";
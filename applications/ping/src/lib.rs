@@ -1,49 +1,38 @@
-//! This application pings a specific IPv4 address and gets ping statistics.
-//! Important: QEMU does not support the ICMP protocol by default so it's important to 
+//! This application pings a specific IPv4 or IPv6 address and gets ping statistics.
+//! Important: QEMU does not support the ICMP protocol by default so it's important to
 //! run this command: sudo sh -c "echo \"0 2147483647\" > /proc/sys/net/ipv4/ping_group_range"
 //! in the environment prior to running this application
 
 #![no_std]
-#![feature(slice_concat_ext)]
 
-#[macro_use] extern crate log;
 #[macro_use] extern crate alloc;
 #[macro_use] extern crate app_io;
-extern crate smoltcp;
-extern crate network_manager;
-extern crate byteorder;
-extern crate hpet;
-extern crate smoltcp_helper;
-extern crate hashbrown;
-extern crate ota_update_client;
 extern crate getopts;
+extern crate dns;
+extern crate net;
+extern crate time;
 
 
 use getopts::{Matches, Options};
-use core::str::FromStr;
-use hashbrown::HashMap;
-use alloc::vec::Vec;        
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use alloc::string::String;
 use alloc::string::ToString;
-use hpet::get_hpet;
-use smoltcp::{
-    socket::{SocketSet, IcmpSocket, IcmpSocketBuffer, IcmpPacketMetadata, IcmpEndpoint},
-    wire::{IpAddress, Icmpv4Repr, Icmpv4Packet},
-    phy::{ChecksumCapabilities},
+use net::{
+    icmp::{Endpoint, PacketBuffer, PacketMetadata, Socket},
+    phy::ChecksumCapabilities,
+    wire::{Icmpv4Packet, Icmpv4Repr, Icmpv6Packet, Icmpv6Repr, Ipv6Address},
+    IpAddress,
 };
-use network_manager::{NetworkInterfaceRef, NETWORK_INTERFACES};
-use byteorder::{ByteOrder, NetworkEndian};
-use smoltcp_helper::{millis_since, poll_iface};
+use time::{Duration, Monotonic};
 
 
-macro_rules! hpet_ticks {
-    () => {
-        match get_hpet().as_ref().ok_or("coudln't get HPET timer") {
-            Ok(time) => time.get_counter(),
-            Err(_) => return println!("couldnt get HPET timer"),
-        }
-    };
-}
+/// Portless icmp messages such as echo request require a 16-bit identifier to bind to
+/// so that only icmp messages with this identifer can pass through the icmp socket
+const IDENT: u16 = 0x22b;
+
+/// The longest to wait for the interface to be polled before checking for replies and timeouts again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 
 pub fn main(args: Vec<String>) -> isize {
@@ -51,18 +40,20 @@ pub fn main(args: Vec<String>) -> isize {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("v", "verbose", "a more detailed view of packets sent and received");
+    opts.optflag("4", "ipv4", "only ping the IPv4 address of the host");
+    opts.optflag("6", "ipv6", "only ping the IPv6 address of the host");
     opts.optopt("c", "count", "amount of echo request packets to send (default: 4)", "N");
     opts.optopt("i", "interval", "interval between packets being sent in miliseconds (default: 1000)", "N");
     opts.optopt("t", "timeout", "maximum time between echo request and echo reply in milliseconds (default: 5000)", "N");
     opts.optopt("s", "buffer size", "size of packet to send to target address, (min: 8, max: 120, default: 40)", "N");
-    
-  
+
+
     let matches = match opts.parse(&args) {
         Ok(m) => m,
         Err(_f) => {
             println!("{}", _f);
             print_usage(&opts);
-            return -1; 
+            return -1;
         }
     };
 
@@ -72,25 +63,23 @@ pub fn main(args: Vec<String>) -> isize {
 
 
     if !matches.free.is_empty() {
-        match IpAddress::from_str(&matches.free[0]) {
+        match resolve(&matches.free[0], &matches) {
             Ok(address) => {
-                let ping_address = address;
-                let result = rmain(&matches, opts, ping_address);
-                match result {
+                match rmain(&matches, address) {
                     Ok(_) => { 0 }
                     Err(e) => {
                          println!("Ping initialization failed: {}.", e);
                         -1
                     }
                 }
-                
+
             }
-            _ => { 
-                println!("Invalid argument {}, not a valid adress", matches.free[0]); 
+            Err(e) => {
+                println!("Invalid argument {}, not a valid address or known host: {}", matches.free[0], e);
                 -1
             },
-        }   
-    
+        }
+
     }
 
     else {
@@ -99,15 +88,35 @@ pub fn main(args: Vec<String>) -> isize {
     }
 }
 
-pub fn rmain(matches: &Matches, _opts: Options, address: IpAddress) -> Result<(), &'static str> {
+/// Resolves the given host name or IP address to the address of that host that should be pinged.
+///
+/// The `-4` and `-6` options select an address of that IP version;
+/// otherwise, the first address of the host is used.
+fn resolve(host: &str, matches: &Matches) -> Result<IpAddress, &'static str> {
+    let addresses = dns::lookup_host(host)?;
+    if matches.opt_present("6") {
+        addresses.into_iter()
+            .find(|address| matches!(address, IpAddress::Ipv6(_)))
+            .ok_or("host has no IPv6 address")
+    } else if matches.opt_present("4") {
+        addresses.into_iter()
+            .find(|address| matches!(address, IpAddress::Ipv4(_)))
+            .ok_or("host has no IPv4 address")
+    } else {
+        addresses.into_iter()
+            .next()
+            .ok_or("host has no addresses")
+    }
+}
+
+pub fn rmain(matches: &Matches, address: IpAddress) -> Result<(), &'static str> {
+
 
-    
     let mut count = 4;
     let mut interval = 1000;
     let mut timeout = 5000;
     let mut buffer_size = 40;
     let mut verbose = false;
-    let did_work = true;
 
 
     if let Some(i) = matches.opt_default("c", "4") {
@@ -131,224 +140,181 @@ pub fn rmain(matches: &Matches, _opts: Options, address: IpAddress) -> Result<()
     if matches.opt_present("v") {
         verbose = true;
     }
-    
-    if did_work { 
-        ping(address, count, interval, timeout, verbose, buffer_size);
-        Ok(())
-    }
-    else {
-        Ok(())
+
+    ping(address, count, interval, timeout, verbose, buffer_size)
+}
+
+/// Writes an echo request with the given sequence number and payload into `buffer`,
+/// which must be [`echo_request_len`] bytes long.
+fn emit_echo_request(remote_addr: IpAddress, seq_no: u16, data: &[u8], buffer: &mut [u8]) {
+    // The interface computes the checksum when it sends the packet,
+    // as the checksum of an ICMPv6 packet depends on its source address.
+    let checksum_caps = ChecksumCapabilities::ignored();
+    match remote_addr {
+        IpAddress::Ipv4(_) => {
+            let icmp_repr = Icmpv4Repr::EchoRequest { ident: IDENT, seq_no, data };
+            icmp_repr.emit(&mut Icmpv4Packet::new_unchecked(buffer), &checksum_caps);
+        }
+        IpAddress::Ipv6(_) => {
+            let icmp_repr = Icmpv6Repr::EchoRequest { ident: IDENT, seq_no, data };
+            let src_addr = IpAddress::Ipv6(Ipv6Address::UNSPECIFIED);
+            icmp_repr.emit(&src_addr, &remote_addr, &mut Icmpv6Packet::new_unchecked(buffer), &checksum_caps);
+        }
     }
 }
 
-/// Used to gain access to the ethernet interface
-fn get_default_iface() -> Result<NetworkInterfaceRef, String> {
-    NETWORK_INTERFACES.lock()
-        .iter()
-        .next()
-        .cloned()
-        .ok_or_else(|| "no network interfaces available".to_string())
+/// Returns the length of an echo request to `remote_addr` with the given payload.
+fn echo_request_len(remote_addr: IpAddress, data: &[u8]) -> usize {
+    match remote_addr {
+        IpAddress::Ipv4(_) => Icmpv4Repr::EchoRequest { ident: IDENT, seq_no: 0, data }.buffer_len(),
+        IpAddress::Ipv6(_) => Icmpv6Repr::EchoRequest { ident: IDENT, seq_no: 0, data }.buffer_len(),
+    }
 }
 
-// Retrieves the echo reply contained in the receive buffer and prints data pertaining to the packet
-fn get_icmp_pong (waiting_queue: &mut HashMap<u16, u64>, times: &mut Vec<u64>, total_time: &mut u64, 
-    repr: Icmpv4Repr, received: &mut u16, remote_addr: IpAddress, timestamp: u64)  {
-    
-    if let Icmpv4Repr::EchoReply { seq_no, data, ..} = repr {
-        if waiting_queue.get(&seq_no).is_some() {
-            let packet_timestamp_ms = NetworkEndian::read_i64(data) as u64;
-            
-            println!("{} bytes from {}: icmp_seq={}, time={}ms",
-                        data.len(), remote_addr, seq_no,
-                        timestamp - packet_timestamp_ms);
-            
-            waiting_queue.remove(&seq_no);
-            *received += 1;
-            times.push(timestamp - packet_timestamp_ms);
-            *total_time += timestamp - packet_timestamp_ms;
+/// Parses the given packet received from `remote_addr`,
+/// and returns the sequence number and payload length if it's an echo reply.
+fn parse_echo_reply(remote_addr: IpAddress, payload: &[u8], verbose: bool) -> Option<(u16, usize)> {
+    // The interface already verified the checksum when it received the packet.
+    let checksum_caps = ChecksumCapabilities::ignored();
+    match remote_addr {
+        IpAddress::Ipv4(_) => {
+            let icmp_packet = Icmpv4Packet::new_checked(payload).ok()?;
+            let icmp_repr = Icmpv4Repr::parse(&icmp_packet, &checksum_caps).ok()?;
+            if verbose {
+                println!("received {:?}", icmp_repr);
+            }
+            match icmp_repr {
+                Icmpv4Repr::EchoReply { seq_no, data, .. } => Some((seq_no, data.len())),
+                _ => None,
+            }
+        }
+        IpAddress::Ipv6(_) => {
+            let icmp_packet = Icmpv6Packet::new_checked(payload).ok()?;
+            let dst_addr = IpAddress::Ipv6(Ipv6Address::UNSPECIFIED);
+            let icmp_repr = Icmpv6Repr::parse(&remote_addr, &dst_addr, &icmp_packet, &checksum_caps).ok()?;
+            if verbose {
+                println!("received {:?}", icmp_repr);
+            }
+            match icmp_repr {
+                Icmpv6Repr::EchoReply { seq_no, data, .. } => Some((seq_no, data.len())),
+                _ => None,
+            }
         }
-    } 
+    }
 }
 
-fn ping(address: IpAddress, count: usize, interval: u64, timeout: u64, verbose: bool, buffer_size: usize) {
+fn ping(address: IpAddress, count: usize, interval: u64, timeout: u64, verbose: bool, buffer_size: usize) -> Result<(), &'static str> {
 
-    let startup_time = hpet_ticks!();
     let remote_addr = address;
     let mut times = Vec::new();
-    
-    // Initialize the ICMP sockett using the smoltcp function using a transmit packet buffer 
-    // and receiving packet buffer
+
+    // Get the interface that can reach the address, e.g., the loopback interface for `127.0.0.1` or `::1`
+    let iface = net::get_interface_for(remote_addr).ok_or("no network interfaces available")?;
+
+    // Initialize the ICMP socket using a transmit packet buffer and receiving packet buffer
     //
     // The payload storage contains the application data and the transport header, and metadata contains the ICMP type
     // and ICMP code, which are used to classify between echo requests and echo replies
-    let icmp_rx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketMetadata::EMPTY], vec![0; 256]);
-    let icmp_tx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketMetadata::EMPTY], vec![0; 256]);
-    let icmp_socket = IcmpSocket::new(icmp_rx_buffer, icmp_tx_buffer);
-    
-    // Get the default ethernet interface to ping with
-    let iface_result = get_default_iface();
-    let iface = match iface_result {
-        Ok(network) => network,
-        Err(err) => return println!("couldn't initialize the network: {}", err),
-    };
+    let icmp_rx_buffer = PacketBuffer::new(vec![PacketMetadata::EMPTY; 4], vec![0; 1024]);
+    let icmp_tx_buffer = PacketBuffer::new(vec![PacketMetadata::EMPTY; 4], vec![0; 1024]);
+    let socket = iface.add_socket(Socket::new(icmp_rx_buffer, icmp_tx_buffer));
+    socket.lock().bind(Endpoint::Ident(IDENT)).map_err(|_e| "the socket failed to bind")?;
+    println!("PING {}, ({}) bytes of data", address, buffer_size);
 
+    let startup_time = time::now::<Monotonic>();
+    let millis_since_startup = || time::now::<Monotonic>().duration_since(startup_time).as_millis() as u64;
 
-    let mut sockets = SocketSet::new(vec![]);
-    let icmp_handle = sockets.add(icmp_socket);
-    
-    let mut send_at = match millis_since(startup_time) {
-        Ok(time) => time,
-        Err(err) => return println!("couldn't get time since start_up: {}", err),
-    };
-    
+    let mut send_at = 0;
     let mut seq_no = 0;
     let mut received: u16 = 0;
     let mut total_time: u64 = 0;
-    let mut echo_payload = vec![0xffu8; buffer_size];
-    let mut timeout_loop = false;
+    let echo_payload = vec![0xffu8; buffer_size];
+
+    // Maps the sequence number of each echo request that hasn't been answered yet to when it was sent
+    let mut waiting_queue = BTreeMap::new();
 
-    // Designate no checksum capabilities 
-    let checksum_caps = ChecksumCapabilities::ignored();
-    
-    // Initiate a hashmap
-    let mut waiting_queue = HashMap::new();  
-    
-    // Portless icmp messages such as echo request require a 16-bit identifier to bind to
-    // so that only icmp messages with this identifer can pass through the icmp socket
-    let ident = 0x22b; 
-    let mut poll_status = true;
-
-    // Makes sure that the icmp handle can communicate with the given ethernet interface
     loop {
-        
-        match poll_iface(&iface, &mut sockets, startup_time) {
-            Ok(var) => poll_status = var,
-            Err(e) => {
-                debug!("poll error: {}", e);
-            }
-        }
+        let timestamp = millis_since_startup();
         {
-            let timestamp = match millis_since(startup_time) {
-                Ok(time) => time,
-                Err(err) => return println!("couldn't get timestamp:{}", err),
-            };
-            let mut socket = sockets.get::<IcmpSocket>(icmp_handle); 
-            
-            // Checks if the icmp socket is open, and only bind the identifier icmp to it if 
-            // it is closed
-            if !socket.is_open() {
-                match socket.bind(IcmpEndpoint::Ident(ident)) {
-                    Ok(_) => (),
-                    Err(e) => return println!("the socket failed to bind: {}", e),
-                }; 
-                send_at = timestamp;
-                println!("PING {}, ({}) bytes of data", address, buffer_size);
-            }
-            
-            // Checks if the icmp sockett can send an echo request
+            let mut socket = socket.lock();
+
+            // Checks if the icmp socket can send an echo request
             if socket.can_send() && seq_no < count as u16 && send_at <= timestamp {
-                
-                NetworkEndian::write_i64(&mut echo_payload, timestamp as i64);
-
-                let icmp_repr = Icmpv4Repr::EchoRequest{
-                        ident,
-                        seq_no,
-                        data: &echo_payload
-                    };
-
-                let icmp_payload = match socket.send(icmp_repr.buffer_len(), remote_addr) {
-                    Ok(payload) => payload,
-                    Err(_err) => return println!("the icmp socket cannot send"),
-                };
-
-                let mut icmp_packet = Icmpv4Packet::new_unchecked(icmp_payload);
-                
-                icmp_repr.emit(&mut icmp_packet, &checksum_caps); //turns or "emits" the raw network stack into an icmpv4 packet,
+                let length = echo_request_len(remote_addr, &echo_payload);
+                let icmp_payload = socket.send(length, remote_addr).map_err(|_e| "the icmp socket cannot send")?;
+                emit_echo_request(remote_addr, seq_no, &echo_payload, icmp_payload);
                 if verbose {
-                    println!("buffer length: {}", icmp_repr.buffer_len());
-                    println!("checking checksum of packet, should be 0: {:?}", icmp_packet.checksum());
-                    println!("checking echo_ident of packet, should be a value: {:?}", icmp_packet.echo_ident());
-                    println!("checking msg_type of packet, should be an echo_request: {:?}", icmp_packet.msg_type());
+                    println!("sent echo request icmp_seq={}, buffer length: {}", seq_no, length);
                 }
-            
-            // Insert the sequence number into the waiting que along with the timestamp after an echo
-            // Request has been sent
-            waiting_queue.insert(seq_no, timestamp);
-            seq_no += 1;
-            send_at += interval;
-            
+
+                // Insert the sequence number into the waiting queue along with the timestamp after an echo
+                // request has been sent
+                waiting_queue.insert(seq_no, timestamp);
+                seq_no += 1;
+                send_at += interval;
             }
 
-            // Once the socket can successfully receive the echo reply, unload the payload and
-            // then return the current time as well as wether the ping has been received         
-            if socket.can_recv() {
-                let (payload, _) = match socket.recv() {
-                    Ok((packet_buff,end_point)) => (packet_buff, end_point),
-                    Err(err) => return println!("err: {} the receive buffer is empty", err), 
-                }; 
-                let icmp_packet = match Icmpv4Packet::new_checked(&payload) {
-                    Ok(packet) => packet,
-                    Err(err) => return println!("err: {}", err),
-                }; 
-                // Turns or "parses" the ICMPv4 packet into a raw level representation
-                let icmp_repr = match Icmpv4Repr::parse(&icmp_packet, &checksum_caps) {
-                    Ok(repr) => repr,
-                    Err(err) => return println!("err: {}", err),
-                }; 
-                
-                get_icmp_pong(&mut waiting_queue, &mut times, &mut total_time, icmp_repr, &mut received, remote_addr, timestamp);
-                if verbose {
-                    println!("buffer length: {}", icmp_repr.buffer_len());
-                    println!("checking checksum of packet, should be above 0: {:?}", icmp_packet.checksum());
-                    println!("checking echo_ident of packet, should be a value: {:?}", icmp_packet.echo_ident());
-                    println!("checking msg_type of packet, should be an echo_reply: {:?}", icmp_packet.msg_type());                
+            // Unload the payload of every received echo reply
+            while socket.can_recv() {
+                let (payload, _) = socket.recv().map_err(|_e| "the receive buffer is empty")?;
+                if let Some((reply_seq_no, length)) = parse_echo_reply(remote_addr, payload, verbose) {
+                    if let Some(sent_at) = waiting_queue.remove(&reply_seq_no) {
+                        let time = timestamp - sent_at;
+                        println!("{} bytes from {}: icmp_seq={}, time={}ms", length, remote_addr, reply_seq_no, time);
+                        received += 1;
+                        times.push(time);
+                        total_time += time;
+                    }
                 }
             }
-            
-            // Uses this retain function to decide whether the sequence you're currently looking at is timed out 
-            waiting_queue.retain(|seq, from| {
-                if timestamp - *from <  timeout {
-                    true
-                } else {
-                    timeout_loop = true;
-                    println!("From {} icmp_seq={} timeout", remote_addr, seq);
-                    false
-                }
-            });
+        }
 
-            // Once all the echorequests have been recieved/timed out or if transmit buffer is unable to be flushed, break from the loop
-            let received_all_packets = seq_no == count as u16 && waiting_queue.is_empty();
-            let unflushed_txbuffer = timeout_loop && !poll_status && seq_no != count as u16;  
-            if received_all_packets || unflushed_txbuffer {
-                break
+        // Uses this retain function to decide whether the sequence you're currently looking at is timed out
+        waiting_queue.retain(|seq, from| {
+            if timestamp - *from < timeout {
+                true
+            } else {
+                println!("From {} icmp_seq={} timeout", remote_addr, seq);
+                false
             }
+        });
+
+        // Once all the echo requests have been received or timed out, break from the loop
+        if seq_no == count as u16 && waiting_queue.is_empty() {
+            break
         }
-    
 
+        // Dropping the socket's guard wakes up the interface's worker task, which sends queued requests,
+        // and every poll of the interface may have received replies
+        iface.wait_for_events(POLL_INTERVAL);
     }
-    
+
     // Computes ping min/avg/max
     let avg_ping = if received != 0 {
         total_time as f64 / (received as f64)
     } else {
         0 as f64
     };
-     
+
     let min_ping = times.iter().min().unwrap_or(&0);
     let max_ping = times.iter().max().unwrap_or(&0);
-    
+
     println!("\n--- {} ping statistics ---", remote_addr);
     println!("{} packets transmitted, {} received, {:.0}% packet loss \nrtt min/avg/max = {}/{}/{}",
             seq_no, received, 100.0 * (seq_no - (received)) as f64 / seq_no as f64, min_ping, avg_ping , max_ping);
-    if received == 0{         
-            println!("\nwarning: Ping/ICMP will not work in QEMU unless you specifically enable it. If you are able to ping  \nthe qemu gateway address 10.0.2.2 and not other addresses, your ICMP is most likely disabled");
+    if received == 0 {
+        match remote_addr {
+            IpAddress::Ipv4(_) => println!("\nwarning: Ping/ICMP will not work in QEMU unless you specifically enable it. If you are able to ping  \nthe qemu gateway address 10.0.2.2 and not other addresses, your ICMP is most likely disabled"),
+            IpAddress::Ipv6(_) => println!("\nwarning: IPv6 addresses other than link-local ones are only assigned once a router advertises a prefix. \nIn QEMU's user-mode network, the gateway's address is fe80::2"),
+        }
     }
+    Ok(())
 }
 
 fn print_usage(opts: &Options) -> isize {
-    let mut brief = "Usage: ping DESTINATION \n \n".to_string();
+    let mut brief = "Usage: ping [-4|-6] DESTINATION \n \n".to_string();
 
-    brief.push_str("pings an IPv4 address and returns ping statistics");
+    brief.push_str("pings a host name, IPv4 address, or IPv6 address and returns ping statistics");
 
     println!("{} \n", opts.usage(&brief));
 
@@ -4,7 +4,6 @@ extern crate alloc;
 
 use alloc::{string::String, vec, vec::Vec};
 use app_io::println;
-use core::str::FromStr;
 use getopts::{Matches, Options};
 use net::{
     icmp::{Endpoint, PacketBuffer, PacketMetadata, Socket},
@@ -53,8 +52,11 @@ pub fn main(args: Vec<String>) -> isize {
 }
 
 fn _main(matches: Matches) -> Result<(), &'static str> {
-    let remote = IpAddress::from_str(matches.free.get(0).ok_or("no arguments_provided")?)
-        .map_err(|_| "invalid argument")?;
+    let host = matches.free.get(0).ok_or("no arguments_provided")?;
+    let remote = dns::lookup_host(host)?
+        .into_iter()
+        .find(|address| matches!(address, IpAddress::Ipv4(_)))
+        .ok_or("host has no IPv4 address")?;
 
     let interface = net::get_default_interface().ok_or("no network interfaces available")?;
 
@@ -140,4 +142,4 @@ fn print_usage(opts: &Options) {
 }
 
 const USAGE: &str = "Usage: ping DESTINATION
-Pings an IPv4 address and displays network statistics";
+Pings a host name or IPv4 address and displays network statistics";
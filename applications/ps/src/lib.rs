@@ -34,12 +34,7 @@ pub fn main(args: Vec<String>) -> isize {
         println!("{0:<5}  {1}", "ID", "NAME");
     }
     else {
-        #[cfg(priority_scheduler)] {
-            println!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6}", "ID", "RUNSTATE", "CPU", "PIN", "TYPE", "PRIORITY", "NAME");
-        }
-        #[cfg(not(priority_scheduler))] {
-            println!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5}", "ID", "RUNSTATE", "CPU", "PIN", "TYPE", "NAME");
-        }
+        println!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6:>12}  {7}", "ID", "RUNSTATE", "CPU", "PIN", "TYPE", "PRIORITY", "TIME", "NAME");
     }
 
     // Print all tasks
@@ -58,18 +53,11 @@ pub fn main(args: Vec<String>) -> isize {
             let task_type = if task.is_an_idle_task {"I"}
                 else if task.is_application() {"A"}
                 else {" "} ;
+            let time = task.cpu_time().map(|t| format!("{}.{:03}", t.as_secs(), t.subsec_millis())).unwrap_or_else(|| String::from("-"));
 
-            #[cfg(priority_scheduler)] {
-                let priority = scheduler::get_priority(&task).map(|priority| format!("{}", priority)).unwrap_or_else(|| String::from("-"));
-                task_string.push_str(
-                    &format!("{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6}\n", 
-                    id, runstate, cpu, pinned, task_type, priority, task.name)
-                );
-            }
-            #[cfg(not(priority_scheduler))] {
-                writeln!(task_string, "{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5}", 
-                    id, runstate, cpu, pinned, task_type, task.name).expect("Failed to write to task_string.");
-            }
+            let priority = scheduler::get_priority(&task).map(|priority| format!("{}", priority)).unwrap_or_else(|| String::from("-"));
+            writeln!(task_string, "{0:<5}  {1:<10}  {2:<4}  {3:<4}  {4:<5}  {5:<10}  {6:>12}  {7}", 
+                id, runstate, cpu, pinned, task_type, priority, time, task.name).expect("Failed to write to task_string.");
         }
     }
     print!("{}", task_string);
@@ -87,7 +75,9 @@ const BRIEF: &str = "Usage: ps [options]\n
     TYPE:      'I' if an idle task, 'A' if an application task, '-' otherwise.
     CPU:       the cpu core the task is currently running on.
     PIN:       the core the task is pinned on, if any.
+    PRIORITY:  the priority of the task, if its core's scheduler uses priorities.
     RUNSTATE:  runnability status of this task, e.g., whether it can be scheduled in.
+    TIME:      the total CPU time this task has consumed, in seconds.
     ID:        the unique identifier for this task.
     NAME:      the name of the task.";
     
\ No newline at end of file
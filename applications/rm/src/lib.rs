@@ -8,6 +8,7 @@ extern crate getopts;
 extern crate path;
 extern crate fs_node;
 extern crate root;
+extern crate mount_table;
 
 use alloc::vec::Vec;
 use alloc::string::String;
@@ -57,11 +58,16 @@ pub fn remove_node(args: Vec<String>) -> Result<(), String> {
 
     for path_string in &matches.free {
         let path = Path::new(path_string.clone());
-        let node_to_delete = match path.get(&working_dir) {
+        let node_to_delete = match path.get_no_follow(&working_dir) {
             Some(node) => node,
             _ => return Err(format!("Couldn't find path {path}")),
         };
 
+        // Mounted filesystems must be unmounted rather than removed.
+        if node_to_delete.is_dir() && mount_table::is_mount_point(&node_to_delete.get_absolute_path()) {
+            return Err(format!("Couldn't remove {path}: it is a mount point, use `umount` instead."));
+        }
+
         // Only remove directories if the user specified "-r". 
         let can_remove_dirs = matches.opt_present("r");
         let path_error = || { format!("Couldn't remove {} from its parent directory.", &path) };
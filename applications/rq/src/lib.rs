@@ -43,9 +43,9 @@ pub fn main(args: Vec<String>) -> isize {
 
         println!("\n{} (apic: {}, proc: {})", core_type, apic_id, processor); 
         
-        if let Some(runqueue) = runqueue::get_runqueue(apic_id).map(|rq| rq.read()) {
+        if let Some(runqueue) = runqueue::get_scheduler(apic_id).map(|sched| sched.read()) {
             let mut runqueue_contents = String::new();
-            for task in runqueue.iter() {
+            for task in runqueue.tasks().iter() {
                 writeln!(runqueue_contents, "{} ({}) {}", 
                     task.name, 
                     task.id,
@@ -53,7 +53,10 @@ pub fn main(args: Vec<String>) -> isize {
                 )
                 .expect("Failed to write to runqueue_contents");
             }
-            println!("RunQueue:\n{}", runqueue_contents);
+            println!("RunQueue ({} scheduler):\n{}", runqueue.name(), runqueue_contents);
+            if let Some(counts) = runqueue::migration_counts(apic_id) {
+                println!("Tasks migrated in: {}, migrated out: {}", counts.migrated_in, counts.migrated_out);
+            }
         }
         
         else {
@@ -45,7 +45,7 @@ pub fn main(args: Vec<String>) -> isize {
     } else if let Some(core) = core {
         run(
             |_| {
-                runqueue::get_runqueue(core);
+                runqueue::get_scheduler(core);
             },
             num,
         )
@@ -53,7 +53,7 @@ pub fn main(args: Vec<String>) -> isize {
         let cpu_count = cpu::cpu_count();
         run(
             |count| {
-                runqueue::get_runqueue((count % cpu_count) as u8);
+                runqueue::get_scheduler((count % cpu_count) as u8);
             },
             num,
         )
@@ -11,7 +11,14 @@ pub fn main(args: Vec<String>) -> isize {
     options
         .optflag("h", "help", "Display this message")
         .optopt("t", "threads", "Spawn <num> threads", "<num>")
-        .optopt("y", "yield", "Yield <num> times in each thread", "<num>");
+        .optopt("y", "yield", "Yield <num> times in each thread", "<num>")
+        .optopt(
+            "i",
+            "imbalanced",
+            "Initially place all threads on core <core>, without pinning them to it",
+            "<core>",
+        )
+        .optflag("n", "no-balance", "Disable load balancing while the benchmark runs");
 
     let matches = match options.parse(args) {
         Ok(matches) => matches,
@@ -33,25 +40,31 @@ pub fn main(args: Vec<String>) -> isize {
     let num_yields = matches
         .opt_get_default("y", 16384)
         .expect("failed to parse the number of yields");
+    let imbalanced_core = matches
+        .opt_get::<u8>("i")
+        .expect("failed to parse the initial core");
+    let no_balance = matches.opt_present("n");
 
     let mut tasks = Vec::with_capacity(num_threads);
     for _ in 0..num_threads {
-        tasks.push(
-            spawn::new_task_builder(worker, num_yields)
-                // Currently, if the tasks aren't pinned to a core, the workers on the same core as
-                // the shell finish significantly slower. The majority of the runtime is taken up by
-                // the shell rather than by our workers, invalidating the benchmark. To fix this we
-                // pin the workers on core 3, assuming the shell is on some other core. This means
-                // the benchmark doesn't incorporate work stealing, but the only reason we're having
-                // this problem in the first place is because work stealing isn't implemented so...
-                // TODO: Remove this when work stealing is implemented.
-                .pin_on_core(3)
-                .block()
-                .spawn()
-                .expect("failed to spawn task"),
-        );
+        let task = spawn::new_task_builder(worker, num_yields)
+            .block()
+            .spawn()
+            .expect("failed to spawn task");
+        // Moving a blocked task to another runqueue is safe, as it hasn't run yet. Unlike
+        // pinning, this allows the load balancer to migrate it to other cores once it runs.
+        if let Some(core) = imbalanced_core {
+            runqueue::remove_task_from_all(&task).expect("failed to remove task from runqueue");
+            runqueue::add_task_to_specific_runqueue(core, (*task).clone())
+                .expect("failed to add task to runqueue");
+        }
+        tasks.push(task);
     }
 
+    let was_balancing = runqueue::is_load_balancing_enabled();
+    runqueue::set_load_balancing(!no_balance);
+    let migrations_before = runqueue::total_migrations();
+
     let start = now::<Monotonic>();
     for task in tasks.iter() {
         task.unblock().expect("failed to unblock task");
@@ -73,8 +86,11 @@ pub fn main(args: Vec<String>) -> isize {
         task.join().expect("failed to join task");
     }
     let end = now::<Monotonic>();
+    let migrations = runqueue::total_migrations() - migrations_before;
+    runqueue::set_load_balancing(was_balancing);
 
     println!("time: {:#?}", end - start);
+    println!("migrations: {}", migrations);
 
     0
 }
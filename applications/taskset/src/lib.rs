@@ -0,0 +1,164 @@
+//! Shows or changes the CPU affinity of a task, i.e., the set of CPUs that it may run on.
+//!
+//! Changing the affinity of a task migrates it to a CPU within its new affinity
+//! if it is currently on the runqueue of a CPU outside of its new affinity.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use app_io::println;
+use cpu::CpuSet;
+use getopts::{Matches, Options};
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optflag(
+        "m",
+        "mask",
+        "interpret <cpus> as a hexadecimal bitmask, e.g., 0x5 for CPUs 0 and 2",
+    );
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn run(matches: &Matches) -> Result<(), String> {
+    let (task_id, cpus) = match matches.free.as_slice() {
+        [task_id] => (task_id, None),
+        [task_id, cpus] => (task_id, Some(cpus)),
+        _ => return Err("expected a task ID and optionally a set of CPUs".to_string()),
+    };
+    let task_id = task_id
+        .parse::<usize>()
+        .map_err(|_| format!("invalid task ID {task_id:?}"))?;
+    let task = task::get_task(task_id).ok_or_else(|| format!("no task with ID {task_id}"))?;
+
+    let old_affinity = task.affinity();
+    let cpus = match cpus {
+        Some(cpus) => cpus,
+        None => {
+            println!("Task {} ({}): affinity {}", task_id, task.name, old_affinity);
+            return Ok(());
+        }
+    };
+
+    let new_affinity = if matches.opt_present("m") {
+        parse_mask(cpus)?
+    } else {
+        cpus.parse::<CpuSet>().map_err(|e| format!("{e}: {cpus:?}"))?
+    };
+    runqueue::set_affinity(&task, new_affinity).map_err(|e| e.to_string())?;
+    println!(
+        "Task {} ({}): affinity changed from {} to {}",
+        task_id, task.name, old_affinity, new_affinity
+    );
+    Ok(())
+}
+
+/// Parses a hexadecimal bitmask of CPUs, with an optional `0x` prefix,
+/// in which bit `n` corresponds to the CPU with ID `n`.
+fn parse_mask(mask: &str) -> Result<CpuSet, String> {
+    let digits = mask.strip_prefix("0x").or_else(|| mask.strip_prefix("0X")).unwrap_or(mask);
+    let invalid = || format!("invalid CPU mask {mask:?}");
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut set = CpuSet::empty();
+    for (i, c) in digits.chars().rev().enumerate() {
+        let nibble = c.to_digit(16).ok_or_else(invalid)?;
+        for bit in 0..4 {
+            if nibble & (1 << bit) == 0 {
+                continue;
+            }
+            let cpu = u8::try_from(i * 4 + bit).map_err(|_| invalid())?;
+            set.insert(cpu);
+        }
+    }
+    Ok(set)
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: taskset [OPTIONS] <task_id> [<cpus>]
+Shows the CPU affinity of the given task, i.e., the set of CPUs it may run on,
+or changes it to <cpus> if given, migrating the task to one of those CPUs if necessary.
+By default, <cpus> is a list of CPU IDs and ranges, e.g., 0-2,5, or `all` for all CPUs.";
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+
+    #[test]
+    fn mask_with_or_without_prefix() {
+        assert_eq!(parse_mask("0x5"), Ok(CpuSet::from_mask(0b101)));
+        assert_eq!(parse_mask("5"), Ok(CpuSet::from_mask(0b101)));
+        assert_eq!(parse_mask("0X1f"), Ok(CpuSet::from_mask(0x1f)));
+        assert_eq!(parse_mask("0xffffffffffffffff"), Ok(CpuSet::from_mask(u64::MAX)));
+    }
+
+    #[test]
+    fn mask_beyond_64_cpus() {
+        let expected: CpuSet = [0, 64, 255].into_iter().collect();
+        let mask = format!("8{}1{}1", "0".repeat(46), "0".repeat(15));
+        assert_eq!(parse_mask(&mask), Ok(expected));
+    }
+
+    #[test]
+    fn mask_with_leading_zeros() {
+        let mask = format!("0x{}3", "0".repeat(100));
+        assert_eq!(parse_mask(&mask), Ok(CpuSet::from_mask(0b11)));
+    }
+
+    #[test]
+    fn zero_mask_is_empty() {
+        assert_eq!(parse_mask("0"), Ok(CpuSet::empty()));
+        assert_eq!(parse_mask("0x0"), Ok(CpuSet::empty()));
+    }
+
+    #[test]
+    fn invalid_masks() {
+        for mask in ["", "0x", "0X", "zz", "0x0x1", "1 2", "-1"] {
+            assert!(parse_mask(mask).is_err(), "{mask:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn out_of_range_cpu() {
+        // Bit 256 doesn't correspond to any possible CPU ID.
+        let mask = format!("1{}", "0".repeat(64));
+        assert!(parse_mask(&mask).is_err());
+        // Zero bits beyond the last possible CPU are fine.
+        let mask = format!("0{}", "f".repeat(64));
+        assert_eq!(parse_mask(&mask), Ok(CpuSet::all()));
+    }
+}
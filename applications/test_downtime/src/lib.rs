@@ -372,10 +372,7 @@ pub fn pick_child_core() -> u8 {
 }
 
 fn nr_tasks_in_rq(core: u8) -> Option<usize> {
-	match runqueue::get_runqueue(core).map(|rq| rq.read()) {
-		Some(rq) => { Some(rq.iter().count()) }
-		_ => { None }
-	}
+	runqueue::get_scheduler(core).map(|sched| sched.read().tasks().len())
 }
 
 fn hpet_2_ns(hpet: u64) -> u64 {
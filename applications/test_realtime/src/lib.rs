@@ -1,11 +1,15 @@
-//! Demo of scheduling a periodic task using the realtime scheduler.
-//! 
+//! Tests the scheduling of periodic real-time tasks using the EDF scheduler.
+//!
+//! This spawns a periodic task with a budget, period, and deadline on the current core,
+//! checks that admission control rejects a task that the core couldn't accommodate alongside it,
+//! and then reports any deadline misses or budget overruns of the periodic task's jobs.
+//!
 //! One potential direction for future testing could be the following:
-//! Let the program take in arguments `n` and `p1` ... `pm`. 
+//! Let the program take in arguments `n` and `p1` ... `pm`.
 //! For each `p_i`, we:
 //! 1. spawn a task with period `pi`
 //! 2. Let each spawned task run for `n` complete periods.
-//! 3. Use`hpet()` to measure the time elapsed between successive executions of each task. 
+//! 3. Use`hpet()` to measure the time elapsed between successive executions of each task.
 //! 4. Calculate statistics on how much the time between successive calls
 //!    to the timing statement deviates from the expected time, i.e. the period `pi`.
 //!    * This is one way to assess the accuracy of the `sleep` function.
@@ -18,44 +22,90 @@ extern crate alloc;
 extern crate spawn;
 extern crate sleep;
 extern crate scheduler;
+extern crate scheduler_edf;
 #[macro_use] extern crate app_io;
 extern crate time;
+extern crate cpu;
 
 use alloc::{
     vec::Vec,
     string::String
 };
+use scheduler::RealtimeParams;
+use scheduler_edf::RealtimeEventKind;
+use time::Duration;
+
+/// The number of periods that the periodic task runs for.
+const ITERATIONS: usize = 5;
+/// The period of the periodic task.
+const PERIOD: Duration = Duration::from_secs(1);
+/// The CPU time reserved for the periodic task in each period.
+const BUDGET: Duration = Duration::from_millis(100);
 
 pub fn main(_args: Vec<String>) -> isize {
-    #[cfg(not(realtime_scheduler))] {
-        println!("Error: `realtime_scheduler` cfg was not enabled!");
-        -1
+    // The periodic task runs on this core, which must use the EDF scheduler.
+    let core = cpu::current_cpu();
+    if scheduler::policy_name(core) != Some("edf") {
+        println!("Switching core {} to the EDF scheduler.", core);
+        if let Err(e) = scheduler::set_policy(core, scheduler::Policy::Edf) {
+            println!("Error: couldn't switch core {} to the EDF scheduler: {}", core, e);
+            return -1;
+        }
     }
-    #[cfg(realtime_scheduler)] {
-        println!("Testing periodic task(s) with the realtime scheduler!");
-        // Build and spawn two real time periodic task(s).
-        // Start them as blocked in order to set the periods before they run
-        let periodic_tb1 = spawn::new_task_builder(_task_delay_tester, 1).block();
-        let periodic_task_1 = periodic_tb1.spawn().unwrap();
-        
-        // Set the periods of the task
-        scheduler::set_periodicity(&periodic_task_1, 1000).unwrap();
-
-        // start the tasks
-        periodic_task_1.unblock().unwrap();
-
-        0
+
+    println!("Testing periodic task(s) with the EDF scheduler!");
+    // Build and spawn the real-time periodic task(s).
+    // Start them as blocked in order to set their parameters before they run.
+    let periodic_task_1 = spawn::new_task_builder(_task_delay_tester, ITERATIONS)
+        .pin_on_core(core)
+        .block()
+        .spawn()
+        .unwrap();
+    if let Err(e) = scheduler::set_realtime_params(&periodic_task_1, RealtimeParams::periodic(BUDGET, PERIOD)) {
+        println!("Error: couldn't admit the periodic task: {}", e);
+        return -1;
+    }
+
+    // The core can't accommodate another task that needs all of its time.
+    let greedy_task = spawn::new_task_builder(_task_delay_tester, 0)
+        .pin_on_core(core)
+        .block()
+        .spawn()
+        .unwrap();
+    let rejected = scheduler::set_realtime_params(&greedy_task, RealtimeParams::periodic(PERIOD, PERIOD)).is_err();
+    greedy_task.unblock().unwrap();
+    greedy_task.join().unwrap();
+    if !rejected {
+        println!("Error: admission control admitted an unschedulable task set.");
+        return -1;
     }
-}    
+    println!("Admission control rejected an unschedulable task set.");
+
+    // start the tasks
+    periodic_task_1.unblock().unwrap();
+    periodic_task_1.join().unwrap();
+
+    let mut misses = 0;
+    for event in scheduler_edf::events().into_iter().filter(|e| e.task_id == periodic_task_1.id) {
+        println!("{:?}", event);
+        if event.kind == RealtimeEventKind::DeadlineMiss {
+            misses += 1;
+        }
+    }
+    if misses > 0 {
+        println!("The periodic task missed {} of {} deadlines.", misses, ITERATIONS);
+        return -1;
+    }
+    println!("The periodic task met all {} deadlines.", ITERATIONS);
+    0
+}
 
 /// A simple task that periodically sleeps and prints a log statement at regular intervals.
-fn _task_delay_tester(_arg: usize) {
+fn _task_delay_tester(iterations: usize) {
     let mut end_time = time::now::<time::Monotonic>();
-    let mut iter = 0;
-    loop {
-        end_time += time::Duration::from_secs(1);
+    for iter in 0..iterations {
+        end_time += PERIOD;
         info!("I run periodically (iter {}).", iter);
-        iter += 1;
         sleep::sleep_until(end_time).unwrap();
     }
 }
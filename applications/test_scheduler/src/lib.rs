@@ -50,8 +50,8 @@ pub fn main(_args: Vec<String>) -> isize {
     let _priority2 = scheduler::get_priority(&taskref2);
     let _priority3 = scheduler::get_priority(&taskref3);
 
-    #[cfg(priority_scheduler)]
-    {
+    // Priorities are only known if core 1 is using a scheduler that supports them.
+    if _priority1.is_some() {
         assert_eq!(_priority1,Some(30));
         assert_eq!(_priority2,Some(20));
         assert_eq!(_priority3,Some(10));
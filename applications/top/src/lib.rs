@@ -0,0 +1,231 @@
+//! Shows the tasks that consume the most CPU time, along with the idle time of each CPU,
+//! in a view that is refreshed periodically until the application is killed.
+//!
+//! CPU usage is computed from the difference in each task's accumulated CPU time
+//! between two consecutive refreshes, so the first view is shown after one refresh interval.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use app_io::{print, println};
+use core::{fmt::Write, time::Duration};
+use getopts::{Matches, Options};
+use task::{RunState, TASKLIST};
+use time::{Instant, Monotonic};
+
+/// How long to wait between refreshes if no delay is given.
+const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+/// How many tasks are shown if no maximum is given.
+const DEFAULT_MAX_TASKS: usize = 20;
+/// Clears the terminal and moves the cursor to its top-left corner.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// The state of a task at the time of a sample.
+struct TaskSample {
+    name: String,
+    runstate: RunState,
+    running_on_cpu: Option<u8>,
+    is_an_idle_task: bool,
+    pinned_core: Option<u8>,
+    cpu_time_ticks: u64,
+}
+
+/// The state of all tasks at a point in time.
+struct Sample {
+    time: Instant,
+    tasks: BTreeMap<usize, TaskSample>,
+}
+
+pub fn main(args: Vec<String>) -> isize {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "d",
+        "delay",
+        "wait <secs> seconds between refreshes (default: 1)",
+        "<secs>",
+    );
+    opts.optopt(
+        "n",
+        "iterations",
+        "exit after refreshing <count> times (default: run until killed)",
+        "<count>",
+    );
+    opts.optopt(
+        "m",
+        "max",
+        "show at most <count> tasks (default: 20)",
+        "<count>",
+    );
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}", e);
+            print_usage(opts);
+            return -1;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return 0;
+    }
+
+    match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Error: {}", e);
+            -1
+        }
+    }
+}
+
+fn run(matches: &Matches) -> Result<(), String> {
+    let delay = match matches.opt_str("d") {
+        Some(secs) => secs
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| *secs > 0.0)
+            .map(|secs| Duration::from_micros((secs * 1_000_000.0) as u64))
+            .ok_or_else(|| "invalid delay".to_string())?,
+        None => DEFAULT_DELAY,
+    };
+    let iterations = matches
+        .opt_str("n")
+        .map(|count| count.parse::<usize>().map_err(|_| "invalid number of iterations".to_string()))
+        .transpose()?;
+    let max_tasks = matches
+        .opt_str("m")
+        .map(|count| count.parse::<usize>().map_err(|_| "invalid maximum number of tasks".to_string()))
+        .transpose()?
+        .unwrap_or(DEFAULT_MAX_TASKS);
+
+    let mut previous = sample();
+    let mut refreshes = 0;
+    while iterations.map_or(true, |iterations| refreshes < iterations) {
+        sleep::sleep(delay).map_err(|_| "failed to sleep".to_string())?;
+        let current = sample();
+        print!("{}{}", CLEAR_SCREEN, render(&previous, &current, delay, max_tasks));
+        previous = current;
+        refreshes += 1;
+    }
+    Ok(())
+}
+
+/// Records the current state of all tasks.
+fn sample() -> Sample {
+    let tasks = TASKLIST
+        .lock()
+        .iter()
+        .map(|(id, task)| {
+            let sample = TaskSample {
+                name: task.name.clone(),
+                runstate: task.runstate(),
+                running_on_cpu: task.running_on_cpu(),
+                is_an_idle_task: task.is_an_idle_task,
+                pinned_core: task.pinned_core(),
+                cpu_time_ticks: task.cpu_time_ticks(),
+            };
+            (*id, sample)
+        })
+        .collect();
+    Sample {
+        time: time::now::<Monotonic>(),
+        tasks,
+    }
+}
+
+/// Returns the share of the `elapsed` time that a task with the given `previous` and `current` samples ran for,
+/// as a percentage of a single CPU.
+fn usage_percent(previous: Option<&TaskSample>, current: &TaskSample, elapsed: Duration) -> f64 {
+    let ticks = current.cpu_time_ticks - previous.map_or(0, |p| p.cpu_time_ticks.min(current.cpu_time_ticks));
+    let run_time = task::ticks_to_duration(ticks).unwrap_or_default();
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        100.0 * run_time.as_secs_f64() / elapsed.as_secs_f64()
+    }
+}
+
+/// Formats the view of the tasks that ran between the `previous` and `current` samples.
+fn render(previous: &Sample, current: &Sample, delay: Duration, max_tasks: usize) -> String {
+    let elapsed = current.time.duration_since(previous.time);
+    let mut out = String::new();
+
+    let num_tasks = current.tasks.len();
+    let num_running = current.tasks.values().filter(|t| t.running_on_cpu.is_some() && !t.is_an_idle_task).count();
+    let _ = writeln!(
+        out,
+        "top - {} tasks, {} running, refreshing every {:.1} s",
+        num_tasks,
+        num_running,
+        delay.as_secs_f64()
+    );
+
+    // Each CPU's idle time is the time its idle task ran for.
+    let mut idle_cpus: Vec<(u8, f64)> = current
+        .tasks
+        .iter()
+        .filter(|(_, t)| t.is_an_idle_task)
+        .filter_map(|(id, t)| Some((t.pinned_core?, usage_percent(previous.tasks.get(id), t, elapsed))))
+        .collect();
+    idle_cpus.sort_by_key(|(cpu, _)| *cpu);
+    let idle_line: Vec<String> = idle_cpus
+        .iter()
+        .map(|(cpu, idle)| format!("CPU {}: {:5.1}% idle", cpu, idle.min(100.0)))
+        .collect();
+    let _ = writeln!(out, "{}\n", idle_line.join("   "));
+
+    let mut usages: Vec<(usize, &TaskSample, f64)> = current
+        .tasks
+        .iter()
+        .filter(|(_, t)| !t.is_an_idle_task)
+        .map(|(id, t)| (*id, t, usage_percent(previous.tasks.get(id), t, elapsed)))
+        .collect();
+    usages.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+
+    let _ = writeln!(
+        out,
+        "{:<5}  {:>6}  {:>12}  {:<10}  {:<4}  NAME",
+        "ID", "CPU%", "TIME", "RUNSTATE", "CPU"
+    );
+    for (id, task, usage) in usages.into_iter().take(max_tasks) {
+        let time = task::ticks_to_duration(task.cpu_time_ticks)
+            .map(|t| format!("{}.{:03}", t.as_secs(), t.subsec_millis()))
+            .unwrap_or_else(|| "-".to_string());
+        let cpu = task
+            .running_on_cpu
+            .map(|cpu| cpu.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(
+            out,
+            "{:<5}  {:>6.1}  {:>12}  {:<10}  {:<4}  {}",
+            id,
+            usage,
+            time,
+            format!("{:?}", task.runstate),
+            cpu,
+            task.name
+        );
+    }
+    out
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+const USAGE: &str = "Usage: top [OPTIONS]
+Shows the tasks that used the most CPU time since the last refresh, and the idle time of each CPU.
+    CPU%:      the share of a single CPU that the task used since the last refresh.
+    TIME:      the total CPU time the task has consumed, in seconds.
+    RUNSTATE:  runnability status of the task, e.g., whether it can be scheduled in.
+    CPU:       the CPU the task is currently running on.";
@@ -0,0 +1,77 @@
+#![no_std]
+#[macro_use] extern crate app_io;
+
+#[macro_use] extern crate alloc;
+extern crate task;
+extern crate getopts;
+extern crate path;
+extern crate fs_node;
+extern crate mount_table;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use getopts::Options;
+use path::Path;
+use fs_node::FileOrDir;
+
+
+pub fn main(args: Vec<String>) -> isize {
+    match umount(args) {
+        Ok(_) => 0,
+        Err(err) => {
+            println!("{}", err);
+            -1
+        }
+    }
+}
+
+fn umount(args: Vec<String>) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => {
+            print_usage(opts);
+            return Err(e.to_string());
+        }
+    };
+
+    if matches.opt_present("h") {
+        print_usage(opts);
+        return Ok(());
+    }
+
+    if matches.free.is_empty() {
+        return Err("umount: missing argument".into());
+    }
+
+    let Ok(working_dir) = task::with_current_task(|t|
+        t.get_env().lock().working_dir.clone()
+    ) else {
+        return Err("failed to get current task".into());
+    };
+
+    for path_string in &matches.free {
+        let path = Path::new(path_string.clone());
+        // Resolving the path yields the mounted filesystem's root directory,
+        // whose absolute path is the path of its mount point.
+        let mount_path = match path.get(&working_dir) {
+            Some(FileOrDir::Dir(dir)) => dir.lock().get_absolute_path(),
+            _ => return Err(format!("Couldn't find directory {path}")),
+        };
+        mount_table::umount(&mount_path)?;
+    }
+
+    Ok(())
+}
+
+fn print_usage(opts: Options) {
+    println!("{}", opts.usage(USAGE));
+}
+
+
+const USAGE: &str = "Usage: umount DIR...
+Unmount the filesystems mounted on each given directory.";
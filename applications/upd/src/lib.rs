@@ -13,19 +13,18 @@ extern crate itertools;
 extern crate getopts;
 extern crate task;
 extern crate ota_update_client;
-extern crate network_manager;
 extern crate memory;
 extern crate mod_mgmt;
 extern crate crate_swap;
-extern crate smoltcp;
 extern crate path;
 extern crate memfs;
 extern crate fs_node;
 extern crate vfs_node;
 extern crate spin;
+extern crate sleep;
 
 
-use core::str::FromStr;
+use core::time::Duration;
 use alloc::{
     string::{String, ToString},
     vec::Vec,
@@ -34,8 +33,6 @@ use alloc::{
 };
 use spin::Once;
 use getopts::{Matches, Options};
-use network_manager::{NetworkInterfaceRef, NETWORK_INTERFACES};
-use smoltcp::wire::IpEndpoint;
 use mod_mgmt::{
     CrateNamespace,
     NamespaceDir,
@@ -44,13 +41,27 @@ use mod_mgmt::{
 use crate_swap::{
     SwapRequest,
     SwapRequestList,
+    TransactionState,
 };
 use memfs::MemFile;
 use path::Path;
 use vfs_node::VFSDirectory;
 use fs_node::{FileOrDir, DirRef};
-use ota_update_client::DIFF_FILE_NAME;
+use ota_update_client::{
+    DIFF_FILE_NAME,
+    MANIFEST_FILE_NAME,
+    MANIFEST_SIGNATURE_FILE_NAME,
+    Manifest,
+    UpdateServer,
+};
+
+
+
+/// How long an applied update is checked for faults before it is committed, by default.
+const DEFAULT_HEALTH_CHECK_SECS: u64 = 10;
 
+/// How often the state of an applied update is checked while waiting for its health-check window to end.
+const STATE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 
 static VERBOSE: Once<bool> = Once::new();
@@ -63,7 +74,8 @@ pub fn main(args: Vec<String>) -> isize {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("v", "verbose", "enable verbose logging");
-    opts.optopt ("d", "destination", "specify the IP address (and optionally, the port) of the update server", "IP_ADDR[:PORT]");
+    opts.optopt ("d", "destination", "specify the host name or IP address (and optionally, the port) of the update server", "HOST[:PORT]");
+    opts.optopt ("w", "window", "specify how many seconds an applied update is checked for faults before it is committed (default: 10)", "SECS");
 
     let matches = match opts.parse(&args) {
         Ok(m) => m,
@@ -98,33 +110,49 @@ pub fn main(args: Vec<String>) -> isize {
 
 
 fn rmain(matches: Matches) -> Result<(), String> {
-    let mut remote_endpoint = if let Some(ip_str) = matches.opt_str("d") {
-        IpEndpoint::from_str(&ip_str)
-            .map_err(|_e| "couldn't parse destination IP address/port".to_string())?
+    let mut server = if let Some(destination) = matches.opt_str("d") {
+        // The port is optional, and IPv6 addresses without a port also contain colons.
+        let (host, port) = match destination.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => {
+                let port = port.parse::<u16>()
+                    .map_err(|_e| "couldn't parse destination port".to_string())?;
+                (host, Some(port))
+            }
+            _ => (destination.as_str(), None),
+        };
+        UpdateServer::new(host, port)
     } else {
-        ota_update_client::default_remote_endpoint()
+        UpdateServer::default()
     };
-    if remote_endpoint.port == 0 {
-        remote_endpoint.port = ota_update_client::default_remote_endpoint().port;
-    }
 
     if verbose!() { println!("MATCHES: {:?}", matches.free); }
 
     match &*matches.free[0] {
         "list" | "ls" => {
-            list(remote_endpoint, matches.free.get(1))
+            list(&mut server, matches.free.get(1))
         }
         "list-diff" | "ls-diff" => {
             let update_build = matches.free.get(1).ok_or_else(|| String::from("missing UPDATE_BUILD argument"))?;
-            diff(remote_endpoint, update_build)
+            diff(&mut server, update_build)
         }
         "download" | "dl" => {
             let update_build = matches.free.get(1).ok_or_else(|| String::from("missing UPDATE_BUILD argument"))?;
-            download(remote_endpoint, update_build, matches.free.get(2..))
+            download(&mut server, update_build, matches.free.get(2..))
         }
         "apply" | "ap" => {
             let base_dir_path = matches.free.get(1).ok_or_else(|| String::from("missing BASE_DIR path argument"))?;
-            apply(&Path::new(base_dir_path.clone()))
+            let window = match matches.opt_str("w") {
+                Some(secs) => secs.parse::<u64>().map_err(|_e| "couldn't parse window seconds".to_string())?,
+                None => DEFAULT_HEALTH_CHECK_SECS,
+            };
+            apply(&Path::new(base_dir_path.clone()), Duration::from_secs(window))
+        }
+        "rollback" | "rb" => {
+            rollback()
+        }
+        "history" => {
+            history();
+            Ok(())
         }
         other => {
             Err(format!("unrecognized command {other:?}"))
@@ -136,15 +164,13 @@ fn rmain(matches: Matches) -> Result<(), String> {
 
 /// Lists the set of crates in the given update_build,
 /// or if no update build is specified, lists all available update builds by default.
-fn list(remote_endpoint: IpEndpoint, update_build: Option<&String>) -> Result<(), String> {
-    let iface = get_default_iface()?;
-
+fn list(server: &mut UpdateServer, update_build: Option<&String>) -> Result<(), String> {
     if let Some(ub) = update_build {
-        let listing = ota_update_client::download_listing(&iface, remote_endpoint, ub)
+        let listing = ota_update_client::download_listing(server, ub)
             .map_err(|e| e.to_string())?;
         println!("{}", listing.join("\n"));
     } else {
-        let update_builds = ota_update_client::download_available_update_builds(&iface, remote_endpoint)
+        let update_builds = ota_update_client::download_available_update_builds(server)
             .map_err(|e| e.to_string())?;
         println!("{}", update_builds.join("\n"));
     }
@@ -154,11 +180,12 @@ fn list(remote_endpoint: IpEndpoint, update_build: Option<&String>) -> Result<()
 
 
 /// Lists the contents of the diff file for the given update build.
-fn diff(remote_endpoint: IpEndpoint, update_build: &str) -> Result<(), String> {
-    let iface = get_default_iface()?;
-
-    let file_str = ota_update_client::download_diff(&iface, remote_endpoint, update_build)
+fn diff(server: &mut UpdateServer, update_build: &str) -> Result<(), String> {
+    let manifest = ota_update_client::download_manifest(server, update_build)
+        .map_err(|e| e.to_string())?;
+    let diff_content = ota_update_client::download_diff(server, update_build, &manifest)
         .map_err(|e| e.to_string())?;
+    let file_str = ota_update_client::as_lines(&diff_content).map_err(|e| e.to_string())?;
     println!("{}", file_str.join("\n"));
 
     Ok(())
@@ -166,56 +193,90 @@ fn diff(remote_endpoint: IpEndpoint, update_build: &str) -> Result<(), String> {
 
 
 /// Downloads all of the new or changed crates from the `diff` file of the 
-fn download(remote_endpoint: IpEndpoint, update_build: &str, crate_list: Option<&[String]>) -> Result<(), String> {
-    let iface = get_default_iface()?;
+/// given update build, or only the crates in the given `crate_list`, 
+/// along with the update build's signed manifest that they are verified against.
+fn download(server: &mut UpdateServer, update_build: &str, crate_list: Option<&[String]>) -> Result<(), String> {
     println!("Downloading crates...");
     let crate_list = if crate_list == Some(&[]) { None } else { crate_list };
 
-    let mut diff_file_lines: Option<Vec<String>> = None;
+    // save each new crate to a file in a new directory as it's downloaded
+    let Ok(curr_dir) = task::with_current_task(|t| t.get_env().lock().working_dir.clone()) else {
+        return Err("failed to get current task's working directory".to_string());
+    };
+    let new_namespace_dir = NamespaceDir::new(make_unique_directory(update_build, &curr_dir)?);
+    // The name of each crate file is something like "k#keyboard-36be916209949cef.o",
+    // from which the crate type prefix ("k#") is removed.
+    let create_file = |name: &str| new_namespace_dir.write_crate_object_file(name, &[]);
+
+    // The manifest and its signature are saved alongside the crates, 
+    // such that they can be verified again before the update is applied.
+    let manifest = ota_update_client::download_manifest(server, update_build)
+        .map_err(|e| format!("failed to download a valid manifest for {update_build}, error: {e}"))?;
+    write_file(MANIFEST_FILE_NAME, manifest.content(), &new_namespace_dir)?;
+    write_file(MANIFEST_SIGNATURE_FILE_NAME, manifest.signature(), &new_namespace_dir)?;
+
+    let mut diff_file_content: Option<Vec<u8>> = None;
 
     let crates = if let Some(crate_list) = crate_list {
         let crate_set = crate_list.iter().cloned().collect::<BTreeSet<String>>();
-        ota_update_client::download_crates(&iface, remote_endpoint, update_build, crate_set).map_err(|e| e.to_string())?
+        ota_update_client::download_crates(server, update_build, &manifest, crate_set, create_file).map_err(|e| e.to_string())?
     } else {
-        let diff_lines = ota_update_client::download_diff(&iface, remote_endpoint, update_build)
+        let diff_content = ota_update_client::download_diff(server, update_build, &manifest)
             .map_err(|e| format!("failed to download diff file for {update_build}, error: {e}"))?;
-        let diff = ota_update_client::parse_diff_lines(&diff_lines).map_err(|e| e.to_string())?;
+        let diff = ota_update_client::as_lines(&diff_content)
+            .and_then(|diff_lines| ota_update_client::parse_diff_lines(&diff_lines))
+            .map_err(|e| e.to_string())?;
 
         // download all of the new crates
         let new_crates_to_download: BTreeSet<String> = diff.pairs.iter().map(|(_old, new)| new.clone()).collect();
-        let crates = ota_update_client::download_crates(&iface, remote_endpoint, update_build, new_crates_to_download).map_err(|e| e.to_string())?;
-        diff_file_lines = Some(diff_lines);
+        let crates = ota_update_client::download_crates(server, update_build, &manifest, new_crates_to_download, create_file).map_err(|e| e.to_string())?;
+        diff_file_content = Some(diff_content);
         crates
     };
     
-    // save each new crate to a file 
-    let Ok(curr_dir) = task::with_current_task(|t| t.get_env().lock().working_dir.clone()) else {
-        return Err("failed to get current task's working directory".to_string());
-    };
-    let new_namespace_dir = NamespaceDir::new(make_unique_directory(update_build, &curr_dir)?);
     for df in crates.into_iter() {
-        let content = df.content.as_result_err_str()?;
-        let size = content.len();
-        // The name of the crate file that we downloaded is something like: "/keyboard_log/k#keyboard-36be916209949cef.o".
-        // We need to get just the basename of the file, then remove the crate type prefix ("k#").
-        let df_path = Path::new(df.name);
-        let cfile = new_namespace_dir.write_crate_object_file(df_path.basename(), content)?;
-        println!("Downloaded crate: {:?}, size {}", cfile.lock().get_absolute_path(), size);
+        let file = df.file.lock();
+        println!("Downloaded crate: {:?}, size {}", file.get_absolute_path(), file.len());
     }
 
     // if downloaded, save the diff file into the base directory
-    if let Some(diffs) = diff_file_lines {
-        let cfile = MemFile::create(String::from(DIFF_FILE_NAME), &new_namespace_dir)?;
-        cfile.lock().write_at(diffs.join("\n").as_bytes(), 0)?;
+    if let Some(diff_content) = diff_file_content {
+        write_file(DIFF_FILE_NAME, &diff_content, &new_namespace_dir)?;
     }
 
     Ok(())
 }
 
 
+/// Creates a new file with the given `name` and `content` in the given `dir`.
+fn write_file(name: &str, content: &[u8], dir: &DirRef) -> Result<(), String> {
+    let file = MemFile::create(String::from(name), dir)?;
+    file.lock().write_at(content, 0)?;
+    Ok(())
+}
+
+
+/// Reads the entire contents of the file with the given `name` in the given `dir`.
+fn read_file(name: &str, dir: &DirRef) -> Result<Vec<u8>, String> {
+    let file = match dir.lock().get(name) {
+        Some(FileOrDir::File(f)) => f,
+        _ => return Err(format!("cannot find file {name:?} in the update base directory")),
+    };
+    let mut content: Vec<u8> = alloc::vec::from_elem(0, file.lock().len());
+    let _bytes_read = file.lock().read_at(&mut content, 0)?;
+    Ok(content)
+}
+
+
 /// Applies an already-downloaded update according the "diff.txt" file
 /// that must be in the given base directory.
-fn apply(base_dir_path: &Path) -> Result<(), String> {
+/// 
+/// The update build's manifest in the base directory must have been signed by a trusted update server,
+/// and the diff file and all new crate files must match it; otherwise, no crates are swapped.
+/// 
+/// The update is applied as a swap transaction, which is rolled back automatically
+/// if any faults occur in the new crates within the given `health_check_window`.
+fn apply(base_dir_path: &Path, health_check_window: Duration) -> Result<(), String> {
     if cfg!(not(loadable)) {
         return Err("Evolutionary updates can only be applied when Theseus is built in loadable mode.".to_string());
     }
@@ -228,12 +289,12 @@ fn apply(base_dir_path: &Path) -> Result<(), String> {
         Some(FileOrDir::Dir(d)) => NamespaceDir::new(d),
         _ => return Err(format!("cannot find an update base directory at path {base_dir_path}")),
     };
-    let diff_file = match new_namespace_dir.lock().get(DIFF_FILE_NAME) { 
-        Some(FileOrDir::File(f)) => f,
-        _ => return Err(format!("cannot find diff file expected at {base_dir_path}/{DIFF_FILE_NAME}")),
-    };
-    let mut diff_content: Vec<u8> = alloc::vec::from_elem(0, diff_file.lock().len()); 
-    let _bytes_read = diff_file.lock().read_at(&mut diff_content, 0)?;
+    let manifest = Manifest::verify(
+        read_file(MANIFEST_FILE_NAME, &new_namespace_dir)?,
+        read_file(MANIFEST_SIGNATURE_FILE_NAME, &new_namespace_dir)?,
+    ).map_err(|e| format!("cannot verify the update in {base_dir_path}: {e}"))?;
+    let diff_content = read_file(DIFF_FILE_NAME, &new_namespace_dir)?;
+    manifest.verify_bytes(DIFF_FILE_NAME, &diff_content).map_err(|e| e.to_string())?;
     let diffs = ota_update_client::as_lines(&diff_content).map_err(|e| e.to_string())
         .and_then(|diff_lines| ota_update_client::parse_diff_lines(&diff_lines).map_err(|e| e.to_string()))?;
 
@@ -268,6 +329,9 @@ fn apply(base_dir_path: &Path) -> Result<(), String> {
         let new_crate_file = new_namespace_dir.get_crate_object_file(&new_crate_module_file_name).ok_or_else(|| 
             format!("cannot find new crate file {new_crate_module_file_name:?} in new namespace dir {base_dir_path}")
         )?;
+        // The crate file must not have been modified since it was downloaded.
+        manifest.verify_file(&new_crate_module_file_name, &new_crate_file)
+            .map_err(|e| format!("crate file {new_crate_module_file_name:?} failed verification: {e}"))?;
 
         let swap_req = SwapRequest::new(
             old_crate_name.as_deref(),
@@ -280,20 +344,62 @@ fn apply(base_dir_path: &Path) -> Result<(), String> {
     }
 
     // now do the actual live crate swap at runtime
-    crate_swap::swap_crates(
+    let transaction = crate_swap::swap_crates_transaction(
         &curr_namespace,
         swap_requests, 
         Some(new_namespace_dir), 
         diffs.state_transfer_functions, 
         kernel_mmi_ref,
         false, // verbose logging
-        false, // enable_crate_cache
+        health_check_window,
     ).map_err(|e| format!("crate swapping failed, error: {e}"))?;
+    println!("Applied update as transaction {}, checking it for faults for {} seconds...", transaction, health_check_window.as_secs());
+
+    // The transaction is checked for faults by its own monitor task, which commits or rolls it back
+    // even if we stop waiting here; we just wait for that in order to report the outcome.
+    loop {
+        match crate_swap::transaction_state(transaction) {
+            Some(TransactionState::Pending) => {}
+            Some(TransactionState::Committed) => {
+                println!("Committed transaction {}. Use `upd rollback` to revert it.", transaction);
+                return Ok(());
+            }
+            Some(TransactionState::RolledBack) => {
+                return Err(format!("faults occurred in the updated crates, transaction {transaction} was rolled back"));
+            }
+            Some(TransactionState::RollbackFailed) => {
+                return Err(format!("faults occurred in the updated crates, and rolling back transaction {transaction} failed"));
+            }
+            None => return Err(format!("transaction {transaction} no longer exists")),
+        }
+        sleep::sleep(STATE_CHECK_INTERVAL).map_err(|_e| "failed to sleep".to_string())?;
+    }
+}
+
 
+/// Rolls back the most recently applied update, swapping the crates it replaced back in.
+fn rollback() -> Result<(), String> {
+    let kernel_mmi_ref = memory::get_kernel_mmi_ref().ok_or_else(|| "couldn't get kernel MMI".to_string())?;
+    let transaction = crate_swap::latest_transaction().ok_or_else(|| "there is no applied update to roll back".to_string())?;
+    crate_swap::rollback_transaction(transaction, kernel_mmi_ref, verbose!())
+        .map_err(|e| format!("failed to roll back transaction {transaction}, error: {e}"))?;
+    println!("Rolled back transaction {}.", transaction);
     Ok(())
 }
 
 
+/// Prints the history of applied updates, i.e., crate swap transactions.
+fn history() {
+    for t in crate_swap::transactions() {
+        println!("Transaction {} ({:?}):", t.id, t.state);
+        println!("    new crates: {}", t.new_crates.join(", "));
+        if !t.old_crates.is_empty() {
+            println!("    retained old crates: {}", t.old_crates.join(", "));
+        }
+    }
+}
+
+
 fn get_my_current_namespace() -> Arc<CrateNamespace> {
     task::with_current_task(|t| t.get_namespace().clone())
         .unwrap_or_else(|_|
@@ -304,16 +410,6 @@ fn get_my_current_namespace() -> Arc<CrateNamespace> {
 }
 
 
-/// Returns the first network interface available in the system.
-fn get_default_iface() -> Result<NetworkInterfaceRef, String> {
-    NETWORK_INTERFACES.lock()
-        .iter()
-        .next()
-        .cloned()
-        .ok_or_else(|| "no network interfaces available".to_string())
-}
-
-
 /// Creates a new directory with a unique name in the given `parent_dir`. 
 /// For example, given a base_name of "my_dir", 
 /// it will create a directory "my_dir.2" if "my_dir" and "my_dir.1" already exist.
@@ -357,4 +453,12 @@ Runs the given update-related COMMAND. Choices include:
         
     apply BASE_DIR
         Applies the evolutionary update specified by the diff file 
-        in the given BASE_DIR, which contains the new crate object files to be used.";
+        in the given BASE_DIR, which contains the new crate object files to be used.
+        The update is rolled back automatically if faults occur in the new crates
+        within the health-check window (see the --window option).
+
+    rollback
+        Rolls back the most recently applied update that hasn't yet been rolled back.
+
+    history
+        Lists all applied updates and their states.";
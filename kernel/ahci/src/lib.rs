@@ -0,0 +1,627 @@
+//! Driver for AHCI (Advanced Host Controller Interface) controllers and the SATA drives attached to them.
+//!
+//! The primary structs of interest are [`AhciController`], which implements `StorageController`,
+//! and [`AhciDrive`], which implements `StorageDevice`.
+//!
+//! All data transfers use DMA: each port has a command list in memory with one entry per command slot,
+//! and each slot has its own physically-contiguous buffer that data is transferred to or from.
+//! Large requests are split across multiple command slots that are issued together;
+//! if both the controller and the drive support Native Command Queuing (NCQ),
+//! these are issued as queued commands that the drive may complete in any order.
+//!
+//! Completion is detected by polling the port registers, so interrupts are left disabled.
+
+#![no_std]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+
+mod regs;
+use regs::*;
+
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, Ordering},
+};
+use alloc::{
+    boxed::Box,
+    format,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex;
+use memory::{
+    MappedPages, PhysicalAddress, PteFlags,
+    allocate_pages_by_bytes, allocate_frames_by_bytes_at, create_contiguous_mapping, get_kernel_mmi_ref,
+};
+use pci::PciDevice;
+use ata::AtaIdentifyData;
+use storage_device::{StorageController, StorageDevice, StorageDeviceRef};
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+
+
+/// The PCI class code of mass storage controllers.
+pub const AHCI_PCI_CLASS: u8 = 0x01;
+/// The PCI subclass code of SATA controllers.
+pub const AHCI_PCI_SUBCLASS: u8 = 0x06;
+/// The PCI programming interface of SATA controllers that use AHCI.
+pub const AHCI_PCI_PROG_IF: u8 = 0x01;
+
+/// The AHCI Base Address (ABAR) is always in BAR5.
+const AHCI_BAR_INDEX: usize = 5;
+
+const SECTOR_SIZE_IN_BYTES: usize = 512;
+
+/// The size of the data buffer for each command slot, which is the most data transferred by a single command.
+const SLOT_BUFFER_SIZE_IN_BYTES: usize = 32 * SECTOR_SIZE_IN_BYTES;
+
+/// Layout of the DMA memory owned by each port, which contains
+/// the command list, the received FIS area, and the command tables.
+const COMMAND_LIST_OFFSET: usize = 0;
+const RECEIVED_FIS_OFFSET: usize = COMMAND_LIST_OFFSET + MAX_COMMAND_SLOTS * size_of::<CommandHeader>();
+const COMMAND_TABLES_OFFSET: usize = 4096;
+const PORT_DMA_MEMORY_SIZE: usize = COMMAND_TABLES_OFFSET + MAX_COMMAND_SLOTS * size_of::<CommandTable>();
+
+const _: () = assert!(RECEIVED_FIS_OFFSET + RECEIVED_FIS_SIZE <= COMMAND_TABLES_OFFSET);
+
+/// How many times to poll a port register before giving up on a port state change.
+const PORT_TIMEOUT_POLLS: usize = 1_000_000;
+/// How many times to poll a port register before giving up on a command's completion.
+const COMMAND_TIMEOUT_POLLS: usize = 100_000_000;
+
+/// The mapping flags used for the AHCI registers and DMA memory.
+const AHCI_MAPPING_FLAGS: PteFlags = PteFlags::from_bits_truncate(
+    PteFlags::new().bits()
+    | PteFlags::VALID.bits()
+    | PteFlags::WRITABLE.bits()
+    | PteFlags::DEVICE_MEMORY.bits()
+);
+
+/// The ATA commands issued to SATA drives.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug)]
+enum AtaCommand {
+    ReadDmaExt       = 0x25,
+    WriteDmaExt      = 0x35,
+    ReadFpdmaQueued  = 0x60,
+    WriteFpdmaQueued = 0x61,
+    FlushCacheExt    = 0xEA,
+    IdentifyDevice   = 0xEC,
+}
+
+/// The value of the device register for commands that use LBA addressing.
+const DEVICE_LBA_MODE: u8 = 1 << 6;
+
+
+/// The memory-mapped registers of an AHCI controller,
+/// which are shared by all of the drives attached to that controller.
+struct Hba {
+    mapped_registers: MappedPages,
+}
+
+impl Hba {
+    /// Returns the generic host control registers.
+    fn registers(&mut self) -> Result<&mut HbaRegisters, &'static str> {
+        self.mapped_registers.as_type_mut(0)
+    }
+
+    /// Returns the registers of the given port.
+    fn port(&mut self, port_num: usize) -> Result<&mut PortRegisters, &'static str> {
+        self.mapped_registers.as_type_mut(port_registers_offset(port_num))
+    }
+}
+
+/// The capabilities of an AHCI controller that are relevant to initializing its drives.
+#[derive(Copy, Clone, Debug)]
+struct HbaCapabilities {
+    /// The number of command slots that each port supports.
+    num_command_slots: usize,
+    /// Whether the controller supports Native Command Queuing.
+    supports_ncq: bool,
+    /// Whether the controller can access DMA memory above 4 GiB.
+    supports_64_bit: bool,
+}
+
+
+/// A single SATA drive attached to one port of an AHCI controller.
+pub struct AhciDrive {
+    /// The registers of the controller that this drive is attached to.
+    hba: Arc<Mutex<Hba>>,
+    /// The number of the controller port that this drive is attached to.
+    port_num: usize,
+    /// The command list, received FIS area, and command tables for this drive's port.
+    port_memory: MappedPages,
+    port_memory_phys: PhysicalAddress,
+    /// The data buffers, one per usable command slot, each of size `SLOT_BUFFER_SIZE_IN_BYTES`.
+    buffers: MappedPages,
+    buffers_phys: PhysicalAddress,
+    /// The number of command slots that can be in flight at once.
+    num_slots: usize,
+    /// Whether data transfers are issued as NCQ commands.
+    ncq: bool,
+    /// Data that represents the characteristics of the drive.
+    identify_data: AtaIdentifyData,
+}
+
+impl AhciDrive {
+    /// Looks for a SATA drive on the given port of the controller, and if found,
+    /// initializes that port and drive and returns an object representing it.
+    fn new(hba: Arc<Mutex<Hba>>, port_num: usize, caps: HbaCapabilities) -> Result<AhciDrive, &'static str> {
+        {
+            let mut hba_locked = hba.lock();
+            let port = hba_locked.port(port_num)?;
+            let ssts = port.ssts.read();
+            if ssts & PORT_SSTS_DET_MASK != PORT_SSTS_DET_PRESENT {
+                return Err("no drive attached");
+            }
+            if (ssts >> PORT_SSTS_IPM_SHIFT) & 0xF != PORT_SSTS_IPM_ACTIVE {
+                return Err("drive was not in the active power state");
+            }
+            match port.sig.read() {
+                SATA_SIG_ATA   => { }, // we support this device type
+                SATA_SIG_ATAPI => return Err("drive was an unsupported SATAPI device"),
+                SATA_SIG_SEMB  => return Err("device was an unsupported enclosure management bridge"),
+                SATA_SIG_PM    => return Err("device was an unsupported port multiplier"),
+                _              => return Err("drive was an unknown device type"),
+            }
+        }
+
+        let (port_memory, port_memory_phys) = create_contiguous_mapping(PORT_DMA_MEMORY_SIZE, AHCI_MAPPING_FLAGS)?;
+        let (buffers, buffers_phys) = create_contiguous_mapping(caps.num_command_slots * SLOT_BUFFER_SIZE_IN_BYTES, AHCI_MAPPING_FLAGS)?;
+        if !caps.supports_64_bit
+            && (port_memory_phys.value() + PORT_DMA_MEMORY_SIZE > u32::MAX as usize
+                || buffers_phys.value() + buffers.size_in_bytes() > u32::MAX as usize)
+        {
+            return Err("AHCI controller only supports 32-bit DMA, but DMA memory was above 4 GiB");
+        }
+
+        let mut drive = AhciDrive {
+            hba,
+            port_num,
+            port_memory,
+            port_memory_phys,
+            buffers,
+            buffers_phys,
+            num_slots: caps.num_command_slots,
+            ncq: false,
+            identify_data: AtaIdentifyData::default(),
+        };
+
+        // Zero the command list and received FIS area, then point the port at them.
+        drive.port_memory.as_slice_mut::<u8>(0, PORT_DMA_MEMORY_SIZE)?.fill(0);
+        {
+            let command_list = drive.port_memory_phys + COMMAND_LIST_OFFSET;
+            let received_fis = drive.port_memory_phys + RECEIVED_FIS_OFFSET;
+            let mut hba_locked = drive.hba.lock();
+            let port = hba_locked.port(port_num)?;
+            stop_port(port)?;
+            port.clb.write(command_list.value() as u32);
+            port.clbu.write((command_list.value() >> 32) as u32);
+            port.fb.write(received_fis.value() as u32);
+            port.fbu.write((received_fis.value() >> 32) as u32);
+            // Clear any lingering errors and interrupt statuses, which are write-1-to-clear.
+            port.serr.write(u32::MAX);
+            port.is.write(u32::MAX);
+            port.ie.write(0);
+            start_port(port)?;
+        }
+
+        drive.identify_data = drive.identify_drive()?;
+        let identify_data = drive.identify_data;
+
+        // Check to see that the drive supports 48-bit LBA, which is required by the DMA EXT and NCQ commands.
+        let command_set_support = identify_data.command_set_support;
+        if command_set_support[1] & (1 << 10) == 0 {
+            return Err("drive does not support 48-bit LBA addressing, which is required by this driver");
+        }
+
+        // Native Command Queuing is supported by the drive if bit 8 of IDENTIFY word 76 is set.
+        let drive_supports_ncq = identify_data.serial_ata_capabilities & (1 << 8) != 0;
+        if caps.supports_ncq && drive_supports_ncq {
+            let queue_depth = (identify_data.queue_depth & 0x1F) as usize + 1;
+            drive.ncq = true;
+            drive.num_slots = core::cmp::min(caps.num_command_slots, queue_depth);
+        }
+
+        Ok(drive)
+    }
+
+    /// Issues an identify command to this drive and returns the result.
+    fn identify_drive(&mut self) -> Result<AtaIdentifyData, &'static str> {
+        let fis = register_fis(AtaCommand::IdentifyDevice, 0, 0, 0, 0);
+        self.prepare_slot(0, &fis, SECTOR_SIZE_IN_BYTES, false)?;
+        self.issue_slots(1, false)?;
+        self.wait_for_slots(1)?;
+
+        let mut buffer = [0u8; SECTOR_SIZE_IN_BYTES];
+        buffer.copy_from_slice(&self.slot_buffer(0)?[..SECTOR_SIZE_IN_BYTES]);
+        Ok(AtaIdentifyData::new(buffer))
+    }
+
+    /// Reads data from this drive starting at the given `offset_in_sectors` into the provided `buffer`.
+    /// The length of the given `buffer` determines the number of bytes to be read.
+    ///
+    /// As content is read from the drive at sector granularity,
+    /// the buffer length must be a multiple of the sector size (512 bytes),
+    /// and the offset is specified in number of sectors (not number of bytes) from the beginning of the drive.
+    ///
+    /// Returns the number of sectors (*not bytes*) that were successfully read from the drive.
+    pub fn read_dma(&mut self, buffer: &mut [u8], offset_in_sectors: usize) -> Result<usize, &'static str> {
+        let sector_count = self.check_request(buffer.len(), offset_in_sectors)?;
+
+        let mut lba = offset_in_sectors;
+        for batch in buffer.chunks_mut(self.num_slots * SLOT_BUFFER_SIZE_IN_BYTES) {
+            let mut slots = 0u32;
+            for (slot, chunk) in batch.chunks(SLOT_BUFFER_SIZE_IN_BYTES).enumerate() {
+                let chunk_sectors = chunk.len() / SECTOR_SIZE_IN_BYTES;
+                self.prepare_transfer(slot, lba, chunk_sectors, false)?;
+                slots |= 1 << slot;
+                lba += chunk_sectors;
+            }
+            self.issue_slots(slots, self.ncq)?;
+            self.wait_for_slots(slots)?;
+
+            for (slot, chunk) in batch.chunks_mut(SLOT_BUFFER_SIZE_IN_BYTES).enumerate() {
+                let len = chunk.len();
+                chunk.copy_from_slice(&self.slot_buffer(slot)?[..len]);
+            }
+        }
+        Ok(sector_count)
+    }
+
+    /// Writes data from the provided `buffer` to this drive, starting at the given `offset_in_sectors` into the drive.
+    /// The length of the given `buffer` determines the number of bytes to be written.
+    ///
+    /// As content is written to the drive at sector granularity,
+    /// the buffer length must be a multiple of the sector size (512 bytes),
+    /// and the offset is specified in number of sectors (not number of bytes) from the beginning of the drive.
+    ///
+    /// Returns the number of sectors (*not bytes*) that were successfully written to the drive.
+    pub fn write_dma(&mut self, buffer: &[u8], offset_in_sectors: usize) -> Result<usize, &'static str> {
+        let sector_count = self.check_request(buffer.len(), offset_in_sectors)?;
+
+        let mut lba = offset_in_sectors;
+        for batch in buffer.chunks(self.num_slots * SLOT_BUFFER_SIZE_IN_BYTES) {
+            let mut slots = 0u32;
+            for (slot, chunk) in batch.chunks(SLOT_BUFFER_SIZE_IN_BYTES).enumerate() {
+                let chunk_sectors = chunk.len() / SECTOR_SIZE_IN_BYTES;
+                self.slot_buffer(slot)?[..chunk.len()].copy_from_slice(chunk);
+                self.prepare_transfer(slot, lba, chunk_sectors, true)?;
+                slots |= 1 << slot;
+                lba += chunk_sectors;
+            }
+            self.issue_slots(slots, self.ncq)?;
+            self.wait_for_slots(slots)?;
+        }
+        Ok(sector_count)
+    }
+
+    /// Instructs the drive to write its volatile write cache to persistent media.
+    pub fn flush_cache(&mut self) -> Result<(), &'static str> {
+        let fis = register_fis(AtaCommand::FlushCacheExt, 0, 0, 0, DEVICE_LBA_MODE);
+        self.prepare_slot(0, &fis, 0, false)?;
+        self.issue_slots(1, false)?;
+        self.wait_for_slots(1)
+    }
+
+    /// Returns the number of the controller port that this drive is attached to.
+    pub fn port_num(&self) -> usize {
+        self.port_num
+    }
+
+    /// Returns `true` if data transfers are issued as Native Command Queuing commands.
+    pub fn uses_ncq(&self) -> bool {
+        self.ncq
+    }
+
+    /// Returns the number of commands that can be in flight to this drive at once.
+    pub fn queue_depth(&self) -> usize {
+        self.num_slots
+    }
+
+    /// Returns the data obtained from identifying this drive.
+    pub fn identify_data(&self) -> &AtaIdentifyData {
+        &self.identify_data
+    }
+
+    /// Checks that a request of `length_in_bytes` at `offset_in_sectors` is valid for this drive,
+    /// and returns the number of sectors that it covers.
+    fn check_request(&self, length_in_bytes: usize, offset_in_sectors: usize) -> Result<usize, &'static str> {
+        if length_in_bytes % SECTOR_SIZE_IN_BYTES != 0 {
+            return Err("The buffer length must be a multiple of sector size (512) bytes. SATA drives can only transfer at sector granularity.");
+        }
+        let sector_count = length_in_bytes / SECTOR_SIZE_IN_BYTES;
+        if offset_in_sectors + sector_count > self.size_in_blocks() {
+            return Err("offset_in_sectors was out of bounds");
+        }
+        Ok(sector_count)
+    }
+
+    /// Fills in the given command slot with a read or write of `sector_count` sectors starting at `lba`,
+    /// which transfers data to or from that slot's buffer.
+    fn prepare_transfer(&mut self, slot: usize, lba: usize, sector_count: usize, write: bool) -> Result<(), &'static str> {
+        let fis = match (self.ncq, write) {
+            // For NCQ commands, the sector count is given in the features register
+            // and the command's tag (its slot) is given in bits 7:3 of the count register.
+            (true,  false) => register_fis(AtaCommand::ReadFpdmaQueued,  lba as u64, (slot as u16) << 3, sector_count as u16, DEVICE_LBA_MODE),
+            (true,  true)  => register_fis(AtaCommand::WriteFpdmaQueued, lba as u64, (slot as u16) << 3, sector_count as u16, DEVICE_LBA_MODE),
+            (false, false) => register_fis(AtaCommand::ReadDmaExt,  lba as u64, sector_count as u16, 0, DEVICE_LBA_MODE),
+            (false, true)  => register_fis(AtaCommand::WriteDmaExt, lba as u64, sector_count as u16, 0, DEVICE_LBA_MODE),
+        };
+        self.prepare_slot(slot, &fis, sector_count * SECTOR_SIZE_IN_BYTES, write)
+    }
+
+    /// Fills in the command header and command table of the given command slot
+    /// such that it sends the given `fis` and transfers `byte_count` bytes to or from that slot's buffer.
+    fn prepare_slot(&mut self, slot: usize, fis: &[u8; FIS_REG_H2D_LENGTH], byte_count: usize, write: bool) -> Result<(), &'static str> {
+        let table_offset = COMMAND_TABLES_OFFSET + slot * size_of::<CommandTable>();
+        let table_phys = self.port_memory_phys + table_offset;
+        let buffer_phys = self.buffers_phys + slot * SLOT_BUFFER_SIZE_IN_BYTES;
+
+        let table: &mut CommandTable = self.port_memory.as_type_mut(table_offset)?;
+        table.cfis[..FIS_REG_H2D_LENGTH].copy_from_slice(fis);
+        table.prdt.dba.write(buffer_phys.value() as u32);
+        table.prdt.dbau.write((buffer_phys.value() >> 32) as u32);
+        table.prdt.dbc.write(byte_count.saturating_sub(1) as u32);
+
+        let header: &mut CommandHeader = self.port_memory.as_type_mut(COMMAND_LIST_OFFSET + slot * size_of::<CommandHeader>())?;
+        let fis_length_in_dwords = (FIS_REG_H2D_LENGTH / 4) as u16;
+        header.flags.write(if write { fis_length_in_dwords | COMMAND_HEADER_WRITE } else { fis_length_in_dwords });
+        header.prdtl.write(if byte_count == 0 { 0 } else { 1 });
+        header.prdbc.write(0);
+        header.ctba.write(table_phys.value() as u32);
+        header.ctbau.write((table_phys.value() >> 32) as u32);
+        Ok(())
+    }
+
+    /// Issues the previously-prepared commands in the given bitmask of command `slots`.
+    fn issue_slots(&mut self, slots: u32, queued: bool) -> Result<(), &'static str> {
+        // Ensure that the command list and tables are written before the controller reads them.
+        fence(Ordering::SeqCst);
+        let mut hba = self.hba.lock();
+        let port = hba.port(self.port_num)?;
+        if queued {
+            port.sact.write(slots);
+        }
+        port.ci.write(slots);
+        Ok(())
+    }
+
+    /// Waits for the commands in the given bitmask of command `slots` to complete.
+    ///
+    /// If any command fails or times out, the port is restarted and an error is returned.
+    fn wait_for_slots(&mut self, slots: u32) -> Result<(), &'static str> {
+        for _ in 0..COMMAND_TIMEOUT_POLLS {
+            let (status, task_file, pending) = {
+                let mut hba = self.hba.lock();
+                let port = hba.port(self.port_num)?;
+                let status = port.is.read();
+                // Acknowledge the interrupt statuses that we observed.
+                port.is.write(status);
+                (status, port.tfd.read(), (port.ci.read() | port.sact.read()) & slots)
+            };
+
+            if status & PORT_IS_ERROR_MASK != 0 || task_file & PORT_TFD_ERR != 0 {
+                error!("AHCI port {}: command failed, interrupt status: {:#X}, task file: {:#X}", self.port_num, status, task_file);
+                self.recover()?;
+                return Err("AHCI command failed");
+            }
+            if pending == 0 {
+                fence(Ordering::SeqCst);
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+
+        error!("AHCI port {}: commands in slots {:#X} timed out", self.port_num, slots);
+        self.recover()?;
+        Err("AHCI command timed out")
+    }
+
+    /// Restarts this drive's port after a failed command, which aborts all outstanding commands.
+    fn recover(&mut self) -> Result<(), &'static str> {
+        let mut hba = self.hba.lock();
+        let port = hba.port(self.port_num)?;
+        stop_port(port)?;
+        port.serr.write(u32::MAX);
+        port.is.write(u32::MAX);
+        start_port(port)
+    }
+
+    /// Returns the data buffer of the given command slot.
+    fn slot_buffer(&mut self, slot: usize) -> Result<&mut [u8], &'static str> {
+        self.buffers.as_slice_mut(slot * SLOT_BUFFER_SIZE_IN_BYTES, SLOT_BUFFER_SIZE_IN_BYTES)
+    }
+}
+
+impl Drop for AhciDrive {
+    /// Stops this drive's port before its DMA memory is freed, such that the controller stops accessing it.
+    ///
+    /// This also covers the error paths of [`AhciDrive::new()`] after the port was started.
+    fn drop(&mut self) {
+        let stopped = self.hba.lock().port(self.port_num).and_then(stop_port);
+        if let Err(e) = stopped {
+            // The controller may still access the DMA memory, so it must never be reused.
+            error!("AHCI port {}: {}, leaking its DMA memory", self.port_num, e);
+            core::mem::forget(core::mem::replace(&mut self.port_memory, MappedPages::empty()));
+            core::mem::forget(core::mem::replace(&mut self.buffers, MappedPages::empty()));
+        }
+    }
+}
+
+impl StorageDevice for AhciDrive {
+    fn size_in_blocks(&self) -> usize {
+        self.identify_data.max_48_bit_lba as usize
+    }
+}
+impl BlockIo for AhciDrive {
+    fn block_size(&self) -> usize { SECTOR_SIZE_IN_BYTES }
+}
+impl KnownLength for AhciDrive {
+    fn len(&self) -> usize { self.block_size() * self.size_in_blocks() }
+}
+impl BlockReader for AhciDrive {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        self.read_dma(buffer, block_offset).map_err(IoError::from)
+    }
+}
+impl BlockWriter for AhciDrive {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        self.write_dma(buffer, block_offset).map_err(IoError::from)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.flush_cache().map_err(IoError::from)
+    }
+}
+
+pub type AhciDriveRef = Arc<Mutex<AhciDrive>>;
+
+
+/// An AHCI controller, which has up to 32 ports with one SATA drive attached to each port.
+pub struct AhciController {
+    /// The drives that were found and initialized on this controller's ports.
+    drives: Vec<AhciDriveRef>,
+}
+
+impl AhciController {
+    /// Creates a new instance of an AHCI controller based on the given PCI device,
+    /// and initializes all of the SATA drives attached to it.
+    pub fn new(pci_device: &PciDevice) -> Result<AhciController, &'static str> {
+        let abar = pci_device.determine_mem_base(AHCI_BAR_INDEX)?;
+        let abar_size = pci_device.determine_mem_size(AHCI_BAR_INDEX) as usize;
+        if abar_size < PORT_REGISTERS_OFFSET {
+            return Err("AHCI base address region was too small");
+        }
+
+        // set the bus mastering bit for this PciDevice, which allows it to use DMA
+        pci_device.pci_set_command_bus_master_bit();
+
+        let mut hba = Hba { mapped_registers: map_registers(abar, abar_size)? };
+        let (caps, ports_implemented) = {
+            let regs = hba.registers()?;
+            // Switch the controller into AHCI mode and disable its interrupts, as we poll for completions.
+            let ghc = regs.ghc.read();
+            regs.ghc.write((ghc | GHC_AE) & !GHC_IE);
+
+            let cap = regs.cap.read();
+            let caps = HbaCapabilities {
+                num_command_slots: ((cap >> CAP_NCS_SHIFT) & CAP_NCS_MASK) as usize + 1,
+                supports_ncq: cap & CAP_SNCQ != 0,
+                supports_64_bit: cap & CAP_S64A != 0,
+            };
+            (caps, regs.pi.read())
+        };
+        let hba = Arc::new(Mutex::new(hba));
+
+        let mut drives = Vec::new();
+        let mut port_descriptions = String::new();
+        for port_num in 0..MAX_PORTS {
+            if ports_implemented & (1 << port_num) == 0 {
+                continue;
+            }
+            if port_registers_offset(port_num + 1) > abar_size {
+                warn!("AHCI port {} is implemented but lies outside of the AHCI base address region", port_num);
+                continue;
+            }
+            let description = match AhciDrive::new(Arc::clone(&hba), port_num, caps) {
+                Ok(drive) => {
+                    let description = format!(
+                        "drive initialized, size: {} sectors, NCQ: {}, queue depth: {}",
+                        drive.size_in_blocks(), drive.uses_ncq(), drive.queue_depth(),
+                    );
+                    drives.push(Arc::new(Mutex::new(drive)));
+                    description
+                }
+                Err(e) => String::from(e),
+            };
+            port_descriptions.push_str(&format!("\n--> port {port_num}: {description}"));
+        }
+
+        info!("AHCI controller at {} ({:?}):{}", pci_device.location, caps, port_descriptions);
+
+        Ok(AhciController { drives })
+    }
+
+    /// Returns the drives that are attached to this controller.
+    pub fn drives(&self) -> &[AhciDriveRef] {
+        &self.drives
+    }
+}
+
+impl StorageController for AhciController {
+    fn devices<'c>(&'c self) -> Box<(dyn Iterator<Item = StorageDeviceRef> + 'c)> {
+        Box::new(
+            self.drives.iter().map(|drive_ref| Arc::clone(drive_ref) as StorageDeviceRef)
+        )
+    }
+}
+
+
+/// Maps the AHCI base address region of `size_in_bytes` at the given physical address.
+fn map_registers(abar: PhysicalAddress, size_in_bytes: usize) -> Result<MappedPages, &'static str> {
+    let pages = allocate_pages_by_bytes(size_in_bytes)
+        .ok_or("ahci::map_registers(): couldn't allocate virtual pages")?;
+    let frames = allocate_frames_by_bytes_at(abar, size_in_bytes)
+        .map_err(|_e| "ahci::map_registers(): couldn't allocate physical frames")?;
+    let kernel_mmi_ref = get_kernel_mmi_ref().ok_or("ahci::map_registers(): KERNEL_MMI was not yet initialized!")?;
+    let mp = kernel_mmi_ref.lock().page_table.map_allocated_pages_to(pages, frames, AHCI_MAPPING_FLAGS)?;
+    Ok(mp)
+}
+
+/// Stops the given port from processing its command list and receiving FISes.
+fn stop_port(port: &mut PortRegisters) -> Result<(), &'static str> {
+    port.cmd.write(port.cmd.read() & !PORT_CMD_ST);
+    if !poll_until(|| port.cmd.read() & PORT_CMD_CR == 0) {
+        return Err("AHCI port's command list did not stop running");
+    }
+    port.cmd.write(port.cmd.read() & !PORT_CMD_FRE);
+    if !poll_until(|| port.cmd.read() & PORT_CMD_FR == 0) {
+        return Err("AHCI port's FIS receive did not stop running");
+    }
+    Ok(())
+}
+
+/// Starts the given port, allowing it to receive FISes and process its command list.
+fn start_port(port: &mut PortRegisters) -> Result<(), &'static str> {
+    port.cmd.write(port.cmd.read() | PORT_CMD_FRE);
+    if !poll_until(|| port.tfd.read() & (PORT_TFD_BSY | PORT_TFD_DRQ) == 0) {
+        return Err("AHCI drive remained busy");
+    }
+    port.cmd.write(port.cmd.read() | PORT_CMD_ST);
+    Ok(())
+}
+
+/// Repeatedly evaluates `condition` until it is true or until `PORT_TIMEOUT_POLLS` attempts have been made.
+///
+/// Returns whether the condition became true.
+fn poll_until<F: FnMut() -> bool>(mut condition: F) -> bool {
+    for _ in 0..PORT_TIMEOUT_POLLS {
+        if condition() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+/// Creates a Register Host-to-Device FIS that issues the given ATA `command`.
+fn register_fis(command: AtaCommand, lba: u64, count: u16, features: u16, device: u8) -> [u8; FIS_REG_H2D_LENGTH] {
+    let mut fis = [0u8; FIS_REG_H2D_LENGTH];
+    fis[0]  = FIS_TYPE_REG_H2D;
+    fis[1]  = 1 << 7; // this FIS is a command, not a control update
+    fis[2]  = command as u8;
+    fis[3]  = features as u8;
+    fis[4]  = lba as u8;
+    fis[5]  = (lba >> 8) as u8;
+    fis[6]  = (lba >> 16) as u8;
+    fis[7]  = device;
+    fis[8]  = (lba >> 24) as u8;
+    fis[9]  = (lba >> 32) as u8;
+    fis[10] = (lba >> 40) as u8;
+    fis[11] = (features >> 8) as u8;
+    fis[12] = count as u8;
+    fis[13] = (count >> 8) as u8;
+    fis
+}
@@ -0,0 +1,236 @@
+//! The memory-mapped registers of an AHCI controller (HBA) and the in-memory structures
+//! that it accesses via DMA, as defined in the AHCI 1.3.1 specification.
+//!
+//! The HBA's registers are mapped from the AHCI Base Address (ABAR, PCI BAR5):
+//! * `HbaRegisters`: the generic host control registers, followed by
+//! * `PortRegisters`: one set of registers for each of the up to 32 ports.
+//!   Only the implemented ports are guaranteed to lie within the ABAR region.
+//!
+//! Each port owns the following DMA structures, which it reads and writes in system memory:
+//! * A command list of 32 `CommandHeader`s, one per command slot.
+//! * A received FIS area, into which the drive's response FISes are copied.
+//! * A `CommandTable` for each command slot, which holds the command FIS and a PRD table.
+
+#![allow(dead_code)] // not all registers are used by the driver
+
+use volatile::{Volatile, ReadOnly};
+use zerocopy::FromBytes;
+
+/// The maximum number of ports that an AHCI controller may implement.
+pub const MAX_PORTS: usize = 32;
+/// The maximum number of command slots that a port may implement.
+pub const MAX_COMMAND_SLOTS: usize = 32;
+
+
+/// The layout in memory of the AHCI generic host control registers.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct HbaRegisters {
+    /// Host Capabilities
+    pub cap:                        ReadOnly<u32>,          // 0x00
+    /// Global Host Control
+    pub ghc:                        Volatile<u32>,          // 0x04
+    /// Interrupt Status
+    pub is:                         Volatile<u32>,          // 0x08
+    /// Ports Implemented
+    pub pi:                         ReadOnly<u32>,          // 0x0C
+    /// Version
+    pub vs:                         ReadOnly<u32>,          // 0x10
+    /// Command Completion Coalescing Control
+    pub ccc_ctl:                    Volatile<u32>,          // 0x14
+    /// Command Completion Coalescing Ports
+    pub ccc_ports:                  Volatile<u32>,          // 0x18
+    /// Enclosure Management Location
+    pub em_loc:                     ReadOnly<u32>,          // 0x1C
+    /// Enclosure Management Control
+    pub em_ctl:                     Volatile<u32>,          // 0x20
+    /// Host Capabilities Extended
+    pub cap2:                       ReadOnly<u32>,          // 0x24
+    /// BIOS/OS Handoff Control and Status
+    pub bohc:                       Volatile<u32>,          // 0x28
+    _reserved:                      [u8; 116],              // 0x2C - 0x9F
+    _vendor_specific:               [u8; 96],               // 0xA0 - 0xFF
+}
+
+const _: () = assert!(core::mem::size_of::<HbaRegisters>() == PORT_REGISTERS_OFFSET);
+
+/// The offset from the AHCI base address of the first port's registers.
+pub const PORT_REGISTERS_OFFSET:    usize = 0x100;
+
+
+/// The layout in memory of the registers of a single AHCI port.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct PortRegisters {
+    /// Command List Base Address, lower 32 bits (1 KiB aligned)
+    pub clb:                        Volatile<u32>,          // 0x00
+    /// Command List Base Address, upper 32 bits
+    pub clbu:                       Volatile<u32>,          // 0x04
+    /// FIS Base Address, lower 32 bits (256-byte aligned)
+    pub fb:                         Volatile<u32>,          // 0x08
+    /// FIS Base Address, upper 32 bits
+    pub fbu:                        Volatile<u32>,          // 0x0C
+    /// Interrupt Status
+    pub is:                         Volatile<u32>,          // 0x10
+    /// Interrupt Enable
+    pub ie:                         Volatile<u32>,          // 0x14
+    /// Command and Status
+    pub cmd:                        Volatile<u32>,          // 0x18
+    _reserved0:                     u32,                    // 0x1C
+    /// Task File Data
+    pub tfd:                        ReadOnly<u32>,          // 0x20
+    /// Signature
+    pub sig:                        ReadOnly<u32>,          // 0x24
+    /// Serial ATA Status (SCR0: SStatus)
+    pub ssts:                       ReadOnly<u32>,          // 0x28
+    /// Serial ATA Control (SCR2: SControl)
+    pub sctl:                       Volatile<u32>,          // 0x2C
+    /// Serial ATA Error (SCR1: SError)
+    pub serr:                       Volatile<u32>,          // 0x30
+    /// Serial ATA Active (SCR3: SActive), used for native command queuing
+    pub sact:                       Volatile<u32>,          // 0x34
+    /// Command Issue
+    pub ci:                         Volatile<u32>,          // 0x38
+    /// Serial ATA Notification (SCR4: SNotification)
+    pub sntf:                       Volatile<u32>,          // 0x3C
+    /// FIS-based Switching Control
+    pub fbs:                        Volatile<u32>,          // 0x40
+    _reserved1:                     [u8; 44],               // 0x44 - 0x6F
+    _vendor_specific:               [u8; 16],               // 0x70 - 0x7F
+}
+
+const _: () = assert!(core::mem::size_of::<PortRegisters>() == 0x80);
+
+/// Returns the offset from the AHCI base address of the registers of the given port.
+pub const fn port_registers_offset(port_num: usize) -> usize {
+    PORT_REGISTERS_OFFSET + port_num * core::mem::size_of::<PortRegisters>()
+}
+
+
+/// HBA Capabilities: Supports 64-bit Addressing
+pub const CAP_S64A:                 u32 = 1 << 31;
+/// HBA Capabilities: Supports Native Command Queuing
+pub const CAP_SNCQ:                 u32 = 1 << 30;
+/// HBA Capabilities: the shift of the Number of Command Slots field (bits 12:8), which is zero-based.
+pub const CAP_NCS_SHIFT:            u32 = 8;
+/// HBA Capabilities: the mask of the Number of Command Slots field, after shifting.
+pub const CAP_NCS_MASK:             u32 = 0x1F;
+
+/// Global Host Control: AHCI Enable
+pub const GHC_AE:                   u32 = 1 << 31;
+/// Global Host Control: Interrupt Enable
+pub const GHC_IE:                   u32 = 1 << 1;
+
+/// Port Command and Status: Start, which allows the port to process the command list.
+pub const PORT_CMD_ST:              u32 = 1 << 0;
+/// Port Command and Status: FIS Receive Enable
+pub const PORT_CMD_FRE:             u32 = 1 << 4;
+/// Port Command and Status: FIS Receive Running
+pub const PORT_CMD_FR:              u32 = 1 << 14;
+/// Port Command and Status: Command List Running
+pub const PORT_CMD_CR:              u32 = 1 << 15;
+
+/// Port Interrupt Status: Task File Error Status
+pub const PORT_IS_TFES:             u32 = 1 << 30;
+/// Port Interrupt Status: Host Bus Fatal Error Status
+pub const PORT_IS_HBFS:             u32 = 1 << 29;
+/// Port Interrupt Status: Host Bus Data Error Status
+pub const PORT_IS_HBDS:             u32 = 1 << 28;
+/// Port Interrupt Status: Interface Fatal Error Status
+pub const PORT_IS_IFS:              u32 = 1 << 27;
+/// All of the Port Interrupt Status bits that indicate a failed command.
+pub const PORT_IS_ERROR_MASK:       u32 = PORT_IS_TFES | PORT_IS_HBFS | PORT_IS_HBDS | PORT_IS_IFS;
+
+/// Port Task File Data: the drive is busy.
+pub const PORT_TFD_BSY:             u32 = 1 << 7;
+/// Port Task File Data: the drive is requesting a data transfer.
+pub const PORT_TFD_DRQ:             u32 = 1 << 3;
+/// Port Task File Data: the drive reported an error.
+pub const PORT_TFD_ERR:             u32 = 1 << 0;
+
+/// Port SATA Status: the Device Detection field (bits 3:0).
+pub const PORT_SSTS_DET_MASK:       u32 = 0xF;
+/// Port SATA Status: a device is present and communication with it is established.
+pub const PORT_SSTS_DET_PRESENT:    u32 = 0x3;
+/// Port SATA Status: the shift of the Interface Power Management field (bits 11:8).
+pub const PORT_SSTS_IPM_SHIFT:      u32 = 8;
+/// Port SATA Status: the interface is in the active state.
+pub const PORT_SSTS_IPM_ACTIVE:     u32 = 0x1;
+
+/// The port signature of a SATA drive.
+pub const SATA_SIG_ATA:             u32 = 0x0000_0101;
+/// The port signature of a SATAPI device, e.g., an optical drive.
+pub const SATA_SIG_ATAPI:           u32 = 0xEB14_0101;
+/// The port signature of an enclosure management bridge.
+pub const SATA_SIG_SEMB:            u32 = 0xC33C_0101;
+/// The port signature of a port multiplier.
+pub const SATA_SIG_PM:              u32 = 0x9669_0101;
+
+
+/// An entry in a port's command list, which describes the command in one command slot.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct CommandHeader {
+    /// Bits 4:0 are the length of the command FIS in dwords,
+    /// bit 6 is set if the command writes to the device.
+    pub flags:                      Volatile<u16>,
+    /// Physical Region Descriptor Table Length, in number of entries.
+    pub prdtl:                      Volatile<u16>,
+    /// Physical Region Descriptor Byte Count, the number of bytes transferred so far.
+    pub prdbc:                      Volatile<u32>,
+    /// Command Table Base Address, lower 32 bits (128-byte aligned)
+    pub ctba:                       Volatile<u32>,
+    /// Command Table Base Address, upper 32 bits
+    pub ctbau:                      Volatile<u32>,
+    _reserved:                      [u32; 4],
+}
+
+const _: () = assert!(core::mem::size_of::<CommandHeader>() == 32);
+
+/// Command Header flags: the command transfers data from memory to the device.
+pub const COMMAND_HEADER_WRITE:     u16 = 1 << 6;
+
+
+/// A command table, which holds the command FIS sent to the drive
+/// and the Physical Region Descriptor (PRD) table that describes the data buffer.
+///
+/// We use a single PRD per command, as each command slot has its own physically-contiguous buffer.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct CommandTable {
+    /// The Command FIS, of which we only use the first 20 bytes (a Register Host-to-Device FIS).
+    pub cfis:                       [u8; 64],
+    /// The ATAPI command, unused for ATA drives.
+    pub acmd:                       [u8; 16],
+    _reserved:                      [u8; 48],
+    pub prdt:                       PrdtEntry,
+    /// Padding to keep each command table 128-byte aligned when they're placed contiguously.
+    _padding:                       [u8; 112],
+}
+
+const _: () = assert!(core::mem::size_of::<CommandTable>() == 256);
+
+
+/// A Physical Region Descriptor, which describes one physically-contiguous data buffer.
+#[derive(FromBytes)]
+#[repr(C)]
+pub struct PrdtEntry {
+    /// Data Base Address, lower 32 bits (word aligned)
+    pub dba:                        Volatile<u32>,
+    /// Data Base Address, upper 32 bits
+    pub dbau:                       Volatile<u32>,
+    _reserved:                      u32,
+    /// Bits 21:0 are the byte count minus one (which must be odd),
+    /// bit 31 requests an interrupt upon completion.
+    pub dbc:                        Volatile<u32>,
+}
+
+const _: () = assert!(core::mem::size_of::<PrdtEntry>() == 16);
+
+
+/// The type of a Register Host-to-Device FIS.
+pub const FIS_TYPE_REG_H2D:         u8 = 0x27;
+/// The length in bytes of a Register Host-to-Device FIS.
+pub const FIS_REG_H2D_LENGTH:       usize = 20;
+/// The size in bytes of the area that receives FISes from the drive.
+pub const RECEIVED_FIS_SIZE:        usize = 256;
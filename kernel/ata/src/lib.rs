@@ -1038,7 +1038,7 @@ pub struct AtaIdentifyData {
 impl AtaIdentifyData {
 	/// Converts the given byte array, which should be the result of an ATA identify command,
 	/// into a struct that contains the identified details of an ATA drive.
-	fn new(arr: [u8; SECTOR_SIZE_IN_BYTES])-> AtaIdentifyData {
+	pub fn new(arr: [u8; SECTOR_SIZE_IN_BYTES])-> AtaIdentifyData {
 		let mut identify_data: AtaIdentifyData = unsafe { core::mem::transmute(arr) };
 		Self::flip_bytes(&mut identify_data.serial_number.0);
 		Self::flip_bytes(&mut identify_data.firmware_version.0);
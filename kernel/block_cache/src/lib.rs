@@ -1,21 +1,32 @@
 //! A caching layer for block based storage devices.
-//! 
+//!
 //! For many storage devices, calls to the backing medium are quite expensive. This layer intends to reduce those calls,
 //! improving efficiency in exchange for additional memory usage. Note that this crate is intended to be used as a part of
 //! `block_io`, but should work on its own.
-//! 
+//!
+//! The [`BlockCache`] is a bounded, write-back cache:
+//! * It holds at most a fixed number of blocks, its *capacity*.
+//!   When full, the least-recently-used block is evicted to make room for a new block.
+//! * Written blocks are only marked as dirty (`Modified`) in the cache rather than written to the storage device.
+//!   Dirty blocks are written back when they are evicted or when the cache is flushed,
+//!   either explicitly via [`BlockCache::flush()`] or periodically by whoever owns the cache.
+//!   If a dirty block cannot be written back, it stays in the cache and the next block is evicted instead.
+//! * It records hit/miss statistics, see [`CacheStats`].
+//!
+//! A [`CachedStorageDevice`] wraps a shared [`BlockCacheRef`] and is itself a `StorageDevice`,
+//! which allows any existing user of a `StorageDevice` (e.g., a filesystem) to transparently use the cache.
+//!
 //! # Limitations
 //! Currently, the `BlockCache` struct is hardcoded to use a `StorageDevice` reference,
-//! when in reality it should just use anything that implements traits like `BlockReader + BlockWriter`. 
-//! 
+//! when in reality it should just use anything that implements traits like `BlockReader + BlockWriter`.
+//!
 //! The read and write functions currently only support reading/writing individual blocks from disk even
 //! when the caller might prefer reading a larger number of contiguous blocks. This is inneficient and an
 //! optimized implementation should read multiple blocks at once if possible.
-//! 
-//! Cached blocks are stored as vectors of bytes on the heap, 
-//! we should do something else such as separate mapped regions. 
-//! Cached blocks cannot yet be dropped to relieve memory pressure. 
-//! 
+//!
+//! Cached blocks are stored as vectors of bytes on the heap,
+//! we should do something else such as separate mapped regions.
+//!
 //! Note that this cache only holds a reference to the underlying block device.
 //! As such if any other system crates perform writes to the underlying device,
 //! in that case the cache will give incorrect and potentially inconsistent results.
@@ -28,125 +39,337 @@
 
 #[macro_use] extern crate alloc;
 extern crate hashbrown;
+extern crate spin;
+extern crate io;
 extern crate storage_device;
 
-use alloc::vec::Vec;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
 use hashbrown::{
     HashMap,
     hash_map::Entry,
 };
+use spin::Mutex;
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
 use storage_device::{StorageDevice, StorageDeviceRef};
 use alloc::borrow::{Cow, ToOwned};
 
+/// The default maximum number of blocks held in a [`BlockCache`],
+/// which is 2 MiB worth of 512-byte blocks.
+pub const DEFAULT_CAPACITY_IN_BLOCKS: usize = 4096;
+
+/// A shareable reference to a [`BlockCache`].
+pub type BlockCacheRef = Arc<Mutex<BlockCache>>;
+
+/// Statistics about the usage of a [`BlockCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of block reads that were served from the cache.
+    pub hits: u64,
+    /// The number of block reads that required reading from the storage device.
+    pub misses: u64,
+    /// The number of blocks that were evicted from the cache to make room for other blocks.
+    pub evictions: u64,
+    /// The number of dirty blocks that were written back to the storage device.
+    pub writebacks: u64,
+    /// The number of blocks currently in the cache.
+    pub cached_blocks: usize,
+    /// The number of blocks currently in the cache that haven't yet been written back.
+    pub dirty_blocks: usize,
+    /// The maximum number of blocks that the cache can hold.
+    pub capacity: usize,
+}
+
 /// A cache to store read and written blocks from a storage device.
 pub struct BlockCache {
     /// The cache of blocks (sectors) read from the storage device,
     /// a map from block number to data byte array.
     cache: InternalCache,
+    /// The blocks in the cache ordered from least- to most-recently used,
+    /// a map from the time of a block's last access to its block number.
+    lru: BTreeMap<u64, usize>,
+    /// The logical time of the most recent access, which increases upon every access.
+    access_counter: u64,
+    /// The maximum number of blocks held in the cache.
+    capacity: usize,
+    /// The size in bytes of each block of the storage device.
+    block_size: usize,
+    /// The number of blocks in the storage device.
+    size_in_blocks: usize,
+    /// Usage statistics, of which only the counters are kept up to date.
+    stats: CacheStats,
     /// The underlying storage device from where the blocks are read/written.
     storage_device: StorageDeviceRef,
 }
 
 impl BlockCache {
-    /// Creates a new `BlockCache` device 
+    /// Creates a new `BlockCache` device with the [default capacity](DEFAULT_CAPACITY_IN_BLOCKS).
     pub fn new(storage_device: StorageDeviceRef) -> BlockCache {
+        Self::with_capacity(storage_device, DEFAULT_CAPACITY_IN_BLOCKS)
+    }
+
+    /// Creates a new `BlockCache` device that holds at most `capacity` blocks,
+    /// which must be at least one.
+    pub fn with_capacity(storage_device: StorageDeviceRef, capacity: usize) -> BlockCache {
+        let (block_size, size_in_blocks) = {
+            let locked_device = storage_device.lock();
+            (locked_device.block_size(), locked_device.size_in_blocks())
+        };
         BlockCache {
             cache: HashMap::new(),
+            lru: BTreeMap::new(),
+            access_counter: 0,
+            capacity: core::cmp::max(capacity, 1),
+            block_size,
+            size_in_blocks,
+            stats: CacheStats::default(),
             storage_device,
         }
     }
 
-    /// Flushes the given block to the backing storage device. 
+    /// Returns the storage device that backs this cache.
+    pub fn storage_device(&self) -> &StorageDeviceRef {
+        &self.storage_device
+    }
+
+    /// Returns the size in bytes of each block in this cache.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the maximum number of blocks that this cache can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of blocks that this cache can hold,
+    /// evicting blocks (and writing back dirty ones) if it currently holds more than that.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), &'static str> {
+        self.capacity = core::cmp::max(capacity, 1);
+        let storage_device = Arc::clone(&self.storage_device);
+        let mut locked_device = storage_device.lock();
+        self.evict_excess_blocks(&mut *locked_device)
+    }
+
+    /// Returns the current usage statistics of this cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached_blocks: self.cache.len(),
+            dirty_blocks: self.cache.values().filter(|cb| cb.is_dirty()).count(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    /// Flushes the given block to the backing storage device.
     /// If the `block_to_flush` is None, all blocks in the entire cache
-    /// will be written back to the storage device.
+    /// will be written back to the storage device, which is then itself flushed.
     pub fn flush(&mut self, block_num: Option<usize>) -> Result<(), &'static str> {
         let mut locked_device = self.storage_device.lock();
         if let Some(bn) = block_num {
             // Flush just one block
             if let Some(cached_block) = self.cache.get_mut(&bn) {
-                Self::flush_block(&mut *locked_device, bn, cached_block)?;
+                if Self::flush_block(&mut *locked_device, bn, cached_block)? {
+                    self.stats.writebacks += 1;
+                }
             }
             // If the block wasn't in the cache, do nothing.
         }
         else {
             // Flush all blocks
             for (bn, cached_block) in self.cache.iter_mut() {
-                Self::flush_block(&mut *locked_device, *bn, cached_block)?;
+                if Self::flush_block(&mut *locked_device, *bn, cached_block)? {
+                    self.stats.writebacks += 1;
+                }
             }
+            locked_device.flush()?;
         }
         Ok(())
     }
 
-    /// An internal function that first checks the cache for a specific block
-    /// in order to avoid reading from the storage device.
-    /// If that block exists in the cache, it is copied into the buffer. 
-    /// If not, it is read from the storage device into the cache, and then copied into the buffer.
-    pub fn read_block(cache: &mut BlockCache, block: usize) -> Result<&[u8], &'static str> {
-        let mut locked_device = cache.storage_device.lock();
-        match cache.cache.entry(block) {
+    /// Writes back the given blocks to the backing storage device, which is then itself flushed.
+    ///
+    /// Blocks that aren't in the cache are ignored.
+    pub fn flush_blocks<I: IntoIterator<Item = usize>>(&mut self, block_nums: I) -> Result<(), &'static str> {
+        let mut locked_device = self.storage_device.lock();
+        for bn in block_nums {
+            if let Some(cached_block) = self.cache.get_mut(&bn) {
+                if Self::flush_block(&mut *locked_device, bn, cached_block)? {
+                    self.stats.writebacks += 1;
+                }
+            }
+        }
+        locked_device.flush()
+    }
+
+    /// Returns the contents of the given block.
+    ///
+    /// This first checks the cache for that block in order to avoid reading from the storage device.
+    /// If that block doesn't exist in the cache, it is read from the storage device into the cache,
+    /// which may evict the least-recently-used block.
+    pub fn read_block(&mut self, block_num: usize) -> Result<&[u8], &'static str> {
+        let storage_device = Arc::clone(&self.storage_device);
+        let mut locked_device = storage_device.lock();
+        let access_time = self.next_access_time();
+        match self.cache.entry(block_num) {
             Entry::Occupied(occ) => {
                 // An existing entry in the cache can be used directly (without going to the backing store)
                 // if it's in the `Modified` or `Shared` state.
                 // But if it's in the `Invalid` state, we have to re-read the block from the storage device.
                 let cached_block = occ.into_mut();
                 match cached_block.state {
-                    CacheState::Modified | CacheState::Shared => Ok(&cached_block.block),
+                    CacheState::Modified | CacheState::Shared => self.stats.hits += 1,
                     CacheState::Invalid => {
-                        locked_device.read_blocks(&mut cached_block.block, block)?;
+                        locked_device.read_blocks(&mut cached_block.block, block_num)?;
                         cached_block.state = CacheState::Shared;
-                        Ok(&cached_block.block)
+                        self.stats.misses += 1;
                     }
                 }
+                self.lru.remove(&cached_block.last_access);
+                cached_block.last_access = access_time;
             }
             Entry::Vacant(vacant) => {
                 // A vacant entry will be read from the backing storage device,
                 // so it will always start out in the `Shared` state.
-                let mut v = vec![0; locked_device.block_size()];
-                locked_device.read_blocks(&mut v, block)?;
-                let cb = CachedBlock {
+                let mut v = vec![0; self.block_size];
+                locked_device.read_blocks(&mut v, block_num)?;
+                vacant.insert(CachedBlock {
                     block: v,
                     state: CacheState::Shared,
-                };
-                let cached_block = vacant.insert(cb);
-                Ok(&cached_block.block)
+                    last_access: access_time,
+                });
+                self.stats.misses += 1;
             }
         }
+        self.lru.insert(access_time, block_num);
+        self.evict_excess_blocks(&mut *locked_device)?;
+        self.cache.get(&block_num)
+            .map(|cb| &cb.block[..])
+            .ok_or("BUG: BlockCache::read_block(): block was evicted immediately after being read")
     }
 
-    pub fn write_block(&mut self, block_num: usize, buffer_to_write: Cow<[u8]>)
-    //pub fn write_block(&mut self, block_num: usize, buffer_to_write: Cow<[u8]>)
-        -> Result<(), &'static str> 
-        {
-            let mut locked_device = self.storage_device.lock();
+    /// Writes the given buffer into the given block in the cache and marks it as dirty.
+    ///
+    /// The block is not written to the storage device until it is evicted or flushed.
+    /// The length of the buffer must be exactly the block size,
+    /// and the block must lie within the storage device.
+    pub fn write_block(&mut self, block_num: usize, buffer_to_write: Cow<[u8]>) -> Result<(), &'static str> {
+        if buffer_to_write.len() != self.block_size {
+            return Err("BlockCache::write_block(): buffer length must be equal to the block size");
+        }
+        if block_num >= self.size_in_blocks {
+            return Err("BlockCache::write_block(): block is beyond the end of the storage device");
+        }
+        let owned_buffer: Vec<u8> = match buffer_to_write {
+            Cow::Borrowed(slice_ref) => slice_ref.to_owned(),
+            Cow::Owned(vec_owned) => vec_owned,
+        };
 
-            let owned_buffer: Vec<u8> = match buffer_to_write {
-                Cow::Borrowed(slice_ref) => slice_ref.to_owned(),
-                Cow::Owned(vec_owned) => vec_owned, 
-            };
+        let access_time = self.next_access_time();
+        let new_cached_block = CachedBlock {
+            block: owned_buffer,
+            state: CacheState::Modified,
+            last_access: access_time,
+        };
+        if let Some(old_cached_block) = self.cache.insert(block_num, new_cached_block) {
+            self.lru.remove(&old_cached_block.last_access);
+        }
+        self.lru.insert(access_time, block_num);
 
-            let mut new_cached_block = CachedBlock {
-                block: owned_buffer,
-                state: CacheState::Modified,
-            };
-            // Currently using a write-through policy right now, so flush the block immediately
-            BlockCache::flush_block(&mut *locked_device, block_num, &mut new_cached_block)?;
-            self.cache.insert(block_num, new_cached_block);
+        let storage_device = Arc::clone(&self.storage_device);
+        let mut locked_device = storage_device.lock();
+        self.evict_excess_blocks(&mut *locked_device)
+    }
 
-            Ok(())
+    /// Reads blocks from the cache into the given `buffer`, starting at the given `block_offset`.
+    ///
+    /// The length of the buffer must be a multiple of the block size.
+    /// Returns the number of blocks read.
+    pub fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, &'static str> {
+        if buffer.len() % self.block_size != 0 {
+            return Err("BlockCache::read_blocks(): buffer length must be a multiple of the block size");
+        }
+        let block_size = self.block_size;
+        for (i, chunk) in buffer.chunks_exact_mut(block_size).enumerate() {
+            chunk.copy_from_slice(self.read_block(block_offset + i)?);
+        }
+        Ok(buffer.len() / block_size)
+    }
+
+    /// Writes blocks from the given `buffer` into the cache, starting at the given `block_offset`.
+    ///
+    /// The length of the buffer must be a multiple of the block size,
+    /// and all blocks must lie within the storage device; otherwise, the cache is left unchanged.
+    /// Returns the number of blocks written.
+    pub fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, &'static str> {
+        if buffer.len() % self.block_size != 0 {
+            return Err("BlockCache::write_blocks(): buffer length must be a multiple of the block size");
+        }
+        match block_offset.checked_add(buffer.len() / self.block_size) {
+            Some(end) if end <= self.size_in_blocks => {}
+            _ => return Err("BlockCache::write_blocks(): blocks are beyond the end of the storage device"),
+        }
+        for (i, chunk) in buffer.chunks_exact(self.block_size).enumerate() {
+            self.write_block(block_offset + i, Cow::Borrowed(chunk))?;
+        }
+        Ok(buffer.len() / self.block_size)
+    }
+
+    /// Returns a new logical time for a block access.
+    fn next_access_time(&mut self) -> u64 {
+        self.access_counter += 1;
+        self.access_counter
+    }
+
+    /// Evicts the least-recently-used blocks until this cache holds no more than its capacity,
+    /// writing back dirty blocks to the given locked `StorageDevice` before evicting them.
+    ///
+    /// A dirty block that cannot be written back is kept in the cache, and the next block is evicted instead.
+    /// Returns an error only if no further block could be evicted,
+    /// in which case the cache temporarily holds more blocks than its capacity.
+    fn evict_excess_blocks(&mut self, locked_device: &mut dyn StorageDevice) -> Result<(), &'static str> {
+        // The access time from which to search for the next block to evict.
+        let mut next_candidate = 0;
+        let mut writeback_error = None;
+        while self.cache.len() > self.capacity {
+            let Some((&access_time, &block_num)) = self.lru.range(next_candidate..).next() else {
+                return Err(writeback_error.unwrap_or("BUG: BlockCache::evict_excess_blocks(): LRU list is missing blocks"));
+            };
+            next_candidate = access_time + 1;
+            if let Some(cached_block) = self.cache.get_mut(&block_num) {
+                match Self::flush_block(locked_device, block_num, cached_block) {
+                    Ok(true) => self.stats.writebacks += 1,
+                    Ok(false) => { }
+                    Err(e) => {
+                        writeback_error.get_or_insert(e);
+                        continue;
+                    }
+                }
+            }
+            self.lru.remove(&access_time);
+            self.cache.remove(&block_num);
+            self.stats.evictions += 1;
+        }
+        Ok(())
     }
 
     /// An internal function that writes out the given `cached_block`
     /// to the given locked `StorageDevice` if the cached block is in the `Modified` state.
-    fn flush_block(locked_device: &mut dyn StorageDevice, block_num: usize, cached_block: &mut CachedBlock) -> Result<(), &'static str> {
+    ///
+    /// Returns `true` if the block was written out.
+    fn flush_block(locked_device: &mut dyn StorageDevice, block_num: usize, cached_block: &mut CachedBlock) -> Result<bool, &'static str> {
         // we only need to actually write blocks in the `Modified` state.
         match cached_block.state {
-            CacheState::Shared | CacheState::Invalid => { },
+            CacheState::Shared | CacheState::Invalid => Ok(false),
             CacheState::Modified => {
                 locked_device.write_blocks(&cached_block.block, block_num)?;
                 cached_block.state = CacheState::Shared;
+                Ok(true)
             }
         }
-        Ok(())
     }
 }
 
@@ -155,12 +378,18 @@ impl BlockCache {
 /// A block from a storage device stored in a cache.
 /// This currently includes the actual owned cached content as a vector of bytes on the heap,
 /// in addition to the `CacheState` of the cached item.
-/// 
-/// TODO: allow non-dirty blocks to be freed (reclaimed) upon memory pressure. 
 #[derive(Debug)]
-struct CachedBlock { // Not sure if this should be public, but it seems necessary to fix type leak. TODO make non-public.
+struct CachedBlock {
     block: Vec<u8>,
     state: CacheState,
+    /// The logical time at which this block was last accessed, its key in [`BlockCache::lru`].
+    last_access: u64,
+}
+
+impl CachedBlock {
+    fn is_dirty(&self) -> bool {
+        matches!(self.state, CacheState::Modified)
+    }
 }
 
 type InternalCache = HashMap<usize, CachedBlock>;
@@ -173,7 +402,7 @@ enum CacheState {
     /// Dirty: the cached item has been modified more recently than the backing store,
     /// so it must be flushed at a future time to guarantee data correctness and consistency.
     /// A `Modified` cached item **cannot** be safely dropped from the cache.
-    /// A `Modified` cached item can be safely read from or overwritten without going to the backing store.  
+    /// A `Modified` cached item can be safely read from or overwritten without going to the backing store.
     Modified,
     /// Clean: the cached item and the backing store are in sync; they have the same value.
     /// A `Shared` cached item can be safely dropped from the cache.
@@ -182,7 +411,98 @@ enum CacheState {
     /// as the backing storage has a more recent copy than the cache.
     /// Therefore, if a read of an `Invalid` cached item is requested,
     /// it must be re-read from the backing storage.
-    /// An `Invalid` item can still be overwritten in the cache without going to the backing store. 
+    /// An `Invalid` item can still be overwritten in the cache without going to the backing store.
     /// An `Invalid` item can be safely dropped from the cache.
-    Invalid,  
+    Invalid,
+}
+
+
+/// A `StorageDevice` that accesses its underlying storage device through a shared [`BlockCache`].
+///
+/// Multiple `CachedStorageDevice`s can share the same cache,
+/// so all of them observe each other's writes even before those writes reach the storage device.
+/// Flushing a `CachedStorageDevice` only writes back the blocks that were written through it
+/// since it was last flushed; other dirty blocks are left to eviction or [`BlockCache::flush()`].
+pub struct CachedStorageDevice {
+    cache: BlockCacheRef,
+    block_size: usize,
+    size_in_blocks: usize,
+    /// The blocks that were written through this device since it was last flushed.
+    written_blocks: BTreeSet<usize>,
+}
+
+impl CachedStorageDevice {
+    /// Creates a new `CachedStorageDevice` that accesses the storage device backing the given `cache`.
+    pub fn new(cache: BlockCacheRef) -> CachedStorageDevice {
+        let (block_size, size_in_blocks) = {
+            let locked_cache = cache.lock();
+            let size_in_blocks = locked_cache.storage_device().lock().size_in_blocks();
+            (locked_cache.block_size(), size_in_blocks)
+        };
+        CachedStorageDevice { cache, block_size, size_in_blocks, written_blocks: BTreeSet::new() }
+    }
+
+    /// Returns the cache that this device reads and writes through.
+    pub fn cache(&self) -> &BlockCacheRef {
+        &self.cache
+    }
+
+    /// Returns an error if the `buffer` doesn't cover a whole number of blocks
+    /// that all lie within this device, starting at the given `block_offset`.
+    fn check_range(&self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        if buffer.len() % self.block_size != 0 {
+            return Err(IoError::InvalidInput);
+        }
+        let num_blocks = buffer.len() / self.block_size;
+        match block_offset.checked_add(num_blocks) {
+            Some(end) if end <= self.size_in_blocks => Ok(num_blocks),
+            _ => Err(IoError::InvalidInput),
+        }
+    }
+}
+
+impl StorageDevice for CachedStorageDevice {
+    fn size_in_blocks(&self) -> usize {
+        self.size_in_blocks
+    }
+}
+
+impl BlockIo for CachedStorageDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+}
+
+impl KnownLength for CachedStorageDevice {
+    fn len(&self) -> usize {
+        self.block_size * self.size_in_blocks
+    }
+}
+
+impl BlockReader for CachedStorageDevice {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        self.check_range(buffer, block_offset)?;
+        self.cache.lock().read_blocks(buffer, block_offset).map_err(IoError::from)
+    }
+}
+
+impl BlockWriter for CachedStorageDevice {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        let num_blocks = self.check_range(buffer, block_offset)?;
+        let mut cache = self.cache.lock();
+        for (i, chunk) in buffer.chunks_exact(self.block_size).enumerate() {
+            let result = cache.write_block(block_offset + i, Cow::Borrowed(chunk));
+            // The range was already checked, so an error means that evicting other blocks failed,
+            // but this block was still written into the cache; the remaining blocks were not.
+            self.written_blocks.insert(block_offset + i);
+            result.map_err(IoError::from)?;
+        }
+        Ok(num_blocks)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.cache.lock().flush_blocks(self.written_blocks.iter().copied())?;
+        self.written_blocks.clear();
+        Ok(())
+    }
 }
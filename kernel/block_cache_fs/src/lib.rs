@@ -0,0 +1,146 @@
+//! Exposes the block caches of all storage devices in the VFS
+//! and periodically writes back their dirty blocks in the background.
+//!
+//! Calling [`init()`] creates the read-only [`BLOCK_CACHE_STATS_PATH`] file,
+//! whose contents are generated on demand from the statistics of each block cache,
+//! and spawns a task that flushes all block caches every [`FLUSH_INTERVAL`].
+
+#![no_std]
+
+#[macro_use] extern crate alloc;
+extern crate spin;
+extern crate fs_node;
+extern crate vfs_node;
+extern crate root;
+extern crate io;
+extern crate memory;
+extern crate spawn;
+extern crate sleep;
+extern crate storage_manager;
+
+use core::fmt::Write;
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use spin::Mutex;
+use fs_node::{DirRef, File, FileOrDir, FileRef, FsNode, Metadata, NodeType, WeakDirRef};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memory::MappedPages;
+use sleep::Duration;
+use vfs_node::VFSDirectory;
+
+
+/// The name of the directory in the root that holds system information files.
+pub const SYS_DIRECTORY_NAME: &str = "sys";
+/// The name of the block cache statistics file within the [`SYS_DIRECTORY_NAME`] directory.
+pub const BLOCK_CACHE_STATS_FILE_NAME: &str = "block_cache";
+/// The absolute path of the block cache statistics file.
+pub const BLOCK_CACHE_STATS_PATH: &str = "/sys/block_cache";
+
+/// How often the background task writes back all dirty blocks in all block caches.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+
+/// Creates the block cache statistics file and spawns the background flush task.
+pub fn init() -> Result<(), &'static str> {
+    let root = root::get_root();
+    let existing = root.lock().get_dir(SYS_DIRECTORY_NAME);
+    let sys_dir = match existing {
+        Some(dir) => dir,
+        None => VFSDirectory::create(SYS_DIRECTORY_NAME.to_string(), root)?,
+    };
+    let stats_file = BlockCacheStatsFile { parent: Arc::downgrade(&sys_dir) };
+    sys_dir.lock().insert(FileOrDir::File(Arc::new(Mutex::new(stats_file)) as FileRef))?;
+
+    spawn::new_task_builder(flush_loop, ())
+        .name("block_cache_flusher".to_string())
+        .spawn()?;
+    Ok(())
+}
+
+/// The entry point of the background task that periodically flushes all block caches.
+fn flush_loop(_: ()) -> Result<(), &'static str> {
+    loop {
+        if sleep::sleep(FLUSH_INTERVAL).is_err() {
+            return Err("block cache flusher task couldn't sleep");
+        }
+        // Errors are already logged by `flush_block_caches()`; keep trying on the next interval.
+        let _ = storage_manager::flush_block_caches();
+    }
+}
+
+
+/// A read-only file whose contents are the statistics of every block cache,
+/// one line per storage device, which is generated each time it is read.
+pub struct BlockCacheStatsFile {
+    parent: WeakDirRef,
+}
+
+impl BlockCacheStatsFile {
+    /// Generates the contents of this file.
+    fn generate(&self) -> String {
+        let mut output = String::new();
+        for (name, cache) in storage_manager::block_caches() {
+            let stats = cache.lock().stats();
+            let _ = writeln!(output,
+                "{}: {} hits, {} misses, {} evictions, {} writebacks, {}/{} blocks cached, {} dirty",
+                name, stats.hits, stats.misses, stats.evictions, stats.writebacks,
+                stats.cached_blocks, stats.capacity, stats.dirty_blocks,
+            );
+        }
+        output
+    }
+}
+
+impl FsNode for BlockCacheStatsFile {
+    fn get_name(&self) -> String {
+        BLOCK_CACHE_STATS_FILE_NAME.to_string()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+}
+
+impl ByteReader for BlockCacheStatsFile {
+    fn read_at(&mut self, buf: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        let output = self.generate();
+        if offset > output.len() {
+            return Err(IoError::InvalidInput);
+        }
+        let count = core::cmp::min(buf.len(), output.len() - offset);
+        buf[..count].copy_from_slice(&output.as_bytes()[offset..(offset + count)]);
+        Ok(count)
+    }
+}
+
+impl ByteWriter for BlockCacheStatsFile {
+    fn write_at(&mut self, _buffer: &[u8], _offset: usize) -> Result<usize, IoError> {
+        Err(IoError::from("block cache statistics file is read-only"))
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl KnownLength for BlockCacheStatsFile {
+    fn len(&self) -> usize {
+        self.generate().len()
+    }
+}
+
+impl File for BlockCacheStatsFile {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("block cache statistics are autogenerated, cannot be memory mapped")
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new(NodeType::File, self.len());
+        metadata.permissions.read_only = true;
+        metadata
+    }
+}
@@ -43,6 +43,15 @@ mod mirror_log_callbacks {
     }
 }
 
+/// Spawns the task that monitors a crate swap transaction until it is committed or rolled back.
+fn spawn_transaction_monitor(id: crate_swap::TransactionId, kernel_mmi_ref: MmiRef, verbose_log: bool) -> Result<(), &'static str> {
+    spawn::new_task_builder(crate_swap::monitor_transaction, (id, kernel_mmi_ref, verbose_log))
+        .name(alloc::format!("swap_transaction_monitor_{id}"))
+        .spawn()
+        .map(|_| ())
+}
+
+
 /// Items that must be held until the end of [`init()`] and should be dropped after.
 pub struct DropAfterInit {
     pub identity_mappings: NoDrop<EarlyIdentityMappedPages>,
@@ -115,6 +124,7 @@ pub fn init(
 
     // after we've initialized the task subsystem, we can use better exception handlers
     exceptions_full::init(idt);
+    crate_swap::set_spawn_monitor_cb(spawn_transaction_monitor);
     
     // boot up the other cores (APs)
     let ap_count = multicore_bringup::handle_ap_cores(
@@ -159,6 +169,7 @@ pub fn init(
     // initialize the rest of our drivers
     device_manager::init(key_producer, mouse_producer)?;
     task_fs::init()?;
+    block_cache_fs::init()?;
 
     // create a SIMD personality
     #[cfg(simd_personality)] {
@@ -0,0 +1,294 @@
+//! A set of CPUs, represented as a bitmask over their IDs.
+
+use core::{fmt, str::FromStr};
+
+/// The number of 64-bit words needed for one bit per possible CPU ID.
+const WORDS: usize = 4;
+
+/// A set of CPUs (cores), represented as a bitmask with one bit per CPU ID (APIC ID).
+///
+/// This is primarily used as a task's CPU affinity, i.e., the set of CPUs that a task may run on.
+///
+/// A `CpuSet` is formatted and parsed as a list of CPU IDs and inclusive ranges of CPU IDs,
+/// e.g., `"0-3,6"`, like the `--cpu-list` format of Linux's `taskset`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CpuSet([u64; WORDS]);
+
+impl CpuSet {
+    /// Returns a set that contains no CPUs.
+    pub const fn empty() -> CpuSet {
+        CpuSet([0; WORDS])
+    }
+
+    /// Returns a set that contains all possible CPUs.
+    pub const fn all() -> CpuSet {
+        CpuSet([u64::MAX; WORDS])
+    }
+
+    /// Returns a set that contains only the given CPU.
+    pub const fn single(cpu: u8) -> CpuSet {
+        let mut words = [0; WORDS];
+        words[(cpu / 64) as usize] = 1 << (cpu % 64);
+        CpuSet(words)
+    }
+
+    /// Returns a set that contains the CPUs whose bits are set in the given `mask`,
+    /// in which bit `n` corresponds to the CPU with ID `n`.
+    ///
+    /// Only CPUs with IDs below 64 can be represented in a single mask.
+    pub const fn from_mask(mask: u64) -> CpuSet {
+        CpuSet([mask, 0, 0, 0])
+    }
+
+    /// Adds the given CPU to this set.
+    pub fn insert(&mut self, cpu: u8) {
+        self.0[(cpu / 64) as usize] |= 1 << (cpu % 64);
+    }
+
+    /// Removes the given CPU from this set.
+    pub fn remove(&mut self, cpu: u8) {
+        self.0[(cpu / 64) as usize] &= !(1 << (cpu % 64));
+    }
+
+    /// Returns whether this set contains the given CPU.
+    pub const fn contains(&self, cpu: u8) -> bool {
+        self.0[(cpu / 64) as usize] & (1 << (cpu % 64)) != 0
+    }
+
+    /// Returns whether this set contains no CPUs.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    /// Returns whether this set contains all possible CPUs.
+    pub fn is_all(&self) -> bool {
+        self.0.iter().all(|word| *word == u64::MAX)
+    }
+
+    /// Returns the number of CPUs in this set.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Returns the only CPU in this set,
+    /// or `None` if this set contains either no CPUs or more than one CPU.
+    pub fn single_cpu(&self) -> Option<u8> {
+        if self.len() == 1 {
+            self.iter().next()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the set of CPUs that are in both this set and the `other` set.
+    pub fn intersection(&self, other: &CpuSet) -> CpuSet {
+        let mut words = self.0;
+        for (word, other_word) in words.iter_mut().zip(other.0.iter()) {
+            *word &= *other_word;
+        }
+        CpuSet(words)
+    }
+
+    /// Returns an iterator over the IDs of the CPUs in this set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(move |cpu| self.contains(*cpu))
+    }
+}
+
+impl FromIterator<u8> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> CpuSet {
+        let mut set = CpuSet::empty();
+        for cpu in iter {
+            set.insert(cpu);
+        }
+        set
+    }
+}
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_all() {
+            return write!(f, "all");
+        }
+        let mut first = true;
+        let mut cpus = self.iter().peekable();
+        while let Some(start) = cpus.next() {
+            // Collapse consecutive CPU IDs into a single range.
+            let mut end = start;
+            while let Some(next) = cpus.next_if(|cpu| Some(*cpu) == end.checked_add(1)) {
+                end = next;
+            }
+            if !first {
+                write!(f, ",")?;
+            }
+            first = false;
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CpuSet({self})")
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = &'static str;
+
+    /// Parses a list of CPU IDs and inclusive ranges of CPU IDs, e.g., `"0-3,6"`,
+    /// or `"all"` for the set of all possible CPUs.
+    fn from_str(s: &str) -> Result<CpuSet, Self::Err> {
+        let s = s.trim();
+        if s == "all" {
+            return Ok(CpuSet::all());
+        }
+        let mut set = CpuSet::empty();
+        for item in s.split(',') {
+            let (start, end) = match item.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => (item, item),
+            };
+            let start = start.trim().parse::<u8>().map_err(|_| "invalid CPU ID in CPU list")?;
+            let end = end.trim().parse::<u8>().map_err(|_| "invalid CPU ID in CPU list")?;
+            if start > end {
+                return Err("invalid CPU range in CPU list");
+            }
+            for cpu in start..=end {
+                set.insert(cpu);
+            }
+        }
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use std::string::ToString;
+
+    fn set(cpus: &[u8]) -> CpuSet {
+        cpus.iter().copied().collect()
+    }
+
+    #[test]
+    fn parse_lists_and_ranges() {
+        assert_eq!("3".parse(), Ok(CpuSet::single(3)));
+        assert_eq!("0-3,6".parse(), Ok(set(&[0, 1, 2, 3, 6])));
+        assert_eq!("5,1,5".parse(), Ok(set(&[1, 5])));
+        assert_eq!("2-2".parse(), Ok(CpuSet::single(2)));
+        assert_eq!(" 0 - 1 , 63-65 ".parse(), Ok(set(&[0, 1, 63, 64, 65])));
+        assert_eq!("0-255".parse(), Ok(CpuSet::all()));
+        assert_eq!("all".parse(), Ok(CpuSet::all()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in ["", ",", "3-1", "256", "0-256", "0-", "-1", "1,,2", "a", "0x1", "1-2-3", "ALL"] {
+            assert!(s.parse::<CpuSet>().is_err(), "{s:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn display_collapses_ranges() {
+        assert_eq!(CpuSet::empty().to_string(), "");
+        assert_eq!(CpuSet::single(7).to_string(), "7");
+        assert_eq!(set(&[0, 1, 2, 3, 6]).to_string(), "0-3,6");
+        assert_eq!(set(&[1, 3, 5]).to_string(), "1,3,5");
+        assert_eq!(set(&[62, 63, 64, 65, 255]).to_string(), "62-65,255");
+        assert_eq!(set(&[254, 255]).to_string(), "254-255");
+        assert_eq!(CpuSet::all().to_string(), "all");
+
+        let mut almost_all = CpuSet::all();
+        almost_all.remove(100);
+        assert_eq!(almost_all.to_string(), "0-99,101-255");
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let sets = [
+            CpuSet::empty(),
+            CpuSet::single(0),
+            CpuSet::single(255),
+            set(&[0, 2, 3, 4, 64, 128, 129]),
+            CpuSet::from_mask(u64::MAX),
+            CpuSet::all(),
+        ];
+        for s in sets.into_iter().filter(|s| !s.is_empty()) {
+            assert_eq!(s.to_string().parse(), Ok(s));
+        }
+    }
+
+    #[test]
+    fn single_and_from_mask() {
+        for cpu in [0, 1, 63, 64, 127, 128, 255] {
+            let single = CpuSet::single(cpu);
+            assert!(single.contains(cpu));
+            assert_eq!(single.len(), 1);
+            assert_eq!(single.single_cpu(), Some(cpu));
+            assert_eq!(single.iter().collect::<std::vec::Vec<_>>(), [cpu]);
+        }
+        assert_eq!(CpuSet::from_mask(0b1011), set(&[0, 1, 3]));
+        assert_eq!(CpuSet::from_mask(1 << 63), CpuSet::single(63));
+        assert!(CpuSet::from_mask(0).is_empty());
+    }
+
+    #[test]
+    fn insert_remove_contains() {
+        let mut s = CpuSet::empty();
+        assert!(s.is_empty());
+        assert_eq!(s.len(), 0);
+        assert_eq!(s.single_cpu(), None);
+
+        s.insert(65);
+        s.insert(65);
+        assert!(s.contains(65));
+        assert!(!s.contains(64) && !s.contains(1));
+        assert_eq!(s.single_cpu(), Some(65));
+
+        s.insert(1);
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.single_cpu(), None);
+
+        s.remove(65);
+        s.remove(200);
+        assert_eq!(s, CpuSet::single(1));
+        s.remove(1);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn all_and_empty() {
+        let all = CpuSet::all();
+        assert!(all.is_all() && !all.is_empty());
+        assert_eq!(all.len(), 256);
+        assert_eq!(all.single_cpu(), None);
+        assert!((0..=u8::MAX).all(|cpu| all.contains(cpu)));
+
+        let empty = CpuSet::empty();
+        assert!(empty.is_empty() && !empty.is_all());
+        assert_eq!(empty.iter().count(), 0);
+        assert_eq!(CpuSet::default(), empty);
+
+        let mut almost_all = all;
+        almost_all.remove(0);
+        assert!(!almost_all.is_all());
+        assert_eq!(almost_all.len(), 255);
+    }
+
+    #[test]
+    fn intersection() {
+        let a = set(&[0, 1, 2, 64, 200]);
+        let b = set(&[1, 2, 3, 200, 201]);
+        assert_eq!(a.intersection(&b), set(&[1, 2, 200]));
+        assert_eq!(a.intersection(&CpuSet::all()), a);
+        assert!(a.intersection(&CpuSet::empty()).is_empty());
+        assert!(CpuSet::single(3).intersection(&CpuSet::single(4)).is_empty());
+    }
+}
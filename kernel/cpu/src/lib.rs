@@ -1,11 +1,14 @@
 //! An abstraction for querying about CPUs (cores) in an SMP multicore system.
 //!
-//! This crate contains no extra functionality.
-//! Currently it just re-exports types and functions from:
+//! This crate defines [`CpuSet`], a set of CPUs used to express CPU affinity.
+//! Otherwise, it just re-exports types and functions from:
 //! * [`apic`] on x86_64
 
 #![no_std]
 
+mod cpu_set;
+pub use cpu_set::CpuSet;
+
 #[cfg(target_arch = "x86_64")]
 pub use apic::{
     CpuId,
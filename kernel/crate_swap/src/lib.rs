@@ -15,6 +15,9 @@ extern crate fs_node;
 extern crate qp_trie;
 extern crate path;
 extern crate by_address;
+extern crate fault_log;
+extern crate time;
+extern crate sleep;
 
 #[cfg(loscd_eval)]
 extern crate hpet;
@@ -47,6 +50,9 @@ use mod_mgmt::{
 use path::Path;
 use by_address::ByAddress;
 
+mod transaction;
+pub use transaction::*;
+
 
 lazy_static! {
     /// The set of crates that have been previously unloaded (e.g., swapped out) from a `CrateNamespace`.
@@ -122,6 +128,8 @@ pub type StateTransferFunction = fn(&Arc<CrateNamespace>, &CrateNamespace) -> Re
 /// When one or more crates is swapped out, they are not fully unloaded, but rather saved in a cache
 /// in order to accelerate future swapping commands. 
 /// 
+/// To be able to revert the swap later on, use [`swap_crates_transaction()`] instead.
+/// 
 pub fn swap_crates(
     this_namespace: &Arc<CrateNamespace>,
     swap_requests: SwapRequestList,
@@ -131,6 +139,49 @@ pub fn swap_crates(
     verbose_log: bool,
     cache_old_crates: bool
 ) -> Result<(), &'static str> {
+    let old_crates = swap_crates_internal(
+        this_namespace,
+        swap_requests,
+        override_namespace_dir,
+        state_transfer_functions,
+        kernel_mmi_ref,
+        verbose_log,
+        cache_old_crates,
+        None,
+    )?;
+
+    if let Some((future_swap_requests, cached_crates)) = old_crates {
+        debug!("swap_crates() [end]: adding old_crates to cache. \n   future_swap_requests: {:?}, \n   old_crates: {:?}", 
+            future_swap_requests, cached_crates.crate_names(true));
+        UNLOADED_CRATE_CACHE.lock().insert(future_swap_requests, cached_crates);
+    }
+    Ok(())
+}
+
+
+/// The old crates that were swapped out by `swap_crates_internal()`, 
+/// along with the list of `SwapRequest`s that would swap them back in.
+type SwappedOutCrates = (SwapRequestList, CrateNamespace);
+
+/// The implementation of [`swap_crates()`].
+/// 
+/// If `cache_old_crates` is `true`, the old crates are retained and returned
+/// instead of being added to the cache of unloaded crates.
+/// 
+/// If `previously_swapped_out` crates are given, those crates are swapped in directly 
+/// instead of loading new crates, as they must have been returned from a prior swap 
+/// with the inverse of the given `swap_requests`.
+#[allow(clippy::too_many_arguments)]
+fn swap_crates_internal(
+    this_namespace: &Arc<CrateNamespace>,
+    swap_requests: SwapRequestList,
+    override_namespace_dir: Option<NamespaceDir>,
+    state_transfer_functions: Vec<String>,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+    cache_old_crates: bool,
+    previously_swapped_out: Option<CrateNamespace>,
+) -> Result<Option<SwappedOutCrates>, &'static str> {
 
     #[cfg(not(loscd_eval))]
     debug!("swap_crates()[0]: \n\t-->override dir: {:?}, \n\t-->cache_old_crates: {:?}, \n\t-->state transfer: {:?},\n\t-->swap_requests: {:?}", 
@@ -149,7 +200,7 @@ pub fn swap_crates(
         #[cfg(not(loscd_eval))] {
             // First, before we perform any expensive crate loading, let's try an optimization
             // based on cached crates that were unloaded during a previous swap operation. 
-            if let Some(previously_cached_crates) = UNLOADED_CRATE_CACHE.lock().remove(&swap_requests) {
+            if let Some(previously_cached_crates) = previously_swapped_out.or_else(|| UNLOADED_CRATE_CACHE.lock().remove(&swap_requests)) {
                 warn!("Using optimized swap routine to swap in previously cached crates: {:?}", previously_cached_crates.crate_names(true));
                 (previously_cached_crates, true)
             } else {
@@ -737,14 +788,10 @@ pub fn swap_crates(
         }
     }
 
-    if cache_old_crates {
-        #[cfg(not(loscd_eval))]
-        {
-            debug!("swap_crates() [end]: adding old_crates to cache. \n   future_swap_requests: {:?}, \n   old_crates: {:?}", 
-                future_swap_requests, cached_crates.crate_names(true));
-            UNLOADED_CRATE_CACHE.lock().insert(future_swap_requests, cached_crates);
-        }
-    }
+    #[cfg(not(loscd_eval))]
+    let old_crates = if cache_old_crates { Some((future_swap_requests, cached_crates)) } else { None };
+    #[cfg(loscd_eval)]
+    let old_crates = None;
 
 
     #[cfg(all(loscd_eval, not(downtime_eval)))] {
@@ -769,7 +816,7 @@ pub fn swap_crates(
         );
     }
 
-    Ok(())
+    Ok(old_crates)
     // here, "namespace_of_new_crates is dropped, but its crates have already been added to the current namespace 
 }
 
@@ -0,0 +1,377 @@
+//! Swap transactions, which allow a crate swapping operation to be reverted
+//! if the new crates misbehave after they were swapped in.
+//!
+//! A swap transaction retains the old crates that were swapped out,
+//! as well as the list of `SwapRequest`s that would swap them back in.
+//! After the swap, the transaction is pending for a health-check window,
+//! during which any new entry in the `fault_log` that occurred in one of the new crates
+//! causes the transaction to be rolled back automatically (see [`check_transaction_health()`]).
+//! Each transaction is checked by its own monitor task (see [`monitor_transaction()`]),
+//! so this happens even if the task that started the transaction has exited.
+//! Once the window has passed without faults, the transaction is committed,
+//! but it can still be rolled back explicitly using [`rollback_transaction()`].
+//!
+//! Because each transaction's rollback relies upon the crates that are currently swapped in,
+//! transactions can only be rolled back in the reverse order in which they occurred.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use spin::{Mutex, Once};
+use by_address::ByAddress;
+use memory::MmiRef;
+use mod_mgmt::{
+    CrateNamespace,
+    NamespaceDir,
+    crate_name_from_path,
+    CRATE_HASH_DELIMITER,
+};
+use path::Path;
+use time::{Duration, Instant, Monotonic};
+use super::{SwapRequest, SwapRequestList, swap_crates_internal};
+
+
+/// The unique identifier of a swap transaction.
+pub type TransactionId = usize;
+
+/// The state of a swap transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionState {
+    /// The new crates are in use and are within their health-check window.
+    Pending,
+    /// The health-check window ended without any faults occurring in the new crates.
+    Committed,
+    /// The new crates were swapped out again, and the old crates are back in use.
+    RolledBack,
+    /// Swapping the old crates back in failed, so the transaction can no longer be rolled back.
+    RollbackFailed,
+}
+
+/// A crate swapping operation that can be reverted.
+struct SwapTransaction {
+    id: TransactionId,
+    /// The namespace that `swap_crates()` was invoked on.
+    namespace: Arc<CrateNamespace>,
+    /// The names of the new crates that were swapped in.
+    new_crates: Vec<String>,
+    /// The requests that swap the old crates back in, in place of the new crates.
+    rollback_requests: SwapRequestList,
+    /// The old crates that were swapped out, which are `None` once they have been swapped back in.
+    old_crates: Option<CrateNamespace>,
+    /// The number of faults that were logged in the new crates before they were swapped in.
+    fault_baseline: usize,
+    /// The end of the health-check window.
+    health_check_deadline: Instant,
+    state: TransactionState,
+}
+
+/// Information about a swap transaction, as returned by [`transactions()`].
+#[derive(Clone, Debug)]
+pub struct TransactionInfo {
+    pub id: TransactionId,
+    pub state: TransactionState,
+    /// The names of the new crates that were swapped in.
+    pub new_crates: Vec<String>,
+    /// The names of the old crates that were swapped out and are retained for a rollback.
+    pub old_crates: Vec<String>,
+}
+
+/// The list of all swap transactions, in the order they occurred.
+static TRANSACTIONS: Mutex<Vec<SwapTransaction>> = Mutex::new(Vec::new());
+
+/// The ID that will be given to the next swap transaction.
+static NEXT_TRANSACTION_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// How often the monitor task of a pending transaction checks its new crates for faults.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The function that spawns a task to run [`monitor_transaction()`] with the given arguments.
+pub type SpawnMonitorFunc = fn(TransactionId, MmiRef, bool) -> Result<(), &'static str>;
+
+static SPAWN_MONITOR_FUNC: Once<SpawnMonitorFunc> = Once::new();
+
+/// Sets the function callback that spawns the monitor task of every new swap transaction.
+///
+/// This crate can't spawn tasks itself, because the `spawn` crate depends on it.
+pub fn set_spawn_monitor_cb(func: SpawnMonitorFunc) {
+    SPAWN_MONITOR_FUNC.call_once(|| func);
+}
+
+
+/// Swaps crates just like [`swap_crates()`](../fn.swap_crates.html),
+/// but as a transaction that can be rolled back to the old crates afterwards.
+///
+/// The old crates are always retained, rather than being added to the cache of unloaded crates.
+/// The transaction remains pending for the given `health_check_window`, during which
+/// a newly-spawned monitor task rolls it back if faults occur in the new crates.
+/// Use [`transaction_state()`] to find out whether it was committed or rolled back.
+///
+/// Note that a swap request that only adds a new crate (without an old crate to replace) is not undone by a rollback.
+///
+/// Transactions aren't supported if Theseus is built with the `loscd_eval` option,
+/// as it doesn't retain the old crates.
+///
+/// Returns the ID of the new transaction.
+pub fn swap_crates_transaction(
+    this_namespace: &Arc<CrateNamespace>,
+    swap_requests: SwapRequestList,
+    override_namespace_dir: Option<NamespaceDir>,
+    state_transfer_functions: Vec<String>,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+    health_check_window: Duration,
+) -> Result<TransactionId, &'static str> {
+    if cfg!(loscd_eval) {
+        return Err("swap_crates_transaction(): transactions aren't supported when built with loscd_eval");
+    }
+    let spawn_monitor = *SPAWN_MONITOR_FUNC.get()
+        .ok_or("swap_crates_transaction(): the function that spawns transaction monitors wasn't set")?;
+
+    let new_crates: Vec<String> = swap_requests.iter()
+        .map(|req| crate_name_from_path(&Path::new(req.new_crate_object_file.lock().get_name())).to_string())
+        .collect();
+
+    // Old crates that aren't loaded are only replaced by moving their object files,
+    // so we must find those object files now in order to be able to move them back later.
+    let mut file_rollback_requests = SwapRequestList::new();
+    for (req, new_crate_name) in swap_requests.iter().zip(new_crates.iter()) {
+        let old_crate_name = match req.old_crate_name {
+            Some(ref ocn) => ocn,
+            None => continue,
+        };
+        if CrateNamespace::get_crate_and_namespace(&req.old_namespace, old_crate_name).is_some() {
+            continue;
+        }
+        if let Some((old_crate_file, _ns)) = CrateNamespace::get_crate_object_file_starting_with(&req.old_namespace, old_crate_name) {
+            file_rollback_requests.push(SwapRequest {
+                old_crate_name: Some(new_crate_name.clone()),
+                old_namespace: ByAddress(Arc::clone(&req.new_namespace)),
+                new_crate_object_file: ByAddress(old_crate_file),
+                new_namespace: ByAddress(Arc::clone(&req.old_namespace)),
+                reexport_new_symbols_as_old: false,
+            });
+        }
+    }
+
+    let fault_baseline = count_faults_in(&new_crates);
+    let (mut rollback_requests, old_crates) = swap_crates_internal(
+        this_namespace,
+        swap_requests,
+        override_namespace_dir,
+        state_transfer_functions,
+        kernel_mmi_ref,
+        verbose_log,
+        true,
+        None,
+    )?.ok_or("BUG: swap_crates_transaction(): the old crates weren't retained")?;
+    rollback_requests.extend(file_rollback_requests);
+
+    let id = NEXT_TRANSACTION_ID.fetch_add(1, Ordering::Relaxed);
+    info!("swap_crates_transaction(): started transaction {} with new crates {:?}", id, new_crates);
+    TRANSACTIONS.lock().push(SwapTransaction {
+        id,
+        namespace: Arc::clone(this_namespace),
+        new_crates,
+        rollback_requests,
+        old_crates: Some(old_crates),
+        fault_baseline,
+        health_check_deadline: time::now::<Monotonic>() + health_check_window,
+        state: TransactionState::Pending,
+    });
+
+    if let Err(e) = spawn_monitor(id, Arc::clone(kernel_mmi_ref), verbose_log) {
+        error!("swap_crates_transaction(): couldn't spawn a monitor for transaction {}, rolling it back: {}", id, e);
+        rollback_transaction(id, kernel_mmi_ref, verbose_log)?;
+        return Err(e);
+    }
+    Ok(id)
+}
+
+
+/// The entry function of the task that monitors a pending transaction, see [`set_spawn_monitor_cb()`].
+///
+/// This checks the transaction periodically until it is committed or rolled back.
+pub fn monitor_transaction((id, kernel_mmi_ref, verbose_log): (TransactionId, MmiRef, bool)) {
+    loop {
+        match check_transaction_health(id, &kernel_mmi_ref, verbose_log) {
+            Ok(TransactionState::Pending) => {}
+            Ok(_) => return,
+            Err(e) => {
+                error!("swap_crates: failed to check the health of transaction {}: {}", id, e);
+                return;
+            }
+        }
+        if let Err(_e) = sleep::sleep(HEALTH_CHECK_INTERVAL) {
+            error!("swap_crates: monitor of transaction {} failed to sleep: {:?}", id, _e);
+            return;
+        }
+    }
+}
+
+
+/// Checks whether any faults have occurred in the new crates of the given pending transaction.
+///
+/// This is invoked periodically by the transaction's monitor task.
+/// If faults have occurred, the transaction is rolled back automatically,
+/// along with all later transactions, which must be rolled back first.
+/// If it can't be rolled back, its state becomes [`TransactionState::RollbackFailed`].
+/// Otherwise, the transaction is committed once its health-check window has ended.
+///
+/// Returns the resulting state of the transaction.
+pub fn check_transaction_health(
+    id: TransactionId,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<TransactionState, &'static str> {
+    {
+        let mut transactions = TRANSACTIONS.lock();
+        let transaction = transactions.iter_mut().find(|t| t.id == id).ok_or("no swap transaction with the given ID")?;
+        if transaction.state != TransactionState::Pending {
+            return Ok(transaction.state);
+        }
+        let faults = count_faults_in(&transaction.new_crates);
+        if faults <= transaction.fault_baseline {
+            if time::now::<Monotonic>() >= transaction.health_check_deadline {
+                info!("swap_crates: committed transaction {}, no faults occurred in its new crates", id);
+                transaction.state = TransactionState::Committed;
+            }
+            return Ok(transaction.state);
+        }
+        warn!("swap_crates: {} fault(s) occurred in the new crates of transaction {}, rolling it back",
+            faults - transaction.fault_baseline, id,
+        );
+    }
+
+    // Transactions can only be rolled back in reverse order, so any later transactions must be rolled back first.
+    while let Some(latest) = latest_transaction().filter(|latest| *latest > id) {
+        warn!("swap_crates: rolling back transaction {} in order to roll back transaction {}", latest, id);
+        if let Err(e) = rollback_transaction(latest, kernel_mmi_ref, verbose_log) {
+            error!("swap_crates: couldn't roll back transaction {}, so transaction {} can't be rolled back either: {}",
+                latest, id, e,
+            );
+            fail_pending_rollback(id);
+            return Ok(TransactionState::RollbackFailed);
+        }
+    }
+
+    if let Err(e) = rollback_transaction(id, kernel_mmi_ref, verbose_log) {
+        // Ensure that the transaction doesn't remain pending if it couldn't even begin to be rolled back.
+        fail_pending_rollback(id);
+        return Err(e);
+    }
+    Ok(TransactionState::RolledBack)
+}
+
+
+/// Swaps the old crates of the given transaction back in, in place of its new crates.
+///
+/// Only the most recent transaction that hasn't yet been rolled back can be rolled back.
+pub fn rollback_transaction(
+    id: TransactionId,
+    kernel_mmi_ref: &MmiRef,
+    verbose_log: bool,
+) -> Result<(), &'static str> {
+    let (namespace, rollback_requests, old_crates) = {
+        let mut transactions = TRANSACTIONS.lock();
+        let latest = transactions.iter_mut()
+            .rev()
+            .find(|t| matches!(t.state, TransactionState::Pending | TransactionState::Committed))
+            .ok_or("there is no swap transaction that can be rolled back")?;
+        if latest.id != id {
+            return Err("only the most recent swap transaction can be rolled back");
+        }
+        let old_crates = latest.old_crates.take().ok_or("the swap transaction is already being rolled back")?;
+        (Arc::clone(&latest.namespace), core::mem::take(&mut latest.rollback_requests), old_crates)
+    };
+
+    info!("swap_crates: rolling back transaction {} with requests {:?}", id, rollback_requests);
+    let result = swap_crates_internal(
+        &namespace,
+        rollback_requests,
+        None,
+        Vec::new(),
+        kernel_mmi_ref,
+        verbose_log,
+        false,
+        Some(old_crates),
+    );
+
+    let mut transactions = TRANSACTIONS.lock();
+    if let Some(transaction) = transactions.iter_mut().find(|t| t.id == id) {
+        transaction.state = match result {
+            Ok(_) => TransactionState::RolledBack,
+            Err(_) => TransactionState::RollbackFailed,
+        };
+    }
+    result.map(|_| ())
+}
+
+
+/// Marks the given transaction as [`TransactionState::RollbackFailed`] if it is still pending,
+/// such that it isn't kept pending forever after it couldn't be rolled back.
+fn fail_pending_rollback(id: TransactionId) {
+    if let Some(transaction) = TRANSACTIONS.lock().iter_mut().find(|t| t.id == id) {
+        if transaction.state == TransactionState::Pending {
+            transaction.state = TransactionState::RollbackFailed;
+        }
+    }
+}
+
+
+/// Returns the current state of the given transaction, or `None` if there is no such transaction.
+pub fn transaction_state(id: TransactionId) -> Option<TransactionState> {
+    TRANSACTIONS.lock().iter().find(|t| t.id == id).map(|t| t.state)
+}
+
+
+/// Returns the ID of the most recent transaction that can be rolled back, if any.
+pub fn latest_transaction() -> Option<TransactionId> {
+    TRANSACTIONS.lock().iter()
+        .rev()
+        .find(|t| matches!(t.state, TransactionState::Pending | TransactionState::Committed))
+        .map(|t| t.id)
+}
+
+
+/// Returns information about all swap transactions, in the order they occurred.
+pub fn transactions() -> Vec<TransactionInfo> {
+    TRANSACTIONS.lock().iter()
+        .map(|t| TransactionInfo {
+            id: t.id,
+            state: t.state,
+            new_crates: t.new_crates.clone(),
+            old_crates: t.old_crates.as_ref()
+                .map(|c| c.crate_names(false).iter().map(|n| n.to_string()).collect())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+
+/// Discards all transactions that are no longer pending, along with the old crates they retained,
+/// such that they can no longer be rolled back.
+///
+/// Pending transactions, and any transactions that follow them, are kept.
+pub fn clear_transaction_history() {
+    let mut transactions = TRANSACTIONS.lock();
+    let first_pending = transactions.iter()
+        .position(|t| t.state == TransactionState::Pending)
+        .unwrap_or(transactions.len());
+    transactions.drain(..first_pending);
+}
+
+
+/// Returns the number of entries in the fault log that occurred in one of the given crates.
+///
+/// The crate in which a panic occurred is only known by its name without a hash.
+fn count_faults_in(crate_names: &[String]) -> usize {
+    fault_log::fault_entries().iter()
+        .filter_map(|fe| fe.crate_error_occured.as_deref())
+        .filter(|faulty_crate| crate_names.iter().any(|name|
+            name == faulty_crate || name.split(CRATE_HASH_DELIMITER).next() == Some(*faulty_crate)
+        ))
+        .count()
+}
@@ -5,9 +5,14 @@
 extern crate spin;
 extern crate event_types;
 extern crate e1000;
+extern crate virtio_net;
+extern crate dhcp_client;
+extern crate slaac;
 extern crate memory;
 extern crate apic;
 extern crate acpi;
+extern crate rtc;
+extern crate time;
 extern crate serial_port;
 extern crate console;
 extern crate logger_x86_64 as logger;
@@ -19,11 +24,8 @@ extern crate network_manager;
 extern crate ethernet_smoltcp_device;
 extern crate mpmc;
 extern crate ixgbe;
-extern crate alloc;
-extern crate fatfs;
-extern crate io;
-extern crate core2;
-#[macro_use] extern crate derive_more;
+#[macro_use] extern crate alloc;
+extern crate fat_fs;
 extern crate mlx5;
 extern crate net;
 
@@ -34,24 +36,26 @@ use memory::MemoryManagementInfo;
 use ethernet_smoltcp_device::EthernetNetworkInterface;
 use network_manager::add_to_network_interfaces;
 use alloc::vec::Vec;
-use io::{ByteReaderWriterWrapper, LockableIo, ReaderWriter};
 use serial_port::{SerialPortAddress, take_serial_port_basic};
-use storage_manager::StorageDevice;
 use memory::PhysicalAddress;
 
+pub use fat_fs::{FatFsAdapter, FatFsIoErrorAdapter};
+
 /// A randomly chosen IP address that must be outside of the DHCP range.
-/// TODO: use DHCP to acquire an IP address.
+///
+/// This is only used by the legacy `network_manager` interfaces;
+/// interfaces registered with the `net` crate are configured by `dhcp_client`.
 const DEFAULT_LOCAL_IP: &str = "10.0.2.15/24"; // the default QEMU user-slirp network gives IP addresses of "10.0.2.*"
 
-/// Standard home router address.
-/// TODO: use DHCP to acquire gateway IP
+/// Standard home router address, only used by the legacy `network_manager` interfaces.
 const DEFAULT_GATEWAY_IP: [u8; 4] = [10, 0, 2, 2]; // the default QEMU user-slirp networking gateway IP
 
 /// Performs early-stage initialization for simple devices needed during early boot.
 ///
 /// This includes:
 /// * local APICs ([`apic`]),
-/// * [`acpi`] tables for system configuration info, including the IOAPIC.
+/// * [`acpi`] tables for system configuration info, including the IOAPIC,
+/// * the [`rtc`] as the source of wall-clock [`time`].
 pub fn early_init(
     rsdp_address: Option<PhysicalAddress>,
     kernel_mmi: &mut MemoryManagementInfo
@@ -63,6 +67,9 @@ pub fn early_init(
     // Then, parse the ACPI tables to acquire system configuration info.
     acpi::init(rsdp_address, &mut kernel_mmi.page_table)?;
 
+    // Use the real-time clock as the source of wall-clock time, e.g., for file timestamps.
+    time::register_clock_source::<rtc::RtcClock>(time::Period::new(rtc::RTC_CLOCK_PERIOD_FEMTOSECONDS));
+
     Ok(())
 }
 
@@ -142,7 +149,7 @@ pub fn init(key_producer: Queue<Event>, mouse_producer: Queue<Event>) -> Result<
             if dev.vendor_id == e1000::INTEL_VEND && dev.device_id == e1000::E1000_DEV {
                 info!("e1000 PCI device found at: {:?}", dev.location);
                 let nic = e1000::E1000Nic::init(dev)?;
-                let interface = net::register_device(nic);
+                let interface = net::register_device(nic)?;
                 nic.lock().init_interrupts(interface)?;
 
                 let e1000_interface = EthernetNetworkInterface::new_ipv4_interface(nic, DEFAULT_LOCAL_IP, &DEFAULT_GATEWAY_IP)?;
@@ -184,6 +191,18 @@ pub fn init(key_producer: Queue<Event>, mouse_producer: Queue<Event>) -> Result<
                 continue;
             }
 
+            if virtio_net::is_virtio_net(dev) {
+                info!("virtio-net PCI device found at: {:?}", dev.location);
+                let nic = virtio_net::VirtioNetNic::init(dev)?;
+                let interface = net::register_device(nic)?;
+                nic.lock().init_interrupts(interface)?;
+
+                let virtio_net_interface = EthernetNetworkInterface::new_ipv4_interface(nic, DEFAULT_LOCAL_IP, &DEFAULT_GATEWAY_IP)?;
+                add_to_network_interfaces(virtio_net_interface);
+
+                continue;
+            }
+
             // here: check for and initialize other ethernet cards
         }
 
@@ -199,114 +218,34 @@ pub fn init(key_producer: Queue<Event>, mouse_producer: Queue<Event>) -> Result<
             &DEFAULT_GATEWAY_IP
         )?;
         add_to_network_interfaces(ixgbe_interface);
-        net::register_device(ixgbe_nic_ref);
+        net::register_device(ixgbe_nic_ref)?;
     }
 
+    // Acquire an address for each interface registered with the `net` crate,
+    // which falls back to a static configuration if no DHCP server answers.
+    dhcp_client::start_all()?;
+    // Each interface also gets a link-local IPv6 address, and global IPv6 addresses
+    // for the prefixes advertised by routers on its link.
+    slaac::start_all()?;
+
+    // The loopback interface is registered last so that it never becomes the default interface
+    // and isn't configured by a DHCP client.
+    loopback::init()?;
+
     // Convenience notification for developers to inform them of no networking devices
     if network_manager::NETWORK_INTERFACES.lock().is_empty() {
         warn!("Note: no network devices found on this system.");
     }
 
     // Discover filesystems from each storage device on the storage controllers initialized above
-    // and mount each filesystem to the root directory by default.
-    if false {
-        for storage_device in storage_manager::storage_devices() {
-            let disk = FatFsAdapter(
-                ReaderWriter::new(
-                    ByteReaderWriterWrapper::from(
-                        LockableIo::<dyn StorageDevice + Send, spin::Mutex<_>, _>::from(storage_device)
-                    )
-                ),
-            );
-
-            if let Ok(filesystem) = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()) {
-                debug!("FATFS data:
-                    fat_type: {:?},
-                    volume_id: {:X?},
-                    volume_label: {:?},
-                    cluster_size: {:?},
-                    status_flags: {:?},
-                    stats: {:?}",
-                    filesystem.fat_type(),
-                    filesystem.volume_id(),
-                    filesystem.volume_label(),
-                    filesystem.cluster_size(),
-                    filesystem.read_status_flags(),
-                    filesystem.stats(),
-                );
-
-                let root = filesystem.root_dir();
-                debug!("Root directory contents:");
-                for f in root.iter() {
-                    debug!("\t {:X?}", f.map(|entry| (entry.file_name(), entry.attributes(), entry.len())));
-                }
-            }
+    // and mount each filesystem into the mount directory, e.g., `/mnt/disk0`.
+    fat_fs::init()?;
+    for i in 0 .. storage_manager::storage_devices().count() {
+        let name = format!("{}{}", storage_manager::STORAGE_DEVICE_NAME_PREFIX, i);
+        if let Err(e) = fat_fs::mount_storage_device(&name) {
+            debug!("No filesystem mounted from storage device {}: {}", name, e);
         }
     }
 
     Ok(())
 }
-
-// TODO: move the following `FatFsAdapter` stuff into a separate crate. 
-
-/// An adapter (wrapper type) that implements traits required by the [`fatfs`] crate
-/// for any I/O device that wants to be usable by [`fatfs`].
-///
-/// To meet [`fatfs`]'s requirements, the underlying I/O stream must be able to 
-/// read, write, and seek while tracking its current offset. 
-/// We use traits from the [`core2`] crate to meet these requirements, 
-/// thus, the given `IO` parameter must implement those [`core2`] traits.
-///
-/// For example, this allows one to access a FAT filesystem 
-/// by reading from or writing to a storage device.
-pub struct FatFsAdapter<IO>(IO);
-impl<IO> FatFsAdapter<IO> {
-    pub fn new(io: IO) -> FatFsAdapter<IO> { FatFsAdapter(io) }
-}
-/// This tells the `fatfs` crate that our read/write/seek functions
-/// may return errors of the type [`FatFsIoErrorAdapter`],
-/// which is a simple wrapper around [`core2::io::Error`].
-impl<IO> fatfs::IoBase for FatFsAdapter<IO> {
-    type Error = FatFsIoErrorAdapter;
-}
-impl<IO> fatfs::Read for FatFsAdapter<IO> where IO: core2::io::Read {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        self.0.read(buf).map_err(Into::into)
-    }
-}
-impl<IO> fatfs::Write for FatFsAdapter<IO> where IO: core2::io::Write {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.0.write(buf).map_err(Into::into)
-    }
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        self.0.flush().map_err(Into::into)
-    }
-}
-impl<IO> fatfs::Seek for FatFsAdapter<IO> where IO: core2::io::Seek {
-    fn seek(&mut self, pos: fatfs::SeekFrom) -> Result<u64, Self::Error> {
-        let core2_pos = match pos {
-            fatfs::SeekFrom::Start(s)   => core2::io::SeekFrom::Start(s),
-            fatfs::SeekFrom::Current(c) => core2::io::SeekFrom::Current(c),
-            fatfs::SeekFrom::End(e)     => core2::io::SeekFrom::End(e),
-        };
-        self.0.seek(core2_pos).map_err(Into::into)
-    }
-}
-
-/// This struct exists to enable us to implement the [`fatfs::IoError`] trait
-/// for the [`core2::io::Error`] trait.
-/// 
-/// This is required because Rust prevents implementing foreign traits for foreign types.
-#[derive(Debug, From, Into)]
-pub struct FatFsIoErrorAdapter(core2::io::Error);
-impl fatfs::IoError for FatFsIoErrorAdapter {
-    fn is_interrupted(&self) -> bool {
-        self.0.kind() == core2::io::ErrorKind::Interrupted
-    }
-    fn new_unexpected_eof_error() -> Self {
-        FatFsIoErrorAdapter(core2::io::ErrorKind::UnexpectedEof.into())
-    }
-    fn new_write_zero_error() -> Self {
-        FatFsIoErrorAdapter(core2::io::ErrorKind::WriteZero.into())
-    }
-}
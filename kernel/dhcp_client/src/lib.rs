@@ -0,0 +1,148 @@
+//! A DHCPv4 client that automatically configures a network interface.
+//!
+//! Each interface gets its own client task, which acquires an IPv4 address,
+//! a default gateway, and DNS servers from a DHCP server, and renews its lease
+//! before it expires.
+//!
+//! If no DHCP server answers within [`STATIC_FALLBACK_TIMEOUT`], the interface
+//! is given a static configuration that works on QEMU's user-mode (slirp) network.
+//! The client keeps running afterwards, so a lease acquired later replaces
+//! the static configuration.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, sync::Arc, vec::Vec};
+use log::{error, info, warn};
+use net::{
+    dhcpv4,
+    wire::{Ipv4Address, Ipv4Cidr},
+    IpAddress, NetworkInterface,
+};
+use task::JoinableTaskRef;
+use time::{Duration, Instant, Monotonic};
+
+/// How long to wait for a DHCP server before falling back to the static configuration.
+pub const STATIC_FALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the client checks its socket for DHCP messages and lease timeouts.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The static IPv4 address used if no DHCP server answers.
+///
+/// The default QEMU user-slirp network gives IP addresses of `10.0.2.*`,
+/// and this one is outside of its DHCP range.
+const STATIC_LOCAL_IP: Ipv4Cidr = Ipv4Cidr::new(Ipv4Address::new(10, 0, 2, 15), 24);
+
+/// The static gateway used if no DHCP server answers.
+///
+/// `10.0.2.2` is the default QEMU user-slirp networking gateway IP.
+const STATIC_GATEWAY_IP: Ipv4Address = Ipv4Address::new(10, 0, 2, 2);
+
+/// The static DNS server used if no DHCP server answers.
+///
+/// `10.0.2.3` is the default QEMU user-slirp DNS server IP.
+const STATIC_DNS_SERVER_IP: Ipv4Address = Ipv4Address::new(10, 0, 2, 3);
+
+/// The source of an interface's current IPv4 configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ConfigSource {
+    /// The interface hasn't been configured yet.
+    None,
+    /// The interface is using the static fallback configuration.
+    Static,
+    /// The interface is using a lease from a DHCP server.
+    Dhcp,
+}
+
+/// A change to the DHCP lease, copied out of a `dhcpv4::Event`.
+enum LeaseEvent {
+    Acquired {
+        address: Ipv4Cidr,
+        router: Option<Ipv4Address>,
+        dns_servers: Vec<IpAddress>,
+    },
+    Lost,
+}
+
+/// Spawns a DHCP client task for the given `interface`.
+///
+/// The task runs for as long as the interface exists.
+pub fn start(interface: Arc<NetworkInterface>) -> Result<JoinableTaskRef, &'static str> {
+    let name = format!("dhcp_client_{:#X}", Arc::as_ptr(&interface) as usize);
+    spawn::new_task_builder(dhcp_client_task, interface)
+        .name(name)
+        .spawn()
+}
+
+/// Spawns a DHCP client task for every network interface registered with the `net` crate.
+pub fn start_all() -> Result<Vec<JoinableTaskRef>, &'static str> {
+    let interfaces = net::get_interfaces().lock().clone();
+    interfaces.into_iter().map(start).collect()
+}
+
+/// The entry point of a DHCP client task.
+fn dhcp_client_task(interface: Arc<NetworkInterface>) {
+    let socket = interface.add_socket(dhcpv4::Socket::new());
+    let mut start_time = time::now::<Monotonic>();
+    let mut source = ConfigSource::None;
+
+    loop {
+        // The interface's worker task polls the interface once the socket is unlocked,
+        // so the lease is copied out of the event before it is applied.
+        let event = match socket.lock().poll() {
+            Some(dhcpv4::Event::Configured(config)) => Some(LeaseEvent::Acquired {
+                address: config.address,
+                router: config.router,
+                dns_servers: config.dns_servers.iter().map(|&s| IpAddress::Ipv4(s)).collect(),
+            }),
+            Some(dhcpv4::Event::Deconfigured) => Some(LeaseEvent::Lost),
+            None => None,
+        };
+
+        match event {
+            Some(LeaseEvent::Acquired { address, router, dns_servers }) => {
+                info!(
+                    "dhcp_client: acquired lease: address {}, gateway {:?}, DNS servers {:?}",
+                    address, router, dns_servers
+                );
+                interface.set_ipv4_config(Some(address), router);
+                interface.set_dns_servers(&dns_servers);
+                source = ConfigSource::Dhcp;
+            }
+            Some(LeaseEvent::Lost) => {
+                // The lease expired without being renewed, so the address can no longer be used.
+                // The client restarts discovery, and the static fallback applies again if no server answers.
+                if source == ConfigSource::Dhcp {
+                    warn!("dhcp_client: lease lost, deconfiguring interface");
+                    interface.set_ipv4_config(None, None);
+                    interface.set_dns_servers(&[]);
+                    source = ConfigSource::None;
+                    start_time = time::now::<Monotonic>();
+                }
+            }
+            None => {}
+        }
+
+        if source == ConfigSource::None && waited_for_server(start_time) {
+            warn!(
+                "dhcp_client: no DHCP server answered, using static address {} and gateway {}",
+                STATIC_LOCAL_IP, STATIC_GATEWAY_IP
+            );
+            interface.set_ipv4_config(Some(STATIC_LOCAL_IP), Some(STATIC_GATEWAY_IP));
+            interface.set_dns_servers(&[IpAddress::Ipv4(STATIC_DNS_SERVER_IP)]);
+            source = ConfigSource::Static;
+        }
+
+        if let Err(e) = sleep::sleep(POLL_INTERVAL) {
+            error!("dhcp_client: couldn't sleep, run state: {:?}", e);
+            return;
+        }
+    }
+}
+
+/// Returns `true` if the client has waited long enough for a DHCP server to answer.
+fn waited_for_server(start_time: Instant) -> bool {
+    time::now::<Monotonic>().duration_since(start_time) >= STATIC_FALLBACK_TIMEOUT
+}
@@ -0,0 +1,119 @@
+//! Finding the DNS servers to query.
+
+use crate::RESOLV_CONF_PATH;
+use alloc::{string::ToString, vec, vec::Vec};
+use core::str::FromStr;
+use fs_node::FileOrDir;
+use log::warn;
+use net::IpAddress;
+use path::Path;
+
+/// Returns the DNS servers to query, in order of preference.
+///
+/// The servers listed in [`RESOLV_CONF_PATH`] take precedence over those
+/// that the network interfaces were configured with.
+pub(crate) fn name_servers() -> Vec<IpAddress> {
+    let configured = read_resolv_conf();
+    if !configured.is_empty() {
+        return configured;
+    }
+
+    let mut servers = Vec::new();
+    let interfaces = net::get_interfaces().lock().clone();
+    for interface in interfaces {
+        for server in interface.dns_servers() {
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
+        }
+    }
+    servers
+}
+
+/// Reads the DNS servers from the config file, if it exists.
+fn read_resolv_conf() -> Vec<IpAddress> {
+    let Some(FileOrDir::File(file)) = Path::get_absolute(&Path::new(RESOLV_CONF_PATH.to_string())) else {
+        return Vec::new();
+    };
+    let file = file.lock();
+    let len = file.len();
+    // Reading at the end of a file is an error, so an empty file must be handled separately.
+    if len == 0 {
+        return Vec::new();
+    }
+    let mut contents = vec![0; len];
+    if let Err(e) = file.read_at(&mut contents, 0) {
+        warn!("dns: couldn't read {}: {:?}", RESOLV_CONF_PATH, e);
+        return Vec::new();
+    }
+    match core::str::from_utf8(&contents) {
+        Ok(contents) => parse_resolv_conf(contents),
+        Err(_) => {
+            warn!("dns: {} isn't valid UTF-8", RESOLV_CONF_PATH);
+            Vec::new()
+        }
+    }
+}
+
+/// Parses the `nameserver <address>` lines of a `resolv.conf`-style file.
+///
+/// Other options are ignored, as are comments starting with `#` or `;`.
+fn parse_resolv_conf(contents: &str) -> Vec<IpAddress> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(['#', ';']).next()?;
+            let mut words = line.split_whitespace();
+            if words.next()? != "nameserver" {
+                return None;
+            }
+            let address = words.next()?;
+            let server = IpAddress::from_str(address).ok();
+            if server.is_none() {
+                warn!("dns: ignoring invalid name server address {:?}", address);
+            }
+            server
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use net::wire::Ipv4Address;
+
+    fn ipv4(a: u8, b: u8, c: u8, d: u8) -> IpAddress {
+        IpAddress::Ipv4(Ipv4Address::new(a, b, c, d))
+    }
+
+    #[test]
+    fn parse_name_servers_in_order() {
+        let contents = "nameserver 10.0.0.1\nnameserver 1.1.1.1\n";
+        assert_eq!(parse_resolv_conf(contents), [ipv4(10, 0, 0, 1), ipv4(1, 1, 1, 1)]);
+    }
+
+    #[test]
+    fn parse_ignores_comments_and_options() {
+        let contents = "# a comment\n\
+            ; another comment\n\
+            search example.com\n\
+            options ndots:2\n\
+            nameserver 8.8.8.8 # trailing comment\n\
+            # nameserver 9.9.9.9\n\
+            \tnameserver\t8.8.4.4;trailing comment\n";
+        assert_eq!(parse_resolv_conf(contents), [ipv4(8, 8, 8, 8), ipv4(8, 8, 4, 4)]);
+    }
+
+    #[test]
+    fn parse_ignores_invalid_lines() {
+        let contents = "nameserver\nnameserver not.an.address\nnameserver 300.1.1.1\nnameservers 10.0.0.2\nnameserver 10.0.0.3";
+        assert_eq!(parse_resolv_conf(contents), [ipv4(10, 0, 0, 3)]);
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(parse_resolv_conf("").is_empty());
+        assert!(parse_resolv_conf("\n\n  \n").is_empty());
+    }
+}
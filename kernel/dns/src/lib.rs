@@ -0,0 +1,198 @@
+//! A DNS stub resolver.
+//!
+//! [`lookup_host`] resolves host names to IPv4 addresses by sending recursive queries
+//! to DNS servers, and caches their answers for as long as the answers' TTL allows.
+//!
+//! The DNS servers are read from [`RESOLV_CONF_PATH`] if that file exists,
+//! which uses the same `nameserver <address>` lines as `/etc/resolv.conf` on Unix-like systems.
+//! Otherwise, the DNS servers that each network interface was configured with,
+//! e.g., by its DHCP client, are used.
+
+#![no_std]
+
+extern crate alloc;
+
+mod config;
+mod message;
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::str::FromStr;
+use log::debug;
+use message::Answer;
+use net::{
+    wire::{IpEndpoint, Ipv4Address},
+    UdpSocket,
+};
+use spin::Mutex;
+use time::{Duration, Instant, Monotonic};
+
+pub use net::IpAddress;
+
+/// The path of the optional file that lists the DNS servers to use.
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// The UDP port that DNS servers listen on.
+const DNS_PORT: u16 = 53;
+/// How long to wait for a DNS server to respond to a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many times a query is sent to each DNS server before trying the next one.
+const ATTEMPTS_PER_SERVER: usize = 2;
+/// The largest DNS response over UDP without extensions.
+const MAX_RESPONSE_LEN: usize = 512;
+
+/// The maximum number of names in the cache.
+const MAX_CACHE_ENTRIES: usize = 64;
+/// The longest an answer is cached, regardless of its TTL.
+const MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to remember that a name doesn't exist.
+const NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+const NOT_FOUND: &str = "host not found";
+
+/// A cached answer.
+struct CacheEntry {
+    /// The addresses of the name, which is empty if the name doesn't exist.
+    addresses: Vec<IpAddress>,
+    expires: Instant,
+}
+
+/// Cached answers, keyed by lowercase host name.
+static CACHE: Mutex<BTreeMap<String, CacheEntry>> = Mutex::new(BTreeMap::new());
+
+/// Resolves the given `host` to its IP addresses.
+///
+/// `host` may also be an IP address literal, which is returned as is,
+/// or `localhost`, which resolves to the loopback address.
+/// Otherwise, a cached answer is returned if it hasn't expired yet,
+/// and the configured DNS servers are queried if not.
+pub fn lookup_host(host: &str) -> Result<Vec<IpAddress>, &'static str> {
+    if let Ok(address) = IpAddress::from_str(host) {
+        return Ok(vec![address]);
+    }
+
+    let name = host.trim_end_matches('.').to_ascii_lowercase();
+    if name == "localhost" {
+        return Ok(vec![IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1))]);
+    }
+
+    if let Some(addresses) = lookup_cache(&name) {
+        return if addresses.is_empty() { Err(NOT_FOUND) } else { Ok(addresses) };
+    }
+
+    let (addresses, ttl) = match query(&name)? {
+        Answer::Addresses { addresses, ttl } => {
+            (addresses, Duration::from_secs(ttl.into()).min(MAX_TTL))
+        }
+        Answer::NotFound => (Vec::new(), NEGATIVE_TTL),
+    };
+    debug!("dns: resolved {:?} to {:?} for {:?}", name, addresses, ttl);
+    insert_cache(name, addresses.clone(), ttl);
+
+    if addresses.is_empty() {
+        Err(NOT_FOUND)
+    } else {
+        Ok(addresses)
+    }
+}
+
+/// Removes all cached answers, e.g., after the DNS servers were changed.
+pub fn flush_cache() {
+    CACHE.lock().clear();
+}
+
+/// Returns the cached addresses of `name`, if they haven't expired.
+fn lookup_cache(name: &str) -> Option<Vec<IpAddress>> {
+    let mut cache = CACHE.lock();
+    let entry = cache.get(name)?;
+    if entry.expires > time::now::<Monotonic>() {
+        Some(entry.addresses.clone())
+    } else {
+        cache.remove(name);
+        None
+    }
+}
+
+fn insert_cache(name: String, addresses: Vec<IpAddress>, ttl: Duration) {
+    let now = time::now::<Monotonic>();
+    let mut cache = CACHE.lock();
+    cache.retain(|_, entry| entry.expires > now);
+    if cache.len() >= MAX_CACHE_ENTRIES && !cache.contains_key(&name) {
+        // Evict the answer that would have expired the soonest.
+        let soonest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires)
+            .map(|(name, _)| name.clone());
+        if let Some(soonest) = soonest {
+            cache.remove(&soonest);
+        }
+    }
+    cache.insert(name, CacheEntry { addresses, expires: now + ttl });
+}
+
+/// Queries the configured DNS servers for the IPv4 addresses of `name`,
+/// trying each one in turn until one of them answers.
+fn query(name: &str) -> Result<Answer, &'static str> {
+    let servers = config::name_servers();
+    if servers.is_empty() {
+        return Err("no DNS servers are configured");
+    }
+
+    let id = random::next_u32() as u16;
+    let query = message::encode_query(id, name, message::TYPE_A)?;
+
+    let mut result = Err("DNS servers didn't respond");
+    for server in servers {
+        for _ in 0..ATTEMPTS_PER_SERVER {
+            match query_server(server, &query, id) {
+                Ok(answer) => return Ok(answer),
+                Err(e) => {
+                    debug!("dns: query for {:?} to {} failed: {}", name, server, e);
+                    result = Err(e);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Sends the given `query` to the DNS server at `server` and waits for its response.
+///
+/// The query is sent from the interface that can reach the server, e.g., the loopback interface
+/// for a server at `127.0.0.1`, and times out after [`QUERY_TIMEOUT`] in total.
+fn query_server(server: IpAddress, query: &[u8], id: u16) -> Result<Answer, &'static str> {
+    let interface = net::get_interface_for(server).ok_or("no network interface can reach the DNS server")?;
+    // A socket is bound to the interface that its local address belongs to.
+    let local_addr = interface
+        .ip_addrs()
+        .iter()
+        .map(|cidr| cidr.address())
+        .find(|addr| matches!(
+            (addr, server),
+            (IpAddress::Ipv4(_), IpAddress::Ipv4(_)) | (IpAddress::Ipv6(_), IpAddress::Ipv6(_))
+        ))
+        .unwrap_or(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED));
+    let socket = UdpSocket::bind(IpEndpoint::new(local_addr, 0)).map_err(|_| "couldn't bind a UDP socket")?;
+    socket
+        .send_to(query, IpEndpoint::new(server, DNS_PORT))
+        .map_err(|_| "couldn't send DNS query")?;
+
+    let deadline = time::now::<Monotonic>() + QUERY_TIMEOUT;
+    let mut buffer = [0; MAX_RESPONSE_LEN];
+    loop {
+        let remaining = deadline
+            .checked_duration_since(time::now::<Monotonic>())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or("DNS query timed out")?;
+        socket.set_read_timeout(Some(remaining));
+        let (len, sender) = socket.recv_from(&mut buffer).map_err(|e| match e {
+            net::Error::TimedOut => "DNS query timed out",
+            _ => "couldn't receive DNS response",
+        })?;
+        let response = &buffer[..len];
+        // Ignore stray datagrams, e.g., late responses to an earlier attempt.
+        if sender.addr != server || message::id(response) != Some(id) {
+            continue;
+        }
+        return message::decode_response(response);
+    }
+}
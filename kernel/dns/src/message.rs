@@ -0,0 +1,294 @@
+//! Encoding of DNS queries and decoding of DNS responses, as defined in RFC 1035.
+
+use alloc::vec::Vec;
+use net::wire::{IpAddress, Ipv4Address};
+
+/// The length of a DNS message header.
+const HEADER_LEN: usize = 12;
+const FLAG_RESPONSE: u16 = 1 << 15;
+const FLAG_TRUNCATED: u16 = 1 << 9;
+const FLAG_RECURSION_DESIRED: u16 = 1 << 8;
+const RCODE_MASK: u16 = 0xF;
+const RCODE_NO_ERROR: u16 = 0;
+const RCODE_NAME_ERROR: u16 = 3;
+
+/// The record type of an IPv4 host address.
+pub(crate) const TYPE_A: u16 = 1;
+/// The Internet class.
+const CLASS_IN: u16 = 1;
+
+const MAX_NAME_LEN: usize = 255;
+const MAX_LABEL_LEN: usize = 63;
+/// The two high bits of a label length that mark a compression pointer.
+const POINTER_MASK: u8 = 0xC0;
+
+/// The answer that a DNS server gave to a query.
+pub(crate) enum Answer {
+    /// The name has the given addresses, which may be cached for `ttl` seconds.
+    Addresses { addresses: Vec<IpAddress>, ttl: u32 },
+    /// The name doesn't exist or has no addresses.
+    NotFound,
+}
+
+/// Encodes a recursive query for records of type `qtype` for the given `name`.
+pub(crate) fn encode_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>, &'static str> {
+    let mut query = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // One question, and no answer, authority, or additional records.
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    let name = name.trim_end_matches('.');
+    if name.len() + 2 > MAX_NAME_LEN {
+        return Err("host name is too long");
+    }
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL_LEN {
+            return Err("host name has an invalid label");
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Returns the ID of the given DNS message.
+pub(crate) fn id(message: &[u8]) -> Option<u16> {
+    read_u16(message, 0)
+}
+
+/// Decodes a response, collecting the IPv4 addresses from its answer records.
+///
+/// Records for aliases (CNAMEs) are skipped, as recursive servers include
+/// the addresses of the canonical name in the same response.
+pub(crate) fn decode_response(response: &[u8]) -> Result<Answer, &'static str> {
+    const TOO_SHORT: &str = "DNS response is too short";
+
+    let flags = read_u16(response, 2).ok_or(TOO_SHORT)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return Err("DNS message isn't a response");
+    }
+    if flags & FLAG_TRUNCATED != 0 {
+        return Err("DNS response was truncated");
+    }
+    match flags & RCODE_MASK {
+        RCODE_NO_ERROR => {}
+        RCODE_NAME_ERROR => return Ok(Answer::NotFound),
+        _ => return Err("DNS server failed to answer the query"),
+    }
+
+    let question_count = read_u16(response, 4).ok_or(TOO_SHORT)?;
+    let answer_count = read_u16(response, 6).ok_or(TOO_SHORT)?;
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..question_count {
+        // Skip the name, type, and class.
+        offset = skip_name(response, offset).ok_or(TOO_SHORT)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+    let mut ttl = u32::MAX;
+    for _ in 0..answer_count {
+        offset = skip_name(response, offset).ok_or(TOO_SHORT)?;
+        let record_type = read_u16(response, offset).ok_or(TOO_SHORT)?;
+        let class = read_u16(response, offset + 2).ok_or(TOO_SHORT)?;
+        let record_ttl = read_u32(response, offset + 4).ok_or(TOO_SHORT)?;
+        let data_len = read_u16(response, offset + 8).ok_or(TOO_SHORT)? as usize;
+        offset += 10;
+        let data = response.get(offset..offset + data_len).ok_or(TOO_SHORT)?;
+        offset += data_len;
+
+        if record_type == TYPE_A && class == CLASS_IN && data.len() == 4 {
+            addresses.push(IpAddress::Ipv4(Ipv4Address::from_bytes(data)));
+            ttl = ttl.min(record_ttl);
+        }
+    }
+
+    if addresses.is_empty() {
+        Ok(Answer::NotFound)
+    } else {
+        Ok(Answer::Addresses { addresses, ttl })
+    }
+}
+
+/// Returns the offset just past the (possibly compressed) name that starts at `offset`.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(offset)?;
+        if len & POINTER_MASK == POINTER_MASK {
+            // A pointer ends the name; where it points to doesn't matter here.
+            return Some(offset + 2);
+        } else if len == 0 {
+            return Some(offset + 1);
+        }
+        offset += 1 + len as usize;
+    }
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    let bytes = message.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(message: &[u8], offset: usize) -> Option<u32> {
+    let bytes = message.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use alloc::vec;
+
+    const TYPE_CNAME: u16 = 5;
+    const TYPE_AAAA: u16 = 28;
+
+    /// Returns a response header with the given flags and numbers of questions and answers.
+    fn header(flags: u16, question_count: u16, answer_count: u16) -> Vec<u8> {
+        let mut message = vec![0x12, 0x34];
+        message.extend_from_slice(&(FLAG_RESPONSE | flags).to_be_bytes());
+        message.extend_from_slice(&question_count.to_be_bytes());
+        message.extend_from_slice(&answer_count.to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        message
+    }
+
+    /// Appends the question for an A record of `example.com`, whose name starts at offset 12.
+    fn push_question(message: &mut Vec<u8>) {
+        message.extend_from_slice(b"\x07example\x03com\x00");
+        message.extend_from_slice(&TYPE_A.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+
+    /// Appends an answer record whose name is a pointer to the question's name.
+    fn push_answer(message: &mut Vec<u8>, record_type: u16, ttl: u32, data: &[u8]) {
+        message.extend_from_slice(&[POINTER_MASK, HEADER_LEN as u8]);
+        message.extend_from_slice(&record_type.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+        message.extend_from_slice(&ttl.to_be_bytes());
+        message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        message.extend_from_slice(data);
+    }
+
+    fn addresses(answer: Answer) -> Option<(Vec<IpAddress>, u32)> {
+        match answer {
+            Answer::Addresses { addresses, ttl } => Some((addresses, ttl)),
+            Answer::NotFound => None,
+        }
+    }
+
+    #[test]
+    fn encode_query_labels() {
+        let query = encode_query(0xABCD, "www.example.com.", TYPE_A).unwrap();
+        assert_eq!(id(&query), Some(0xABCD));
+        assert_eq!(read_u16(&query, 2), Some(FLAG_RECURSION_DESIRED));
+        assert_eq!(read_u16(&query, 4), Some(1));
+        assert_eq!(&query[HEADER_LEN..], b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+    }
+
+    #[test]
+    fn encode_query_invalid_names() {
+        assert!(encode_query(0, "", TYPE_A).is_err());
+        assert!(encode_query(0, "a..b", TYPE_A).is_err());
+        assert!(encode_query(0, &"a".repeat(MAX_LABEL_LEN + 1), TYPE_A).is_err());
+        let long_name = vec!["a".repeat(MAX_LABEL_LEN); 4].join(".");
+        assert!(encode_query(0, &long_name, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn skip_name_labels_and_pointers() {
+        let message = b"\x07example\x03com\x00\xC0\x00\x03www\xC0\x00";
+        assert_eq!(skip_name(message, 0), Some(13));
+        assert_eq!(skip_name(message, 13), Some(15));
+        assert_eq!(skip_name(message, 15), Some(21));
+    }
+
+    #[test]
+    fn skip_name_pointer_loop() {
+        // A pointer to itself must not be followed.
+        let message = b"\xC0\x00";
+        assert_eq!(skip_name(message, 0), Some(2));
+    }
+
+    #[test]
+    fn skip_name_truncated() {
+        assert_eq!(skip_name(b"\x07exam", 0), None);
+        assert_eq!(skip_name(b"\x07example", 0), None);
+        assert_eq!(skip_name(b"", 0), None);
+    }
+
+    #[test]
+    fn decode_compressed_answers() {
+        let mut response = header(0, 1, 2);
+        push_question(&mut response);
+        push_answer(&mut response, TYPE_A, 300, &[93, 184, 216, 34]);
+        push_answer(&mut response, TYPE_A, 60, &[93, 184, 216, 35]);
+
+        let (addresses, ttl) = addresses(decode_response(&response).unwrap()).unwrap();
+        assert_eq!(addresses, [
+            IpAddress::Ipv4(Ipv4Address::new(93, 184, 216, 34)),
+            IpAddress::Ipv4(Ipv4Address::new(93, 184, 216, 35)),
+        ]);
+        assert_eq!(ttl, 60);
+    }
+
+    #[test]
+    fn decode_skips_non_a_answers() {
+        let mut response = header(0, 1, 3);
+        push_question(&mut response);
+        push_answer(&mut response, TYPE_CNAME, 300, b"\x03www\xC0\x0C");
+        push_answer(&mut response, TYPE_AAAA, 300, &[0; 16]);
+        push_answer(&mut response, TYPE_A, 120, &[10, 0, 0, 1]);
+
+        let (addresses, ttl) = addresses(decode_response(&response).unwrap()).unwrap();
+        assert_eq!(addresses, [IpAddress::Ipv4(Ipv4Address::new(10, 0, 0, 1))]);
+        assert_eq!(ttl, 120);
+    }
+
+    #[test]
+    fn decode_only_non_a_answers() {
+        let mut response = header(0, 1, 1);
+        push_question(&mut response);
+        push_answer(&mut response, TYPE_AAAA, 300, &[0; 16]);
+        assert!(addresses(decode_response(&response).unwrap()).is_none());
+    }
+
+    #[test]
+    fn decode_name_error() {
+        let mut response = header(RCODE_NAME_ERROR, 1, 0);
+        push_question(&mut response);
+        assert!(addresses(decode_response(&response).unwrap()).is_none());
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut query = encode_query(1, "example.com", TYPE_A).unwrap();
+        assert!(decode_response(&query).is_err());
+        query[2] |= (FLAG_RESPONSE >> 8) as u8;
+        assert!(decode_response(&query).is_ok());
+
+        let mut truncated_flag = header(FLAG_TRUNCATED, 1, 0);
+        push_question(&mut truncated_flag);
+        assert!(decode_response(&truncated_flag).is_err());
+
+        let server_failure = header(2, 0, 0);
+        assert!(decode_response(&server_failure).is_err());
+    }
+
+    #[test]
+    fn decode_truncated_packets() {
+        let mut response = header(0, 1, 1);
+        push_question(&mut response);
+        push_answer(&mut response, TYPE_A, 300, &[93, 184, 216, 34]);
+
+        for len in 0..response.len() {
+            assert!(decode_response(&response[..len]).is_err(), "accepted a response truncated to {} bytes", len);
+        }
+        assert!(decode_response(&response).is_ok());
+    }
+}
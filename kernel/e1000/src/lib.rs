@@ -259,8 +259,8 @@ impl E1000Nic {
     /// Initializes the interrupt handler and enables interrupts for this E1000 NIC.
     ///
     /// The provided `interface` must be the network interface associated with this E1000 NIC.
-    /// This interface will be polled in a deferred task upon an interrupt being triggered
-    /// for a received packet.
+    /// This interface's worker task will be woken up by a deferred task upon an interrupt
+    /// being triggered for a received packet.
     pub fn init_interrupts(
         &mut self,
         interface: Arc<net::NetworkInterface>,
@@ -269,7 +269,7 @@ impl E1000Nic {
         let deferred_task = deferred_interrupt_tasks::register_interrupt_handler(
             self.interrupt_num,
             e1000_handler,
-            poll_interface,
+            wake_interface_worker,
             interface,
             Some(format!("e1000_deferred_task_irq_{:#X}", self.interrupt_num)),
         )
@@ -385,7 +385,8 @@ impl E1000Nic {
         // This doesn't prevent all of the rx buffers from being used, they will still all be used fully.
         rx_regs.set_rdt((E1000_NUM_RX_DESC - 1) as u32); 
         // TODO: document these various e1000 flags and why we're setting them
-        regs.rctl.write(regs::RCTL_EN| regs::RCTL_SBP | regs::RCTL_LBM_NONE | regs::RTCL_RDMTS_HALF | regs::RCTL_BAM | regs::RCTL_SECRC  | regs::RCTL_BSIZE_2048);
+        // Multicast frames are accepted (RCTL_MPE) because IPv6 neighbor and router discovery rely on them.
+        regs.rctl.write(regs::RCTL_EN| regs::RCTL_SBP | regs::RCTL_MPE | regs::RCTL_LBM_NONE | regs::RTCL_RDMTS_HALF | regs::RCTL_BAM | regs::RCTL_SECRC  | regs::RCTL_BSIZE_2048);
 
         Ok((rx_descs, rx_bufs_in_use))
     }
@@ -492,7 +493,9 @@ extern "x86-interrupt" fn e1000_handler(_stack_frame: InterruptStackFrame) {
 
 /// This function is used as a deferred interrupt task.
 ///
-/// After processing the interrupt, the network interface associated with the `e1000` NIC will be polled to process the received data.
-fn poll_interface(interface: &Arc<net::NetworkInterface>) -> Result<(), net::Error> {
-    interface.poll()
+/// After processing the interrupt, the worker task of the network interface associated with the `e1000` NIC
+/// is woken up to process the received data.
+fn wake_interface_worker(interface: &Arc<net::NetworkInterface>) -> Result<(), net::Error> {
+    interface.wake_worker();
+    Ok(())
 }
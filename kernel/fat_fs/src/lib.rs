@@ -0,0 +1,703 @@
+//! Exposes FAT filesystems that reside on storage devices as nodes in the VFS.
+//!
+//! The main items are:
+//! * [`FatFsAdapter`]: a wrapper type that allows any I/O stream implementing
+//!   the [`core2`] I/O traits to be used as the backing disk of a [`fatfs::FileSystem`].
+//! * [`FatDirectory`] and [`FatFile`]: implementations of the [`Directory`] and [`File`] traits
+//!   that forward all operations to an underlying FAT filesystem.
+//! * [`mount_storage_device()`]: opens the FAT filesystem on a storage device and mounts
+//!   its root directory beneath the mount directory, e.g., at `/mnt/disk0`.
+//!
+//! Calling [`init()`] registers the [`FAT_FS_TYPE`] filesystem type with the `mount_table`,
+//! which allows FAT filesystems to be mounted anywhere, e.g., via the `mount` application.
+//!
+//! Similar to the nodes in `task_fs`, the nodes below a mounted volume's root directory are
+//! computed lazily: they only store their path within the FAT filesystem,
+//! and each operation re-opens that path on the filesystem.
+//! Thus, the contents of the disk are never cached in the VFS itself;
+//! instead, disk blocks are cached by the storage device's shared block cache,
+//! see `storage_manager::cached_storage_device()`.
+//!
+//! Like all other nodes in the VFS, FAT nodes only hold weak references to their parent directory.
+//! Because nothing keeps a lazily-computed directory alive, a FAT node's parent directory
+//! is recomputed from its path on demand, relative to the mounted volume's root directory,
+//! which is kept alive by the `mount_table`.
+
+#![no_std]
+
+#[macro_use] extern crate alloc;
+#[macro_use] extern crate log;
+#[macro_use] extern crate derive_more;
+extern crate spin;
+extern crate core2;
+extern crate fatfs;
+extern crate fs_node;
+extern crate vfs_node;
+extern crate mount_table;
+extern crate memory;
+extern crate io;
+extern crate storage_manager;
+extern crate time;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::time::Duration;
+use spin::Mutex;
+use fatfs::{Read as _, Seek as _, Write as _};
+use fs_node::{DirRef, Directory, File, FileOrDir, FileRef, FsNode, Metadata, NodeType, Permissions, WeakDirRef};
+use io::{ByteReader, ByteReaderWriterWrapper, ByteWriter, IoError, KnownLength, LockableIo, ReaderWriter};
+use memory::MappedPages;
+use storage_manager::{StorageDevice, StorageDeviceRef};
+use vfs_node::VFSDirectory;
+
+
+/// The name of the FAT filesystem type, as registered with the `mount_table`.
+pub const FAT_FS_TYPE: &str = "fat";
+
+/// The byte-wise, seekable I/O stream type used to access a [`StorageDevice`]
+/// as the backing disk of a FAT filesystem.
+pub type StorageDeviceIo = ReaderWriter<ByteReaderWriterWrapper<
+    LockableIo<'static, dyn StorageDevice + Send, Mutex<dyn StorageDevice + Send>, StorageDeviceRef>
+>>;
+
+/// A FAT filesystem that resides on a [`StorageDevice`].
+pub type FatFileSystem = fatfs::FileSystem<FatFsAdapter<StorageDeviceIo>>;
+
+/// A shareable reference to a [`FatFileSystem`].
+pub type FatFileSystemRef = Arc<Mutex<FatFileSystem>>;
+
+type FatDir<'fs> = fatfs::Dir<'fs, FatFsAdapter<StorageDeviceIo>>;
+
+/// The maximum number of temporary names that are tried when replacing a node.
+const MAX_TEMPORARY_NAMES: usize = 100;
+
+
+/// Registers the FAT filesystem type with the `mount_table`.
+pub fn init() -> Result<(), &'static str> {
+    mount_table::register_filesystem_type(FAT_FS_TYPE, create_filesystem)
+}
+
+/// Attempts to open a FAT filesystem on the given `storage_device`.
+///
+/// Returns an error if the device does not contain a valid FAT filesystem.
+pub fn open(storage_device: StorageDeviceRef) -> Result<FatFileSystem, &'static str> {
+    let disk = FatFsAdapter::new(ReaderWriter::new(
+        ByteReaderWriterWrapper::from(
+            LockableIo::<dyn StorageDevice + Send, Mutex<_>, _>::from(storage_device)
+        )
+    ));
+    fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        .map_err(|_e| "storage device does not contain a valid FAT filesystem")
+}
+
+/// Creates the root directory of the FAT filesystem on the storage device named `source`,
+/// e.g., `disk0`. This is the [`mount_table::FileSystemConstructor`] for [`FAT_FS_TYPE`].
+fn create_filesystem(source: &str, name: String) -> Result<DirRef, &'static str> {
+    let storage_device = storage_manager::storage_device_by_name(source)
+        .ok_or("no storage device with that name exists")?;
+    let filesystem = open(storage_manager::cached_storage_device(&storage_device))?;
+    debug!("Opened FAT filesystem on {}: fat_type: {:?}, volume_id: {:X?}, volume_label: {:?}, cluster_size: {:?}",
+        source,
+        filesystem.fat_type(),
+        filesystem.volume_id(),
+        filesystem.volume_label(),
+        filesystem.cluster_size(),
+    );
+    Ok(FatDirectory::new_root(Arc::new(Mutex::new(filesystem)), name))
+}
+
+/// Mounts the FAT filesystem on the storage device named `source` (e.g., `disk0`)
+/// at a mount point of the same name within the mount directory, i.e., `/mnt/<source>`.
+///
+/// The mount point is created if it does not yet exist.
+///
+/// Returns the root directory of the newly-mounted filesystem.
+pub fn mount_storage_device(source: &str) -> Result<DirRef, &'static str> {
+    let fs_root = create_filesystem(source, source.to_string())?;
+    let mount_dir = mount_table::mount_directory()?;
+    let existing = mount_dir.lock().get_dir(source);
+    let mount_point = match existing {
+        Some(dir) => dir,
+        None => VFSDirectory::create(source.to_string(), &mount_dir)?,
+    };
+    mount_table::mount(Arc::clone(&fs_root), &mount_point, FAT_FS_TYPE, source)?;
+    Ok(fs_root)
+}
+
+
+/// A directory within a FAT filesystem.
+///
+/// The root directory of a mounted FAT volume is persistent in the VFS,
+/// while all other `FatDirectory` instances are computed on demand
+/// when they are looked up via [`Directory::get()`].
+#[derive(Clone)]
+pub struct FatDirectory {
+    /// The name of this directory.
+    name: String,
+    /// The path of this directory relative to the root of the FAT filesystem.
+    /// This is empty for the root directory of the filesystem.
+    fs_path: String,
+    /// The FAT filesystem that contains this directory.
+    filesystem: FatFileSystemRef,
+    /// The root directory of the FAT filesystem, which is persistent while it is mounted.
+    root: WeakDirRef,
+    /// The parent directory that was explicitly set via [`FsNode::set_parent_dir()`], if any.
+    ///
+    /// If `None`, the parent directory is computed from `fs_path`, see [`parent_of()`].
+    parent: Option<WeakDirRef>,
+}
+
+impl FatDirectory {
+    /// Creates a new directory called `name` that represents the root of the given FAT `filesystem`.
+    ///
+    /// The new directory has no parent; it is meant to be mounted via [`mount_table::mount()`].
+    pub fn new_root(filesystem: FatFileSystemRef, name: String) -> DirRef {
+        Arc::new_cyclic(|root: &Weak<Mutex<FatDirectory>>| Mutex::new(FatDirectory {
+            name,
+            fs_path: String::new(),
+            filesystem,
+            root: root.clone() as WeakDirRef,
+            parent: None,
+        })) as DirRef
+    }
+
+    /// Returns a reference to the FAT filesystem that contains this directory.
+    pub fn filesystem(&self) -> &FatFileSystemRef {
+        &self.filesystem
+    }
+
+    /// Returns the FAT filesystem path of a child node with the given `name`.
+    fn child_path(&self, name: &str) -> String {
+        if self.fs_path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.fs_path, name)
+        }
+    }
+
+    /// Opens this directory within the given locked `filesystem`.
+    fn open<'fs>(&self, filesystem: &'fs FatFileSystem) -> Result<FatDir<'fs>, &'static str> {
+        let root_dir = filesystem.root_dir();
+        if self.fs_path.is_empty() {
+            Ok(root_dir)
+        } else {
+            root_dir.open_dir(&self.fs_path).map_err(fat_error_to_str)
+        }
+    }
+
+    /// Writes the given `node` and all of its contents into this directory on disk,
+    /// where it will be called `name`.
+    fn copy_to_disk(&self, node: &FileOrDir, name: &str) -> Result<(), &'static str> {
+        let name = name.to_string();
+        match node {
+            FileOrDir::File(file) => {
+                let mut contents = vec![0u8; file.lock().len()];
+                if !contents.is_empty() {
+                    file.lock().read_at(&mut contents, 0).map_err(<&'static str>::from)?;
+                }
+                let filesystem = self.filesystem.lock();
+                let dir = self.open(&filesystem)?;
+                let mut fat_file = dir.create_file(&name).map_err(fat_error_to_str)?;
+                fat_file.truncate().map_err(fat_error_to_str)?;
+                fat_file.write_all(&contents).map_err(fat_error_to_str)?;
+                fat_file.flush().map_err(fat_error_to_str)?;
+            }
+            FileOrDir::Dir(dir) => {
+                {
+                    let filesystem = self.filesystem.lock();
+                    self.open(&filesystem)?.create_dir(&name).map_err(fat_error_to_str)?;
+                }
+                let new_dir = FatDirectory {
+                    name: name.clone(),
+                    fs_path: self.child_path(&name),
+                    filesystem: Arc::clone(&self.filesystem),
+                    root: self.root.clone(),
+                    parent: None,
+                };
+                let children = dir.lock().list();
+                for child_name in children {
+                    let child = dir.lock().get(&child_name);
+                    if let Some(child) = child {
+                        new_dir.copy_to_disk(&child, &child_name)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the node called `name` from this directory on disk,
+    /// including all of its contents if it is a directory.
+    fn remove_from_disk(&self, name: &str) -> Result<(), &'static str> {
+        let filesystem = self.filesystem.lock();
+        let dir = self.open(&filesystem)?;
+        remove_recursively(&dir, name).map_err(fat_error_to_str)
+    }
+
+    /// Returns a name based on `name` that no node in this directory has,
+    /// under which a node can be written before it replaces another node.
+    fn temporary_name(&self, name: &str) -> Result<String, &'static str> {
+        (0..MAX_TEMPORARY_NAMES)
+            .map(|i| format!(".{name}.{i}.tmp"))
+            .find(|temp_name| self.get(temp_name).is_none())
+            .ok_or("couldn't find an unused temporary name in FAT directory")
+    }
+}
+
+impl Directory for FatDirectory {
+    /// Writes the given `node` into this directory on disk.
+    ///
+    /// Because the FAT filesystem is the sole owner of its contents,
+    /// the given `node` is *copied* to disk, including all of its children if it is a directory.
+    /// Further modifications to the given `node` will not be reflected on disk;
+    /// callers should obtain the on-disk node via [`Directory::get()`] after inserting it.
+    ///
+    /// If a node with the same name exists, the given `node` is first written under a temporary name,
+    /// such that the existing node is only removed once its replacement is entirely on disk.
+    fn insert(&mut self, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        let name = node.get_name();
+        let Some(old_node) = self.get(&name) else {
+            if let Err(e) = self.copy_to_disk(&node, &name) {
+                let _ = self.remove_from_disk(&name);
+                return Err(e);
+            }
+            return Ok(None);
+        };
+
+        let temp_name = self.temporary_name(&name)?;
+        if let Err(e) = self.copy_to_disk(&node, &temp_name) {
+            let _ = self.remove_from_disk(&temp_name);
+            return Err(e);
+        }
+        let Some(old_node) = self.remove(&old_node) else {
+            let _ = self.remove_from_disk(&temp_name);
+            return Err("couldn't remove existing node with the same name from FAT directory");
+        };
+        let filesystem = self.filesystem.lock();
+        let dir = self.open(&filesystem)?;
+        dir.rename(&temp_name, &dir, &name).map_err(fat_error_to_str)?;
+        Ok(Some(old_node))
+    }
+
+    fn get(&self, name: &str) -> Option<FileOrDir> {
+        let filesystem = self.filesystem.lock();
+        let dir = self.open(&filesystem).ok()?;
+        let entry = dir.iter()
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_name().eq_ignore_ascii_case(name))?;
+        let entry_name = entry.file_name();
+        if entry_name == "." || entry_name == ".." {
+            return None;
+        }
+
+        let is_dir = entry.is_dir();
+        drop(dir);
+        drop(filesystem);
+
+        let fs_path = self.child_path(&entry_name);
+        let filesystem = Arc::clone(&self.filesystem);
+        let root = self.root.clone();
+        if is_dir {
+            let dir = FatDirectory { name: entry_name, fs_path, filesystem, root, parent: None };
+            Some(FileOrDir::Dir(Arc::new(Mutex::new(dir)) as DirRef))
+        } else {
+            let file = FatFile { name: entry_name, fs_path, filesystem, root, parent: None };
+            Some(FileOrDir::File(Arc::new(Mutex::new(file)) as FileRef))
+        }
+    }
+
+    fn list(&self) -> Vec<String> {
+        let filesystem = self.filesystem.lock();
+        match self.open(&filesystem) {
+            Ok(dir) => dir.iter()
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name())
+                .filter(|name| name != "." && name != "..")
+                .collect(),
+            Err(e) => {
+                error!("FatDirectory::list(): couldn't open {:?}: {}", self.fs_path, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Renames a node within this directory on disk.
+    ///
+    /// Unlike other directories, this does not replace an existing node called `new_name`.
+    ///
+    /// Because FAT names are case-insensitive, a node can be renamed to a name that only differs in case;
+    /// such renames go through a temporary name.
+    fn rename(&mut self, old_name: &str, new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        let node = self.get(old_name).ok_or("no node with that name exists in this FAT directory")?;
+        let old_name = node.get_name();
+        if old_name == new_name {
+            return Ok(None);
+        }
+        let case_only = old_name.eq_ignore_ascii_case(new_name);
+        if !case_only && self.get(new_name).is_some() {
+            return Err("a node with that name already exists in this FAT directory");
+        }
+        let temp_name = if case_only { Some(self.temporary_name(&old_name)?) } else { None };
+
+        let filesystem = self.filesystem.lock();
+        let dir = self.open(&filesystem)?;
+        match temp_name {
+            Some(temp_name) => {
+                dir.rename(&old_name, &dir, &temp_name).map_err(fat_error_to_str)?;
+                if let Err(e) = dir.rename(&temp_name, &dir, new_name) {
+                    let _ = dir.rename(&temp_name, &dir, &old_name);
+                    return Err(fat_error_to_str(e));
+                }
+            }
+            None => dir.rename(&old_name, &dir, new_name).map_err(fat_error_to_str)?,
+        }
+        Ok(None)
+    }
+
+    fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
+        let name = node.get_name();
+        let mut old_node = self.get(&name)?;
+        {
+            let filesystem = self.filesystem.lock();
+            let dir = self.open(&filesystem).ok()?;
+            if let Err(e) = dir.remove(&name) {
+                error!("FatDirectory::remove(): couldn't remove {:?}: {}", name, fat_error_to_str(e));
+                return None;
+            }
+        }
+        old_node.set_parent_dir(Weak::<Mutex<FatDirectory>>::new());
+        Some(old_node)
+    }
+
+    fn metadata(&self) -> Metadata {
+        if self.fs_path.is_empty() {
+            return Metadata::new(NodeType::Directory, 0);
+        }
+        entry_metadata(&self.filesystem, &self.fs_path)
+            .unwrap_or_else(|| Metadata::new(NodeType::Directory, 0))
+    }
+}
+
+impl FsNode for FatDirectory {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        match self.parent {
+            Some(ref parent) => parent.upgrade(),
+            // The root directory only has a parent once it is mounted.
+            None if self.fs_path.is_empty() => None,
+            None => parent_of(&self.fs_path, &self.filesystem, &self.root),
+        }
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = Some(new_parent);
+    }
+}
+
+
+/// A file within a FAT filesystem, which is computed on demand
+/// when it is looked up via [`Directory::get()`].
+pub struct FatFile {
+    /// The name of this file.
+    name: String,
+    /// The path of this file relative to the root of the FAT filesystem.
+    fs_path: String,
+    /// The FAT filesystem that contains this file.
+    filesystem: FatFileSystemRef,
+    /// The root directory of the FAT filesystem, which is persistent while it is mounted.
+    root: WeakDirRef,
+    /// The parent directory that was explicitly set via [`FsNode::set_parent_dir()`], if any.
+    ///
+    /// If `None`, the parent directory is computed from `fs_path`, see [`parent_of()`].
+    parent: Option<WeakDirRef>,
+}
+
+impl ByteReader for FatFile {
+    fn read_at(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        let filesystem = self.filesystem.lock();
+        let mut file = filesystem.root_dir().open_file(&self.fs_path).map_err(fat_error_to_io)?;
+        let len = file.seek(fatfs::SeekFrom::End(0)).map_err(fat_error_to_io)? as usize;
+        if offset >= len {
+            return Err(IoError::InvalidInput);
+        }
+        file.seek(fatfs::SeekFrom::Start(offset as u64)).map_err(fat_error_to_io)?;
+
+        // read from the offset until the end of the file, but not more than the buffer length
+        let read_bytes = core::cmp::min(len - offset, buffer.len());
+        file.read_exact(&mut buffer[..read_bytes]).map_err(fat_error_to_io)?;
+        Ok(read_bytes)
+    }
+}
+
+impl ByteWriter for FatFile {
+    fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        let filesystem = self.filesystem.lock();
+        let mut file = filesystem.root_dir().open_file(&self.fs_path).map_err(fat_error_to_io)?;
+        let len = file.seek(fatfs::SeekFrom::End(0)).map_err(fat_error_to_io)? as usize;
+        // FAT files cannot be seeked past their end, so fill any gap with zeros.
+        if offset > len {
+            let padding = vec![0u8; offset - len];
+            file.write_all(&padding).map_err(fat_error_to_io)?;
+        } else {
+            file.seek(fatfs::SeekFrom::Start(offset as u64)).map_err(fat_error_to_io)?;
+        }
+        file.write_all(buffer).map_err(fat_error_to_io)?;
+        file.flush().map_err(fat_error_to_io)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        // Each write is flushed immediately, see `write_at()`.
+        Ok(())
+    }
+}
+
+impl KnownLength for FatFile {
+    fn len(&self) -> usize {
+        let filesystem = self.filesystem.lock();
+        let len = filesystem.root_dir()
+            .open_file(&self.fs_path)
+            .and_then(|mut file| file.seek(fatfs::SeekFrom::End(0)));
+        match len {
+            Ok(len) => len as usize,
+            Err(e) => {
+                error!("FatFile::len(): couldn't open {:?}: {}", self.fs_path, fat_error_to_str(e));
+                0
+            }
+        }
+    }
+}
+
+impl File for FatFile {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("Mapping a FatFile as a MappedPages object is unimplemented")
+    }
+
+    fn metadata(&self) -> Metadata {
+        entry_metadata(&self.filesystem, &self.fs_path)
+            .unwrap_or_else(|| Metadata::new(NodeType::File, self.len()))
+    }
+}
+
+impl FsNode for FatFile {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        match self.parent {
+            Some(ref parent) => parent.upgrade(),
+            // The root directory only has a parent once it is mounted.
+            None if self.fs_path.is_empty() => None,
+            None => parent_of(&self.fs_path, &self.filesystem, &self.root),
+        }
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = Some(new_parent);
+    }
+}
+
+
+/// Returns the directory that contains the node at the given `fs_path` within a FAT filesystem,
+/// whose root directory is `root`.
+///
+/// Non-root directories are computed on demand, so the returned directory may be a new instance.
+fn parent_of(fs_path: &str, filesystem: &FatFileSystemRef, root: &WeakDirRef) -> Option<DirRef> {
+    let parent_path = match fs_path.rsplit_once('/') {
+        Some((parent_path, _name)) => parent_path,
+        None => return root.upgrade(),
+    };
+    let name = parent_path.rsplit('/').next().unwrap_or(parent_path);
+    let parent = FatDirectory {
+        name: name.to_string(),
+        fs_path: parent_path.to_string(),
+        filesystem: Arc::clone(filesystem),
+        root: root.clone(),
+        parent: None,
+    };
+    Some(Arc::new(Mutex::new(parent)) as DirRef)
+}
+
+/// Returns the metadata of the node at the given non-empty `fs_path` within a FAT filesystem,
+/// based on its directory entry, or `None` if that entry couldn't be found.
+fn entry_metadata(filesystem: &FatFileSystemRef, fs_path: &str) -> Option<Metadata> {
+    let (parent_path, name) = fs_path.rsplit_once('/').unwrap_or(("", fs_path));
+    let filesystem = filesystem.lock();
+    let root_dir = filesystem.root_dir();
+    let dir = if parent_path.is_empty() {
+        root_dir
+    } else {
+        root_dir.open_dir(parent_path).ok()?
+    };
+    let entry = dir.iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().eq_ignore_ascii_case(name))?;
+
+    let (node_type, size) = if entry.is_dir() {
+        (NodeType::Directory, 0)
+    } else {
+        (NodeType::File, entry.len() as usize)
+    };
+    Some(Metadata {
+        node_type,
+        size,
+        created: fat_time_to_duration(entry.created()),
+        modified: fat_time_to_duration(entry.modified()),
+        permissions: Permissions {
+            read_only: entry.attributes().contains(fatfs::FileAttributes::READ_ONLY),
+            executable: false,
+        },
+    })
+}
+
+/// Converts a FAT timestamp into a duration since the Unix epoch.
+///
+/// FAT timestamps have no time zone, so they are interpreted as UTC.
+fn fat_time_to_duration(date_time: fatfs::DateTime) -> Duration {
+    let (date, time_of_day) = (date_time.date, date_time.time);
+    let days = time::days_from_civil(date.year as i64, date.month as u32, date.day as u32);
+    let seconds = days * 86_400
+        + time_of_day.hour as i64 * 3600
+        + time_of_day.min as i64 * 60
+        + time_of_day.sec as i64;
+    Duration::from_secs(seconds.max(0) as u64) + Duration::from_millis(time_of_day.millis as u64)
+}
+
+/// Removes the node called `name` from the given `dir`, including all of its contents if it is a directory.
+fn remove_recursively(dir: &FatDir, name: &str) -> Result<(), fatfs::Error<FatFsIoErrorAdapter>> {
+    let is_dir = dir.iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name() == name)
+        .map_or(false, |e| e.is_dir());
+    if is_dir {
+        let subdir = dir.open_dir(name)?;
+        let children: Vec<String> = subdir.iter()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name())
+            .filter(|child| child != "." && child != "..")
+            .collect();
+        for child in children {
+            remove_recursively(&subdir, &child)?;
+        }
+    }
+    dir.remove(name)
+}
+
+/// Converts a [`fatfs::Error`] into a static string describing that error.
+fn fat_error_to_str(error: fatfs::Error<FatFsIoErrorAdapter>) -> &'static str {
+    match error {
+        fatfs::Error::Io(_)                           => "I/O error while accessing FAT filesystem",
+        fatfs::Error::UnexpectedEof                   => "unexpected end of file in FAT filesystem",
+        fatfs::Error::WriteZero                       => "failed to write to FAT filesystem",
+        fatfs::Error::InvalidInput                    => "invalid input to FAT filesystem",
+        fatfs::Error::NotFound                        => "file or directory not found in FAT filesystem",
+        fatfs::Error::AlreadyExists                   => "file or directory already exists in FAT filesystem",
+        fatfs::Error::DirectoryIsNotEmpty             => "FAT directory is not empty",
+        fatfs::Error::CorruptedFileSystem             => "FAT filesystem is corrupted",
+        fatfs::Error::NotEnoughSpace                  => "not enough space in FAT filesystem",
+        fatfs::Error::InvalidFileNameLength           => "invalid file name length for FAT filesystem",
+        fatfs::Error::UnsupportedFileNameCharacter    => "unsupported character in FAT file name",
+        _                                             => "unknown FAT filesystem error",
+    }
+}
+
+/// Converts a [`fatfs::Error`] into an [`IoError`].
+fn fat_error_to_io(error: fatfs::Error<FatFsIoErrorAdapter>) -> IoError {
+    match error {
+        fatfs::Error::InvalidInput => IoError::InvalidInput,
+        other => IoError::Other(fat_error_to_str(other)),
+    }
+}
+
+
+/// An adapter (wrapper type) that implements traits required by the [`fatfs`] crate
+/// for any I/O device that wants to be usable by [`fatfs`].
+///
+/// To meet [`fatfs`]'s requirements, the underlying I/O stream must be able to
+/// read, write, and seek while tracking its current offset.
+/// We use traits from the [`core2`] crate to meet these requirements,
+/// thus, the given `IO` parameter must implement those [`core2`] traits.
+///
+/// For example, this allows one to access a FAT filesystem
+/// by reading from or writing to a storage device.
+pub struct FatFsAdapter<IO>(IO);
+impl<IO> FatFsAdapter<IO> {
+    pub fn new(io: IO) -> FatFsAdapter<IO> { FatFsAdapter(io) }
+}
+/// This tells the `fatfs` crate that our read/write/seek functions
+/// may return errors of the type [`FatFsIoErrorAdapter`],
+/// which is a simple wrapper around [`core2::io::Error`].
+impl<IO> fatfs::IoBase for FatFsAdapter<IO> {
+    type Error = FatFsIoErrorAdapter;
+}
+impl<IO> fatfs::Read for FatFsAdapter<IO> where IO: core2::io::Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).map_err(Into::into)
+    }
+}
+impl<IO> fatfs::Write for FatFsAdapter<IO> where IO: core2::io::Write {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).map_err(Into::into)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().map_err(Into::into)
+    }
+}
+impl<IO> fatfs::Seek for FatFsAdapter<IO> where IO: core2::io::Seek {
+    fn seek(&mut self, pos: fatfs::SeekFrom) -> Result<u64, Self::Error> {
+        let core2_pos = match pos {
+            fatfs::SeekFrom::Start(s)   => core2::io::SeekFrom::Start(s),
+            fatfs::SeekFrom::Current(c) => core2::io::SeekFrom::Current(c),
+            fatfs::SeekFrom::End(e)     => core2::io::SeekFrom::End(e),
+        };
+        self.0.seek(core2_pos).map_err(Into::into)
+    }
+}
+
+/// This struct exists to enable us to implement the [`fatfs::IoError`] trait
+/// for the [`core2::io::Error`] trait.
+///
+/// This is required because Rust prevents implementing foreign traits for foreign types.
+#[derive(Debug, From, Into)]
+pub struct FatFsIoErrorAdapter(core2::io::Error);
+impl fatfs::IoError for FatFsIoErrorAdapter {
+    fn is_interrupted(&self) -> bool {
+        self.0.kind() == core2::io::ErrorKind::Interrupted
+    }
+    fn new_unexpected_eof_error() -> Self {
+        FatFsIoErrorAdapter(core2::io::ErrorKind::UnexpectedEof.into())
+    }
+    fn new_write_zero_error() -> Self {
+        FatFsIoErrorAdapter(core2::io::ErrorKind::WriteZero.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use fatfs::{Date, DateTime, Time};
+
+    fn fat_time(year: u16, month: u16, day: u16, hour: u16, min: u16, sec: u16, millis: u16) -> DateTime {
+        DateTime::new(Date::new(year, month, day), Time::new(hour, min, sec, millis))
+    }
+
+    #[test]
+    fn fat_time_conversion() {
+        // The earliest FAT timestamp.
+        assert_eq!(fat_time_to_duration(fat_time(1980, 1, 1, 0, 0, 0, 0)), Duration::from_secs(315_532_800));
+        assert_eq!(
+            fat_time_to_duration(fat_time(2000, 2, 29, 12, 34, 56, 780)),
+            Duration::from_secs(951_827_696) + Duration::from_millis(780),
+        );
+        assert_eq!(fat_time_to_duration(fat_time(2024, 3, 1, 0, 0, 0, 0)), Duration::from_secs(1_709_251_200));
+        assert_eq!(fat_time_to_duration(fat_time(2107, 12, 31, 23, 59, 58, 0)), Duration::from_secs(4_354_819_198));
+    }
+}
@@ -239,6 +239,11 @@ pub fn print_fault_log() {
     println_both!("------------------ END OF LOG --------------------------");
 }
 
+/// Returns a copy of all entries in the fault log, in the order they were logged.
+pub fn fault_entries() -> Vec<FaultEntry> {
+    FAULT_LIST.lock().clone()
+}
+
 /// Add a `FaultEntry` to fault log.
 pub fn log_handled_fault(fe: FaultEntry){
     FAULT_LIST.lock().push(fe);
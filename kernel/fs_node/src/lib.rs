@@ -10,19 +10,25 @@
 //! Some functions return an enum FileOrDir; this allows us to seamlessly call functions on the return types of
 //! other filesystem functions, and then we simply match on the FSnode to extract the concrete type
 //! to perform the desired function
+//!
+//! Both Files and Directories can describe themselves with [`Metadata`], which includes
+//! the node's type, size, timestamps and [`Permissions`].
+//! A symbolic link is a File that reports the path it points to via [`File::symlink_target()`].
 
 #[macro_use] extern crate alloc;
 extern crate spin;
 extern crate memory;
 extern crate io;
+extern crate time;
 
 use core::fmt;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use spin::Mutex;
 use alloc::sync::{Arc, Weak};
 use memory::MappedPages;
 use io::{ByteReader, ByteWriter, KnownLength};
+use time::Duration;
 
 
 /// A reference to any type that implements the [`File`] trait,
@@ -67,12 +73,44 @@ pub trait FsNode {
     /// This is useful for ensuring correctness when inserting or removing 
     /// files or directories from their parent directory.
     fn set_parent_dir(&mut self, new_parent: WeakDirRef);
+
+    /// Changes the name of this node.
+    ///
+    /// This does not update the node's parent directory;
+    /// use [`Directory::rename()`] or [`move_node()`] to rename a node within a directory.
+    ///
+    /// The default implementation returns an error, as not all nodes can be renamed.
+    fn set_name(&mut self, _new_name: String) -> Result<(), &'static str> {
+        Err("this node cannot be renamed")
+    }
 }
 
 // Trait for files, implementors of File must also implement FsNode
 pub trait File : FsNode + ByteReader + ByteWriter + KnownLength {
     /// Returns a view of this file as an immutable memory-mapped region.
     fn as_mapping(&self) -> Result<&MappedPages, &'static str>;
+
+    /// Returns the metadata of this file.
+    ///
+    /// The default implementation only reports the file's type and size.
+    fn metadata(&self) -> Metadata {
+        Metadata::new(NodeType::File, self.len())
+    }
+
+    /// Sets the permissions of this file.
+    ///
+    /// The default implementation returns an error, as not all files support permissions.
+    fn set_permissions(&mut self, _permissions: Permissions) -> Result<(), &'static str> {
+        Err("this file does not support changing its permissions")
+    }
+
+    /// If this file is a symbolic link, returns the path of the node that it points to.
+    ///
+    /// Symbolic links are followed by `path::Path::get()`.
+    /// The default implementation returns `None`, as most files are not symbolic links.
+    fn symlink_target(&self) -> Option<String> {
+        None
+    }
 }
 
 /// Trait for directories, implementors of Directory must also implement FsNode
@@ -109,12 +147,284 @@ pub trait Directory : FsNode {
 
     /// Removes a file or directory from this directory and returns it if found.
     /// Also, the returned node's parent directory reference is cleared.
+    ///
+    /// Returns `None` if the node was not found or if this directory is read-only.
     /// 
     /// The lock on `node` must not be held because it will be acquired within this function.
     fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir>;
 
     /// Lists the names of the nodes in this directory.
     fn list(&self) -> Vec<String>;
+
+    /// Atomically renames the node called `old_name` in this directory to `new_name`.
+    /// If an existing node is called `new_name`, that node is replaced and returned,
+    /// and its parent directory is cleared.
+    ///
+    /// To move a node into a different directory, use [`move_node()`].
+    ///
+    /// The default implementation returns an error, as not all directories support renaming.
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        Err("this directory does not support renaming its nodes")
+    }
+
+    /// Returns `true` if nodes in this directory can be detached from it
+    /// and moved into another directory via [`move_node()`].
+    ///
+    /// Directories whose nodes are merely views of their underlying contents,
+    /// e.g., those computed lazily from a disk, cannot support this.
+    ///
+    /// The default implementation returns `false`.
+    fn supports_move(&self) -> bool {
+        false
+    }
+
+    /// Returns the metadata of this directory.
+    ///
+    /// The default implementation only reports the directory's type.
+    fn metadata(&self) -> Metadata {
+        Metadata::new(NodeType::Directory, 0)
+    }
+
+    /// Sets the permissions of this directory.
+    ///
+    /// The default implementation returns an error, as not all directories support permissions.
+    fn set_permissions(&mut self, _permissions: Permissions) -> Result<(), &'static str> {
+        Err("this directory does not support changing its permissions")
+    }
+}
+
+/// The type of a filesystem node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeType {
+    File,
+    Directory,
+    /// A symbolic link, which is a special kind of `File`; see [`File::symlink_target()`].
+    Symlink,
+}
+
+/// The access permissions of a filesystem node.
+///
+/// Theseus has no notion of users, so these permissions apply to all accessors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Permissions {
+    /// Whether the node cannot be modified:
+    /// read-only files cannot be written to,
+    /// and read-only directories cannot have nodes inserted into or removed from them.
+    pub read_only: bool,
+    /// Whether the node is executable, e.g., a crate object file.
+    pub executable: bool,
+}
+
+/// Information about a filesystem node that is independent of its contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Whether the node is a file or a directory.
+    pub node_type: NodeType,
+    /// The size in bytes of the node's contents; this is `0` for directories.
+    pub size: usize,
+    /// The time at which the node was created, as a duration since the Unix epoch.
+    /// This is zero if unknown.
+    pub created: Duration,
+    /// The time at which the node was last modified, as a duration since the Unix epoch.
+    /// This is zero if unknown.
+    pub modified: Duration,
+    /// The node's access permissions.
+    pub permissions: Permissions,
+}
+
+impl Metadata {
+    /// Creates a new `Metadata` with the given type and size,
+    /// unknown timestamps, and default permissions.
+    pub fn new(node_type: NodeType, size: usize) -> Metadata {
+        Metadata {
+            node_type,
+            size,
+            created: Duration::ZERO,
+            modified: Duration::ZERO,
+            permissions: Permissions::default(),
+        }
+    }
+
+    /// Returns `true` if this node is a `File`.
+    pub fn is_file(&self) -> bool {
+        self.node_type == NodeType::File
+    }
+
+    /// Returns `true` if this node is a `Directory`.
+    pub fn is_dir(&self) -> bool {
+        self.node_type == NodeType::Directory
+    }
+
+    /// Returns `true` if this node is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.node_type == NodeType::Symlink
+    }
+}
+
+/// The timestamps and permissions of a filesystem node,
+/// which can be embedded in `File` and `Directory` implementations
+/// to help them track and report their [`Metadata`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attributes {
+    /// The time at which the node was created.
+    pub created: Duration,
+    /// The time at which the node was last modified.
+    pub modified: Duration,
+    /// The node's access permissions.
+    pub permissions: Permissions,
+}
+
+impl Attributes {
+    /// Creates new attributes for a node that was created at the current time.
+    pub fn new() -> Attributes {
+        Self::with_permissions(Permissions::default())
+    }
+
+    /// Creates new attributes with the given `permissions`
+    /// for a node that was created at the current time.
+    pub fn with_permissions(permissions: Permissions) -> Attributes {
+        let now = current_time();
+        Attributes {
+            created: now,
+            modified: now,
+            permissions,
+        }
+    }
+
+    /// Marks the node as modified at the current time.
+    pub fn touch(&mut self) {
+        self.modified = current_time();
+    }
+
+    /// Returns an error if the node is read-only.
+    pub fn check_writable(&self) -> Result<(), &'static str> {
+        if self.permissions.read_only {
+            Err("permission denied: node is read-only")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Creates the [`Metadata`] of a node with these attributes and the given type and size.
+    pub fn metadata(&self, node_type: NodeType, size: usize) -> Metadata {
+        Metadata {
+            node_type,
+            size,
+            created: self.created,
+            modified: self.modified,
+            permissions: self.permissions,
+        }
+    }
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the current wall-clock time as a duration since the Unix epoch,
+/// which is used to timestamp filesystem nodes.
+///
+/// This is derived from the monotonic clock, see [`time::approximate_wall_time()`],
+/// so it is cheap enough to call on every modification of a node.
+///
+/// Returns zero if no wall-clock time source has been registered yet, e.g., during early boot.
+pub fn current_time() -> Duration {
+    time::approximate_wall_time().unwrap_or(Duration::ZERO)
+}
+
+/// Serializes all moves of nodes between different directories,
+/// such that the ancestors of a move's destination directory cannot change during that move.
+static MOVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Atomically moves the node called `old_name` in the `source` directory
+/// into the `destination` directory, where it will be called `new_name`.
+///
+/// If an existing node in the `destination` directory is called `new_name`,
+/// that node is replaced and returned.
+///
+/// If both directories are the same, this is equivalent to [`Directory::rename()`].
+/// Otherwise, both directories are locked for the duration of the move,
+/// such that concurrent lookups observe the node in exactly one of the two directories.
+/// If the node cannot be inserted into the `destination` directory,
+/// it is restored to the `source` directory.
+///
+/// Returns an error if the `source` directory doesn't support moving nodes out of it,
+/// or if the node is a directory that contains the `destination` directory.
+///
+/// This doesn't account for filesystems mounted beneath the moved node;
+/// use `mount_table::move_node()` to move nodes within the VFS.
+///
+/// The locks on `source`, `destination` and the node being moved must not be held.
+pub fn move_node(
+    source: &DirRef,
+    old_name: &str,
+    destination: &DirRef,
+    new_name: &str,
+) -> Result<Option<FileOrDir>, &'static str> {
+    if Arc::ptr_eq(source, destination) {
+        return source.lock().rename(old_name, new_name);
+    }
+
+    let _move_guard = MOVE_LOCK.lock();
+    // Always lock the two directories in the same order to avoid deadlock.
+    let mut src;
+    let mut dest;
+    if (Arc::as_ptr(source) as *const u8) < (Arc::as_ptr(destination) as *const u8) {
+        src = source.lock();
+        dest = destination.lock();
+    } else {
+        dest = destination.lock();
+        src = source.lock();
+    }
+
+    if !src.supports_move() {
+        return Err("nodes cannot be moved out of the source directory");
+    }
+    let node = src.get(old_name).ok_or("no node with that name exists in the source directory")?;
+    // A directory cannot be moved into itself or any of its subdirectories.
+    // This is checked while holding the locks on both directories,
+    // such that the destination cannot be moved beneath the node in the meantime.
+    if let FileOrDir::Dir(ref dir) = node {
+        let mut ancestor = Arc::clone(destination);
+        loop {
+            if Arc::ptr_eq(&ancestor, dir) {
+                return Err("cannot move a directory into itself or one of its subdirectories");
+            }
+            let parent = if Arc::ptr_eq(&ancestor, destination) {
+                dest.get_parent_dir()
+            } else if Arc::ptr_eq(&ancestor, source) {
+                src.get_parent_dir()
+            } else {
+                ancestor.lock().get_parent_dir()
+            };
+            match parent {
+                // The root directory is its own parent.
+                Some(p) if !Arc::ptr_eq(&p, &ancestor) => ancestor = p,
+                _ => break,
+            }
+        }
+    }
+
+    let mut node = src.remove(&node).ok_or("couldn't remove the node from the source directory")?;
+    if old_name != new_name {
+        if let Err(e) = node.set_name(new_name.to_string()) {
+            node.set_parent_dir(Arc::downgrade(source));
+            src.insert(node)?;
+            return Err(e);
+        }
+    }
+    node.set_parent_dir(Arc::downgrade(destination));
+    match dest.insert(node.clone()) {
+        Ok(replaced) => Ok(replaced),
+        Err(e) => {
+            let _ = node.set_name(old_name.to_string());
+            node.set_parent_dir(Arc::downgrade(source));
+            src.insert(node)?;
+            Err(e)
+        }
+    }
 }
 
 /// Allows us to return a generic type that can be matched by the caller to extract the underlying type
@@ -160,6 +470,13 @@ impl FsNode for FileOrDir {
             FileOrDir::Dir(dir) => dir.lock().set_parent_dir(new_parent),
         }
     }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        match self {
+            FileOrDir::File(file) => file.lock().set_name(new_name),
+            FileOrDir::Dir(dir) => dir.lock().set_name(new_name),
+        }
+    }
 }
 
 impl KnownLength for FileOrDir {
@@ -190,4 +507,28 @@ impl FileOrDir {
             FileOrDir::Dir(_) => true,
         }
     }
+
+    /// If this `FileOrDir` is a symbolic link, returns the path of the node that it points to.
+    pub fn symlink_target(&self) -> Option<String> {
+        match &self {
+            FileOrDir::File(f) => f.lock().symlink_target(),
+            FileOrDir::Dir(_) => None,
+        }
+    }
+
+    /// Returns the metadata of this `FileOrDir`.
+    pub fn metadata(&self) -> Metadata {
+        match &self {
+            FileOrDir::File(f) => f.lock().metadata(),
+            FileOrDir::Dir(d) => d.lock().metadata(),
+        }
+    }
+
+    /// Sets the permissions of this `FileOrDir`.
+    pub fn set_permissions(&self, permissions: Permissions) -> Result<(), &'static str> {
+        match &self {
+            FileOrDir::File(f) => f.lock().set_permissions(permissions),
+            FileOrDir::Dir(d) => d.lock().set_permissions(permissions),
+        }
+    }
 }
@@ -17,7 +17,7 @@ use alloc::{
 };
 use io::{ByteReader, ByteWriter, IoError, KnownLength};
 use spin::Mutex;
-use fs_node::{FileOrDir, FileRef, DirRef, WeakDirRef, File, FsNode};
+use fs_node::{Attributes, FileOrDir, FileRef, DirRef, WeakDirRef, File, FsNode, Metadata, NodeType, Permissions};
 use memory::MappedPages;
 
 /// A file in memory that is backed by the heap, i.e., a `Vec`.
@@ -28,6 +28,8 @@ pub struct HeapFile {
     vec: Vec<u8>,
     /// The parent directory that contains this file.
     parent: WeakDirRef,
+    /// The timestamps and permissions of this file.
+    attributes: Attributes,
 }
 
 impl HeapFile {
@@ -44,6 +46,7 @@ impl HeapFile {
             name, 
             vec, 
             parent: Arc::downgrade(parent), 
+            attributes: Attributes::new(),
         };
         let file_ref = Arc::new(Mutex::new(hf)) as FileRef;
         parent.lock().insert(FileOrDir::File(file_ref.clone()))?;
@@ -65,6 +68,9 @@ impl ByteReader for HeapFile {
 
 impl ByteWriter for HeapFile {
     fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        self.attributes.check_writable()?;
+        self.attributes.touch();
+
         let final_len = offset + buffer.len();
         // Handle the need for reallocation and padding bytes.
         if final_len > self.vec.len() {
@@ -90,6 +96,15 @@ impl File for HeapFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("Mapping a HeapFile as a MappedPages object is unimplemented")
     }
+
+    fn metadata(&self) -> Metadata {
+        self.attributes.metadata(NodeType::File, self.vec.len())
+    }
+
+    fn set_permissions(&mut self, permissions: Permissions) -> Result<(), &'static str> {
+        self.attributes.permissions = permissions;
+        Ok(())
+    }
 }
 
 impl FsNode for HeapFile {
@@ -104,4 +119,9 @@ impl FsNode for HeapFile {
     fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
         self.parent = new_parent;
     }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        self.name = new_name;
+        Ok(())
+    }
 }
@@ -0,0 +1,875 @@
+//! A higher-level HTTP/1.1 client that sends requests to URLs
+//! over persistent connections of the `net` crate's blocking TCP sockets.
+//!
+//! Unlike [`send_request()`](../fn.send_request.html), the [`HttpClient`] builds requests itself,
+//! understands all of the ways a response body can be delimited (`Content-Length`,
+//! chunked transfer encoding, or the server closing the connection),
+//! follows redirects, and can stream response bodies into a file
+//! instead of buffering them in memory, resuming them with range requests if needed.
+
+use core::{fmt, str};
+use core::time::Duration;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use fs_node::{DirRef, FileRef};
+use memfs::MemFile;
+use net::{TcpStream, wire::IpEndpoint};
+use percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
+use super::check_http_request;
+
+/// The port used by URLs that don't specify one.
+const DEFAULT_PORT: u16 = 80;
+/// The default timeout for connecting and for each read or write on a connection.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The default maximum number of redirects followed for a single request.
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+/// The maximum number of headers in a response.
+const MAX_HEADERS: usize = 64;
+/// The maximum length of a response's status line and headers,
+/// and of each line of a chunked body.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+/// How many bytes are read from a connection at once.
+const READ_CHUNK_SIZE: usize = 4096;
+
+
+/// An `http://` URL.
+///
+/// Other schemes, user information, and fragments aren't supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    /// The host name or IP address of the server.
+    pub host: String,
+    /// The TCP port of the server.
+    pub port: u16,
+    /// The absolute path of the resource, including its query string, if any.
+    pub path: String,
+}
+
+impl Url {
+    /// Creates a URL for the resource at `path` on the server at `host` and `port`.
+    ///
+    /// Unlike [`Url::parse()`], characters in `path` that would otherwise end the path
+    /// are percent-encoded, e.g., a `#` or `?` in a file name.
+    pub fn new(host: &str, port: u16, path: &str) -> Result<Url, &'static str> {
+        let path = utf8_percent_encode(path, DEFAULT_ENCODE_SET).to_string();
+        let path = if path.starts_with('/') { path } else { format!("/{}", path) };
+        Url::from_parts(host, port, path)
+    }
+
+    /// Parses a URL of the form `http://host[:port][/path][?query][#fragment]`.
+    ///
+    /// The host may be an IPv6 address enclosed in brackets, e.g., `http://[::1]:8080/`.
+    /// The fragment, if present, is ignored, as it's never sent to the server.
+    pub fn parse(url: &str) -> Result<Url, &'static str> {
+        let rest = strip_prefix_ignore_case(url.trim(), "http://")
+            .ok_or("http_client: only http:// URLs are supported")?;
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, path) = match rest.find(&['/', '?'][..]) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') { format!("/{}", path) } else { path.to_string() };
+        let path = utf8_percent_encode(&path, SPACE_ENCODE_SET).to_string();
+
+        if authority.contains('@') {
+            return Err("http_client: URLs with user information aren't supported");
+        }
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            let (host, after) = bracketed.split_once(']').ok_or("http_client: unterminated IPv6 address in URL")?;
+            match after {
+                "" => (host, None),
+                _ => (host, Some(after.strip_prefix(':').ok_or("http_client: invalid characters after IPv6 address in URL")?)),
+            }
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port.parse::<u16>().map_err(|_e| "http_client: invalid port in URL")?,
+            None => DEFAULT_PORT,
+        };
+        Url::from_parts(host, port, path)
+    }
+
+    fn from_parts(host: &str, port: u16, path: String) -> Result<Url, &'static str> {
+        if host.is_empty() {
+            return Err("http_client: URL has no host");
+        }
+        if host.chars().chain(path.chars()).any(|c| c.is_ascii_control() || c.is_whitespace()) {
+            return Err("http_client: URL contains whitespace or control characters");
+        }
+        Ok(Url { host: host.to_string(), port, path })
+    }
+
+    /// Resolves the `location` of a redirect relative to this URL.
+    ///
+    /// `location` may be an absolute URL, an absolute path on the same server,
+    /// or a path relative to this URL's path.
+    pub fn join(&self, location: &str) -> Result<Url, &'static str> {
+        let location = location.trim();
+        if strip_prefix_ignore_case(location, "http://").is_some() {
+            return Url::parse(location);
+        }
+        if location.contains("://") {
+            return Err("http_client: redirected to a URL with an unsupported scheme");
+        }
+        if location.starts_with("//") {
+            return Url::parse(&format!("http:{}", location));
+        }
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let base = self.path.split('?').next().unwrap_or_default();
+            let dir = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", dir, location)
+        };
+        let path = utf8_percent_encode(&path, SPACE_ENCODE_SET).to_string();
+        Url::from_parts(&self.host, self.port, path)
+    }
+
+    /// Returns the value of this URL's `Host` header.
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == DEFAULT_PORT { host } else { format!("{}:{}", host, self.port) }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}{}", self.host_header(), self.path)
+    }
+}
+
+/// Encodes only the characters that may appear in a URL typed by a user
+/// but not in the target of a request line.
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+struct SPACE_ENCODE_SET;
+impl percent_encoding::EncodeSet for SPACE_ENCODE_SET {
+    fn contains(&self, byte: u8) -> bool {
+        byte == b' ' || byte == b'"' || byte == b'<' || byte == b'>' || !(0x20..0x7F).contains(&byte)
+    }
+}
+
+fn strip_prefix_ignore_case<'s>(s: &'s str, prefix: &str) -> Option<&'s str> {
+    match s.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+
+/// The HTTP methods supported by the [`HttpClient`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+        }
+    }
+}
+
+
+/// A response that has been fully received from a server.
+pub struct Response {
+    /// The status code, e.g., 200, 404.
+    pub status_code: u16,
+    /// The reason, e.g., "OK", "Not Found".
+    pub reason: String,
+    /// The headers, as (name, value) pairs in the order they were received.
+    pub headers: Vec<(String, String)>,
+    /// The body, after any transfer encoding was removed.
+    /// This is empty if the body was streamed elsewhere, e.g., by [`HttpClient::download()`].
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the value of the first header with the given `name`, which is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Returns true if the status code indicates success, i.e., it is within 200-299.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+
+    /// Returns the body of this `Response` as a `Result`,
+    /// in which `Ok(body)` is returned if the status code indicates success,
+    /// and `Err((status_code, reason))` is returned otherwise.
+    pub fn as_result(&self) -> Result<&[u8], (u16, &str)> {
+        if self.is_success() {
+            Ok(&self.body)
+        } else {
+            Err((self.status_code, &self.reason))
+        }
+    }
+
+    /// A convenience function that just returns a standard Err `&str`.
+    pub fn as_result_err_str(&self) -> Result<&[u8], &'static str> {
+        self.as_result().map_err(|_e| {
+            error!("http_client: error code {}, reason {:?}", _e.0, _e.1);
+            "http_client: response had an error status code"
+        })
+    }
+}
+
+fn find_header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    headers.iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// A function that receives the parts of a response body as they arrive.
+type Sink<'s> = dyn FnMut(&[u8]) -> Result<(), &'static str> + 's;
+
+/// How the end of a response body is determined.
+enum BodyLength {
+    /// The response has no body.
+    Empty,
+    /// The body is exactly this many bytes long.
+    Fixed(usize),
+    /// The body uses the chunked transfer encoding.
+    Chunked,
+    /// The body ends when the server closes the connection.
+    UntilClose,
+}
+
+
+/// The stream that responses are received from, which is a TCP stream except in tests.
+trait Source {
+    /// Reads bytes into `buf`, returning how many were read, which is 0 once the stream is closed.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, &'static str>;
+}
+
+impl Source for TcpStream {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, &'static str> {
+        TcpStream::read(self, buf).map_err(stream_error)
+    }
+}
+
+/// An open connection to a server, along with any bytes received on it
+/// that haven't been consumed yet.
+struct Connection<S: Source = TcpStream> {
+    host: String,
+    port: u16,
+    stream: S,
+    buffer: Vec<u8>,
+    /// The number of responses fully received on this connection.
+    responses: usize,
+}
+
+impl Connection {
+    fn open(host: &str, port: u16, timeout: Duration) -> Result<Connection, &'static str> {
+        let address = dns::lookup_host(host)?
+            .into_iter()
+            .next()
+            .ok_or("http_client: host has no IP addresses")?;
+        let stream = TcpStream::connect(IpEndpoint::new(address, port), Some(timeout))
+            .map_err(|e| match e {
+                net::Error::TimedOut => "http_client: timed out connecting to server",
+                net::Error::ConnectionRefused => "http_client: server refused the connection",
+                _ => "http_client: couldn't connect to server",
+            })?;
+        stream.set_read_timeout(Some(timeout));
+        stream.set_write_timeout(Some(timeout));
+        debug!("http_client: connected to {}:{}", host, port);
+        Ok(Connection { host: host.to_string(), port, stream, buffer: Vec::new(), responses: 0 })
+    }
+
+    fn write_all(&self, mut data: &[u8]) -> Result<(), &'static str> {
+        while !data.is_empty() {
+            let written = self.stream.write(data).map_err(stream_error)?;
+            data = &data[written..];
+        }
+        Ok(())
+    }
+}
+
+impl<S: Source> Connection<S> {
+    /// Reads more bytes from the server into the buffer,
+    /// returning how many were read, which is 0 once the server has closed the connection.
+    fn fill(&mut self) -> Result<usize, &'static str> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let len = self.stream.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..len]);
+        Ok(len)
+    }
+
+    /// Reads a line terminated by CRLF, returning it without the terminator.
+    fn read_line(&mut self) -> Result<String, &'static str> {
+        let mut searched = 0;
+        loop {
+            if let Some(i) = self.buffer[searched..].windows(2).position(|w| w == b"\r\n") {
+                let end = searched + i;
+                let line = str::from_utf8(&self.buffer[..end])
+                    .map_err(|_e| "http_client: received a line that isn't valid UTF-8")?
+                    .to_string();
+                self.buffer.drain(..end + 2);
+                return Ok(line);
+            }
+            searched = self.buffer.len().saturating_sub(1);
+            if self.buffer.len() > MAX_HEAD_LEN {
+                return Err("http_client: received a line that is too long");
+            }
+            if self.fill()? == 0 {
+                return Err("http_client: connection closed before full response was received");
+            }
+        }
+    }
+
+    /// Reads exactly `len` bytes, passing them to `sink` as they arrive.
+    fn read_exact(&mut self, mut len: usize, sink: &mut Sink) -> Result<(), &'static str> {
+        while len > 0 {
+            if self.buffer.is_empty() && self.fill()? == 0 {
+                return Err("http_client: connection closed before full response was received");
+            }
+            let available = len.min(self.buffer.len());
+            sink(&self.buffer[..available])?;
+            self.buffer.drain(..available);
+            len -= available;
+        }
+        Ok(())
+    }
+
+    /// Receives a response's status line and headers.
+    ///
+    /// Returns the status code, reason, headers, and whether the server
+    /// uses HTTP/1.1 and therefore keeps the connection open by default.
+    fn read_head(&mut self) -> Result<(u16, String, Vec<(String, String)>, bool), &'static str> {
+        loop {
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut response = httparse::Response::new(&mut headers);
+            match response.parse(&self.buffer) {
+                Ok(httparse::Status::Complete(head_len)) => {
+                    let status_code = response.code.ok_or("http_client: response had no status code")?;
+                    let reason = response.reason.unwrap_or_default().to_string();
+                    let is_http_1_1 = response.version == Some(1);
+                    let headers = response.headers.iter()
+                        .map(|h| (h.name.to_string(), String::from_utf8_lossy(h.value).trim().to_string()))
+                        .collect();
+                    self.buffer.drain(..head_len);
+                    return Ok((status_code, reason, headers, is_http_1_1));
+                }
+                Ok(httparse::Status::Partial) => {}
+                Err(_e) => {
+                    error!("http_client: error parsing response headers: {:?}", _e);
+                    return Err("http_client: received a malformed response");
+                }
+            }
+            if self.buffer.len() > MAX_HEAD_LEN {
+                return Err("http_client: response headers are too long");
+            }
+            if self.fill()? == 0 {
+                return Err(CLOSED_BEFORE_RESPONSE);
+            }
+        }
+    }
+
+    /// Receives a response body of the given `length`, passing its contents to `sink`.
+    fn read_body(&mut self, length: BodyLength, sink: &mut Sink) -> Result<(), &'static str> {
+        match length {
+            BodyLength::Empty => Ok(()),
+            BodyLength::Fixed(len) => self.read_exact(len, sink),
+            BodyLength::Chunked => {
+                loop {
+                    let line = self.read_line()?;
+                    // Chunk extensions after a semicolon are ignored.
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    let size = usize::from_str_radix(size, 16)
+                        .map_err(|_e| "http_client: invalid chunk size in chunked response")?;
+                    if size == 0 {
+                        break;
+                    }
+                    self.read_exact(size, sink)?;
+                    if !self.read_line()?.is_empty() {
+                        return Err("http_client: chunk didn't end with CRLF in chunked response");
+                    }
+                }
+                // Skip the trailer headers, which end with an empty line.
+                while !self.read_line()?.is_empty() { }
+                Ok(())
+            }
+            BodyLength::UntilClose => {
+                loop {
+                    if !self.buffer.is_empty() {
+                        sink(&self.buffer)?;
+                        self.buffer.clear();
+                    }
+                    if self.fill()? == 0 {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+const CLOSED_BEFORE_RESPONSE: &str = "http_client: connection closed before a response was received";
+
+fn stream_error(error: net::Error) -> &'static str {
+    match error {
+        net::Error::TimedOut => "http_client: timed out waiting for the server",
+        net::Error::ConnectionReset => "http_client: connection was reset by the server",
+        _ => "http_client: error on TCP connection",
+    }
+}
+
+/// Determines how the body of a response to a `method` request is delimited.
+fn body_length(method: Method, status_code: u16, headers: &[(String, String)]) -> Result<BodyLength, &'static str> {
+    if method == Method::Head || (100..200).contains(&status_code) || status_code == 204 || status_code == 304 {
+        return Ok(BodyLength::Empty);
+    }
+    if let Some(encoding) = find_header(headers, "Transfer-Encoding") {
+        return if encoding.rsplit(',').next().map(str::trim).map_or(false, |e| e.eq_ignore_ascii_case("chunked")) {
+            Ok(BodyLength::Chunked)
+        } else {
+            Err("http_client: unsupported transfer encoding")
+        };
+    }
+    match find_header(headers, "Content-Length") {
+        Some(len) => len.parse::<usize>()
+            .map(BodyLength::Fixed)
+            .map_err(|_e| "http_client: invalid Content-Length header"),
+        None => Ok(BodyLength::UntilClose),
+    }
+}
+
+/// Returns the first byte position of a `Content-Range: bytes <start>-<end>/<length>` header.
+fn content_range_start(headers: &[(String, String)]) -> Option<usize> {
+    let range = find_header(headers, "Content-Range")?.trim().strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+fn is_redirect(status_code: u16) -> bool {
+    matches!(status_code, 301 | 302 | 303 | 307 | 308)
+}
+
+
+/// An HTTP/1.1 client that keeps its connection to a server open between requests.
+///
+/// Requests to the same host and port as the previous request reuse its connection,
+/// unless the server closed it. Redirects are followed automatically.
+///
+/// # Example
+/// ```
+/// let mut client = HttpClient::new();
+/// let listing = client.get(&Url::parse("http://10.0.2.2:8090/updates.txt")?)?;
+/// let file = client.download(&Url::parse("http://10.0.2.2:8090/build/diff.txt")?, "diff.txt", &dir)?;
+/// ```
+pub struct HttpClient {
+    connection: Option<Connection>,
+    timeout: Duration,
+    max_redirects: usize,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient::new()
+    }
+}
+
+impl HttpClient {
+    /// Creates a new client that isn't connected to any server yet.
+    pub fn new() -> HttpClient {
+        HttpClient {
+            connection: None,
+            timeout: DEFAULT_TIMEOUT,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// Sets the timeout for connecting to a server and for each read and write on the connection.
+    ///
+    /// This doesn't bound the total time of a request: a large body may take
+    /// much longer to receive, as long as the server keeps sending data.
+    pub fn with_timeout(mut self, timeout: Duration) -> HttpClient {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of redirects followed for a single request.
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> HttpClient {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Sends a GET request to `url` and returns the response, including its body.
+    pub fn get(&mut self, url: &Url) -> Result<Response, &'static str> {
+        self.send(Method::Get, url)
+    }
+
+    /// Sends a HEAD request to `url` and returns the response, which has no body.
+    pub fn head(&mut self, url: &Url) -> Result<Response, &'static str> {
+        self.send(Method::Head, url)
+    }
+
+    /// Sends a request to `url` and returns the response, including its body.
+    pub fn send(&mut self, method: Method, url: &Url) -> Result<Response, &'static str> {
+        let mut body = Vec::new();
+        let mut response = self.request(method, url, 0, &mut |data: &[u8]| {
+            body.extend_from_slice(data);
+            Ok(())
+        })?;
+        response.body = body;
+        Ok(response)
+    }
+
+    /// Downloads the resource at `url` into a new file called `name` in the `parent` directory,
+    /// without buffering the whole body in memory.
+    ///
+    /// Any existing node called `name` in `parent` is replaced by the new file,
+    /// so no stale contents of a previous, longer file remain after the download.
+    ///
+    /// Returns the new file, or an error if the server
+    /// didn't respond with a success status code.
+    pub fn download(&mut self, url: &Url, name: &str, parent: &DirRef) -> Result<FileRef, &'static str> {
+        let file = MemFile::create(name.to_string(), parent)?;
+        let mut offset = 0;
+        self.download_with(url, |data| {
+            file.lock().write_at(data, offset)?;
+            offset += data.len();
+            Ok(())
+        })?;
+        Ok(file)
+    }
+
+    /// Downloads the resource at `url`, passing each part of its body to `sink` as it arrives.
+    ///
+    /// Returns the response, whose body is empty, or an error if the server
+    /// didn't respond with a success status code, in which case `sink` is never called.
+    pub fn download_with<F>(&mut self, url: &Url, mut sink: F) -> Result<Response, &'static str>
+        where F: FnMut(&[u8]) -> Result<(), &'static str>
+    {
+        let response = self.request(Method::Get, url, 0, &mut sink)?;
+        if !response.is_success() {
+            error!("http_client: failed to download {}, error {}: {}", url, response.status_code, response.reason);
+            return Err("http_client: download failed with an error status code");
+        }
+        Ok(response)
+    }
+
+    /// Downloads the resource at `url` from byte `offset` onwards, passing each part
+    /// of its body to `sink` as it arrives, e.g., to resume a download that failed.
+    ///
+    /// A `Range` request is sent if `offset` isn't zero. If the server doesn't support
+    /// range requests and responds with the whole resource, the bytes before `offset`
+    /// are discarded, so `sink` always receives the resource starting at `offset`.
+    ///
+    /// Unlike [`download_with()`](Self::download_with), an error status code isn't an error:
+    /// the response is returned without calling `sink`, so that callers can tell it apart
+    /// from a failed connection. The status code is `416` if `offset` is at or past the end of the resource.
+    pub fn download_range_with<F>(&mut self, url: &Url, offset: usize, mut sink: F) -> Result<Response, &'static str>
+        where F: FnMut(&[u8]) -> Result<(), &'static str>
+    {
+        self.request(Method::Get, url, offset, &mut sink)
+    }
+
+    /// Closes the connection to the server, if one is open.
+    pub fn close(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.stream.shutdown();
+        }
+    }
+
+    /// Sends a request, following redirects, and passes the body of a
+    /// successful final response to `sink`, starting at byte `offset`.
+    /// The bodies of redirects and error responses are discarded.
+    fn request(
+        &mut self,
+        method: Method,
+        url: &Url,
+        offset: usize,
+        sink: &mut Sink,
+    ) -> Result<Response, &'static str> {
+        let mut url = url.clone();
+        let mut redirects = 0;
+        loop {
+            let response = self.request_once(method, &url, offset, sink)?;
+            let location = match response.header("Location") {
+                Some(location) if is_redirect(response.status_code) => location,
+                _ => return Ok(response),
+            };
+            if redirects >= self.max_redirects {
+                return Err("http_client: too many redirects");
+            }
+            redirects += 1;
+            url = url.join(location)?;
+            debug!("http_client: redirected to {}", url);
+        }
+    }
+
+    /// Sends one request, reconnecting and retrying once if a reused connection
+    /// turns out to have been closed by the server in the meantime.
+    fn request_once(
+        &mut self,
+        method: Method,
+        url: &Url,
+        offset: usize,
+        sink: &mut Sink,
+    ) -> Result<Response, &'static str> {
+        let range = if offset > 0 { format!("Range: bytes={}-\r\n", offset) } else { String::new() };
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}Accept-Encoding: identity\r\nConnection: keep-alive\r\n\r\n",
+            method.as_str(),
+            url.path,
+            url.host_header(),
+            range,
+        );
+        if !check_http_request(request.as_bytes()) {
+            return Err("http_client: created an improper HTTP request");
+        }
+
+        let reusable = matches!(&self.connection, Some(c) if c.host == url.host && c.port == url.port);
+        if !reusable {
+            self.close();
+        }
+        let mut reused = self.connection.is_some();
+        loop {
+            let mut connection = match self.connection.take() {
+                Some(connection) => connection,
+                None => Connection::open(&url.host, url.port, self.timeout)?,
+            };
+            let head = connection.write_all(request.as_bytes()).and_then(|_| connection.read_head());
+            let (status_code, reason, headers, is_http_1_1) = match head {
+                Ok(head) => head,
+                Err(_e) if reused => {
+                    // The server may close an idle keep-alive connection at any time.
+                    debug!("http_client: reused connection failed ({}), reconnecting", _e);
+                    connection.stream.shutdown();
+                    reused = false;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let length = body_length(method, status_code, &headers)?;
+            let keep_alive = !matches!(length, BodyLength::UntilClose) && match find_header(&headers, "Connection") {
+                Some(c) if c.eq_ignore_ascii_case("close") => false,
+                Some(c) if c.eq_ignore_ascii_case("keep-alive") => true,
+                _ => is_http_1_1,
+            };
+
+            if (200..300).contains(&status_code) {
+                let mut skip = offset;
+                if status_code == 206 && offset > 0 {
+                    if content_range_start(&headers) != Some(offset) {
+                        return Err("http_client: server responded with a different range than requested");
+                    }
+                    skip = 0;
+                }
+                // If the server ignored the range, the part of the body before `offset` is discarded.
+                connection.read_body(length, &mut |data: &[u8]| {
+                    let skipped = skip.min(data.len());
+                    skip -= skipped;
+                    if skipped < data.len() { sink(&data[skipped..]) } else { Ok(()) }
+                })?;
+            } else {
+                connection.read_body(length, &mut |_data: &[u8]| Ok(()))?;
+            }
+            connection.responses += 1;
+            trace!("http_client: received response {} on connection to {}:{}", connection.responses, url.host, url.port);
+
+            if keep_alive {
+                self.connection = Some(connection);
+            } else {
+                connection.stream.shutdown();
+            }
+            return Ok(Response { status_code, reason, headers, body: Vec::new() });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use core::cell::RefCell;
+    use alloc::{collections::VecDeque, vec};
+
+    /// A source that returns the given pieces, one per read, and then reports that it's closed.
+    struct Pieces(RefCell<VecDeque<Vec<u8>>>);
+
+    impl Source for Pieces {
+        fn read(&self, buf: &mut [u8]) -> Result<usize, &'static str> {
+            let mut pieces = self.0.borrow_mut();
+            let piece = match pieces.front_mut() {
+                Some(piece) => piece,
+                None => return Ok(0),
+            };
+            let len = piece.len().min(buf.len());
+            buf[..len].copy_from_slice(&piece[..len]);
+            piece.drain(..len);
+            if piece.is_empty() {
+                pieces.pop_front();
+            }
+            Ok(len)
+        }
+    }
+
+    fn connection(pieces: &[&[u8]]) -> Connection<Pieces> {
+        Connection {
+            host: String::from("example.com"),
+            port: DEFAULT_PORT,
+            stream: Pieces(RefCell::new(pieces.iter().map(|p| p.to_vec()).collect())),
+            buffer: Vec::new(),
+            responses: 0,
+        }
+    }
+
+    fn read_body(connection: &mut Connection<Pieces>, length: BodyLength) -> Result<Vec<u8>, &'static str> {
+        let mut body = Vec::new();
+        connection.read_body(length, &mut |data: &[u8]| {
+            body.extend_from_slice(data);
+            Ok(())
+        })?;
+        Ok(body)
+    }
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    fn url(host: &str, port: u16, path: &str) -> Url {
+        Url { host: host.to_string(), port, path: path.to_string() }
+    }
+
+    #[test]
+    fn parse_url() {
+        assert_eq!(Url::parse("http://example.com"), Ok(url("example.com", 80, "/")));
+        assert_eq!(Url::parse(" HTTP://example.com:8090/a/b.txt "), Ok(url("example.com", 8090, "/a/b.txt")));
+        assert_eq!(Url::parse("http://example.com?q=1"), Ok(url("example.com", 80, "/?q=1")));
+        assert_eq!(Url::parse("http://example.com/a?q=1#frag"), Ok(url("example.com", 80, "/a?q=1")));
+        assert_eq!(Url::parse("http://example.com/a b"), Ok(url("example.com", 80, "/a%20b")));
+        assert_eq!(Url::parse("http://[::1]:8080/x"), Ok(url("::1", 8080, "/x")));
+        assert_eq!(Url::parse("http://[::1]"), Ok(url("::1", 80, "/")));
+    }
+
+    #[test]
+    fn parse_invalid_url() {
+        assert!(Url::parse("https://example.com/").is_err());
+        assert!(Url::parse("example.com/").is_err());
+        assert!(Url::parse("http://user@example.com/").is_err());
+        assert!(Url::parse("http://example.com:http/").is_err());
+        assert!(Url::parse("http://example.com:65536/").is_err());
+        assert!(Url::parse("http:///path").is_err());
+        assert!(Url::parse("http://[::1/").is_err());
+        assert!(Url::parse("http://[::1]8080/").is_err());
+    }
+
+    #[test]
+    fn display_url() {
+        assert_eq!(url("example.com", 80, "/a").to_string(), "http://example.com/a");
+        assert_eq!(url("::1", 8080, "/").to_string(), "http://[::1]:8080/");
+    }
+
+    #[test]
+    fn join_absolute_redirect() {
+        let base = url("example.com", 8090, "/a/b.txt");
+        assert_eq!(base.join("http://other.org/c"), Ok(url("other.org", 80, "/c")));
+        assert_eq!(base.join("//other.org:81/c"), Ok(url("other.org", 81, "/c")));
+        assert!(base.join("https://other.org/c").is_err());
+    }
+
+    #[test]
+    fn join_relative_redirect() {
+        let base = url("example.com", 8090, "/a/b.txt?x=/y");
+        assert_eq!(base.join("/c/d.txt"), Ok(url("example.com", 8090, "/c/d.txt")));
+        assert_eq!(base.join("d.txt"), Ok(url("example.com", 8090, "/a/d.txt")));
+        assert_eq!(base.join("d e.txt"), Ok(url("example.com", 8090, "/a/d%20e.txt")));
+        assert_eq!(url("example.com", 80, "/").join("d.txt"), Ok(url("example.com", 80, "/d.txt")));
+    }
+
+    #[test]
+    fn read_chunked_body() {
+        let mut connection = connection(&[
+            b"4\r\nWi", b"ki\r\n5;name=value\r\npedia\r", b"\n0\r\n",
+            b"Expires: never\r\nX-Other: 1\r\n\r\nHTTP/1.1",
+        ]);
+        assert_eq!(read_body(&mut connection, BodyLength::Chunked), Ok(b"Wikipedia".to_vec()));
+        // The bytes of the next response are left in the buffer.
+        assert_eq!(connection.buffer, b"HTTP/1.1");
+    }
+
+    #[test]
+    fn read_chunked_body_with_uppercase_hex_size() {
+        let body = vec![b'x'; 0x1A];
+        let mut data = b"1A\r\n".to_vec();
+        data.extend_from_slice(&body);
+        data.extend_from_slice(b"\r\n0\r\n\r\n");
+        let mut connection = connection(&[&data]);
+        assert_eq!(read_body(&mut connection, BodyLength::Chunked), Ok(body));
+    }
+
+    #[test]
+    fn read_chunked_body_with_bad_size() {
+        for data in [&b"zz\r\nabc\r\n0\r\n\r\n"[..], b"\r\n0\r\n\r\n", b"-1\r\n0\r\n\r\n", b"0x4\r\nWiki\r\n0\r\n\r\n"] {
+            assert!(read_body(&mut connection(&[data]), BodyLength::Chunked).is_err());
+        }
+    }
+
+    #[test]
+    fn read_malformed_chunked_body() {
+        // The chunk is longer than its size.
+        assert!(read_body(&mut connection(&[b"3\r\nWiki\r\n0\r\n\r\n"]), BodyLength::Chunked).is_err());
+        // The connection is closed in the middle of a chunk, before the last chunk, or in the trailer.
+        assert!(read_body(&mut connection(&[b"4\r\nWi"]), BodyLength::Chunked).is_err());
+        assert!(read_body(&mut connection(&[b"4\r\nWiki\r\n"]), BodyLength::Chunked).is_err());
+        assert!(read_body(&mut connection(&[b"4\r\nWiki\r\n0\r\nExpires: never\r\n"]), BodyLength::Chunked).is_err());
+    }
+
+    #[test]
+    fn read_fixed_and_until_close_bodies() {
+        let mut fixed = connection(&[b"Wiki", b"pediaHTTP"]);
+        assert_eq!(read_body(&mut fixed, BodyLength::Fixed(9)), Ok(b"Wikipedia".to_vec()));
+        assert_eq!(fixed.buffer, b"HTTP");
+        assert_eq!(read_body(&mut fixed, BodyLength::Empty), Ok(Vec::new()));
+        assert!(read_body(&mut connection(&[b"Wiki"]), BodyLength::Fixed(9)).is_err());
+        assert_eq!(read_body(&mut connection(&[b"Wiki", b"pedia"]), BodyLength::UntilClose), Ok(b"Wikipedia".to_vec()));
+    }
+
+    #[test]
+    fn body_length_of_responses() {
+        assert!(matches!(body_length(Method::Get, 200, &headers(&[("content-length", "42")])), Ok(BodyLength::Fixed(42))));
+        assert!(matches!(body_length(Method::Get, 200, &headers(&[])), Ok(BodyLength::UntilClose)));
+        assert!(matches!(body_length(Method::Get, 200, &headers(&[("Transfer-Encoding", "gzip, Chunked")])), Ok(BodyLength::Chunked)));
+        // The transfer encoding takes precedence over the Content-Length.
+        assert!(matches!(
+            body_length(Method::Get, 200, &headers(&[("Content-Length", "42"), ("Transfer-Encoding", "chunked")])),
+            Ok(BodyLength::Chunked)
+        ));
+        assert!(matches!(body_length(Method::Head, 200, &headers(&[("Content-Length", "42")])), Ok(BodyLength::Empty)));
+        for status_code in [101, 204, 304] {
+            assert!(matches!(body_length(Method::Get, status_code, &headers(&[("Content-Length", "42")])), Ok(BodyLength::Empty)));
+        }
+    }
+
+    #[test]
+    fn body_length_of_invalid_responses() {
+        assert!(body_length(Method::Get, 200, &headers(&[("Transfer-Encoding", "gzip")])).is_err());
+        assert!(body_length(Method::Get, 200, &headers(&[("Transfer-Encoding", "chunked, gzip")])).is_err());
+        assert!(body_length(Method::Get, 200, &headers(&[("Content-Length", "-1")])).is_err());
+        assert!(body_length(Method::Get, 200, &headers(&[("Content-Length", "forty-two")])).is_err());
+    }
+
+    #[test]
+    fn content_range_start_of_responses() {
+        assert_eq!(content_range_start(&headers(&[("Content-Range", "bytes 100-199/200")])), Some(100));
+        assert_eq!(content_range_start(&headers(&[("content-range", " bytes 0-0/*")])), Some(0));
+        assert_eq!(content_range_start(&headers(&[])), None);
+        assert_eq!(content_range_start(&headers(&[("Content-Range", "bytes */200")])), None);
+        assert_eq!(content_range_start(&headers(&[("Content-Range", "items 100-199/200")])), None);
+    }
+}
@@ -1,5 +1,8 @@
 //! Functions for creating and sending HTTP requests and receiving responses.
 //! 
+//! The [`HttpClient`] is the easiest way to fetch resources from a URL.
+//! The lower-level [`send_request()`] sends hand-assembled requests over an existing
+//! TCP socket of the legacy `network_manager` stack.
 
 #![no_std]
 #![feature(slice_concat_ext)]
@@ -10,6 +13,11 @@ extern crate smoltcp;
 extern crate network_manager;
 extern crate hpet;
 extern crate httparse;
+extern crate dns;
+extern crate net;
+extern crate fs_node;
+extern crate memfs;
+extern crate percent_encoding;
 #[macro_use] extern crate smoltcp_helper;
 
 use core::str;
@@ -18,10 +26,14 @@ use alloc::string::String;
 use hpet::get_hpet;
 use smoltcp::{
     socket::{SocketSet, TcpSocket, SocketHandle},
+    wire::{IpAddress, IpEndpoint, Ipv4Address, Ipv6Address},
 };
 use network_manager::{NetworkInterfaceRef};
 use smoltcp_helper::{millis_since, poll_iface};
 
+mod client;
+pub use client::{HttpClient, Method, Response, Url};
+
 /// The states that implement the finite state machine for 
 /// sending and receiving the HTTP request and response, respectively.
 #[derive(Debug)]
@@ -42,6 +54,24 @@ pub fn check_http_request(request_bytes: &[u8]) -> bool {
 }
 
 
+/// Resolves the given `host`, either a host name or an IP address, 
+/// into the remote endpoint at the given `port` that an HTTP request can be sent to.
+/// 
+/// If the host has multiple addresses, the first one is used.
+pub fn resolve_endpoint(host: &str, port: u16) -> Result<IpEndpoint, &'static str> {
+    let address = dns::lookup_host(host)?
+        .into_iter()
+        .next()
+        .ok_or("http_client: host has no IP addresses")?;
+    // The `dns` crate uses a newer version of smoltcp than this crate does.
+    let address = match address {
+        dns::IpAddress::Ipv4(address) => IpAddress::Ipv4(Ipv4Address::from_bytes(&address.0)),
+        dns::IpAddress::Ipv6(address) => IpAddress::Ipv6(Ipv6Address::from_bytes(&address.0)),
+    };
+    Ok(IpEndpoint::new(address, port))
+}
+
+
 /// TODO: create a proper HttpRequest type with header creation fields and actual verification
 pub type HttpRequest = String;
 
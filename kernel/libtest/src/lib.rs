@@ -40,10 +40,7 @@ macro_rules! CPU_ID {
 
 /// Helper function return the tasks in a given core's runqueue
 pub fn nr_tasks_in_rq(core: u8) -> Option<usize> {
-	match runqueue::get_runqueue(core).map(|rq| rq.read()) {
-		Some(rq) => { Some(rq.iter().count()) }
-		_ => { None }
-	}
+	runqueue::get_scheduler(core).map(|sched| sched.read().tasks().len())
 }
 
 
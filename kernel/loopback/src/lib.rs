@@ -0,0 +1,92 @@
+//! A software loopback network device.
+//!
+//! Every frame sent through the device is received by it again,
+//! so the interface that it's registered with can reach itself at `127.0.0.1` and `::1`.
+//! This allows clients and servers to talk to each other over TCP or UDP
+//! within a single Theseus instance, without any networking hardware.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{collections::VecDeque, sync::Arc, vec};
+use irq_safety::MutexIrqSafe;
+use net::{
+    wire::{IpCidr, Ipv4Address, Ipv4Cidr, Ipv6Address, Ipv6Cidr},
+    NetworkDevice, NetworkInterface,
+};
+use nic_buffers::{ReceiveBuffer, ReceivedFrame};
+use nic_initialization::init_rx_buf_pool;
+use spin::Once;
+
+/// The address of the loopback interface.
+pub const LOOPBACK_ADDRESS: Ipv4Cidr = Ipv4Cidr::new(Ipv4Address::new(127, 0, 0, 1), 8);
+/// The IPv6 address of the loopback interface.
+pub const LOOPBACK_ADDRESS_V6: Ipv6Cidr = Ipv6Cidr::new(Ipv6Address::LOOPBACK, 128);
+
+/// The MAC address of the loopback device, a locally-administered unicast address.
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+/// The number of frames that can be waiting to be received at once.
+/// Frames sent while the queue is full are dropped.
+const RX_BUFFER_POOL_SIZE: usize = 128;
+/// The size of each receive buffer, which fits a standard Ethernet frame.
+const RX_BUFFER_SIZE: u16 = 2048;
+
+lazy_static::lazy_static! {
+    /// The pool of buffers that sent frames are copied into.
+    static ref RX_BUFFER_POOL: mpmc::Queue<ReceiveBuffer> = mpmc::Queue::with_capacity(RX_BUFFER_POOL_SIZE);
+}
+
+static LOOPBACK: Once<MutexIrqSafe<Loopback>> = Once::new();
+
+/// A network device that receives every frame that it sends.
+pub struct Loopback {
+    frames: VecDeque<ReceivedFrame>,
+}
+
+impl NetworkDevice for Loopback {
+    fn send(&mut self, buf: &[u8]) -> Result<(), net::Error> {
+        let length = u16::try_from(buf.len())
+            .ok()
+            .filter(|&length| length <= RX_BUFFER_SIZE)
+            .ok_or(net::Error::Truncated)?;
+        let mut buffer = RX_BUFFER_POOL.pop().ok_or(net::Error::Exhausted)?;
+        buffer.set_length(length).map_err(|_| net::Error::Truncated)?;
+        buffer.copy_from_slice(buf);
+        self.frames.push_back(ReceivedFrame(vec![buffer]));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<ReceivedFrame> {
+        self.frames.pop_front()
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        MAC_ADDRESS
+    }
+}
+
+/// Creates the loopback device, registers it with the `net` crate,
+/// and assigns [`LOOPBACK_ADDRESS`] and [`LOOPBACK_ADDRESS_V6`] to its interface.
+///
+/// The loopback interface should be registered after all NICs,
+/// so that it doesn't become the default interface.
+pub fn init() -> Result<Arc<NetworkInterface>, &'static str> {
+    if LOOPBACK.is_completed() {
+        return Err("the loopback device was already initialized");
+    }
+    init_rx_buf_pool(RX_BUFFER_POOL_SIZE, RX_BUFFER_SIZE, &RX_BUFFER_POOL)?;
+    let device = LOOPBACK.call_once(|| {
+        MutexIrqSafe::new(Loopback {
+            frames: VecDeque::with_capacity(RX_BUFFER_POOL_SIZE),
+        })
+    });
+
+    let interface = net::register_device(device)?;
+    interface.set_ipv4_config(Some(LOOPBACK_ADDRESS), None);
+    interface
+        .add_ip_addr(IpCidr::Ipv6(LOOPBACK_ADDRESS_V6))
+        .map_err(|_| "couldn't assign the IPv6 loopback address")?;
+    Ok(interface)
+}
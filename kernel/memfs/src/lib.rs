@@ -14,7 +14,7 @@ extern crate io;
 
 
 use alloc::string::String;
-use fs_node::{DirRef, WeakDirRef, File, FsNode};
+use fs_node::{Attributes, DirRef, WeakDirRef, File, FsNode, Metadata, NodeType, Permissions};
 use memory::{MappedPages, get_kernel_mmi_ref, allocate_pages_by_bytes, PteFlags};
 use alloc::sync::Arc;
 use spin::Mutex;
@@ -32,6 +32,8 @@ pub struct MemFile {
     mp: MappedPages,
     /// The parent directory that contains this file.
     parent: WeakDirRef,
+    /// The timestamps and permissions of this file.
+    attributes: Attributes,
 }
 
 impl MemFile {
@@ -48,6 +50,7 @@ impl MemFile {
             len,
             mp: mapped_pages, 
             parent: Arc::downgrade(parent), 
+            attributes: Attributes::new(),
         };
         let file_ref = Arc::new(Mutex::new(memfile)) as FileRef;
         parent.lock().insert(FileOrDir::File(file_ref.clone()))?; // adds the newly created file to the tree
@@ -72,6 +75,9 @@ impl ByteReader for MemFile {
 
 impl ByteWriter for MemFile {
     fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        self.attributes.check_writable()?;
+        self.attributes.touch();
+
         // error out if the underlying mapped pages are already allocated and not writeable
         if !self.mp.flags().is_writable() && self.mp.size_in_bytes() != 0 {
             return Err(IoError::from("MemFile::write(): existing MappedPages were not writable"));
@@ -144,6 +150,15 @@ impl File for MemFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Ok(&self.mp)
     }
+
+    fn metadata(&self) -> Metadata {
+        self.attributes.metadata(NodeType::File, self.len)
+    }
+
+    fn set_permissions(&mut self, permissions: Permissions) -> Result<(), &'static str> {
+        self.attributes.permissions = permissions;
+        Ok(())
+    }
 }
 
 impl FsNode for MemFile {
@@ -158,4 +173,9 @@ impl FsNode for MemFile {
     fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
         self.parent = new_parent;
     }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        self.name = new_name;
+        Ok(())
+    }
 }
@@ -20,7 +20,7 @@ use bootloader_modules::BootloaderModule;
 use cow_arc::CowArc;
 use rustc_demangle::demangle;
 use qp_trie::Trie;
-use fs_node::{FileOrDir, File, FileRef, DirRef};
+use fs_node::{FileOrDir, File, FileRef, DirRef, Permissions};
 use vfs_node::VFSDirectory;
 use path::Path;
 use memfs::MemFile;
@@ -158,10 +158,12 @@ fn parse_bootloader_modules_into_files(
             MemFile::from_mapped_pages(pages, file_name.to_string(), size, dir)
         };
         // Get the existing (or create a new) namespace directory corresponding to the given directory name.
-        let _new_file = match prefix_map.entry(dir_name.clone()) {
+        let new_file = match prefix_map.entry(dir_name.clone()) {
             btree_map::Entry::Vacant(vacant) => create_file( vacant.insert(create_dir(&dir_name)?) )?,
             btree_map::Entry::Occupied(occ)  => create_file( occ.get() )?,
         };
+        // Crate object files are executable and must not be modified after boot.
+        new_file.lock().set_permissions(Permissions { read_only: true, executable: true })?;
         Ok(())
     };
 
@@ -0,0 +1,269 @@
+//! The table of filesystems that are mounted into the VFS at runtime.
+//!
+//! A mounted filesystem is represented by its root directory, which is mounted on top of
+//! an existing directory in the VFS, called the *mount point*.
+//! Mounting a filesystem does not modify the mount point or its parent directory;
+//! instead, path resolution in the `path` crate consults this table via [`lookup()`]
+//! and transparently crosses from the mount point into the mounted root directory.
+//! Note that code which traverses directories directly via `Directory::get()`
+//! rather than via a `Path` will still observe the original (covered) mount point.
+//!
+//! This crate also offers a registry of filesystem types, which allows a filesystem
+//! to be created by name (e.g., from the `mount` application) via [`create_filesystem()`].
+//! The [`TMPFS`] type, an in-memory filesystem backed by `VFSDirectory`
+//! and in-memory files, is always available.
+
+#![no_std]
+
+#[macro_use] extern crate log;
+#[macro_use] extern crate alloc;
+extern crate spin;
+extern crate fs_node;
+extern crate vfs_node;
+extern crate root;
+
+use core::fmt;
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::Mutex;
+use fs_node::{DirRef, FileOrDir, FsNode};
+use vfs_node::VFSDirectory;
+
+
+/// The name of the directory in the root that holds mount points for storage volumes.
+pub const MOUNT_DIRECTORY_NAME: &str = "mnt";
+
+/// The name of the built-in in-memory filesystem type.
+pub const TMPFS: &str = "tmpfs";
+
+/// The signature of a function that creates a new instance of a filesystem type.
+///
+/// # Arguments
+/// * `source`: a filesystem-specific string describing where the filesystem's contents come from,
+///    e.g., the name of a storage device.
+/// * `name`: the name that the root directory of the new filesystem must have,
+///    which is the name of the mount point that it will be mounted on.
+///
+/// Returns the root directory of the new filesystem,
+/// which must not yet be inserted into any other directory.
+pub type FileSystemConstructor = fn(source: &str, name: String) -> Result<DirRef, &'static str>;
+
+/// All currently-mounted filesystems, keyed by the absolute path of their mount point.
+static MOUNT_TABLE: Mutex<BTreeMap<String, Mount>> = Mutex::new(BTreeMap::new());
+
+/// All filesystem types that have been registered, in addition to the built-in [`TMPFS`].
+static FILESYSTEM_TYPES: Mutex<BTreeMap<&'static str, FileSystemConstructor>> = Mutex::new(BTreeMap::new());
+
+
+/// An entry in the mount table.
+#[derive(Clone)]
+pub struct Mount {
+    /// The absolute path of the mount point.
+    pub path: String,
+    /// The type of the mounted filesystem, e.g., [`TMPFS`].
+    pub fs_type: String,
+    /// The source of the mounted filesystem, e.g., the name of a storage device.
+    pub source: String,
+    /// The root directory of the mounted filesystem.
+    pub root: DirRef,
+}
+
+impl fmt::Debug for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on {} type {}", self.source, self.path, self.fs_type)
+    }
+}
+
+
+/// Registers a new filesystem type that can subsequently be created by name
+/// via [`create_filesystem()`].
+///
+/// Returns an error if a filesystem type with the same name was already registered.
+pub fn register_filesystem_type(fs_type: &'static str, constructor: FileSystemConstructor) -> Result<(), &'static str> {
+    if fs_type == TMPFS {
+        return Err("cannot replace the built-in tmpfs filesystem type");
+    }
+    let mut types = FILESYSTEM_TYPES.lock();
+    if types.contains_key(fs_type) {
+        return Err("a filesystem type with that name was already registered");
+    }
+    types.insert(fs_type, constructor);
+    Ok(())
+}
+
+/// Returns the names of all filesystem types that can be created.
+pub fn filesystem_types() -> Vec<&'static str> {
+    let mut types = vec![TMPFS];
+    types.extend(FILESYSTEM_TYPES.lock().keys());
+    types
+}
+
+/// Creates a new instance of the given `fs_type` filesystem from the given `source`,
+/// whose root directory will be called `name`.
+///
+/// The returned root directory can then be mounted using [`mount()`].
+pub fn create_filesystem(fs_type: &str, source: &str, name: String) -> Result<DirRef, &'static str> {
+    if fs_type == TMPFS {
+        return create_tmpfs(source, name);
+    }
+    let constructor = FILESYSTEM_TYPES.lock()
+        .get(fs_type)
+        .copied()
+        .ok_or("unknown filesystem type")?;
+    constructor(source, name)
+}
+
+/// Creates an empty in-memory filesystem; the `source` is ignored.
+fn create_tmpfs(_source: &str, name: String) -> Result<DirRef, &'static str> {
+    let directory = VFSDirectory::new(name, Weak::<Mutex<VFSDirectory>>::new());
+    Ok(Arc::new(Mutex::new(directory)) as DirRef)
+}
+
+
+/// Mounts the filesystem whose root directory is `fs_root` on top of the given `mount_point`.
+///
+/// The root directory must have the same name as the mount point,
+/// such that the absolute paths of nodes within the mounted filesystem are correct.
+/// The root directory's parent is set to the mount point's parent.
+///
+/// # Arguments
+/// * `fs_root`: the root directory of the filesystem to mount.
+/// * `mount_point`: the existing directory that will be covered by the mounted filesystem.
+/// * `fs_type`: the type of the mounted filesystem, used for informational purposes.
+/// * `source`: the source of the mounted filesystem, used for informational purposes.
+pub fn mount(fs_root: DirRef, mount_point: &DirRef, fs_type: &str, source: &str) -> Result<(), &'static str> {
+    if Arc::ptr_eq(mount_point, root::get_root()) {
+        return Err("cannot mount a filesystem over the root directory");
+    }
+    let (path, name, parent) = {
+        let locked_mount_point = mount_point.lock();
+        (locked_mount_point.get_absolute_path(), locked_mount_point.get_name(), locked_mount_point.get_parent_dir())
+    };
+    let parent = parent.ok_or("the mount point has no parent directory")?;
+    if fs_root.lock().get_name() != name {
+        return Err("the mounted filesystem's root directory must have the same name as its mount point");
+    }
+
+    let mut table = MOUNT_TABLE.lock();
+    if table.contains_key(&path) {
+        return Err("a filesystem is already mounted at that path");
+    }
+    fs_root.lock().set_parent_dir(Arc::downgrade(&parent));
+    info!("Mounted {} filesystem from {:?} at {:?}", fs_type, source, path);
+    table.insert(path.clone(), Mount {
+        path,
+        fs_type: fs_type.to_string(),
+        source: source.to_string(),
+        root: fs_root,
+    });
+    Ok(())
+}
+
+/// Unmounts the filesystem mounted at the given absolute `path`, uncovering its mount point.
+///
+/// Returns an error if no filesystem is mounted there,
+/// or if another filesystem is mounted beneath that path.
+pub fn umount(path: &str) -> Result<Mount, &'static str> {
+    let path = normalize(path);
+    let mut table = MOUNT_TABLE.lock();
+    let prefix = format!("{path}/");
+    if table.keys().any(|p| p.starts_with(&prefix)) {
+        return Err("another filesystem is mounted beneath that path");
+    }
+    let mount = table.remove(path).ok_or("no filesystem is mounted at that path")?;
+    mount.root.lock().set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
+    info!("Unmounted {:?}", mount);
+    Ok(mount)
+}
+
+/// Moves the node called `old_name` in the `source` directory into the `destination` directory,
+/// where it will be called `new_name`, while keeping the mount table consistent.
+///
+/// This is like [`fs_node::move_node()`], but if the moved node is a directory that contains mount points,
+/// those mount points are rekeyed to their new paths.
+/// A mount point itself cannot be moved or renamed; its filesystem must be unmounted instead.
+///
+/// The locks on `source`, `destination` and the node being moved must not be held.
+pub fn move_node(
+    source: &DirRef,
+    old_name: &str,
+    destination: &DirRef,
+    new_name: &str,
+) -> Result<Option<FileOrDir>, &'static str> {
+    // The mount table must not be locked while resolving paths or moving the node,
+    // because getting the parent or path of some nodes (e.g., in task_fs) resolves paths through `lookup()`.
+    let old_path = join(&source.lock().get_absolute_path(), old_name);
+    let new_path = join(&destination.lock().get_absolute_path(), new_name);
+    if is_mount_point(&old_path) {
+        return Err("cannot move a mount point; unmount its filesystem instead");
+    }
+
+    let replaced = fs_node::move_node(source, old_name, destination, new_name)?;
+
+    let mut table = MOUNT_TABLE.lock();
+    let old_prefix = format!("{old_path}/");
+    let moved_mounts: Vec<String> = table.keys()
+        .filter(|path| path.starts_with(&old_prefix))
+        .cloned()
+        .collect();
+    for path in moved_mounts {
+        if let Some(mut mount) = table.remove(&path) {
+            mount.path = format!("{new_path}/{}", &path[old_prefix.len()..]);
+            debug!("Moved mount point {:?} to {:?}", path, mount.path);
+            table.insert(mount.path.clone(), mount);
+        }
+    }
+    Ok(replaced)
+}
+
+/// Returns the root directory of the filesystem mounted at the given absolute `path`, if any.
+pub fn lookup(path: &str) -> Option<DirRef> {
+    let table = MOUNT_TABLE.lock();
+    if table.is_empty() {
+        return None;
+    }
+    table.get(normalize(path)).map(|m| Arc::clone(&m.root))
+}
+
+/// Returns `true` if a filesystem is mounted at the given absolute `path`.
+pub fn is_mount_point(path: &str) -> bool {
+    MOUNT_TABLE.lock().contains_key(normalize(path))
+}
+
+/// Returns a list of all mounted filesystems, sorted by the path of their mount points.
+pub fn mounts() -> Vec<Mount> {
+    MOUNT_TABLE.lock().values().cloned().collect()
+}
+
+/// Returns the directory beneath which storage volumes are mounted by default,
+/// creating it within the root directory if necessary.
+pub fn mount_directory() -> Result<DirRef, &'static str> {
+    let root = root::get_root();
+    let existing = root.lock().get_dir(MOUNT_DIRECTORY_NAME);
+    match existing {
+        Some(dir) => Ok(dir),
+        None => VFSDirectory::create(MOUNT_DIRECTORY_NAME.to_string(), root),
+    }
+}
+
+/// Returns the absolute path of the node called `name` within the directory at the absolute `dir_path`.
+fn join(dir_path: &str, name: &str) -> String {
+    if dir_path.ends_with('/') {
+        format!("{dir_path}{name}")
+    } else {
+        format!("{dir_path}/{name}")
+    }
+}
+
+/// Removes a trailing path delimiter from the given `path`, unless it is the root.
+fn normalize(path: &str) -> &str {
+    if path.len() > 1 {
+        path.trim_end_matches('/')
+    } else {
+        path
+    }
+}
@@ -0,0 +1,439 @@
+//! Blocking TCP and UDP sockets, modeled after those in Rust's `std::net`.
+//!
+//! Unlike [`Socket`](crate::Socket), these sockets own their underlying smoltcp socket
+//! and poll their interface themselves. Operations block the current task
+//! until the socket's state changes or the operation's timeout elapses.
+//!
+//! Each socket uses the interface chosen by [`get_interface_for`] for its remote
+//! or local address, so sockets with an unspecified local address use the default interface.
+
+use crate::{get_interface_for, Error, NetworkInterface, Result};
+use alloc::{sync::Arc, vec, vec::Vec};
+use smoltcp::{
+    iface::SocketHandle,
+    socket::{tcp, udp},
+    wire::IpEndpoint,
+};
+use spin::Mutex;
+use time::{Duration, Monotonic};
+
+/// The size of a TCP socket's receive and transmit buffers.
+const TCP_BUFFER_SIZE: usize = 16 * 1024;
+/// The size of a UDP socket's receive and transmit payload buffers.
+const UDP_BUFFER_SIZE: usize = 16 * 1024;
+/// The maximum number of packets queued in a UDP socket's receive and transmit buffers.
+const UDP_PACKET_CAPACITY: usize = 32;
+/// The number of connections a [`TcpListener`] can accept concurrently.
+const LISTEN_BACKLOG: usize = 4;
+/// The longest a blocked task waits before polling its interface again,
+/// e.g., so that unacknowledged data is retransmitted.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The first port in the dynamic (ephemeral) port range defined by RFC 6335.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// Returns a random port from the ephemeral port range.
+fn ephemeral_port() -> u16 {
+    let count = (u16::MAX - EPHEMERAL_PORT_START) as u32 + 1;
+    EPHEMERAL_PORT_START + (random::next_u32() % count) as u16
+}
+
+/// Polls `interface` until `condition` returns `Some`, or until `timeout` elapses.
+///
+/// A timeout of `None` blocks indefinitely.
+fn block_until<R, F>(interface: &NetworkInterface, timeout: Option<Duration>, mut condition: F) -> Result<R>
+where
+    F: FnMut() -> Option<Result<R>>,
+{
+    let deadline = timeout.map(|timeout| time::now::<Monotonic>() + timeout);
+    let mut needs_poll = true;
+    loop {
+        if needs_poll {
+            // Poll errors are caused by individual packets and don't affect this socket.
+            let _ = interface.poll();
+        }
+        if let Some(result) = condition() {
+            return result;
+        }
+
+        let wait = match deadline {
+            Some(deadline) => {
+                let now = time::now::<Monotonic>();
+                if now >= deadline {
+                    return Err(Error::TimedOut);
+                }
+                core::cmp::min(deadline - now, POLL_INTERVAL)
+            }
+            None => POLL_INTERVAL,
+        };
+        // If another task polled the interface in the meantime, polling again is unnecessary.
+        needs_poll = !interface.wait_for_events(wait);
+    }
+}
+
+fn new_tcp_socket() -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+    )
+}
+
+fn new_udp_socket() -> udp::Socket<'static> {
+    udp::Socket::new(
+        udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; UDP_PACKET_CAPACITY],
+            vec![0; UDP_BUFFER_SIZE],
+        ),
+        udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; UDP_PACKET_CAPACITY],
+            vec![0; UDP_BUFFER_SIZE],
+        ),
+    )
+}
+
+/// A TCP connection between a local and a remote socket.
+///
+/// Dropping the stream closes the connection. Data that hasn't yet been sent
+/// is still delivered to the remote host.
+pub struct TcpStream {
+    interface: Arc<NetworkInterface>,
+    handle: SocketHandle,
+    read_timeout: Mutex<Option<Duration>>,
+    write_timeout: Mutex<Option<Duration>>,
+}
+
+impl TcpStream {
+    fn new(interface: Arc<NetworkInterface>, handle: SocketHandle) -> Self {
+        Self {
+            interface,
+            handle,
+            read_timeout: Mutex::new(None),
+            write_timeout: Mutex::new(None),
+        }
+    }
+
+    /// Opens a TCP connection to `remote`.
+    ///
+    /// Blocks until the connection is established, or until `timeout` elapses.
+    pub fn connect(remote: IpEndpoint, timeout: Option<Duration>) -> Result<Self> {
+        let interface = get_interface_for(remote.addr).ok_or(Error::Unaddressable)?;
+        let handle = interface.add_socket_handle(new_tcp_socket());
+        let stream = Self::new(interface, handle);
+
+        stream
+            .interface
+            .with_socket_and_context::<tcp::Socket, _, _>(handle, |socket, context| {
+                socket.connect(context, remote, ephemeral_port())
+            })?;
+
+        block_until(&stream.interface, timeout, || {
+            stream.with_socket(|socket| match socket.state() {
+                tcp::State::SynSent | tcp::State::SynReceived => None,
+                tcp::State::Closed => Some(Err(Error::ConnectionRefused)),
+                _ => Some(Ok(())),
+            })
+        })?;
+        Ok(stream)
+    }
+
+    fn with_socket<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut tcp::Socket<'static>) -> R,
+    {
+        self.interface.with_socket(self.handle, f)
+    }
+
+    /// Reads data received from the remote host into `buf`.
+    ///
+    /// Blocks until at least one byte is available, and returns the number of bytes read.
+    /// Returns `Ok(0)` once the remote host has closed its side of the connection.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let timeout = *self.read_timeout.lock();
+        block_until(&self.interface, timeout, || {
+            self.with_socket(|socket| {
+                if socket.can_recv() {
+                    Some(socket.recv_slice(buf).map_err(Error::from))
+                } else if !socket.may_recv() {
+                    Some(Ok(0))
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// Queues the data in `buf` to be sent to the remote host.
+    ///
+    /// Blocks until there is room in the transmit buffer, and returns the number of bytes queued.
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let timeout = *self.write_timeout.lock();
+        let written = block_until(&self.interface, timeout, || {
+            self.with_socket(|socket| {
+                if !socket.may_send() {
+                    Some(Err(Error::ConnectionReset))
+                } else if socket.can_send() {
+                    Some(socket.send_slice(buf).map_err(Error::from))
+                } else {
+                    None
+                }
+            })
+        })?;
+        // Send the data right away instead of waiting for the next poll.
+        let _ = self.interface.poll();
+        Ok(written)
+    }
+
+    /// Blocks until all queued data has been sent and acknowledged by the remote host.
+    pub fn flush(&self) -> Result<()> {
+        let timeout = *self.write_timeout.lock();
+        block_until(&self.interface, timeout, || {
+            self.with_socket(|socket| {
+                if socket.send_queue() == 0 {
+                    Some(Ok(()))
+                } else if !socket.may_send() {
+                    Some(Err(Error::ConnectionReset))
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// Closes the sending half of the connection.
+    ///
+    /// Queued data is still sent, and data can still be received until the remote host closes its side.
+    pub fn shutdown(&self) {
+        self.with_socket(|socket| socket.close());
+        let _ = self.interface.poll();
+    }
+
+    /// Returns the local endpoint of the connection.
+    pub fn local_endpoint(&self) -> Result<IpEndpoint> {
+        self.with_socket(|socket| socket.local_endpoint())
+            .ok_or(Error::NotConnected)
+    }
+
+    /// Returns the remote endpoint of the connection.
+    pub fn remote_endpoint(&self) -> Result<IpEndpoint> {
+        self.with_socket(|socket| socket.remote_endpoint())
+            .ok_or(Error::NotConnected)
+    }
+
+    /// Sets the timeout of [`read`](Self::read). `None` blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock() = timeout;
+    }
+
+    /// Returns the timeout of [`read`](Self::read).
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock()
+    }
+
+    /// Sets the timeout of [`write`](Self::write) and [`flush`](Self::flush).
+    /// `None` blocks indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        *self.write_timeout.lock() = timeout;
+    }
+
+    /// Returns the timeout of [`write`](Self::write) and [`flush`](Self::flush).
+    pub fn write_timeout(&self) -> Option<Duration> {
+        *self.write_timeout.lock()
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        self.interface.close_tcp_socket(self.handle);
+        let _ = self.interface.poll();
+    }
+}
+
+/// A TCP socket that listens for incoming connections.
+pub struct TcpListener {
+    interface: Arc<NetworkInterface>,
+    port: u16,
+    /// The listening sockets; each one accepts a single connection and is then replaced.
+    backlog: Mutex<Vec<SocketHandle>>,
+}
+
+impl TcpListener {
+    /// Listens for connections to `local.port` on the interface that `local.addr` belongs to.
+    ///
+    /// Connections to any of that interface's addresses are accepted.
+    /// If the port is 0, a random ephemeral port is used.
+    pub fn bind(local: IpEndpoint) -> Result<Self> {
+        let interface = get_interface_for(local.addr).ok_or(Error::Unaddressable)?;
+        let port = if local.port == 0 { ephemeral_port() } else { local.port };
+
+        let mut backlog = Vec::with_capacity(LISTEN_BACKLOG);
+        for _ in 0..LISTEN_BACKLOG {
+            match listening_socket(&interface, port) {
+                Ok(handle) => backlog.push(handle),
+                Err(e) => {
+                    for handle in backlog {
+                        interface.remove_socket(handle);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(Self {
+            interface,
+            port,
+            backlog: Mutex::new(backlog),
+        })
+    }
+
+    /// Blocks until a remote host connects, and returns the new connection and the remote endpoint.
+    pub fn accept(&self) -> Result<(TcpStream, IpEndpoint)> {
+        let handle = block_until(&self.interface, None, || {
+            let mut backlog = self.backlog.lock();
+            let index = backlog.iter().position(|&handle| {
+                let state = self.interface.with_socket::<tcp::Socket, _, _>(handle, |s| s.state());
+                !matches!(state, tcp::State::Listen | tcp::State::SynReceived)
+            })?;
+            Some(listening_socket(&self.interface, self.port)
+                .map(|replacement| core::mem::replace(&mut backlog[index], replacement)))
+        })?;
+
+        let stream = TcpStream::new(self.interface.clone(), handle);
+        let remote = stream.remote_endpoint()?;
+        Ok((stream, remote))
+    }
+
+    /// Returns the port that this listener is bound to.
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        for &handle in self.backlog.lock().iter() {
+            self.interface.remove_socket(handle);
+        }
+    }
+}
+
+/// Adds a TCP socket to `interface` that listens on `port`.
+fn listening_socket(interface: &NetworkInterface, port: u16) -> Result<SocketHandle> {
+    let handle = interface.add_socket_handle(new_tcp_socket());
+    match interface.with_socket::<tcp::Socket, _, _>(handle, |socket| socket.listen(port)) {
+        Ok(()) => Ok(handle),
+        Err(e) => {
+            interface.remove_socket(handle);
+            Err(e.into())
+        }
+    }
+}
+
+/// A UDP socket.
+///
+/// Dropping the socket discards any queued packets.
+pub struct UdpSocket {
+    interface: Arc<NetworkInterface>,
+    handle: SocketHandle,
+    port: u16,
+    read_timeout: Mutex<Option<Duration>>,
+    write_timeout: Mutex<Option<Duration>>,
+}
+
+impl UdpSocket {
+    /// Binds a UDP socket to `local.port` on the interface that `local.addr` belongs to.
+    ///
+    /// Datagrams sent to any of that interface's addresses are received.
+    /// If the port is 0, a random ephemeral port is used.
+    pub fn bind(local: IpEndpoint) -> Result<Self> {
+        let interface = get_interface_for(local.addr).ok_or(Error::Unaddressable)?;
+        let port = if local.port == 0 { ephemeral_port() } else { local.port };
+
+        let handle = interface.add_socket_handle(new_udp_socket());
+        if let Err(e) = interface.with_socket::<udp::Socket, _, _>(handle, |socket| socket.bind(port)) {
+            interface.remove_socket(handle);
+            return Err(e.into());
+        }
+
+        Ok(Self {
+            interface,
+            handle,
+            port,
+            read_timeout: Mutex::new(None),
+            write_timeout: Mutex::new(None),
+        })
+    }
+
+    fn with_socket<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut udp::Socket<'static>) -> R,
+    {
+        self.interface.with_socket(self.handle, f)
+    }
+
+    /// Sends the data in `buf` to `remote` as a single datagram.
+    ///
+    /// Blocks until there is room in the transmit buffer, and returns the number of bytes sent.
+    pub fn send_to(&self, buf: &[u8], remote: IpEndpoint) -> Result<usize> {
+        let timeout = *self.write_timeout.lock();
+        block_until(&self.interface, timeout, || {
+            self.with_socket(|socket| {
+                socket.can_send().then(|| {
+                    socket.send_slice(buf, remote).map(|_| buf.len()).map_err(Error::from)
+                })
+            })
+        })?;
+        // Send the datagram right away instead of waiting for the next poll.
+        let _ = self.interface.poll();
+        Ok(buf.len())
+    }
+
+    /// Receives a single datagram into `buf`.
+    ///
+    /// Blocks until a datagram arrives, and returns its length and the endpoint that sent it.
+    /// Returns [`Error::Truncated`] if the datagram doesn't fit into `buf`.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpEndpoint)> {
+        let timeout = *self.read_timeout.lock();
+        block_until(&self.interface, timeout, || {
+            self.with_socket(|socket| {
+                socket.can_recv().then(|| socket.recv_slice(buf).map_err(Error::from))
+            })
+        })
+    }
+
+    /// Returns the port that this socket is bound to.
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sets the timeout of [`recv_from`](Self::recv_from). `None` blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock() = timeout;
+    }
+
+    /// Returns the timeout of [`recv_from`](Self::recv_from).
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock()
+    }
+
+    /// Sets the timeout of [`send_to`](Self::send_to). `None` blocks indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        *self.write_timeout.lock() = timeout;
+    }
+
+    /// Returns the timeout of [`send_to`](Self::send_to).
+    pub fn write_timeout(&self) -> Option<Duration> {
+        *self.write_timeout.lock()
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        self.interface.remove_socket(self.handle);
+    }
+}
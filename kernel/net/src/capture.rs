@@ -0,0 +1,183 @@
+//! Capturing the frames that an interface sends and receives, e.g., for writing them to a pcap file.
+//!
+//! Captured frames are copied into a bounded buffer; once it's full, the oldest frames are dropped.
+
+mod filter;
+
+pub use filter::{Filter, Protocol, Side};
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use irq_safety::MutexIrqSafe;
+use time::{Duration, Monotonic};
+
+/// The default maximum number of bytes of each frame that are captured.
+pub const DEFAULT_SNAP_LEN: usize = 65535;
+/// The default size of the buffer that holds captured frames, in bytes.
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Whether a frame was received or sent by the interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// A copy of a frame that an interface received or sent.
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    /// When the frame was captured, as the time since the Unix epoch
+    /// if a wall clock is available, or as the time since boot otherwise.
+    pub timestamp: Duration,
+    pub direction: Direction,
+    /// The length of the whole frame, which is longer than `data` if the frame was truncated.
+    pub original_len: usize,
+    /// The captured bytes of the frame, starting with its Ethernet header.
+    pub data: Vec<u8>,
+}
+
+/// Options for capturing frames on an interface.
+#[derive(Clone, Debug)]
+pub struct CaptureConfig {
+    /// Only frames that match this filter are captured, or all frames if `None`.
+    pub filter: Option<Filter>,
+    /// The maximum number of bytes of each frame that are captured.
+    pub snap_len: usize,
+    /// The maximum number of bytes of frames that are buffered until they're taken.
+    pub buffer_size: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            snap_len: DEFAULT_SNAP_LEN,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+}
+
+/// Statistics about an interface's current or most recent capture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// The number of frames that matched the filter.
+    pub captured: usize,
+    /// The number of captured frames that were dropped because the buffer was full.
+    pub dropped: usize,
+    /// The number of frames in the buffer that haven't been taken yet.
+    pub buffered: usize,
+}
+
+/// The capture state of an interface.
+pub(crate) struct Capture {
+    /// Whether frames are being captured, checked before locking `state` for every frame.
+    active: AtomicBool,
+    /// Frames are recorded while the device is locked with interrupts disabled,
+    /// so this lock must not be held by a task that could be preempted.
+    state: MutexIrqSafe<CaptureState>,
+}
+
+struct CaptureState {
+    config: CaptureConfig,
+    /// The wall time at which the monotonic clock started, or zero if no wall clock is available,
+    /// which is added to the monotonic time of each captured frame.
+    boot_time: Duration,
+    frames: VecDeque<CapturedFrame>,
+    buffered_bytes: usize,
+    stats: CaptureStats,
+}
+
+impl Capture {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            state: MutexIrqSafe::new(CaptureState {
+                config: CaptureConfig::default(),
+                boot_time: Duration::ZERO,
+                frames: VecDeque::new(),
+                buffered_bytes: 0,
+                stats: CaptureStats::default(),
+            }),
+        }
+    }
+
+    /// Starts capturing frames, discarding any frames buffered by a previous capture.
+    pub(crate) fn start(&self, config: CaptureConfig) {
+        // Reading the wall clock may require slow port I/O, e.g., to the RTC,
+        // so it's done once here instead of while the device is locked for every frame.
+        let boot_time = time::approximate_wall_time()
+            .map_or(Duration::ZERO, |now| now.saturating_sub(since_boot()));
+        let mut state = self.state.lock();
+        state.config = config;
+        state.boot_time = boot_time;
+        state.frames.clear();
+        state.buffered_bytes = 0;
+        state.stats = CaptureStats::default();
+        self.active.store(true, Ordering::Release);
+    }
+
+    /// Stops capturing frames. Frames that were already captured can still be taken.
+    pub(crate) fn stop(&self) {
+        self.active.store(false, Ordering::Release);
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn take_frames(&self) -> Vec<CapturedFrame> {
+        let mut state = self.state.lock();
+        state.buffered_bytes = 0;
+        core::mem::take(&mut state.frames).into()
+    }
+
+    pub(crate) fn stats(&self) -> CaptureStats {
+        let state = self.state.lock();
+        CaptureStats {
+            buffered: state.frames.len(),
+            ..state.stats
+        }
+    }
+
+    /// Records a copy of the given `frame` if capturing is active and the frame matches the filter.
+    pub(crate) fn record(&self, direction: Direction, frame: &[u8]) {
+        if !self.is_active() {
+            return;
+        }
+        let mut state = self.state.lock();
+        // The capture may have been stopped while waiting for the lock.
+        if !self.is_active() {
+            return;
+        }
+        if let Some(ref filter) = state.config.filter {
+            if !filter.matches(frame) {
+                return;
+            }
+        }
+
+        let data = frame[..frame.len().min(state.config.snap_len)].to_vec();
+        state.stats.captured += 1;
+        while !state.frames.is_empty() && state.buffered_bytes + data.len() > state.config.buffer_size {
+            if let Some(oldest) = state.frames.pop_front() {
+                state.buffered_bytes -= oldest.data.len();
+                state.stats.dropped += 1;
+            }
+        }
+        state.buffered_bytes += data.len();
+        state.frames.push_back(CapturedFrame {
+            timestamp: state.boot_time + since_boot(),
+            direction,
+            original_len: frame.len(),
+            data,
+        });
+    }
+}
+
+/// Returns the time since the monotonic clock started, or zero if it hasn't been registered yet.
+fn since_boot() -> Duration {
+    if time::has_clock_source::<Monotonic>() {
+        time::now::<Monotonic>().duration_since(time::Instant::ZERO)
+    } else {
+        Duration::ZERO
+    }
+}
@@ -0,0 +1,529 @@
+//! Filters that select which frames are captured, using a subset of the
+//! [pcap-filter](https://www.tcpdump.org/manpages/pcap-filter.7.html) syntax.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::str::FromStr;
+use smoltcp::wire::{IpAddress, Ipv4Address, Ipv6Address};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PROTOCOL_ICMPV6: u8 = 58;
+
+/// A filter expression that a frame either matches or doesn't.
+///
+/// Filters are parsed from expressions such as `tcp port 80`,
+/// `udp and not host 10.0.2.3`, or `arp or (icmp and dst host 10.0.2.15)`.
+/// The supported primitives are:
+/// * `arp`, `ip`, `ip6`, `icmp`, `icmp6`, `tcp`, and `udp`, which match frames of that protocol.
+/// * `[src|dst] host <address>`, which matches IPv4 or IPv6 packets to or from `address`.
+/// * `[src|dst] port <port>`, which matches TCP or UDP packets to or from `port`.
+///
+/// Primitives can be combined with `and` (`&&`), `or` (`||`), `not` (`!`), and parentheses.
+/// As in pcap-filter, `and` and `or` have the same precedence and are evaluated left to right,
+/// and adjacent primitives without an operator between them are joined with `and`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Protocol(Protocol),
+    Host(Side, IpAddress),
+    Port(Side, u16),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+/// The protocols that a [`Filter`] can match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Arp,
+    Ipv4,
+    Ipv6,
+    Icmp,
+    Icmpv6,
+    Tcp,
+    Udp,
+}
+
+/// Which address or port of a packet a [`Filter`] matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Source,
+    Destination,
+    Either,
+}
+
+impl Side {
+    fn matches<T: PartialEq>(self, value: &T, source: &T, destination: &T) -> bool {
+        match self {
+            Side::Source => value == source,
+            Side::Destination => value == destination,
+            Side::Either => value == source || value == destination,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = &'static str;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(expression);
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+        let filter = parser.expression()?;
+        if parser.position != tokens.len() {
+            return Err("unexpected token in capture filter");
+        }
+        Ok(filter)
+    }
+}
+
+impl Filter {
+    /// Returns whether the given Ethernet `frame` matches this filter.
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        self.matches_headers(&Headers::parse(frame))
+    }
+
+    fn matches_headers(&self, headers: &Headers) -> bool {
+        match self {
+            Filter::Protocol(protocol) => match protocol {
+                Protocol::Arp => headers.ethertype == ETHERTYPE_ARP,
+                Protocol::Ipv4 => headers.ethertype == ETHERTYPE_IPV4,
+                Protocol::Ipv6 => headers.ethertype == ETHERTYPE_IPV6,
+                Protocol::Icmp => headers.ethertype == ETHERTYPE_IPV4 && headers.protocol == Some(PROTOCOL_ICMP),
+                Protocol::Icmpv6 => headers.ethertype == ETHERTYPE_IPV6 && headers.protocol == Some(PROTOCOL_ICMPV6),
+                Protocol::Tcp => headers.protocol == Some(PROTOCOL_TCP),
+                Protocol::Udp => headers.protocol == Some(PROTOCOL_UDP),
+            },
+            Filter::Host(side, address) => match headers.addresses {
+                Some((source, destination)) => side.matches(address, &source, &destination),
+                None => false,
+            },
+            Filter::Port(side, port) => match headers.ports {
+                Some((source, destination)) => side.matches(port, &source, &destination),
+                None => false,
+            },
+            Filter::Not(filter) => !filter.matches_headers(headers),
+            Filter::And(left, right) => left.matches_headers(headers) && right.matches_headers(headers),
+            Filter::Or(left, right) => left.matches_headers(headers) || right.matches_headers(headers),
+        }
+    }
+}
+
+/// The fields of a frame's headers that filters can match.
+#[derive(Default)]
+struct Headers {
+    ethertype: u16,
+    /// The source and destination IP addresses.
+    addresses: Option<(IpAddress, IpAddress)>,
+    /// The IP protocol number of the transport layer.
+    protocol: Option<u8>,
+    /// The source and destination TCP or UDP ports.
+    ports: Option<(u16, u16)>,
+}
+
+impl Headers {
+    /// Parses the headers of an Ethernet frame, stopping at the first one that is truncated.
+    fn parse(frame: &[u8]) -> Self {
+        let mut headers = Headers::default();
+        let (Some(mut ethertype), Some(mut payload)) = (read_u16(frame, 12), frame.get(14..)) else {
+            return headers;
+        };
+        if ethertype == ETHERTYPE_VLAN {
+            let (Some(inner_ethertype), Some(inner_payload)) = (read_u16(payload, 2), payload.get(4..)) else {
+                return headers;
+            };
+            ethertype = inner_ethertype;
+            payload = inner_payload;
+        }
+        headers.ethertype = ethertype;
+
+        let transport = match ethertype {
+            ETHERTYPE_IPV4 => {
+                let Some(header_len) = payload.first().map(|b| usize::from(b & 0x0F) * 4) else {
+                    return headers;
+                };
+                let (Some(source), Some(destination)) = (payload.get(12..16), payload.get(16..20)) else {
+                    return headers;
+                };
+                headers.addresses = Some((
+                    IpAddress::Ipv4(Ipv4Address::from_bytes(source)),
+                    IpAddress::Ipv4(Ipv4Address::from_bytes(destination)),
+                ));
+                headers.protocol = payload.get(9).copied();
+                // Only the first fragment of a packet has the transport header.
+                let fragment_offset = read_u16(payload, 6).unwrap_or(0) & 0x1FFF;
+                if fragment_offset != 0 {
+                    return headers;
+                }
+                payload.get(header_len..)
+            }
+            ETHERTYPE_IPV6 => {
+                let (Some(source), Some(destination)) = (payload.get(8..24), payload.get(24..40)) else {
+                    return headers;
+                };
+                headers.addresses = Some((
+                    IpAddress::Ipv6(Ipv6Address::from_bytes(source)),
+                    IpAddress::Ipv6(Ipv6Address::from_bytes(destination)),
+                ));
+                // Extension headers aren't skipped, so packets with them don't match any transport protocol.
+                headers.protocol = payload.get(6).copied();
+                payload.get(40..)
+            }
+            _ => None,
+        };
+
+        if let (Some(PROTOCOL_TCP | PROTOCOL_UDP), Some(transport)) = (headers.protocol, transport) {
+            if let (Some(source), Some(destination)) = (read_u16(transport, 0), read_u16(transport, 2)) {
+                headers.ports = Some((source, destination));
+            }
+        }
+        headers
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Splits a filter expression into words, operators, and parentheses.
+fn tokenize(expression: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in expression.char_indices() {
+        let is_symbol = matches!(c, '(' | ')' | '!');
+        if c.is_whitespace() || is_symbol {
+            if let Some(s) = start.take() {
+                tokens.push(&expression[s..i]);
+            }
+            if is_symbol {
+                tokens.push(&expression[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&expression[s..]);
+    }
+    tokens
+}
+
+/// A recursive-descent parser for filter expressions.
+struct Parser<'t, 'e> {
+    tokens: &'t [&'e str],
+    position: usize,
+}
+
+impl<'t, 'e> Parser<'t, 'e> {
+    fn peek(&self) -> Option<&'e str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'e str> {
+        let token = self.peek();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Parses a sequence of terms joined by `and` or `or`.
+    fn expression(&mut self) -> Result<Filter, &'static str> {
+        let mut filter = self.term()?;
+        loop {
+            filter = match self.peek() {
+                Some("and" | "&&") => {
+                    self.next();
+                    Filter::And(Box::new(filter), Box::new(self.term()?))
+                }
+                Some("or" | "||") => {
+                    self.next();
+                    Filter::Or(Box::new(filter), Box::new(self.term()?))
+                }
+                None | Some(")") => return Ok(filter),
+                // Adjacent terms are implicitly joined with `and`.
+                Some(_) => Filter::And(Box::new(filter), Box::new(self.term()?)),
+            };
+        }
+    }
+
+    /// Parses a negated term, a parenthesized expression, or a primitive.
+    fn term(&mut self) -> Result<Filter, &'static str> {
+        match self.next().ok_or("capture filter ended unexpectedly")? {
+            "not" | "!" => Ok(Filter::Not(Box::new(self.term()?))),
+            "(" => {
+                let filter = self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(filter),
+                    _ => Err("missing closing parenthesis in capture filter"),
+                }
+            }
+            "arp" => Ok(Filter::Protocol(Protocol::Arp)),
+            "ip" => Ok(Filter::Protocol(Protocol::Ipv4)),
+            "ip6" => Ok(Filter::Protocol(Protocol::Ipv6)),
+            "icmp" => Ok(Filter::Protocol(Protocol::Icmp)),
+            "icmp6" => Ok(Filter::Protocol(Protocol::Icmpv6)),
+            "tcp" => Ok(Filter::Protocol(Protocol::Tcp)),
+            "udp" => Ok(Filter::Protocol(Protocol::Udp)),
+            "src" => self.qualified(Side::Source),
+            "dst" => self.qualified(Side::Destination),
+            "host" | "port" => {
+                self.position -= 1;
+                self.qualified(Side::Either)
+            }
+            _ => Err("unknown primitive in capture filter"),
+        }
+    }
+
+    /// Parses a `host <address>` or `port <port>` primitive.
+    fn qualified(&mut self, side: Side) -> Result<Filter, &'static str> {
+        let kind = self.next();
+        let value = self.next().ok_or("missing host or port in capture filter")?;
+        match kind {
+            Some("host") => IpAddress::from_str(value)
+                .map(|address| Filter::Host(side, address))
+                .map_err(|_| "invalid host address in capture filter"),
+            Some("port") => value
+                .parse()
+                .map(|port| Filter::Port(side, port))
+                .map_err(|_| "invalid port in capture filter"),
+            _ => Err("expected `host` or `port` in capture filter"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use alloc::vec;
+
+    const SOURCE_V4: [u8; 4] = [10, 0, 2, 15];
+    const DESTINATION_V4: [u8; 4] = [10, 0, 2, 3];
+    const SOURCE_V6: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const DESTINATION_V6: [u8; 16] = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Returns the first 8 bytes of a TCP or UDP header.
+    fn ports(source: u16, destination: u16) -> Vec<u8> {
+        let mut transport = Vec::new();
+        transport.extend_from_slice(&source.to_be_bytes());
+        transport.extend_from_slice(&destination.to_be_bytes());
+        transport.extend_from_slice(&[0; 4]);
+        transport
+    }
+
+    fn ipv4(protocol: u8, transport: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0];
+        packet.extend_from_slice(&SOURCE_V4);
+        packet.extend_from_slice(&DESTINATION_V4);
+        packet.extend_from_slice(transport);
+        ethernet(ETHERTYPE_IPV4, &packet)
+    }
+
+    fn ipv6(next_header: u8, transport: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0, 0, 0, next_header, 64];
+        packet.extend_from_slice(&SOURCE_V6);
+        packet.extend_from_slice(&DESTINATION_V6);
+        packet.extend_from_slice(transport);
+        ethernet(ETHERTYPE_IPV6, &packet)
+    }
+
+    fn arp() -> Vec<u8> {
+        ethernet(ETHERTYPE_ARP, &[0; 28])
+    }
+
+    fn tcp() -> Vec<u8> {
+        ipv4(PROTOCOL_TCP, &ports(49152, 80))
+    }
+
+    fn matches(expression: &str, frame: &[u8]) -> bool {
+        Filter::from_str(expression).unwrap().matches(frame)
+    }
+
+    fn host_v4(side: Side, address: [u8; 4]) -> Filter {
+        Filter::Host(side, IpAddress::Ipv4(Ipv4Address::from_bytes(&address)))
+    }
+
+    fn and(left: Filter, right: Filter) -> Filter {
+        Filter::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Filter, right: Filter) -> Filter {
+        Filter::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(filter: Filter) -> Filter {
+        Filter::Not(Box::new(filter))
+    }
+
+    #[test]
+    fn parse_primitives() {
+        assert_eq!(Filter::from_str("arp"), Ok(Filter::Protocol(Protocol::Arp)));
+        assert_eq!(Filter::from_str("ip"), Ok(Filter::Protocol(Protocol::Ipv4)));
+        assert_eq!(Filter::from_str("ip6"), Ok(Filter::Protocol(Protocol::Ipv6)));
+        assert_eq!(Filter::from_str("icmp"), Ok(Filter::Protocol(Protocol::Icmp)));
+        assert_eq!(Filter::from_str("icmp6"), Ok(Filter::Protocol(Protocol::Icmpv6)));
+        assert_eq!(Filter::from_str("tcp"), Ok(Filter::Protocol(Protocol::Tcp)));
+        assert_eq!(Filter::from_str("udp"), Ok(Filter::Protocol(Protocol::Udp)));
+        assert_eq!(Filter::from_str("host 10.0.2.3"), Ok(host_v4(Side::Either, DESTINATION_V4)));
+        assert_eq!(Filter::from_str("src host 10.0.2.15"), Ok(host_v4(Side::Source, SOURCE_V4)));
+        assert_eq!(Filter::from_str("dst host 10.0.2.3"), Ok(host_v4(Side::Destination, DESTINATION_V4)));
+        assert_eq!(
+            Filter::from_str("host fe80::1"),
+            Ok(Filter::Host(Side::Either, IpAddress::Ipv6(Ipv6Address::from_bytes(&SOURCE_V6)))),
+        );
+        assert_eq!(Filter::from_str("port 80"), Ok(Filter::Port(Side::Either, 80)));
+        assert_eq!(Filter::from_str("src port 80"), Ok(Filter::Port(Side::Source, 80)));
+        assert_eq!(Filter::from_str("dst port 65535"), Ok(Filter::Port(Side::Destination, 65535)));
+    }
+
+    #[test]
+    fn parse_operators() {
+        let tcp = || Filter::Protocol(Protocol::Tcp);
+        let udp = || Filter::Protocol(Protocol::Udp);
+        let port = || Filter::Port(Side::Either, 53);
+        assert_eq!(Filter::from_str("tcp port 53"), Ok(and(tcp(), port())));
+        assert_eq!(Filter::from_str("tcp and port 53"), Ok(and(tcp(), port())));
+        assert_eq!(Filter::from_str("tcp && port 53"), Ok(and(tcp(), port())));
+        assert_eq!(Filter::from_str("tcp || udp"), Ok(or(tcp(), udp())));
+        assert_eq!(Filter::from_str("not tcp"), Ok(not(tcp())));
+        assert_eq!(Filter::from_str("!tcp"), Ok(not(tcp())));
+        assert_eq!(Filter::from_str("not not tcp"), Ok(not(not(tcp()))));
+        // `and` and `or` have the same precedence and are evaluated left to right.
+        assert_eq!(Filter::from_str("tcp or udp and port 53"), Ok(and(or(tcp(), udp()), port())));
+        assert_eq!(Filter::from_str("tcp or (udp and port 53)"), Ok(or(tcp(), and(udp(), port()))));
+        assert_eq!(Filter::from_str("!(tcp or udp)"), Ok(not(or(tcp(), udp()))));
+        assert_eq!(Filter::from_str("  ((tcp))  "), Ok(tcp()));
+    }
+
+    #[test]
+    fn parse_malformed_expressions() {
+        for expression in [
+            "", " ", "tcp and", "or tcp", "not", "(tcp", "tcp)", "()", "(tcp or)",
+            "http", "TCP", "src", "src tcp", "src udp 53", "dst host", "host",
+            "host 10.0.2", "host example.com", "port", "port http", "port 65536", "port -1",
+        ] {
+            assert!(Filter::from_str(expression).is_err(), "{:?} should be rejected", expression);
+        }
+    }
+
+    #[test]
+    fn match_protocols() {
+        let udp = ipv4(PROTOCOL_UDP, &ports(49152, 53));
+        let icmp = ipv4(PROTOCOL_ICMP, &[8, 0, 0, 0]);
+        let tcp6 = ipv6(PROTOCOL_TCP, &ports(49152, 80));
+        let icmp6 = ipv6(PROTOCOL_ICMPV6, &[128, 0, 0, 0]);
+        let frames = [("arp", arp()), ("tcp", tcp()), ("udp", udp), ("icmp", icmp), ("tcp6", tcp6), ("icmp6", icmp6)];
+        let expected: [(&str, &[&str]); 7] = [
+            ("arp", &["arp"]),
+            ("ip", &["tcp", "udp", "icmp"]),
+            ("ip6", &["tcp6", "icmp6"]),
+            ("icmp", &["icmp"]),
+            ("icmp6", &["icmp6"]),
+            ("tcp", &["tcp", "tcp6"]),
+            ("udp", &["udp"]),
+        ];
+        for (expression, matching) in expected {
+            for (name, frame) in &frames {
+                assert_eq!(matches(expression, frame), matching.contains(name), "{:?} on {} frame", expression, name);
+            }
+        }
+    }
+
+    #[test]
+    fn match_hosts() {
+        let frame = tcp();
+        assert!(matches("host 10.0.2.15", &frame));
+        assert!(matches("host 10.0.2.3", &frame));
+        assert!(!matches("host 10.0.2.2", &frame));
+        assert!(matches("src host 10.0.2.15", &frame));
+        assert!(!matches("src host 10.0.2.3", &frame));
+        assert!(matches("dst host 10.0.2.3", &frame));
+        assert!(!matches("dst host 10.0.2.15", &frame));
+        assert!(!matches("host 10.0.2.15", &arp()));
+
+        let frame = ipv6(PROTOCOL_UDP, &ports(546, 547));
+        assert!(matches("src host fe80::1", &frame));
+        assert!(matches("dst host fe80::2", &frame));
+        assert!(!matches("dst host fe80::1", &frame));
+        // An IPv4 address never matches an IPv6 packet.
+        assert!(!matches("host 10.0.2.15", &frame));
+    }
+
+    #[test]
+    fn match_ports() {
+        let frame = tcp();
+        assert!(matches("port 80", &frame));
+        assert!(matches("port 49152", &frame));
+        assert!(!matches("port 443", &frame));
+        assert!(matches("src port 49152", &frame));
+        assert!(!matches("src port 80", &frame));
+        assert!(matches("dst port 80", &frame));
+        assert!(!matches("dst port 49152", &frame));
+        assert!(matches("udp dst port 547", &ipv6(PROTOCOL_UDP, &ports(546, 547))));
+        // Ports are only matched in TCP and UDP packets, even if the bytes at their offsets are equal.
+        assert!(!matches("port 2048", &ipv4(PROTOCOL_ICMP, &ports(2048, 2048))));
+        assert!(!matches("port 0", &arp()));
+    }
+
+    #[test]
+    fn match_operators() {
+        let frame = tcp();
+        assert!(matches("tcp and port 80", &frame));
+        assert!(!matches("tcp and port 443", &frame));
+        assert!(matches("udp or port 80", &frame));
+        assert!(!matches("udp or port 443", &frame));
+        assert!(matches("not udp", &frame));
+        assert!(!matches("!tcp", &frame));
+        assert!(matches("arp or (tcp and dst host 10.0.2.3)", &frame));
+        assert!(!matches("arp or (tcp and src host 10.0.2.3)", &frame));
+    }
+
+    #[test]
+    fn match_vlan_tagged_frame() {
+        let untagged = tcp();
+        let mut frame = untagged[..12].to_vec();
+        frame.extend_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+        frame.extend_from_slice(&[0, 42]);
+        frame.extend_from_slice(&untagged[12..]);
+        assert!(matches("ip and tcp and src host 10.0.2.15 and dst port 80", &frame));
+    }
+
+    #[test]
+    fn match_fragments() {
+        let mut frame = tcp();
+        // Set the fragment offset of the IPv4 header, so the payload doesn't start with a TCP header.
+        frame[14 + 7] = 1;
+        assert!(matches("tcp and host 10.0.2.3", &frame));
+        assert!(!matches("port 80", &frame));
+    }
+
+    #[test]
+    fn match_truncated_frames() {
+        let frame = tcp();
+        // A frame that ends within the TCP ports still matches its protocol and addresses.
+        assert!(matches("tcp and host 10.0.2.3", &frame[..14 + 20 + 3]));
+        assert!(!matches("port 80", &frame[..14 + 20 + 3]));
+        // A frame that ends within the IPv4 addresses only matches its protocol.
+        assert!(matches("ip", &frame[..14 + 19]));
+        assert!(!matches("host 10.0.2.3", &frame[..14 + 19]));
+        for len in 0..14 {
+            for expression in ["arp", "ip", "ip6", "tcp", "udp", "host 10.0.2.3", "port 80"] {
+                assert!(!matches(expression, &frame[..len]), "{:?} on frame of length {}", expression, len);
+            }
+            assert!(matches("not ip", &frame[..len]));
+        }
+    }
+}
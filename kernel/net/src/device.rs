@@ -6,7 +6,10 @@ use smoltcp::phy;
 
 pub use phy::DeviceCapabilities;
 
-use crate::Error;
+use crate::{
+    capture::{Capture, Direction},
+    Error,
+};
 
 /// Standard maximum transition unit for ethernet cards.
 const STANDARD_MTU: usize = 1500;
@@ -44,22 +47,38 @@ pub trait NetworkDevice: Send + Sync + Any {
 /// ```
 /// error[E0210]: type parameter `T` must be used as the type parameter for some local type (e.g., `MyStruct<T>`)
 /// ```
+///
+/// The wrapper also copies the frames that pass through it into the interface's
+/// [`Capture`], if a capture is active.
 pub(crate) struct DeviceWrapper<'a> {
     pub(crate) inner: &'a mut dyn NetworkDevice,
+    pub(crate) capture: &'a Capture,
 }
 
 impl<'a> phy::Device for DeviceWrapper<'a> {
-    type RxToken<'b> = RxToken where Self: 'b;
+    type RxToken<'b> = RxToken<'b> where Self: 'b;
 
     type TxToken<'c> = TxToken<'c> where Self: 'c;
 
     fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         let frame = self.inner.receive()?;
-        Some((RxToken { inner: frame }, TxToken { device: self.inner }))
+        Some((
+            RxToken {
+                inner: frame,
+                capture: self.capture,
+            },
+            TxToken {
+                device: self.inner,
+                capture: self.capture,
+            },
+        ))
     }
 
     fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
-        Some(TxToken { device: self.inner })
+        Some(TxToken {
+            device: self.inner,
+            capture: self.capture,
+        })
     }
 
     fn capabilities(&self) -> DeviceCapabilities {
@@ -68,11 +87,12 @@ impl<'a> phy::Device for DeviceWrapper<'a> {
 }
 
 /// The receive token.
-pub(crate) struct RxToken {
+pub(crate) struct RxToken<'a> {
     inner: ReceivedFrame,
+    capture: &'a Capture,
 }
 
-impl phy::RxToken for RxToken {
+impl<'a> phy::RxToken for RxToken<'a> {
     fn consume<R, F>(mut self, _timestamp: smoltcp::time::Instant, f: F) -> smoltcp::Result<R>
     where
         F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
@@ -84,6 +104,7 @@ impl phy::RxToken for RxToken {
             );
         }
         let slice = self.inner.0.first_mut().ok_or(Error::Exhausted)?;
+        self.capture.record(Direction::Received, slice);
         f(slice)
     }
 }
@@ -91,6 +112,7 @@ impl phy::RxToken for RxToken {
 /// The transmit token.
 pub(crate) struct TxToken<'a> {
     device: &'a mut dyn NetworkDevice,
+    capture: &'a Capture,
 }
 
 impl<'a> phy::TxToken for TxToken<'a> {
@@ -106,6 +128,7 @@ impl<'a> phy::TxToken for TxToken<'a> {
         let mut buf = vec![0; len];
         let ret = f(&mut buf)?;
         self.device.send(&buf)?;
+        self.capture.record(Direction::Sent, &buf);
         Ok(ret)
     }
 }
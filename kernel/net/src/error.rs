@@ -13,6 +13,14 @@ pub enum Error {
     Malformed,
     Dropped,
     NotSupported,
+    /// A blocking socket operation did not complete before its timeout elapsed.
+    TimedOut,
+    /// The remote host refused to accept a connection.
+    ConnectionRefused,
+    /// The connection was reset or closed by the remote host.
+    ConnectionReset,
+    /// The socket isn't connected.
+    NotConnected,
     Unknown,
 }
 
@@ -50,7 +58,11 @@ impl From<Error> for smoltcp::Error {
             Error::Dropped => Self::Dropped,
             Error::NotSupported => Self::NotSupported,
             // TODO: Ideally smoltcp::Error would have an unknown variant.
-            Error::Unknown => Self::Illegal,
+            Error::TimedOut
+            | Error::ConnectionRefused
+            | Error::ConnectionReset
+            | Error::NotConnected
+            | Error::Unknown => Self::Illegal,
         }
     }
 }
@@ -69,6 +81,10 @@ impl core::fmt::Display for Error {
             Error::Malformed => "malformed packet",
             Error::Dropped => "dropped by socket",
             Error::NotSupported => "not supported by network stack",
+            Error::TimedOut => "operation timed out",
+            Error::ConnectionRefused => "connection refused",
+            Error::ConnectionReset => "connection reset",
+            Error::NotConnected => "socket is not connected",
             Error::Unknown => "unknown network error",
         })
     }
@@ -1,9 +1,26 @@
-use crate::{device::DeviceWrapper, NetworkDevice, Result, Socket};
-use alloc::vec::Vec;
-use core::marker::PhantomData;
+use crate::{
+    capture::{Capture, CaptureConfig, CaptureStats, CapturedFrame},
+    device::DeviceWrapper,
+    worker::WorkerSignal,
+    Error, NetworkDevice, Result, Socket,
+};
+use alloc::{sync::Arc, task::Wake, vec::Vec};
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
 use irq_safety::MutexIrqSafe;
 use mutex_sleep::MutexSleep;
-use smoltcp::{iface, phy::DeviceCapabilities, socket::AnySocket, wire};
+use smoltcp::{
+    iface::SocketHandle,
+    phy::DeviceCapabilities,
+    socket::{tcp, AnySocket},
+    iface, wire,
+};
+use spin::Mutex;
+use time::{Duration, Monotonic};
+use wait_queue::WaitQueue;
 
 pub use smoltcp::iface::SocketSet;
 pub use wire::{IpAddress, IpCidr};
@@ -16,31 +33,59 @@ pub struct NetworkInterface {
     inner: MutexSleep<iface::Interface<'static>>,
     device: &'static MutexIrqSafe<dyn crate::NetworkDevice>,
     sockets: MutexSleep<SocketSet<'static>>,
+    dns_servers: Mutex<Vec<IpAddress>>,
+    events: Arc<SocketEvents>,
+    /// TCP sockets that were closed by their owner but may still have data to send.
+    ///
+    /// They are removed from `sockets` once the connection has been fully closed.
+    closing_tcp_sockets: Mutex<Vec<SocketHandle>>,
+    worker: Arc<WorkerSignal>,
+    capture: Capture,
+}
+
+/// Wakes up tasks that are waiting for the state of an interface's sockets to change.
+pub(crate) struct SocketEvents {
+    /// Incremented every time the interface is polled.
+    generation: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl SocketEvents {
+    fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.waiters.notify_all();
+    }
+}
+
+/// Used as a timer callback that wakes up waiting tasks once their timeout has elapsed.
+///
+/// This doesn't count as a poll, so woken tasks can tell that they must poll the interface themselves.
+impl Wake for SocketEvents {
+    fn wake(self: Arc<Self>) {
+        self.waiters.notify_all();
+    }
 }
 
 impl NetworkInterface {
-    pub(crate) fn new<T>(device: &'static MutexIrqSafe<T>, ip: IpCidr, gateway: IpAddress) -> Self
+    /// Creates a new interface for the given device without any IP addresses or routes.
+    pub(crate) fn new<T>(device: &'static MutexIrqSafe<T>) -> Self
     where
         T: NetworkDevice,
     {
         let hardware_addr = wire::EthernetAddress(device.lock().mac_address()).into();
 
-        let mut routes = iface::Routes::new();
-
-        match gateway {
-            IpAddress::Ipv4(addr) => routes.add_default_ipv4_route(addr),
-            IpAddress::Ipv6(addr) => routes.add_default_ipv6_route(addr),
-        }
-        .expect("btree map route storage exhausted");
+        let routes = iface::Routes::new();
 
+        let capture = Capture::new();
         let mut wrapper = DeviceWrapper {
             inner: &mut *device.lock(),
+            capture: &capture,
         };
         let inner = MutexSleep::new(
             iface::InterfaceBuilder::new()
                 .random_seed(random::next_u64())
                 .hardware_addr(hardware_addr)
-                .ip_addrs(heapless::Vec::<_, 5>::from_slice(&[ip]).unwrap())
+                .ip_addrs(heapless::Vec::<_, 5>::new())
                 .routes(routes)
                 .neighbor_cache(iface::NeighborCache::new())
                 .finalize(&mut wrapper),
@@ -52,9 +97,110 @@ impl NetworkInterface {
             inner,
             device,
             sockets,
+            dns_servers: Mutex::new(Vec::new()),
+            events: Arc::new(SocketEvents {
+                generation: AtomicUsize::new(0),
+                waiters: WaitQueue::new(),
+            }),
+            closing_tcp_sockets: Mutex::new(Vec::new()),
+            worker: Arc::new(WorkerSignal::new()),
+            capture,
         }
     }
 
+    /// Returns the IP addresses assigned to the interface.
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        let inner = self.inner.lock().expect("failed to lock inner interface");
+        inner.ip_addrs().to_vec()
+    }
+
+    /// Replaces the interface's IPv4 address and default IPv4 gateway.
+    ///
+    /// If `address` is `None`, the interface will no longer have an IPv4 address,
+    /// and if `gateway` is `None`, the default IPv4 route is removed.
+    /// Addresses and routes of other IP versions are left untouched.
+    pub fn set_ipv4_config(&self, address: Option<wire::Ipv4Cidr>, gateway: Option<wire::Ipv4Address>) {
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        inner.update_ip_addrs(|addrs| {
+            addrs.retain(|addr| !matches!(addr, IpCidr::Ipv4(_)));
+            if let Some(address) = address {
+                addrs
+                    .push(IpCidr::Ipv4(address))
+                    .expect("interface IP address storage exhausted");
+            }
+        });
+        match gateway {
+            Some(gateway) => {
+                inner
+                    .routes_mut()
+                    .add_default_ipv4_route(gateway)
+                    .expect("btree map route storage exhausted");
+            }
+            None => {
+                inner.routes_mut().remove_default_ipv4_route();
+            }
+        }
+    }
+
+    /// Adds `address` to the interface's IP addresses, unless it's already assigned.
+    ///
+    /// Link-local IPv6 addresses are kept after all other addresses,
+    /// because smoltcp uses the first address of the destination's IP version as the source address.
+    pub fn add_ip_addr(&self, address: IpCidr) -> Result<()> {
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        let mut result = Ok(());
+        inner.update_ip_addrs(|addrs| {
+            if addrs.contains(&address) {
+                return;
+            }
+            if addrs.push(address).is_err() {
+                result = Err(Error::Exhausted);
+                return;
+            }
+            addrs.sort_by_key(is_ipv6_link_local);
+        });
+        result
+    }
+
+    /// Removes `address` from the interface's IP addresses.
+    ///
+    /// Returns `true` if the address was assigned to the interface.
+    pub fn remove_ip_addr(&self, address: IpCidr) -> bool {
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        let mut removed = false;
+        inner.update_ip_addrs(|addrs| {
+            removed = addrs.contains(&address);
+            addrs.retain(|addr| *addr != address);
+        });
+        removed
+    }
+
+    /// Replaces the interface's default IPv6 gateway, or removes it if `gateway` is `None`.
+    pub fn set_ipv6_gateway(&self, gateway: Option<wire::Ipv6Address>) {
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        match gateway {
+            Some(gateway) => {
+                inner
+                    .routes_mut()
+                    .add_default_ipv6_route(gateway)
+                    .expect("btree map route storage exhausted");
+            }
+            None => {
+                inner.routes_mut().remove_default_ipv6_route();
+            }
+        }
+    }
+
+    /// Returns the DNS servers that the interface was configured with.
+    pub fn dns_servers(&self) -> Vec<IpAddress> {
+        self.dns_servers.lock().clone()
+    }
+
+    /// Sets the DNS servers used to resolve host names on this interface.
+    pub fn set_dns_servers(&self, servers: &[IpAddress]) {
+        *self.dns_servers.lock() = servers.to_vec();
+    }
+
     /// Adds a socket to the interface.
     pub fn add_socket<T>(&self, socket: T) -> Socket<T>
     where
@@ -68,24 +214,181 @@ impl NetworkInterface {
         Socket {
             handle,
             sockets: &self.sockets,
+            worker: &self.worker,
             phantom_data: PhantomData,
         }
     }
 
     /// Polls the sockets associated with the interface.
+    ///
+    /// Afterwards, all tasks blocked in [`wait_for_events`] are woken up.
+    ///
+    /// [`wait_for_events`]: Self::wait_for_events
     pub fn poll(&self) -> Result<()> {
-        let mut inner = self.inner.lock().expect("failed to lock inner interface");
-        let mut wrapper = DeviceWrapper {
-            inner: &mut *self.device.lock(),
+        let result = {
+            let mut inner = self.inner.lock().expect("failed to lock inner interface");
+            let mut wrapper = DeviceWrapper {
+                inner: &mut *self.device.lock(),
+                capture: &self.capture,
+            };
+            let mut sockets = self.sockets.lock().expect("failed to lock sockets");
+
+            let result = inner.poll(now(), &mut wrapper, &mut sockets);
+
+            self.closing_tcp_sockets.lock().retain(|&handle| {
+                let state = sockets.get::<tcp::Socket>(handle).state();
+                let closed = matches!(state, tcp::State::Closed | tcp::State::TimeWait);
+                if closed {
+                    sockets.remove(handle);
+                }
+                !closed
+            });
+            result
         };
+        self.events.notify();
+
+        result?;
+        Ok(())
+    }
+
+    /// Wakes up the interface's worker task so that it polls the interface soon.
+    ///
+    /// NIC drivers call this after receiving packets.
+    pub fn wake_worker(&self) {
+        self.worker.notify();
+    }
+
+    pub(crate) fn worker_signal(&self) -> Arc<WorkerSignal> {
+        self.worker.clone()
+    }
+
+    /// Returns how long the interface can wait before it must be polled again,
+    /// or `None` if no timers are pending.
+    pub(crate) fn poll_delay(&self) -> Option<Duration> {
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        let sockets = self.sockets.lock().expect("failed to lock sockets");
+        inner
+            .poll_delay(now(), &sockets)
+            .map(|delay| Duration::from_micros(delay.total_micros()))
+    }
+
+    /// Blocks the current task until this interface is next polled,
+    /// or until the given `timeout` has elapsed.
+    ///
+    /// Returns `true` if the interface was polled in the meantime, or `false` if the timeout elapsed.
+    /// Callers must re-check the state of their sockets afterwards,
+    /// as the poll may not have affected them.
+    pub fn wait_for_events(&self, timeout: Duration) -> bool {
+        let generation = self.events.generation.load(Ordering::Acquire);
+        let deadline = time::now::<Monotonic>() + timeout;
+        sleep::future::sleep(timeout, Waker::from(self.events.clone()));
+        let woken = self.events.waiters.wait_until(&|| {
+            if self.events.generation.load(Ordering::Acquire) != generation {
+                Some(true)
+            } else if time::now::<Monotonic>() >= deadline {
+                Some(false)
+            } else {
+                None
+            }
+        });
+        // If the current task can't block, e.g., during early boot, the caller just polls again.
+        woken.unwrap_or(false)
+    }
+
+    /// Runs the given closure on the socket with the given `handle`.
+    pub(crate) fn with_socket<T, F, R>(&self, handle: SocketHandle, f: F) -> R
+    where
+        T: AnySocket<'static>,
+        F: FnOnce(&mut T) -> R,
+    {
         let mut sockets = self.sockets.lock().expect("failed to lock sockets");
+        f(sockets.get_mut::<T>(handle))
+    }
 
-        inner.poll(smoltcp::time::Instant::ZERO, &mut wrapper, &mut sockets)?;
+    /// Runs the given closure on the socket with the given `handle`
+    /// and the interface's context, which is needed to initiate TCP connections.
+    pub(crate) fn with_socket_and_context<T, F, R>(&self, handle: SocketHandle, f: F) -> R
+    where
+        T: AnySocket<'static>,
+        F: FnOnce(&mut T, &mut iface::Context<'static>) -> R,
+    {
+        // The interface must be locked before the sockets to match the order in `poll`.
+        let mut inner = self.inner.lock().expect("failed to lock inner interface");
+        let mut sockets = self.sockets.lock().expect("failed to lock sockets");
+        f(sockets.get_mut::<T>(handle), inner.context())
+    }
 
-        Ok(())
+    /// Adds a socket to the interface and returns its handle.
+    pub(crate) fn add_socket_handle<T>(&self, socket: T) -> SocketHandle
+    where
+        T: AnySocket<'static>,
+    {
+        self.sockets.lock().expect("failed to lock sockets").add(socket)
+    }
+
+    /// Removes the socket with the given `handle` from the interface.
+    pub(crate) fn remove_socket(&self, handle: SocketHandle) {
+        self.sockets.lock().expect("failed to lock sockets").remove(handle);
+    }
+
+    /// Closes the TCP socket with the given `handle`, which is removed from the interface
+    /// once its remaining data has been sent and the connection has been closed.
+    pub(crate) fn close_tcp_socket(&self, handle: SocketHandle) {
+        self.with_socket::<tcp::Socket, _, _>(handle, |socket| socket.close());
+        self.closing_tcp_sockets.lock().push(handle);
     }
 
     pub fn capabilities(&self) -> DeviceCapabilities {
         self.device.lock().capabilities()
     }
+
+    /// Returns the MAC address of the interface's device.
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.device.lock().mac_address()
+    }
+
+    /// Starts copying the frames that the interface sends and receives into a buffer,
+    /// from which they can be taken with [`take_captured_frames`].
+    ///
+    /// Frames buffered by a previous capture are discarded.
+    ///
+    /// [`take_captured_frames`]: Self::take_captured_frames
+    pub fn start_capture(&self, config: CaptureConfig) {
+        self.capture.start(config);
+    }
+
+    /// Stops capturing frames. Frames that were already captured can still be taken.
+    pub fn stop_capture(&self) {
+        self.capture.stop();
+    }
+
+    /// Returns whether the interface is currently capturing frames.
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_active()
+    }
+
+    /// Removes and returns the captured frames from the capture buffer, oldest first.
+    ///
+    /// New frames are captured when the interface is polled, so [`wait_for_events`]
+    /// can be used to wait for them.
+    ///
+    /// [`wait_for_events`]: Self::wait_for_events
+    pub fn take_captured_frames(&self) -> Vec<CapturedFrame> {
+        self.capture.take_frames()
+    }
+
+    /// Returns statistics about the current or most recent capture.
+    pub fn capture_stats(&self) -> CaptureStats {
+        self.capture.stats()
+    }
+}
+
+fn is_ipv6_link_local(address: &IpCidr) -> bool {
+    matches!(address, IpCidr::Ipv6(cidr) if cidr.address().is_link_local())
+}
+
+/// Returns the current time as a smoltcp timestamp, which drives retransmissions and timeouts.
+fn now() -> smoltcp::time::Instant {
+    let elapsed = time::now::<time::Monotonic>().duration_since(time::Instant::ZERO);
+    smoltcp::time::Instant::from_micros(elapsed.as_micros() as i64)
 }
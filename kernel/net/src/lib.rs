@@ -4,35 +4,28 @@ extern crate alloc;
 
 use alloc::{sync::Arc, vec::Vec};
 use irq_safety::MutexIrqSafe;
-use smoltcp::wire::Ipv4Address;
 use spin::Mutex;
 
+mod blocking;
+pub mod capture;
 mod device;
 mod error;
 mod interface;
 mod socket;
+mod worker;
 
+pub use blocking::{TcpListener, TcpStream, UdpSocket};
 pub use device::{DeviceCapabilities, NetworkDevice};
 pub use error::{Error, Result};
 pub use interface::{IpAddress, IpCidr, NetworkInterface, SocketSet};
 pub use smoltcp::{
     phy,
-    socket::{icmp, tcp, udp},
+    socket::{dhcpv4, icmp, raw, tcp, udp},
     time::Instant,
     wire,
 };
 pub use socket::Socket;
 
-/// A randomly chosen IP address that must be outside of the DHCP range.
-///
-/// The default QEMU user-slirp network gives IP address of `10.0.2.*`.
-const DEFAULT_LOCAL_IP: &str = "10.0.2.15/24";
-
-/// Standard home router address.
-///
-/// `10.0.2.2` is the default QEMU user-slirp networking gateway IP.
-const DEFAULT_GATEWAY_IP: IpAddress = IpAddress::Ipv4(Ipv4Address::new(10, 0, 2, 2));
-
 // TODO: Make mutex rwlock?
 // TODO: Use atomic append-only vec?
 static NETWORK_INTERFACES: Mutex<Vec<Arc<NetworkInterface>>> = Mutex::new(Vec::new());
@@ -40,21 +33,26 @@ static NETWORK_INTERFACES: Mutex<Vec<Arc<NetworkInterface>>> = Mutex::new(Vec::n
 /// Registers a network device.
 ///
 /// The function will convert the device to an interface and it will then be
-/// accessible using [`get_interfaces()`].
-pub fn register_device<T>(device: &'static MutexIrqSafe<T>) -> Arc<NetworkInterface>
+/// accessible using [`get_interfaces()`]. A worker task is spawned to poll the
+/// interface in the background; NIC drivers should wake it up using
+/// [`NetworkInterface::wake_worker`] when they receive packets.
+pub fn register_device<T>(
+    device: &'static MutexIrqSafe<T>,
+) -> core::result::Result<Arc<NetworkInterface>, &'static str>
 where
     T: 'static + NetworkDevice + Send,
 {
-    let interface = NetworkInterface::new(
-        device,
-        // TODO: use DHCP to acquire an IP address and gateway.
-        DEFAULT_LOCAL_IP.parse().unwrap(),
-        DEFAULT_GATEWAY_IP,
-    );
+    // The interface is configured later, e.g., by a DHCP client.
+    let interface = NetworkInterface::new(device);
 
     let interface_arc = Arc::new(interface);
-    NETWORK_INTERFACES.lock().push(interface_arc.clone());
-    interface_arc
+    let index = {
+        let mut interfaces = NETWORK_INTERFACES.lock();
+        interfaces.push(interface_arc.clone());
+        interfaces.len() - 1
+    };
+    worker::spawn(interface_arc.clone(), index)?;
+    Ok(interface_arc)
 }
 
 /// Returns a list of available interfaces behind a mutex.
@@ -66,3 +64,23 @@ pub fn get_interfaces() -> &'static Mutex<Vec<Arc<NetworkInterface>>> {
 pub fn get_default_interface() -> Option<Arc<NetworkInterface>> {
     NETWORK_INTERFACES.lock().get(0).cloned()
 }
+
+/// Gets the interface with an IP address in the same subnet as `addr`,
+/// e.g., the loopback interface for `127.0.0.1`.
+///
+/// Falls back to the default interface if no interface is in the same subnet,
+/// including if `addr` is unspecified.
+pub fn get_interface_for(addr: IpAddress) -> Option<Arc<NetworkInterface>> {
+    // Checking an interface's addresses may block, so the list must not be locked meanwhile.
+    let interfaces = NETWORK_INTERFACES.lock().clone();
+    interfaces
+        .iter()
+        .find(|interface| {
+            interface
+                .ip_addrs()
+                .iter()
+                .any(|cidr| cidr.contains_addr(&addr))
+        })
+        .or_else(|| interfaces.first())
+        .cloned()
+}
@@ -1,3 +1,4 @@
+use crate::worker::WorkerSignal;
 use core::{
     marker::PhantomData,
     ops::{Deref, DerefMut},
@@ -13,12 +14,16 @@ use smoltcp::{
 /// In order to use the socket, it must be locked using the [`lock`] method.
 /// This will lock the interface's list of sockets, and so the guard returned by
 /// [`lock`] must be dropped before calling [`Interface::poll`].
+///
+/// Dropping the guard wakes up the interface's worker task,
+/// which then polls the interface to act upon any changes made to the socket.
 pub struct Socket<'a, T>
 where
     T: AnySocket<'static> + ?Sized,
 {
     pub(crate) handle: SocketHandle,
     pub(crate) sockets: &'a MutexSleep<SocketSet<'static>>,
+    pub(crate) worker: &'a WorkerSignal,
     pub(crate) phantom_data: PhantomData<T>,
 }
 
@@ -28,9 +33,19 @@ where
 {
     handle: SocketHandle,
     sockets: MutexSleepGuard<'a, SocketSet<'static>>,
+    worker: &'a WorkerSignal,
     phantom_data: PhantomData<T>,
 }
 
+impl<'a, T> Drop for LockedSocket<'a, T>
+where
+    T: AnySocket<'static> + ?Sized,
+{
+    fn drop(&mut self) {
+        self.worker.notify();
+    }
+}
+
 impl<'a, T> Deref for LockedSocket<'a, T>
 where
     T: AnySocket<'static>,
@@ -59,6 +74,7 @@ where
         LockedSocket {
             handle: self.handle,
             sockets: self.sockets.lock().expect("failed to lock sockets"),
+            worker: self.worker,
             phantom_data: PhantomData,
         }
     }
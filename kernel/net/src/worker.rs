@@ -0,0 +1,84 @@
+//! The background task that drives a network interface.
+//!
+//! Every interface has a worker task that polls it whenever the NIC receives packets,
+//! whenever one of its sockets is used, and whenever smoltcp needs to act on a timer,
+//! e.g., to retransmit TCP segments or to send keepalives.
+//! Applications therefore don't need to poll the interface themselves.
+
+use crate::NetworkInterface;
+use alloc::{format, sync::Arc, task::Wake};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+};
+use log::debug;
+use time::{Duration, Monotonic};
+use wait_queue::WaitQueue;
+
+/// The longest the worker sleeps between polls, even if smoltcp has no pending timers.
+///
+/// This bounds the delay for sockets that are used without waking the worker,
+/// e.g., if their owner keeps them locked across several operations.
+const MAX_POLL_DELAY: Duration = Duration::from_secs(1);
+
+/// Wakes up an interface's worker task.
+pub(crate) struct WorkerSignal {
+    /// Whether the interface should be polled as soon as possible.
+    pending: AtomicBool,
+    waiters: WaitQueue,
+}
+
+impl WorkerSignal {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Requests that the worker polls the interface.
+    pub(crate) fn notify(&self) {
+        self.pending.store(true, Ordering::Release);
+        self.waiters.notify_all();
+    }
+
+    /// Blocks until [`notify`](Self::notify) is called or until `timeout` elapses.
+    fn wait(self: &Arc<Self>, timeout: Duration) {
+        let deadline = time::now::<Monotonic>() + timeout;
+        sleep::future::sleep(timeout, Waker::from(self.clone()));
+        let _ = self.waiters.wait_until(&|| {
+            let pending = self.pending.swap(false, Ordering::AcqRel);
+            (pending || time::now::<Monotonic>() >= deadline).then_some(())
+        });
+    }
+}
+
+/// Used as a timer callback that wakes up the worker once its poll delay has elapsed.
+impl Wake for WorkerSignal {
+    fn wake(self: Arc<Self>) {
+        self.waiters.notify_all();
+    }
+}
+
+/// Spawns the worker task for the given `interface`.
+pub(crate) fn spawn(interface: Arc<NetworkInterface>, index: usize) -> Result<(), &'static str> {
+    spawn::new_task_builder(worker_task, interface)
+        .name(format!("net_worker_{index}"))
+        .spawn()?;
+    Ok(())
+}
+
+/// The entry point of an interface's worker task.
+fn worker_task(interface: Arc<NetworkInterface>) {
+    let signal = interface.worker_signal();
+    loop {
+        if let Err(e) = interface.poll() {
+            // Errors are caused by individual packets, e.g., unsupported protocols.
+            debug!("net_worker: error polling interface: {:?}", e);
+        }
+        let delay = interface
+            .poll_delay()
+            .map_or(MAX_POLL_DELAY, |delay| delay.min(MAX_POLL_DELAY));
+        signal.wait(delay);
+    }
+}
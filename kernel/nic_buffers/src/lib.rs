@@ -126,9 +126,11 @@ impl ReceiveBuffer {
 
     /// Sets the buffers length.
     ///
-    /// Returns an error if the length is greater than the current length.
+    /// Returns an error if the length is greater than the size of the underlying memory.
+    /// A buffer's length is reset to zero when it is returned to its pool,
+    /// so a recycled buffer may grow back up to its full size.
     pub fn set_length(&mut self, length: u16) -> Result<(), &'static str> {
-        if length > self.length {
+        if usize::from(length) > self.mp.size_in_bytes() {
             Err("ReceiveBuffer::set_length(): length too long")
         } else {
             self.length = length;
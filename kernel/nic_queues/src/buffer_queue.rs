@@ -0,0 +1,166 @@
+//! Receive and transmit queues for NICs whose queues are not fixed rings of hardware descriptors,
+//! but queues of buffers that the NIC may complete out of order, e.g., virtio virtqueues.
+//!
+//! Each buffer given to such a NIC is identified by a *token* chosen by the [`BufferQueue`],
+//! which the NIC hands back once it has finished with that buffer.
+//! The [`BufferRxQueue`] and [`BufferTxQueue`] track which `ReceiveBuffer` or `TransmitBuffer`
+//! belongs to each token, while the driver implements [`BufferQueue`] to access the NIC's queue.
+
+use alloc::{
+    collections::VecDeque,
+    vec,
+    vec::Vec,
+};
+use memory::PhysicalAddress;
+use nic_buffers::{ReceiveBuffer, ReceivedFrame, TransmitBuffer};
+use super::obtain_receive_buffer;
+
+/// The hooks through which a [`BufferRxQueue`] or [`BufferTxQueue`] accesses a NIC's queue.
+pub trait BufferQueue {
+    /// Returns the number of distinct tokens, i.e., every token is less than this.
+    fn num_tokens(&self) -> u16;
+
+    /// Returns whether another buffer can currently be given to the NIC.
+    fn can_add_buffer(&self) -> bool;
+
+    /// Gives the buffer at the given physical address to the NIC,
+    /// which either fills it with a received frame (`device_writable`) or transmits its first `len` bytes.
+    ///
+    /// Returns the token that identifies the buffer. The NIC must be notified afterwards.
+    fn add_buffer(&mut self, phys_addr: PhysicalAddress, len: u16, device_writable: bool) -> Result<u16, &'static str>;
+
+    /// Returns the token of the next buffer that the NIC has finished with,
+    /// along with the length of the frame received into it, if any.
+    fn pop_completed(&mut self) -> Option<(u16, usize)>;
+}
+
+/// A receive queue whose buffers are identified by the tokens of a [`BufferQueue`].
+pub struct BufferRxQueue<Q: BufferQueue> {
+    /// The number of the queue, stored here for our convenience.
+    pub id: u8,
+    /// The NIC's queue.
+    pub queue: Q,
+    /// The receive buffers in use by the NIC, indexed by their token.
+    pub rx_bufs_in_use: Vec<Option<ReceiveBuffer>>,
+    pub rx_buffer_size_bytes: u16,
+    /// The queue of received Ethernet frames, ready for consumption by a higher layer.
+    pub received_frames: VecDeque<ReceivedFrame>,
+    /// The cpu which this queue is mapped to.
+    pub cpu_id: Option<u8>,
+    /// Pool where `ReceiveBuffer`s are stored.
+    pub rx_buffer_pool: &'static mpmc::Queue<ReceiveBuffer>,
+}
+
+impl<Q: BufferQueue> BufferRxQueue<Q> {
+    /// Creates a new receive queue and gives receive buffers to the NIC until its `queue` is full.
+    ///
+    /// The NIC must be notified afterwards.
+    pub fn new(
+        id: u8,
+        queue: Q,
+        rx_buffer_size_bytes: u16,
+        rx_buffer_pool: &'static mpmc::Queue<ReceiveBuffer>,
+    ) -> Result<BufferRxQueue<Q>, &'static str> {
+        let mut rx_bufs_in_use = Vec::with_capacity(queue.num_tokens() as usize);
+        rx_bufs_in_use.resize_with(queue.num_tokens() as usize, || None);
+        let mut rx_queue = BufferRxQueue {
+            id,
+            queue,
+            rx_bufs_in_use,
+            rx_buffer_size_bytes,
+            received_frames: VecDeque::new(),
+            cpu_id: None,
+            rx_buffer_pool,
+        };
+        rx_queue.refill()?;
+        Ok(rx_queue)
+    }
+
+    /// Gives receive buffers to the NIC until its queue is full.
+    ///
+    /// Returns `true` if any buffers were added, in which case the NIC must be notified.
+    pub fn refill(&mut self) -> Result<bool, &'static str> {
+        let mut added = false;
+        while self.queue.can_add_buffer() {
+            let mut buffer = obtain_receive_buffer(self.rx_buffer_pool, self.rx_buffer_size_bytes)?;
+            buffer.set_length(self.rx_buffer_size_bytes)?;
+            let token = self.queue.add_buffer(buffer.phys_addr(), self.rx_buffer_size_bytes, true)?;
+            let slot = self.rx_bufs_in_use.get_mut(token as usize).ok_or("BUG: BufferQueue returned an invalid token")?;
+            *slot = Some(buffer);
+            added = true;
+        }
+        Ok(added)
+    }
+
+    /// Removes all received packets from the queue and stores them in `received_frames`,
+    /// then gives new receive buffers to the NIC.
+    ///
+    /// Returns `true` if the NIC must be notified about new receive buffers.
+    pub fn poll_queue_and_store_received_packets(&mut self) -> Result<bool, &'static str> {
+        let mut received = false;
+        while let Some((token, frame_len)) = self.queue.pop_completed() {
+            let Some(mut buffer) = self.rx_bufs_in_use.get_mut(token as usize).and_then(Option::take) else {
+                error!("rx queue {}: NIC returned unknown token {}", self.id, token);
+                continue;
+            };
+            received = true;
+            buffer.set_length(frame_len as u16)?;
+            self.received_frames.push_back(ReceivedFrame(vec![buffer]));
+        }
+        if received {
+            self.refill()
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns the earliest received ethernet frame.
+    pub fn return_frame(&mut self) -> Option<ReceivedFrame> {
+        self.received_frames.pop_front()
+    }
+}
+
+/// A transmit queue whose buffers are identified by the tokens of a [`BufferQueue`].
+pub struct BufferTxQueue<Q: BufferQueue> {
+    /// The number of the queue, stored here for our convenience.
+    pub id: u8,
+    /// The NIC's queue.
+    pub queue: Q,
+    /// The transmit buffers in use by the NIC, indexed by their token.
+    pub tx_bufs_in_flight: Vec<Option<TransmitBuffer>>,
+    /// The cpu which this queue is mapped to.
+    pub cpu_id: Option<u8>,
+}
+
+impl<Q: BufferQueue> BufferTxQueue<Q> {
+    /// Creates a new, empty transmit queue.
+    pub fn new(id: u8, queue: Q) -> BufferTxQueue<Q> {
+        let mut tx_bufs_in_flight = Vec::with_capacity(queue.num_tokens() as usize);
+        tx_bufs_in_flight.resize_with(queue.num_tokens() as usize, || None);
+        BufferTxQueue { id, queue, tx_bufs_in_flight, cpu_id: None }
+    }
+
+    /// Frees the transmit buffers of all packets that the NIC has finished sending.
+    pub fn reclaim(&mut self) {
+        while let Some((token, _len)) = self.queue.pop_completed() {
+            if self.tx_bufs_in_flight.get_mut(token as usize).and_then(Option::take).is_none() {
+                error!("tx queue {}: NIC returned unknown token {}", self.id, token);
+            }
+        }
+    }
+
+    /// Adds a packet to this transmit queue, which is sent once the NIC is notified.
+    ///
+    /// If the queue is full, this waits for the NIC to finish sending earlier packets.
+    pub fn send_on_queue(&mut self, transmit_buffer: TransmitBuffer) -> Result<(), &'static str> {
+        self.reclaim();
+        while !self.queue.can_add_buffer() {
+            core::hint::spin_loop();
+            self.reclaim();
+        }
+        let token = self.queue.add_buffer(transmit_buffer.phys_addr(), transmit_buffer.length(), false)?;
+        let slot = self.tx_bufs_in_flight.get_mut(token as usize).ok_or("BUG: BufferQueue returned an invalid token")?;
+        *slot = Some(transmit_buffer);
+        Ok(())
+    }
+}
@@ -5,6 +5,9 @@
 //! The SW queues defined here hold the ring of DMA descriptors that it shares with the HW,
 //! as well as other information such as the buffers received from the queues,
 //! the tail register for each queue and the cpu the queue is mapped to.
+//!
+//! NICs whose queues hold buffers that may be completed out of order rather than a fixed ring
+//! of hardware descriptors, e.g., virtio NICs, use the [`BufferRxQueue`] and [`BufferTxQueue`] instead.
 
 #![no_std]
 
@@ -23,6 +26,9 @@ use intel_ethernet::descriptors::{RxDescriptor, TxDescriptor};
 use nic_buffers::{ReceiveBuffer, ReceivedFrame, TransmitBuffer};
 pub use nic_buffers::NIC_MAPPING_FLAGS;
 
+mod buffer_queue;
+pub use buffer_queue::{BufferQueue, BufferRxQueue, BufferTxQueue};
+
 /// The register trait that gives access to only those registers required for receiving a packet.
 /// The Rx queue control registers can only be accessed by the physical NIC.
 pub trait RxQueueRegisters {
@@ -93,16 +99,7 @@ impl<S: RxQueueRegisters, T: RxDescriptor> RxQueue<S,T> {
             // Now that we are "removing" the current receive buffer from the list of receive buffers that the NIC can use,
             // (because we're saving it for higher layers to use),
             // we need to obtain a new `ReceiveBuffer` and set it up such that the NIC will use it for future receivals.
-            let new_receive_buf = match self.rx_buffer_pool.pop() {
-                Some(rx_buf) => rx_buf,
-                None => {
-                    warn!("NIC RX BUF POOL WAS EMPTY.... reallocating! This means that no task is consuming the accumulated received ethernet frames.");
-                    // if the pool was empty, then we allocate a new receive buffer
-                    let len = self.rx_buffer_size_bytes;
-                    let (mp, phys_addr) = create_contiguous_mapping(len as usize, NIC_MAPPING_FLAGS)?;
-                    ReceiveBuffer::new(mp, phys_addr, len, self.rx_buffer_pool)?
-                }
-            };
+            let new_receive_buf = obtain_receive_buffer(self.rx_buffer_pool, self.rx_buffer_size_bytes)?;
 
             // actually tell the NIC about the new receive buffer, and that it's ready for use now
             self.rx_descs[cur].set_packet_address(new_receive_buf.phys_addr());
@@ -137,6 +134,23 @@ impl<S: RxQueueRegisters, T: RxDescriptor> RxQueue<S,T> {
     }
 }
 
+/// Returns a `ReceiveBuffer` from the given `pool`,
+/// or allocates a new one of the given size if the pool is empty.
+fn obtain_receive_buffer(
+    pool: &'static mpmc::Queue<ReceiveBuffer>,
+    size_in_bytes: u16,
+) -> Result<ReceiveBuffer, &'static str> {
+    match pool.pop() {
+        Some(rx_buf) => Ok(rx_buf),
+        None => {
+            warn!("NIC RX BUF POOL WAS EMPTY.... reallocating! This means that no task is consuming the accumulated received ethernet frames.");
+            // if the pool was empty, then we allocate a new receive buffer
+            let (mp, phys_addr) = create_contiguous_mapping(size_in_bytes as usize, NIC_MAPPING_FLAGS)?;
+            ReceiveBuffer::new(mp, phys_addr, size_in_bytes, pool)
+        }
+    }
+}
+
 /// A struct that holds all information for a transmit queue. 
 /// There should be one such object per queue.
 pub struct TxQueue<S: TxQueueRegisters, T: TxDescriptor> {
@@ -1,63 +1,41 @@
 //! Functions to communicate with a network server that provides over-the-air live update functionality.
 //! 
+//! Each update build is described by a manifest that names the update build and lists the SHA3-512 hash
+//! of every file in it, and that is signed by the update server with an Ed25519 key. The public half of that key is
+//! baked into this crate at build time, so that only update builds from a trusted server are accepted:
+//! every file downloaded from an update build is checked against its verified manifest.
 
 #![no_std]
 #![feature(slice_concat_ext)]
 
 #[macro_use] extern crate log;
 #[macro_use] extern crate alloc;
-extern crate smoltcp;
-extern crate network_manager;
-extern crate hpet;
 extern crate spawn;
 extern crate task;
+extern crate fs_node;
 extern crate sha3;
-extern crate percent_encoding;
-extern crate rand;
+extern crate ed25519_compact;
 extern crate http_client;
 extern crate itertools;
-#[macro_use] extern crate smoltcp_helper;
 
 
 use core::str;
 use alloc::{
     vec::Vec,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     string::{String, ToString},
 };
 use itertools::Itertools;
-use hpet::get_hpet;
-use smoltcp::{
-    wire::{Ipv4Address, IpEndpoint},
-    socket::{SocketSet, TcpSocket, TcpSocketBuffer, TcpState},
-};
 use sha3::{Digest, Sha3_512};
-use percent_encoding::{DEFAULT_ENCODE_SET, utf8_percent_encode};
-use network_manager::{NetworkInterfaceRef};
-use rand::{
-    SeedableRng,
-    RngCore,
-    rngs::SmallRng
-};
-use http_client::{HttpResponse, ConnectedTcpSocket, send_request, check_http_request};
-use smoltcp_helper::{STARTING_FREE_PORT, connect, millis_since, poll_iface};
+use ed25519_compact::{PublicKey, Signature};
+use fs_node::FileRef;
+use http_client::{HttpClient, Url};
 
-/// The IP address of the update server.
-const DEFAULT_DESTINATION_IP_ADDR: [u8; 4] = [10, 0, 2, 2]; // the IP of the host machine when running on QEMU.
+/// The host name or IP address of the update server.
+pub const DEFAULT_DESTINATION_HOST: &str = "10.0.2.2"; // the IP of the host machine when running on QEMU.
 
 /// The TCP port on the update server that listens for update requests 
-const DEFAULT_DESTINATION_PORT: u16 = 8090;
-
-/// The default remote endpoint, server IP and port, of the update server.
-pub fn default_remote_endpoint() -> IpEndpoint {
-    IpEndpoint::new(
-        Ipv4Address::from_bytes(&DEFAULT_DESTINATION_IP_ADDR).into(), 
-        DEFAULT_DESTINATION_PORT
-    )
-}
-
-/// The time limit in milliseconds to wait for a response to an HTTP request.
-const HTTP_REQUEST_TIMEOUT_MILLIS: u64 = 10000;
+pub const DEFAULT_DESTINATION_PORT: u16 = 8090;
 
 /// The path of the update builds file, located at the root of the build server.
 /// This file contains the list of all update build instances available,
@@ -72,20 +50,176 @@ const LISTING_FILE_NAME: &str = "listing.txt";
 /// which contains the mapping of old crates to new crates, indicating how a swap should take place. 
 pub const DIFF_FILE_NAME: &str = "diff.txt";
 
-/// The name of the directory containing the checksums of each crate object file
-/// inside of a given update build.
-const CHECKSUMS_DIR_NAME: &str = "checksums";
+/// The name (and relative path) of the manifest file inside each update build directory,
+/// which contains the SHA3-512 hash of each file in that update build.
+pub const MANIFEST_FILE_NAME: &str = "manifest.txt";
 
-/// The file extension that is appended onto each crate object file's checksum file.
-const CHECKSUM_FILE_EXTENSION: &str = ".sha512";
+/// The name (and relative path) of the file inside each update build directory
+/// that contains the Ed25519 signature of its manifest file.
+pub const MANIFEST_SIGNATURE_FILE_NAME: &str = "manifest.txt.sig";
+
+/// The Ed25519 public key that update builds must be signed with, as 64 hexadecimal digits.
+///
+/// It is baked in at build time from the `THESEUS_OTA_PUBLIC_KEY` environment variable,
+/// which the top-level Makefile sets after a key pair has been generated with `make ota_keygen`.
+/// Without it, no update build can be verified.
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("THESEUS_OTA_PUBLIC_KEY");
+
+/// How many times a crate object file's download is resumed after its connection failed.
+const MAX_DOWNLOAD_RETRIES: usize = 5;
 
 
 
 /// A file that has been downloaded over the network, 
-/// including its name and a byte array containing its contents.
+/// including its name and the file that its contents were written into.
 pub struct DownloadedFile {
     pub name: String,
-    pub content: HttpResponse,
+    pub file: FileRef,
+}
+
+
+/// An update server, along with an HTTP connection to it
+/// that is reused for every file downloaded from it.
+pub struct UpdateServer {
+    host: String,
+    port: u16,
+    client: HttpClient,
+}
+impl UpdateServer {
+    /// Creates a handle to the update server at the given `host` name or IP address.
+    /// 
+    /// If `port` is `None`, the default update server port is used.
+    /// The server is connected to when the first file is downloaded from it.
+    pub fn new(host: &str, port: Option<u16>) -> UpdateServer {
+        UpdateServer {
+            host: host.to_string(),
+            port: port.unwrap_or(DEFAULT_DESTINATION_PORT),
+            client: HttpClient::new(),
+        }
+    }
+
+    /// Returns the URL of the file at the given absolute path on this server.
+    fn url(&self, absolute_path: &str) -> Result<Url, &'static str> {
+        Url::new(&self.host, self.port, absolute_path)
+    }
+}
+impl Default for UpdateServer {
+    fn default() -> UpdateServer {
+        UpdateServer::new(DEFAULT_DESTINATION_HOST, None)
+    }
+}
+
+
+/// The verified manifest of an update build,
+/// which maps the name of each file in the update build to its SHA3-512 hash.
+pub struct Manifest {
+    content: Vec<u8>,
+    signature: Vec<u8>,
+    build: String,
+    hashes: BTreeMap<String, String>,
+}
+impl Manifest {
+    /// Verifies the `signature` of the given manifest `content` with the public key
+    /// that was baked into this image, and then parses the manifest.
+    ///
+    /// The manifest starts with a line of the form `build <name>`, which names the update build,
+    /// followed by one line per file, consisting of the file's hash in hexadecimal,
+    /// followed by whitespace and the file's name, as output by `rhash --sha3-512`.
+    pub fn verify(content: Vec<u8>, signature: Vec<u8>) -> Result<Manifest, &'static str> {
+        let public_key_hex = UPDATE_PUBLIC_KEY
+            .ok_or("ota_update_client: no update signing key was built into this image, see `make ota_keygen`")?;
+        let public_key = decode_hex(public_key_hex.trim())
+            .and_then(|key| PublicKey::from_slice(&key).ok())
+            .ok_or("ota_update_client: the update signing key built into this image is invalid")?;
+        let sig = Signature::from_slice(&signature)
+            .map_err(|_e| "ota_update_client: manifest signature is malformed")?;
+        public_key.verify(&content, &sig)
+            .map_err(|_e| "ota_update_client: manifest signature is invalid, the update build is not trusted")?;
+
+        let lines = as_lines(&content)?;
+        let build = lines.first()
+            .and_then(|line| line.strip_prefix("build "))
+            .map(|build| build.trim().to_string())
+            .filter(|build| !build.is_empty())
+            .ok_or("ota_update_client: manifest doesn't name its update build")?;
+        let mut hashes = BTreeMap::new();
+        for line in &lines[1..] {
+            let mut parts = line.split_whitespace();
+            if let (Some(hash), Some(file_name)) = (parts.next(), parts.next()) {
+                hashes.insert(file_name.to_string(), hash.to_ascii_lowercase());
+            }
+        }
+        Ok(Manifest { content, signature, build, hashes })
+    }
+
+    /// Returns the name of the update build that this manifest was signed for.
+    pub fn build(&self) -> &str {
+        &self.build
+    }
+
+    /// Returns the contents of the manifest file, which were signed.
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Returns the signature of the manifest file.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Returns true if the manifest lists a file with the given name.
+    pub fn contains(&self, file_name: &str) -> bool {
+        self.hashes.contains_key(file_name)
+    }
+
+    /// Checks that the given `content` of the file named `file_name` matches its hash in the manifest.
+    pub fn verify_bytes(&self, file_name: &str, content: &[u8]) -> Result<(), &'static str> {
+        let mut hasher = Sha3_512::new();
+        hasher.update(content);
+        self.check_hash(file_name, hasher)
+    }
+
+    /// Checks that the contents of the given `file` named `file_name` match its hash in the manifest.
+    pub fn verify_file(&self, file_name: &str, file: &FileRef) -> Result<(), &'static str> {
+        let file = file.lock();
+        let len = file.len();
+        let mut hasher = Sha3_512::new();
+        let mut buffer = vec![0; 4096];
+        let mut offset = 0;
+        while offset < len {
+            let bytes_read = file.read_at(&mut buffer, offset)?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+            offset += bytes_read;
+        }
+        self.check_hash(file_name, hasher)
+    }
+
+    fn check_hash(&self, file_name: &str, hasher: Sha3_512) -> Result<(), &'static str> {
+        let expected = self.hashes.get(file_name).ok_or_else(|| {
+            error!("ota_update_client: file {:?} is not listed in the update build's manifest", file_name);
+            "ota_update_client: file is not listed in the update build's manifest"
+        })?;
+        if *expected == format!("{:x}", hasher.finalize()) {
+            Ok(())
+        } else {
+            error!("ota_update_client: file {:?} did not match the hash value in the update build's manifest", file_name);
+            Err("ota_update_client: file did not match the hash value in the update build's manifest")
+        }
+    }
+}
+
+
+/// Decodes a string of hexadecimal digits into bytes.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
 }
 
 
@@ -108,49 +242,75 @@ impl CrateSet {
 }
 
 
-/// Connects to the update server over the given network interface
-/// and downloads the list of available update builds.
+/// Downloads the list of available update builds from the update server.
 /// An update build is a compiled instance of Theseus that contains all crates' object files.
 pub fn download_available_update_builds(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint,
+    server: &mut UpdateServer,
 ) -> Result<Vec<String>, &'static str> {
-    download_string_file(iface, remote_endpoint, UPDATE_BUILDS_PATH)
+    download_string_file(server, UPDATE_BUILDS_PATH)
 }
 
 
-/// Connects to the update server over the given network interface
-/// and downloads the list of crates present in the given update build.
+/// Downloads the list of crates present in the given update build from the update server.
 pub fn download_listing(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint,
+    server: &mut UpdateServer,
     update_build: &str,
 ) -> Result<Vec<String>, &'static str> {
-    download_string_file(iface, remote_endpoint, &format!("/{update_build}/{LISTING_FILE_NAME}"))
+    download_string_file(server, &format!("/{update_build}/{LISTING_FILE_NAME}"))
+}
+
+
+/// Downloads the manifest of the given update build from the update server,
+/// along with its signature, which is verified before the manifest is returned.
+///
+/// The manifest must name the given `update_build`, so that the manifest of another
+/// (e.g., older) update build that was signed by the same key can't be substituted for it.
+pub fn download_manifest(
+    server: &mut UpdateServer,
+    update_build: &str,
+) -> Result<Manifest, &'static str> {
+    let url = server.url(&format!("/{update_build}/{MANIFEST_FILE_NAME}"))?;
+    let url_sig = server.url(&format!("/{update_build}/{MANIFEST_SIGNATURE_FILE_NAME}"))?;
+    let content = server.client.get(&url)?.as_result_err_str()?.to_vec();
+    let signature = server.client.get(&url_sig)?.as_result_err_str()?.to_vec();
+    let manifest = Manifest::verify(content, signature)?;
+    if manifest.build() != update_build {
+        error!("ota_update_client: requested update build {:?}, but its manifest was signed for update build {:?}",
+            update_build, manifest.build()
+        );
+        return Err("ota_update_client: manifest was signed for a different update build");
+    }
+    Ok(manifest)
 }
 
 
-/// Connects to the update server over the given network interface
-/// and downloads the diff file in the given update build,
+/// Downloads the diff file in the given update build from the update server,
 /// which dictates which crates should be swapped.
+///
+/// The diff file is checked against the update build's verified `manifest`,
+/// and its raw contents are returned such that they can be saved and verified again later.
+/// Use `as_lines()` and `parse_diff_lines()` to interpret them.
 pub fn download_diff(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint,
+    server: &mut UpdateServer,
     update_build: &str,
-) -> Result<Vec<String>, &'static str> {
-    download_string_file(iface, remote_endpoint, &format!("/{update_build}/{DIFF_FILE_NAME}"))
+    manifest: &Manifest,
+) -> Result<Vec<u8>, &'static str> {
+    let url = server.url(&format!("/{update_build}/{DIFF_FILE_NAME}"))?;
+    let response = server.client.get(&url)?;
+    let content = response.as_result_err_str()?;
+    manifest.verify_bytes(DIFF_FILE_NAME, content)?;
+    Ok(content.to_vec())
 }
 
 
 /// Convenience function for downloading files and returning their contents as Strings per line. 
 fn download_string_file(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint,
+    server: &mut UpdateServer,
     file_path: &str,
 ) -> Result<Vec<String>, &'static str> {
-    let file = download_file(iface, remote_endpoint, file_path)?;
-    let content = file.content.as_result_err_str()?;
-    as_lines(content)
+    let url = server.url(file_path)?;
+    let response = server.client.get(&url)?;
+    as_lines(response.as_result_err_str()?)
 }
 
 
@@ -225,241 +385,80 @@ pub fn parse_diff_lines(diffs: &Vec<String>) -> Result<Diff, &'static str> {
 
 
 
-/// Connects to the update server over the given network interface
-/// and downloads the object files for the specified `crates`.
+/// Downloads the object files for the specified `crates` from the update server,
+/// reusing one connection for all of them.
 /// 
-/// It also downloads the checksum file for each crate object file 
-/// in order to verify that each object file was completely downloaded correctly.
+/// Each crate object file is checked against its hash in the update build's verified `manifest`,
+/// so a file that wasn't signed by the update server is rejected.
+/// If the connection fails while a file is being downloaded, the download is resumed
+/// from where it left off on a new connection, up to `MAX_DOWNLOAD_RETRIES` times per file.
 /// 
 /// A list of available update builds can be obtained by calling `download_available_update_builds()`.
 /// 
 /// # Arguments
-/// * `iface`: a reference to an initialized network interface for sockets to use.
+/// * `server`: the update server to download the crates from.
 /// * `update_build`: the string name of the update build that the downloaded crates will belong to.
+/// * `manifest`: the verified manifest of the `update_build`, from `download_manifest()`.
 /// * `crates`: a set of crate names, e.g., "k#my_crate-3d0cd20d4e1d4ba9.o",
 ///    that will be downloaded from the given `update_build` on the server. 
+/// * `create_file`: a function that creates the file that the crate object file
+///    with the given name will be written into.
 /// 
 /// Returns the list of crate object files (as `DownloadedFile`s) in the given `update_build`.
-pub fn download_crates(
-    iface: &NetworkInterfaceRef, 
-    remote_endpoint: IpEndpoint,
+pub fn download_crates<F>(
+    server: &mut UpdateServer,
     update_build: &str,
+    manifest: &Manifest,
     crates: BTreeSet<String>,
-) -> Result<Vec<DownloadedFile>, &'static str> {
-
-    let mut paths_to_download: Vec<String> = Vec::new();
+    mut create_file: F,
+) -> Result<Vec<DownloadedFile>, &'static str> 
+    where F: FnMut(&str) -> Result<FileRef, &'static str>
+{
+    let mut crate_object_files: Vec<DownloadedFile> = Vec::with_capacity(crates.len());
     for file_name in crates.iter() {
+        // Don't bother downloading files that couldn't be verified anyway.
+        if !manifest.contains(file_name) {
+            error!("ota_update_client: crate {:?} is not listed in the manifest of update build {:?}", file_name, update_build);
+            return Err("ota_update_client: crate is not listed in the update build's manifest");
+        }
         let path = format!("/{update_build}/{file_name}");
-        let path_sha = format!("/{update_build}/{CHECKSUMS_DIR_NAME}/{file_name}{CHECKSUM_FILE_EXTENSION}");
-        paths_to_download.push(path);
-        paths_to_download.push(path_sha);
-    }
-
-    let mut crate_object_files = download_files(iface, remote_endpoint, paths_to_download)?;
-
-    // iterate through each file downloaded, and verify the sha512 hashes
-    for chunk in crate_object_files.chunks(2) {
-        match chunk {
-            [file, hash_file] => {
-                let hash_file_str = hash_file.content.as_result_err_str()
-                    .and_then(|content| str::from_utf8(content)
-                        .map_err(|_e| "couldn't convert downloaded hash file into a UTF8 string")
-                    )?;
-                    
-                if let Some(hash_value) = hash_file_str.split_whitespace().next() {
-                    if verify_hash(file.content.as_result_err_str()?, hash_value) {
-                        // success! the hash matched, so the file was properly downloaded
-                    } else {
-                        error!("ota_update_client: downloaded file {:?} did not match the expected hash value! Try downloading it again.", file.name);
-                        return Err("ota_update_client: downloaded file did not match the expected hash value");
-                    }
-                } else {
-                    error!("ota_update_client: hash file {:?} had unexpected contents: it should start with a 64-digit hex hash value.", hash_file.name);
-                    return Err("ota_update_client: hash file had unexpected contents");
+        let url = server.url(&path)?;
+
+        let file = create_file(file_name)?;
+        let mut offset = 0;
+        let mut retries = 0;
+        loop {
+            let result = server.client.download_range_with(&url, offset, |data| {
+                file.lock().write_at(data, offset)?;
+                offset += data.len();
+                Ok(())
+            });
+            match result {
+                // The file was already completely downloaded when the connection failed.
+                Ok(response) if response.status_code == 416 && offset > 0 => break,
+                Ok(response) => {
+                    response.as_result_err_str()?;
+                    break;
                 }
+                Err(e) if retries < MAX_DOWNLOAD_RETRIES => {
+                    retries += 1;
+                    warn!("ota_update_client: download of {:?} failed after {} bytes ({}), resuming (retry {} of {})",
+                        path, offset, e, retries, MAX_DOWNLOAD_RETRIES,
+                    );
+                    server.client.close();
+                }
+                Err(e) => return Err(e),
             }
-            _ => {
-                error!("ota_update_client: missing crate object file or hash file for downloaded file pair {:?}, cannot verify file integrity.", 
-                    chunk.iter().next().map(|f| &f.name)
-                );
-                return Err("ota_update_client: missing crate object file or hash file for downloaded file pair");
-            }
-        }
-    }
-
-    // All hashes are verified, just return the crate object files themselves without the hash files
-    crate_object_files.retain(|file| !file.name.ends_with(CHECKSUM_FILE_EXTENSION));
-
-    Ok(crate_object_files)
-}
-
-
-/// A convenience function for downloading just one file. See `download_files()`.
-fn download_file<S: AsRef<str>>(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint, 
-    absolute_path: S,
-) -> Result<DownloadedFile, &'static str> {
-    
-    download_files(iface, remote_endpoint, vec![absolute_path])?
-        .into_iter()
-        .next()
-        .ok_or("no file received from the server")
-}
-
-
-/// Connects to the update server over the given network interface
-/// and downloads the given files, each specified with its full absolute path on the update server.
-/// 
-/// Returns an error if any of the given `absolute_paths` didn't exist, 
-/// or if there was any other error on the remote server.
-fn download_files<S: AsRef<str>>(
-    iface: &NetworkInterfaceRef,
-    remote_endpoint: IpEndpoint,
-    absolute_paths: Vec<S>,
-) -> Result<Vec<DownloadedFile>, &'static str> {
-    if absolute_paths.is_empty() { 
-        return Err("no download paths given");
-    }
-
-    let startup_time = hpet_ticks!();
-    let mut rng = SmallRng::seed_from_u64(startup_time);
-    let rng_upper_bound = u16::max_value() - STARTING_FREE_PORT;
-
-    // the below items may be overwritten on each loop iteration, if the socket was closed and we need to create a new one
-    let mut local_port = STARTING_FREE_PORT + (rng.next_u32() as u16 % rng_upper_bound);
-    let mut tcp_rx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
-    let mut tcp_tx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
-    let mut tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
-    let mut sockets = SocketSet::new(Vec::with_capacity(1));
-    let mut tcp_handle = sockets.add(tcp_socket);
-
-    // first, attempt to connect the socket to the remote server
-    connect(iface, &mut sockets, tcp_handle, remote_endpoint, local_port, startup_time)?;
-    debug!("ota_update_client: socket connected successfully!");
-
-    // iterate over the provided list of file paths, and retrieve each one via HTTP
-    let mut downloaded_files: Vec<DownloadedFile> = Vec::with_capacity(absolute_paths.len());
-    let last_index = absolute_paths.len() - 1;
-    for (i, path) in absolute_paths.iter().enumerate() {
-        let path = path.as_ref();
-        let is_last_request = i == last_index;
-
-        let http_request = {
-            let method = "GET";
-            let uri = utf8_percent_encode(path, DEFAULT_ENCODE_SET);
-            let version = "HTTP/1.1";
-            let connection = if is_last_request { "Connection: close" } else { "Connection: keep-alive" };
-            format!("{} {} {}\r\n{}\r\n{}\r\n\r\n",
-                method,
-                uri,
-                version,
-                format_args!("Host: {}:{}", remote_endpoint.addr, remote_endpoint.port), // host
-                connection
-            )
-        };
-        if !check_http_request(http_request.as_bytes()) {
-            error!("ota_update_client: created improper/incomplete HTTP request: {:?}.", http_request);
-            return Err("ota_update_client: created improper/incomplete HTTP request");
-        }
-
-        // Check that the socket is still connected. If not, we need to create a new one and connect it before use. 
-        // if !is_connected(&mut sockets, tcp_handle) {
-        // for now, we just always close the old socket and connect a new one
-        {
-            debug!("ota_update_client: remote endpoint closed socket after response, opening a new socket.");
-            // first, close the existing socket
-            {
-                let mut socket = sockets.get::<TcpSocket>(tcp_handle);
-                socket.close(); 
-                socket.abort(); 
-            }
-            let _packet_io_occurred = poll_iface(iface, &mut sockets, startup_time)?;
-            
-            // second, create an entirely new socket and connect it
-            local_port = STARTING_FREE_PORT + (rng.next_u32() as u16 % rng_upper_bound);
-            tcp_rx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
-            tcp_tx_buffer = TcpSocketBuffer::new(vec![0; 4096]);
-            tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
-            sockets = SocketSet::new(Vec::with_capacity(1));
-            tcp_handle = sockets.add(tcp_socket);
-            connect(iface, &mut sockets, tcp_handle, remote_endpoint, local_port, startup_time)?;
         }
 
-        // send the HTTP request and obtain a response
-        let response = {
-            let mut connected_tcp_socket = ConnectedTcpSocket::new(iface, &mut sockets, tcp_handle)?;
-            send_request(http_request, &mut connected_tcp_socket, Some(HTTP_REQUEST_TIMEOUT_MILLIS))?
-        };
-
-        if response.status_code != 200 {
-            error!("ota_update_client: failed to download {:?}, Error {}: {}", path, response.status_code, response.reason);
-            break;
-        }
+        manifest.verify_file(file_name, &file)?;
+        debug!("ota_update_client: downloaded {:?} ({} bytes)", path, offset);
 
-        downloaded_files.push(DownloadedFile {
-            name: path.into(),
-            content: response,
+        crate_object_files.push(DownloadedFile {
+            name: path,
+            file,
         });
     }
 
-
-    let mut _loop_ctr = 0;
-    let mut issued_close = false;
-    let timeout_millis = 3000; // 3 second timeout
-    let start = hpet_ticks!();
-    loop {
-        _loop_ctr += 1;
-
-        let _packet_io_occurred = poll_iface(iface, &mut sockets, startup_time)?;
-
-        let mut socket = sockets.get::<TcpSocket>(tcp_handle);
-        if !issued_close {
-            debug!("ota_update_client: socket state is {:?}", socket.state());
-
-            debug!("ota_update_client: closing socket...");
-            socket.close();
-            debug!("ota_update_client: socket state (after close) is now {:?}", socket.state());
-            issued_close = true;
-        }
-
-        if socket.state() == TcpState::Closed {
-            break;
-        }
-
-        if _loop_ctr % 50000 == 0 {
-            debug!("ota_update_client: socket state (looping) is now {:?}", socket.state());
-        }
-
-        // check to make sure we haven't timed out
-        if millis_since(start)? > timeout_millis {
-            debug!("ota_update_client: timed out waiting to close socket, closing it manually with an abort.");
-            socket.abort();
-            // Don't break out of the loop here!  we need to let this socket actually send the close msg
-            // to the remote endpoint, which requires another call to poll_iface (which will happen at the top of the loop).
-            // Then, the socket will be in the Closed state, and that conditional above will break out of the loop. 
-        }
-    }
-
-    if downloaded_files.len() != absolute_paths.len() {
-        return Err("failed to download all specified files");
-    }
-
-    Ok(downloaded_files)
-}
-
-
-// /// A convenience function that checks whether a socket is connected, 
-// /// which is currently true when both `may_send()` and `may_recv()` are true.
-// fn is_connected(sockets: &mut SocketSet, tcp_handle: SocketHandle) -> bool {
-//     let socket = sockets.get::<TcpSocket>(tcp_handle);
-//     socket.may_send() && socket.may_recv()
-// }
-
-
-/// Returns true if the SHA3 512-bit hash of the given `content` matches the given `hash` string.
-/// The `hash` string must be 64 hexadecimal characters, otherwise `false` will be returned. 
-fn verify_hash(content: &[u8], hash: &str) -> bool {
-    let result = Sha3_512::digest(content);
-    hash == format!("{result:x}")
+    Ok(crate_object_files)
 }
@@ -6,6 +6,7 @@ extern crate alloc;
 extern crate spin;
 extern crate fs_node;
 extern crate root;
+extern crate mount_table;
 
 use core::{
     fmt,
@@ -22,6 +23,9 @@ use fs_node::{FileOrDir, FileRef, DirRef};
 pub const PATH_DELIMITER: &str = "/";
 pub const EXTENSION_DELIMITER: &str = ".";
 
+/// The maximum number of symbolic links that can be followed while resolving a single path.
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
 
 /// A structure that represents a relative or absolute path
 /// to a file or directory.
@@ -195,8 +199,27 @@ impl Path {
 
     /// Returns the file or directory specified by the given path, 
     /// which can either be absolute or relative from the given starting directory.
+    ///
+    /// If the path traverses a mount point, the root directory of the filesystem
+    /// mounted there (as recorded in the [`mount_table`]) is used instead of the mount point.
+    ///
+    /// Symbolic links are followed, including one at the end of the path.
+    /// Returns `None` if following symbolic links results in a loop
+    /// or exceeds [`MAX_SYMLINK_DEPTH`].
     pub fn get(&self, starting_dir: &DirRef) -> Option<FileOrDir> {
-        // let current_path = { Path::new(starting_dir.lock().get_absolute_path()) };
+        self.resolve(starting_dir, true)
+    }
+
+    /// Like [`Path::get()`], but if the final component of the path is a symbolic link,
+    /// the link itself is returned instead of the node that it points to.
+    ///
+    /// This is useful for operating on links themselves, e.g., removing or renaming them.
+    pub fn get_no_follow(&self, starting_dir: &DirRef) -> Option<FileOrDir> {
+        self.resolve(starting_dir, false)
+    }
+
+    /// The implementation of [`Path::get()`] and [`Path::get_no_follow()`].
+    fn resolve(&self, starting_dir: &DirRef, follow_final_symlink: bool) -> Option<FileOrDir> {
         let mut curr_dir = {
             if self.is_absolute() {
                 Arc::clone(root::get_root())
@@ -205,9 +228,21 @@ impl Path {
                 Arc::clone(starting_dir)
             }
         };
+        // The components of the absolute path of `curr_dir`, used to look up mount points.
+        let mut curr_path: Vec<String> = if self.is_absolute() {
+            Vec::new()
+        } else {
+            Self::absolute_components(&curr_dir)
+        };
 
-        for component in self.components() {
-            match component {
+        // The components that remain to be resolved, in reverse order.
+        let mut remaining: Vec<String> = self.components().rev().map(ToString::to_string).collect();
+        // The symbolic links followed so far, identified by their absolute path
+        // and the number of components that remained after them.
+        let mut followed_symlinks: Vec<(String, usize)> = Vec::new();
+
+        while let Some(component) = remaining.pop() {
+            match component.as_str() {
                 "." => { 
                     // stay in the current directory, do nothing. 
                 }
@@ -215,21 +250,66 @@ impl Path {
                     // navigate to parent directory
                     let parent_dir = curr_dir.lock().get_parent_dir()?;
                     curr_dir = parent_dir;
+                    curr_path.pop();
                 }
                 cmpnt => {
                     // navigate to child directory, or return the child file
-                    let child_dir = match curr_dir.lock().get(cmpnt) {
-                        Some(FileOrDir::File(f)) => return Some(FileOrDir::File(f)),
+                    let child = curr_dir.lock().get(cmpnt);
+                    let child_dir = match child {
+                        Some(FileOrDir::File(f)) => {
+                            let target = f.lock().symlink_target();
+                            match target {
+                                Some(target) if follow_final_symlink || !remaining.is_empty() => {
+                                    curr_path.push(cmpnt.to_string());
+                                    let symlink = (Self::join_absolute(&curr_path), remaining.len());
+                                    curr_path.pop();
+                                    if followed_symlinks.contains(&symlink) || followed_symlinks.len() >= MAX_SYMLINK_DEPTH {
+                                        return None;
+                                    }
+                                    followed_symlinks.push(symlink);
+
+                                    // continue resolving from the link's target,
+                                    // which is relative to the directory that contains the link.
+                                    let target = Path::new(target);
+                                    if target.is_absolute() {
+                                        curr_dir = Arc::clone(root::get_root());
+                                        curr_path.clear();
+                                    }
+                                    remaining.extend(target.components().rev().map(ToString::to_string));
+                                    continue;
+                                }
+                                _ => return Some(FileOrDir::File(f)),
+                            }
+                        }
                         Some(FileOrDir::Dir(d)) => d,
                         None => return None,
                     };
-                    curr_dir = child_dir;
+                    curr_path.push(cmpnt.to_string());
+                    // cross into the filesystem mounted on this child directory, if any
+                    curr_dir = mount_table::lookup(&Self::join_absolute(&curr_path)).unwrap_or(child_dir);
                 }
             }
         }
         Some(FileOrDir::Dir(curr_dir))
     }
 
+    /// Returns the components of the absolute path of the given directory.
+    fn absolute_components(dir: &DirRef) -> Vec<String> {
+        Path::new(dir.lock().get_absolute_path())
+            .components()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Joins the given path components into an absolute path string.
+    fn join_absolute(components: &[String]) -> String {
+        let mut path = String::new();
+        for component in components {
+            write!(path, "{PATH_DELIMITER}{component}").expect("Failed to create new path from its components");
+        }
+        path
+    }
+
     /// Returns the file specified by the given path, which can be either absolute,
     /// or relative from the given starting directory. 
     ///
@@ -252,6 +332,27 @@ impl Path {
         }
     }
 
+    /// Returns the directory that contains (or would contain) the node specified by this path,
+    /// along with the name of that node, i.e., the last component of this path.
+    ///
+    /// Unlike [`Path::get()`], the node itself need not exist, so this can be used to find
+    /// where a new node should be created. The path can be either absolute,
+    /// or relative from the given starting directory.
+    pub fn get_parent_and_name(&self, starting_dir: &DirRef) -> Result<(DirRef, String), &'static str> {
+        let trimmed = self.path.trim_end_matches(PATH_DELIMITER);
+        let (dir_path, name) = match trimmed.rsplit_once(PATH_DELIMITER) {
+            Some(("", name)) => (PATH_DELIMITER, name),
+            Some((dir_path, name)) => (dir_path, name),
+            None => (".", trimmed),
+        };
+        if name.is_empty() {
+            return Err("path does not name a node");
+        }
+        let dir = Path::new(dir_path.to_string()).get_dir(starting_dir)
+            .ok_or("couldn't find the directory that contains the path")?;
+        Ok((dir, name.to_string()))
+    }
+
     /// Returns the file or directory specified by the given absolute path
     pub fn get_absolute(path: &Path) -> Option<FileOrDir> {
         if path.is_absolute() {
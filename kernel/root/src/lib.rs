@@ -8,6 +8,7 @@
 #[macro_use] extern crate lazy_static;
 extern crate spin;
 extern crate fs_node;
+extern crate vfs_node;
 
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
@@ -61,6 +62,14 @@ impl Directory for RootDirectory {
         self.children.keys().cloned().collect()
     }
 
+    fn rename(&mut self, old_name: &str, new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        vfs_node::rename_child(&mut self.children, old_name, new_name)
+    }
+
+    fn supports_move(&self) -> bool {
+        true
+    }
+
     fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
         // Prevents removal of root
         if let FileOrDir::Dir(dir) = node {
@@ -11,6 +11,7 @@ extern crate spin;
 extern crate state_store;
 #[macro_use] extern crate log;
 extern crate x86_64;
+extern crate time;
 
 use port_io::Port;
 use irq_safety::hold_interrupts;
@@ -18,6 +19,7 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
 // use spin::Once;
 use state_store::{get_state, insert_state, SSCached};
+use time::{ClockSource, Duration, WallTime};
 
 
 //standard port to write to on CMOS to select registers
@@ -104,6 +106,28 @@ pub struct RtcTime {
     pub months: u8,
     pub years: u8,
 }
+
+impl RtcTime {
+    /// Converts this RTC time into a duration since the Unix epoch,
+    /// i.e., 00:00:00 on January 1st, 1970.
+    ///
+    /// The RTC only stores a two-digit year, which is assumed to be in the 21st century.
+    pub fn to_unix_time(&self) -> Duration {
+        let days_since_epoch = time::days_from_civil(
+            2000 + self.years as i64,
+            self.months.clamp(1, 12) as u32,
+            self.days.max(1) as u32,
+        ) as u64;
+
+        Duration::from_secs(
+            days_since_epoch * 86400
+            + self.hours as u64 * 3600
+            + self.minutes as u64 * 60
+            + self.seconds as u64
+        )
+    }
+}
+
 use core::fmt;
 impl fmt::Display for RtcTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -133,6 +157,22 @@ pub fn read_rtc() -> RtcTime {
     }
 }
 
+/// The period of the [`RtcClock`], which has a resolution of one second.
+pub const RTC_CLOCK_PERIOD_FEMTOSECONDS: u64 = 1_000_000_000_000_000;
+
+/// A wall-clock time source backed by the real-time clock.
+///
+/// This can be registered with the `time` crate using [`RTC_CLOCK_PERIOD_FEMTOSECONDS`].
+pub struct RtcClock;
+
+impl ClockSource for RtcClock {
+    type ClockType = WallTime;
+
+    fn now() -> Duration {
+        read_rtc().to_unix_time()
+    }
+}
+
 /// Returns the current RTC tick count.
 pub fn get_rtc_ticks() -> Option<usize> {
     RTC_TICKS.get().map(|ticks| ticks.load(Ordering::Acquire))
@@ -0,0 +1,356 @@
+//! Load balancing, which migrates tasks from the runqueues of busy cores to those of less busy cores.
+//!
+//! Balancing happens in two ways, both of which are driven by [`balance()`]:
+//! * Periodically, every [`BALANCE_INTERVAL`] invocations of the scheduler on a core,
+//!   that core pushes some of its tasks to the least busy core if their loads are imbalanced.
+//! * When a core has nothing to run except its idle task, it requests work using [`request_work()`].
+//!   The next busy core that checks for such requests (every [`WORK_REQUEST_INTERVAL`] invocations
+//!   of its scheduler) then pushes one of its tasks to the idle core,
+//!   such that idle cores effectively steal work from busy cores.
+//!
+//! A core's load is the number of runnable tasks on its runqueue, excluding its idle task.
+//! Only tasks that are runnable, not currently running, and whose affinity includes the target core are migrated.
+//!
+//! Independently of load balancing, [`balance()`] also evicts tasks whose affinity no longer includes
+//! the current core, after [`set_affinity()`](super::set_affinity) has requested that using [`request_eviction()`].
+//!
+//! Tasks are only ever migrated away from a core by that core itself, from within its scheduler.
+//! This guarantees that a migrated task isn't in the middle of being switched out on its old core,
+//! which other cores can't determine because a task is marked as no longer running
+//! before its context has been saved.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use alloc::vec::Vec;
+use atomic_linked_list::atomic_map::AtomicMap;
+use task::TaskRef;
+use super::{get_scheduler, get_least_busy_scheduler, SCHEDULERS};
+
+/// The number of invocations of the scheduler on a core between each periodic balancing of that core.
+pub const BALANCE_INTERVAL: usize = 64;
+
+/// The number of invocations of the scheduler on a core between each check for work requests from idle cores.
+pub const WORK_REQUEST_INTERVAL: usize = 8;
+
+/// The maximum number of tasks that a core migrates away in a single periodic balancing.
+const MAX_MIGRATIONS_PER_BALANCE: usize = 4;
+
+/// Whether load balancing is enabled.
+static LOAD_BALANCING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The set of idle cores that have requested work, one bit per APIC ID.
+static WORK_REQUESTS: [AtomicU64; 4] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+/// The set of cores whose runqueues contain tasks that aren't allowed to run on that core, one bit per APIC ID.
+static PENDING_EVICTIONS: [AtomicU64; 4] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+/// The load balancing statistics of each core.
+static CORE_STATS: AtomicMap<u8, CoreStats> = AtomicMap::new();
+
+/// The load balancing statistics of a single core.
+#[derive(Default)]
+struct CoreStats {
+    /// The number of times that the scheduler has invoked [`balance()`] on this core.
+    balance_calls: AtomicUsize,
+    /// The number of tasks that were migrated to this core.
+    migrated_in: AtomicUsize,
+    /// The number of tasks that were migrated away from this core.
+    migrated_out: AtomicUsize,
+}
+
+/// The number of tasks that the load balancer migrated to and away from a single core.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MigrationCounts {
+    pub migrated_in: usize,
+    pub migrated_out: usize,
+}
+
+
+/// Starts tracking the load balancing statistics of the given core.
+pub(crate) fn init_core(which_core: u8) {
+    CORE_STATS.insert(which_core, CoreStats::default());
+}
+
+/// Enables or disables load balancing for all cores.
+///
+/// Disabling load balancing doesn't move any tasks back to the cores they were migrated away from.
+pub fn set_load_balancing(enabled: bool) {
+    LOAD_BALANCING_ENABLED.store(enabled, Ordering::Release);
+}
+
+/// Returns whether load balancing is enabled.
+pub fn is_load_balancing_enabled() -> bool {
+    LOAD_BALANCING_ENABLED.load(Ordering::Acquire)
+}
+
+/// Returns the number of tasks that the load balancer migrated to and away from the given core.
+pub fn migration_counts(which_core: u8) -> Option<MigrationCounts> {
+    CORE_STATS.get(&which_core).map(|stats| MigrationCounts {
+        migrated_in: stats.migrated_in.load(Ordering::Relaxed),
+        migrated_out: stats.migrated_out.load(Ordering::Relaxed),
+    })
+}
+
+/// Returns the total number of tasks that the load balancer has migrated between cores.
+pub fn total_migrations() -> usize {
+    CORE_STATS.iter()
+        .map(|(_core, stats)| stats.migrated_out.load(Ordering::Relaxed))
+        .sum()
+}
+
+/// Requests that a busy core migrates one of its tasks to the given core,
+/// which should be done when the given core has nothing to run except its idle task.
+pub fn request_work(which_core: u8) {
+    if is_load_balancing_enabled() {
+        let (word, bit) = request_bit(which_core);
+        WORK_REQUESTS[word].fetch_or(bit, Ordering::Relaxed);
+    }
+}
+
+/// Requests that the given core migrates away all tasks on its runqueue that aren't allowed to run on it,
+/// which should be done when a task's affinity has been changed to exclude that core.
+pub fn request_eviction(which_core: u8) {
+    let (word, bit) = request_bit(which_core);
+    PENDING_EVICTIONS[word].fetch_or(bit, Ordering::Relaxed);
+}
+
+/// Balances the load of the given core, which must be the current core,
+/// by migrating some of its tasks to other cores.
+///
+/// Tasks that aren't allowed to run on the given core are always migrated away from it,
+/// even if load balancing is disabled.
+///
+/// This should be invoked by the scheduler each time it runs on the current core,
+/// before it selects the next task to run.
+///
+/// Returns the number of tasks that were migrated away from the given core.
+pub fn balance(this_core: u8) -> usize {
+    let mut migrated = 0;
+
+    let (word, bit) = request_bit(this_core);
+    if PENDING_EVICTIONS[word].load(Ordering::Relaxed) & bit != 0 {
+        PENDING_EVICTIONS[word].fetch_and(!bit, Ordering::Relaxed);
+        migrated += evict(this_core);
+    }
+
+    if !is_load_balancing_enabled() {
+        return migrated;
+    }
+    let stats = match CORE_STATS.get(&this_core) {
+        Some(stats) => stats,
+        None => return migrated,
+    };
+    let balance_calls = stats.balance_calls.fetch_add(1, Ordering::Relaxed) + 1;
+
+    // Serve a request from an idle core, but only if this core has work to spare.
+    if balance_calls % WORK_REQUEST_INTERVAL == 0
+        && WORK_REQUESTS.iter().any(|w| w.load(Ordering::Relaxed) != 0)
+        && load(this_core) > 1
+    {
+        if let Some(idle_core) = take_work_request(this_core) {
+            migrated += migrate(this_core, idle_core, 1);
+        }
+    }
+
+    if balance_calls % BALANCE_INTERVAL == 0 {
+        let this_load = load(this_core);
+        let least_loaded = super::cores()
+            .filter(|core| *core != this_core)
+            .map(|core| (core, load(core)))
+            .min_by_key(|(_core, load)| *load);
+        if let Some((target_core, target_load)) = least_loaded {
+            // Migrating tasks only helps if it doesn't make the target core busier than this core.
+            if this_load > target_load + 1 {
+                let num_tasks = core::cmp::min((this_load - target_load) / 2, MAX_MIGRATIONS_PER_BALANCE);
+                migrated += migrate(this_core, target_core, num_tasks);
+            }
+        }
+    }
+
+    migrated
+}
+
+/// Returns the number of runnable tasks on the given core's runqueue, excluding its idle task.
+fn load(which_core: u8) -> usize {
+    get_scheduler(which_core)
+        .map(|sched| sched.read().tasks().iter()
+            .filter(|t| !t.is_an_idle_task && t.is_runnable())
+            .count()
+        )
+        .unwrap_or(0)
+}
+
+/// Returns whether the given task may be migrated away from its current core to the core `to`.
+fn is_migratable(task: &TaskRef, to: u8) -> bool {
+    !task.is_an_idle_task
+        && task.is_runnable()
+        && task.running_on_cpu().is_none()
+        && task.can_run_on(to)
+}
+
+/// Migrates all tasks on the runqueue of the given core, which must be the current core,
+/// that aren't allowed to run on that core to the least busy core within their affinity.
+///
+/// A task that is currently running is migrated once it has been switched out,
+/// so the eviction is requested again until no such tasks remain.
+///
+/// Returns the number of tasks that were migrated.
+fn evict(this_core: u8) -> usize {
+    let this_sched = match get_scheduler(this_core) {
+        Some(s) => s,
+        None => return 0,
+    };
+
+    let (tasks, remaining): (Vec<TaskRef>, bool) = {
+        let mut this_locked = this_sched.write();
+        let (running, tasks): (Vec<TaskRef>, Vec<TaskRef>) = this_locked.tasks().into_iter()
+            .filter(|t| !t.is_an_idle_task && !t.can_run_on(this_core))
+            .partition(|t| t.running_on_cpu().is_some());
+        for task in &tasks {
+            this_locked.remove_task(task);
+        }
+        (tasks, !running.is_empty())
+    };
+    if remaining {
+        request_eviction(this_core);
+    }
+
+    let mut migrated = 0;
+    for task in tasks {
+        let target = get_least_busy_scheduler(&task.affinity())
+            .ok_or("couldn't find any runqueues within the task's affinity")
+            .and_then(|(core, sched)| {
+                let mut locked = sched.write();
+                locked.add_task(task.clone())?;
+                if task.has_exited() {
+                    locked.remove_task(&task);
+                }
+                Ok(core)
+            });
+        match target {
+            Ok(core) => {
+                // The task's affinity may have been changed again while it wasn't on any runqueue.
+                if !task.can_run_on(core) {
+                    request_eviction(core);
+                }
+                #[cfg(not(any(rq_eval, downtime_eval)))]
+                debug!("Evicted task {:?} from core {} to core {}", task, this_core, core);
+                record_migration(this_core, core, 1);
+                migrated += 1;
+            }
+            Err(e) => {
+                error!("runqueue: couldn't evict task {:?} from core {}: {}", task, this_core, e);
+                let _ = this_sched.write().add_task(task);
+                request_eviction(this_core);
+            }
+        }
+    }
+    migrated
+}
+
+/// Migrates up to `num_tasks` migratable tasks from the runqueue of the core `from` to that of the core `to`.
+///
+/// Returns the number of tasks that were migrated.
+fn migrate(from: u8, to: u8, num_tasks: usize) -> usize {
+    let (from_sched, to_sched) = match (get_scheduler(from), get_scheduler(to)) {
+        (Some(f), Some(t)) => (f, t),
+        _ => return 0,
+    };
+
+    // The two runqueues are never locked at the same time,
+    // which avoids deadlock with another core that is migrating tasks in the opposite direction.
+    let tasks: Vec<TaskRef> = {
+        let mut from_locked = from_sched.write();
+        // The tasks at the back of the runqueue are the ones that would otherwise wait the longest.
+        let tasks: Vec<TaskRef> = from_locked.tasks().into_iter()
+            .rev()
+            .filter(|t| is_migratable(t, to))
+            .take(num_tasks)
+            .collect();
+        for task in &tasks {
+            from_locked.remove_task(task);
+        }
+        tasks
+    };
+
+    let mut migrated = 0;
+    let mut failed = Vec::new();
+    {
+        let mut to_locked = to_sched.write();
+        for task in tasks {
+            match to_locked.add_task(task.clone()) {
+                Ok(()) => {
+                    // The task may have been killed while it wasn't on any runqueue,
+                    // in which case it must not remain on a runqueue.
+                    if task.has_exited() {
+                        to_locked.remove_task(&task);
+                    }
+                    // Likewise, its affinity may have been changed to exclude the core it was migrated to.
+                    if !task.can_run_on(to) {
+                        request_eviction(to);
+                    }
+                    migrated += 1;
+                }
+                Err(e) => {
+                    error!("runqueue: couldn't migrate task {:?} from core {} to core {}: {}", task, from, to, e);
+                    failed.push(task);
+                }
+            }
+        }
+    }
+    if !failed.is_empty() {
+        let mut from_locked = from_sched.write();
+        for task in failed {
+            if !task.can_run_on(from) {
+                request_eviction(from);
+            }
+            let _ = from_locked.add_task(task);
+        }
+    }
+
+    if migrated > 0 {
+        #[cfg(not(any(rq_eval, downtime_eval)))]
+        debug!("Migrated {} task(s) from core {} to core {}", migrated, from, to);
+        record_migration(from, to, migrated);
+    }
+    migrated
+}
+
+/// Updates the statistics of the cores `from` and `to` after `num_tasks` tasks were migrated between them.
+fn record_migration(from: u8, to: u8, num_tasks: usize) {
+    if let Some(stats) = CORE_STATS.get(&from) {
+        stats.migrated_out.fetch_add(num_tasks, Ordering::Relaxed);
+    }
+    if let Some(stats) = CORE_STATS.get(&to) {
+        stats.migrated_in.fetch_add(num_tasks, Ordering::Relaxed);
+    }
+}
+
+/// Claims a pending work request from any core other than `this_core`,
+/// returning the core that requested work.
+fn take_work_request(this_core: u8) -> Option<u8> {
+    let (own_word, own_bit) = request_bit(this_core);
+    for (word_index, word) in WORK_REQUESTS.iter().enumerate() {
+        let mut requests = word.load(Ordering::Relaxed);
+        if word_index == own_word {
+            requests &= !own_bit;
+        }
+        while requests != 0 {
+            let bit = 1 << requests.trailing_zeros();
+            // Only one core may serve each request.
+            if word.fetch_and(!bit, Ordering::Relaxed) & bit != 0 {
+                let core = word_index * 64 + bit.trailing_zeros() as usize;
+                if SCHEDULERS.get(&(core as u8)).is_some() {
+                    return Some(core as u8);
+                }
+            }
+            requests &= !bit;
+        }
+    }
+    None
+}
+
+/// Returns the index of the word in [`WORK_REQUESTS`] or [`PENDING_EVICTIONS`]
+/// and the bit within that word for the given core.
+fn request_bit(which_core: u8) -> (usize, u64) {
+    ((which_core / 64) as usize, 1 << (which_core % 64))
+}
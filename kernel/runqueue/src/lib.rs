@@ -1,60 +1,345 @@
-//! This crate contains the API of the `RunQueue` structure, Runqueue Structure should contain
-//! list of tasks with additional scheduling information depending on the scheduler.
-//! All crates except the scheduler should refer to this crate to access functions on `RunQueue`.
-//! 
+//! This crate contains the API of the per-core runqueues, i.e., the lists of tasks
+//! that each core's scheduler can choose from.
+//!
+//! Each core has its own instance of a [`Scheduler`], which owns that core's runqueue
+//! and defines the scheduling policy used on that core.
+//! A core's scheduler can be replaced at runtime using [`set_scheduler()`],
+//! which migrates all of the tasks on the old scheduler's runqueue to the new scheduler.
+//!
+//! Each task may only be on the runqueue of a core within its CPU affinity,
+//! which can be changed for a spawned task using [`set_affinity()`].
+//!
+//! Tasks that may run on more than one core are migrated from busy cores' runqueues to those of less busy cores
+//! by the load balancer, which each core's scheduler invokes using [`balance()`].
+//! Idle cores request work from busy cores using [`request_work()`].
+//!
+//! All crates except the scheduler should refer to this crate to access the runqueues.
 
 #![no_std]
 
 extern crate alloc;
+#[macro_use] extern crate log;
 extern crate mutex_preemption;
 extern crate atomic_linked_list;
 extern crate task;
-#[macro_use] extern crate cfg_if;
-cfg_if! {
-    if #[cfg(priority_scheduler)] {
-        extern crate runqueue_priority as runqueue;
-    } else if #[cfg(realtime_scheduler)] {
-        extern crate runqueue_realtime as runqueue;
-    } else {
-        extern crate runqueue_round_robin as runqueue;
-    }
-}
+extern crate cpu;
 
 #[cfg(single_simd_task_optimization)]
 extern crate single_simd_task_optimization;
 
+mod balance;
+pub use balance::*;
+
+use core::time::Duration;
+use alloc::{boxed::Box, vec::Vec};
 use mutex_preemption::RwLockPreempt;
+use atomic_linked_list::atomic_map::AtomicMap;
 use task::TaskRef;
-use runqueue::RunQueue;
+use cpu::CpuSet;
+
+
+/// A scheduling policy that selects the next task to run on a single core.
+///
+/// Each scheduler instance owns the runqueue of the core that it was created for,
+/// which contains all of the tasks that may run on that core.
+pub trait Scheduler: Send + Sync {
+    /// Returns the name of this scheduler's policy, e.g., `"round robin"`.
+    fn name(&self) -> &'static str;
+
+    /// Selects the next task to run on this scheduler's core.
+    ///
+    /// The idle task should only be chosen if no other task is runnable.
+    /// A task whose affinity doesn't include this scheduler's core must never be chosen,
+    /// as it may remain on this runqueue until it is migrated away (see [`set_affinity()`]).
+    /// Returns `None` if there is no schedule-able task.
+    fn select_next_task(&mut self) -> Option<TaskRef>;
+
+    /// Adds the given task to this scheduler's runqueue.
+    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str>;
+
+    /// Removes the given task from this scheduler's runqueue.
+    ///
+    /// Returns `true` if the task was on this runqueue.
+    fn remove_task(&mut self, task: &TaskRef) -> bool;
+
+    /// Returns how busy this scheduler's core is, used to choose a core for new tasks.
+    fn busyness(&self) -> usize;
+
+    /// Returns references to all tasks on this scheduler's runqueue.
+    fn tasks(&self) -> Vec<TaskRef>;
+
+    /// Removes all tasks from this scheduler's runqueue and returns them.
+    fn drain(&mut self) -> Vec<TaskRef>;
+
+    /// Sets the priority of the given task, if it is on this scheduler's runqueue.
+    ///
+    /// Returns `Ok(true)` if the task's priority was set, `Ok(false)` if the task isn't on this runqueue,
+    /// or an error if this scheduler doesn't use task priorities.
+    fn set_priority(&mut self, _task: &TaskRef, _priority: u8) -> Result<bool, &'static str> {
+        Err("this scheduler doesn't use task priorities")
+    }
+
+    /// Returns the priority of the given task, or `None` if the task isn't on this runqueue
+    /// or this scheduler doesn't use task priorities.
+    fn priority(&self, _task: &TaskRef) -> Option<u8> {
+        None
+    }
+
+    /// Sets the real-time parameters of the given task, if it is on this scheduler's runqueue.
+    ///
+    /// Returns `Ok(true)` if the task's parameters were set, `Ok(false)` if the task isn't on this runqueue,
+    /// or an error if this scheduler doesn't support real-time tasks or can't admit the task.
+    fn set_realtime_params(&mut self, _task: &TaskRef, _params: RealtimeParams) -> Result<bool, &'static str> {
+        Err("this scheduler doesn't support real-time tasks")
+    }
+
+    /// Returns the real-time parameters of the given task, or `None` if the task isn't on this runqueue,
+    /// isn't a real-time task, or this scheduler doesn't support real-time tasks.
+    fn realtime_params(&self, _task: &TaskRef) -> Option<RealtimeParams> {
+        None
+    }
+}
+
+/// The parameters of a periodic real-time task.
+///
+/// Each period, the task releases a job that must complete within `deadline` after its release,
+/// and that may consume up to `budget` of CPU time.
+/// Schedulers may ignore the parameters that their policy doesn't use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RealtimeParams {
+    /// The maximum CPU time that the task may consume in each period.
+    pub budget: Duration,
+    /// The time between the releases of successive jobs of the task.
+    pub period: Duration,
+    /// The time after each release by which the released job must complete.
+    pub deadline: Duration,
+}
+
+impl RealtimeParams {
+    /// Returns the parameters of a task whose deadline is the end of each period.
+    pub const fn periodic(budget: Duration, period: Duration) -> RealtimeParams {
+        RealtimeParams { budget, period, deadline: period }
+    }
+
+    /// Returns an error unless `0 < budget <= deadline <= period`.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.budget.is_zero() {
+            Err("the budget of a real-time task must be greater than zero")
+        } else if self.budget > self.deadline {
+            Err("the budget of a real-time task must not exceed its deadline")
+        } else if self.deadline > self.period {
+            Err("the deadline of a real-time task must not exceed its period")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The scheduler instance of a single core, which can be replaced at runtime.
+pub type PerCoreScheduler = RwLockPreempt<Box<dyn Scheduler>>;
+
+/// There is one scheduler per core, each core only accesses its own private scheduler
+/// to select a task from that core's runqueue to schedule in.
+static SCHEDULERS: AtomicMap<u8, PerCoreScheduler> = AtomicMap::new();
+
+
+/// Initializes the runqueue of the given core, which is an `apic_id`,
+/// using the given `scheduler` for that core.
+pub fn init(which_core: u8, scheduler: Box<dyn Scheduler>) -> Result<(), &'static str> {
+    trace!("Created runqueue ({}) for core {}", scheduler.name(), which_core);
+    if SCHEDULERS.insert(which_core, RwLockPreempt::new(scheduler)).is_some() {
+        error!("BUG: runqueue::init(): runqueue already exists for core {}!", which_core);
+        Err("runqueue already exists for this core")
+    } else {
+        balance::init_core(which_core);
+        Ok(())
+    }
+}
 
+/// Returns the scheduler of the given core, which is an `apic_id`.
+pub fn get_scheduler(which_core: u8) -> Option<&'static PerCoreScheduler> {
+    SCHEDULERS.get(&which_core)
+}
 
-/// Creates a new `RunQueue` for the given core, which is an `apic_id`.
-pub fn init(which_core: u8) -> Result<(), &'static str> {
-    RunQueue::init(which_core)
+/// Returns an iterator over the IDs of all cores that have a runqueue.
+pub fn cores() -> impl Iterator<Item = u8> {
+    SCHEDULERS.iter().map(|(core, _sched)| *core)
 }
 
-/// Returns the `RunQueue` of the given core, which is an `apic_id`.
-pub fn get_runqueue(which_core: u8) -> Option<&'static RwLockPreempt<RunQueue>> {
-    RunQueue::get_runqueue(which_core)
+/// Returns references to all tasks on the runqueue of the given core, which is an `apic_id`.
+pub fn get_tasks(which_core: u8) -> Option<Vec<TaskRef>> {
+    get_scheduler(which_core).map(|sched| sched.read().tasks())
 }
 
-/// Returns the "least busy" core
+/// Replaces the scheduler of the given core, which is an `apic_id`, with the given `scheduler`.
+///
+/// All tasks on the old scheduler's runqueue are migrated to the new scheduler's runqueue,
+/// though any policy-specific state of those tasks (e.g., their priority) is not preserved.
+/// Returns the old scheduler, whose runqueue is now empty.
+pub fn set_scheduler(which_core: u8, mut scheduler: Box<dyn Scheduler>) -> Result<Box<dyn Scheduler>, &'static str> {
+    let mut locked = get_scheduler(which_core)
+        .ok_or("Couldn't get the runqueue for the given core")?
+        .write();
+
+    let tasks = locked.drain();
+    for task in &tasks {
+        if let Err(e) = scheduler.add_task(task.clone()) {
+            error!("runqueue::set_scheduler(): couldn't migrate task {:?} to the {} scheduler: {}", task, scheduler.name(), e);
+            // Tasks must not be lost, so they're all returned to the old scheduler.
+            for t in &tasks {
+                let _ = locked.add_task(t.clone());
+            }
+            return Err(e);
+        }
+    }
+
+    debug!("Changed the scheduler of core {} from {} to {}, migrated {} tasks",
+        which_core, locked.name(), scheduler.name(), tasks.len(),
+    );
+    Ok(core::mem::replace(&mut *locked, scheduler))
+}
+
+/// Returns the "least busy" core, based on the busyness reported by each core's scheduler.
 pub fn get_least_busy_core() -> Option<u8> {
-    RunQueue::get_least_busy_core()
+    get_least_busy_scheduler(&CpuSet::all()).map(|(core, _)| core)
 }
 
-/// Chooses the "least busy" core's runqueue
+/// Returns the "least busy" core within the given set of `allowed` cores, along with its scheduler.
+/// See [`get_least_busy_core()`].
+fn get_least_busy_scheduler(allowed: &CpuSet) -> Option<(u8, &'static PerCoreScheduler)> {
+    let mut min_sched: Option<(u8, &'static PerCoreScheduler, usize)> = None;
+
+    for (core, sched) in SCHEDULERS.iter().filter(|(core, _)| allowed.contains(**core)) {
+        let busyness = sched.read().busyness();
+        if min_sched.map_or(true, |min| busyness < min.2) {
+            min_sched = Some((*core, sched, busyness));
+        }
+    }
+
+    min_sched.map(|m| (m.0, m.1))
+}
+
+/// Chooses the "least busy" core's runqueue among the cores within the task's affinity
 /// and adds the given `Task` reference to that core's runqueue.
 pub fn add_task_to_any_runqueue(task: TaskRef) -> Result<(), &'static str> {
-    RunQueue::add_task_to_any_runqueue(task)
+    let (_core, sched) = get_least_busy_scheduler(&task.affinity())
+        .ok_or("couldn't find any runqueues within the task's affinity to add the task to!")?;
+    sched.write().add_task(task)
 }
 
 /// Adds the given `Task` reference to given core's runqueue.
+///
+/// Returns an error if the given core isn't within the task's affinity.
 pub fn add_task_to_specific_runqueue(which_core: u8, task: TaskRef) -> Result<(), &'static str> {
-    RunQueue::add_task_to_specific_runqueue(which_core, task)
+    if !task.can_run_on(which_core) {
+        return Err("the given core isn't within the task's CPU affinity");
+    }
+    get_scheduler(which_core)
+        .ok_or("Couldn't get RunQueue for the given core")?
+        .write()
+        .add_task(task)
+}
+
+/// Removes a `TaskRef` from the given core's runqueue.
+pub fn remove_task_from_specific_runqueue(which_core: u8, task: &TaskRef) -> Result<(), &'static str> {
+    get_scheduler(which_core)
+        .ok_or("Couldn't get RunQueue for the given core")?
+        .write()
+        .remove_task(task);
+    Ok(())
 }
 
 /// Removes a `TaskRef` from all `RunQueue`s that exist on the entire system.
+///
+/// This is a brute force approach that iterates over all runqueues.
 pub fn remove_task_from_all(task: &TaskRef) -> Result<(), &'static str> {
-    RunQueue::remove_task_from_all(task)
+    for (_core, sched) in SCHEDULERS.iter() {
+        sched.write().remove_task(task);
+    }
+    Ok(())
+}
+
+/// Sets the set of cores that the given task may run on, i.e., its CPU affinity.
+///
+/// If the task is on the runqueue of a core that isn't in the new affinity,
+/// that core migrates it to a core in the new affinity the next time it runs its scheduler,
+/// or once the task is switched out if it's currently running on that core.
+///
+/// A real-time task's reservation is admitted on a single core, so it doesn't follow the task to another core:
+/// unless the new affinity still pins the task to its current core, the task becomes a best-effort task
+/// once it's migrated, and its real-time parameters must be set again on its new core.
+///
+/// Returns an error if the new affinity doesn't include any core that has a runqueue,
+/// or if the task is an idle task, which must remain on its own core.
+pub fn set_affinity(task: &TaskRef, affinity: CpuSet) -> Result<(), &'static str> {
+    if task.is_an_idle_task {
+        return Err("the affinity of an idle task cannot be changed");
+    }
+    if !cores().any(|core| affinity.contains(core)) {
+        return Err("the CPU affinity doesn't include any core with a runqueue");
+    }
+    if let Some(params) = get_realtime_params(task) {
+        if affinity.single_cpu() != task.pinned_core() {
+            warn!("runqueue::set_affinity(): task {:?} loses its real-time reservation {:?} once it's migrated \
+                off its current core, set its real-time parameters again afterwards", task, params,
+            );
+        }
+    }
+    task.set_affinity(affinity);
+
+    for (core, sched) in SCHEDULERS.iter() {
+        if !affinity.contains(*core) && sched.read().tasks().contains(task) {
+            balance::request_eviction(*core);
+        }
+    }
+    Ok(())
+}
+
+/// Sets the priority of the given task on every runqueue that it is on.
+///
+/// Returns an error if the task isn't on any runqueue,
+/// or if it is on the runqueue of a scheduler that doesn't use task priorities.
+pub fn set_priority(task: &TaskRef, priority: u8) -> Result<(), &'static str> {
+    set_on_all_runqueues(task, |sched| sched.set_priority(task, priority))
+}
+
+/// Returns the priority of the given task, from the first runqueue that it is found on.
+///
+/// Returns `None` if the task isn't on any runqueue of a scheduler that uses task priorities.
+pub fn get_priority(task: &TaskRef) -> Option<u8> {
+    SCHEDULERS.iter().find_map(|(_core, sched)| sched.read().priority(task))
+}
+
+/// Sets the real-time parameters of the given task on every runqueue that it is on.
+///
+/// Returns an error if the task isn't on any runqueue,
+/// or if it is on the runqueue of a scheduler that doesn't support real-time tasks or can't admit it.
+pub fn set_realtime_params(task: &TaskRef, params: RealtimeParams) -> Result<(), &'static str> {
+    params.validate()?;
+    set_on_all_runqueues(task, |sched| sched.set_realtime_params(task, params))
+}
+
+/// Returns the real-time parameters of the given task, from the first runqueue that it is found on.
+///
+/// Returns `None` if the task isn't a real-time task on any runqueue.
+pub fn get_realtime_params(task: &TaskRef) -> Option<RealtimeParams> {
+    SCHEDULERS.iter().find_map(|(_core, sched)| sched.read().realtime_params(task))
+}
+
+/// Applies the given scheduling parameter setter `f` to every core's scheduler.
+///
+/// A scheduler that doesn't support the parameter only causes an error if the `task` is on its runqueue.
+fn set_on_all_runqueues<F>(task: &TaskRef, mut f: F) -> Result<(), &'static str>
+    where F: FnMut(&mut dyn Scheduler) -> Result<bool, &'static str>
+{
+    let mut found = false;
+    for (_core, sched) in SCHEDULERS.iter() {
+        let mut locked = sched.write();
+        match f(&mut **locked) {
+            Ok(found_here) => found |= found_here,
+            Err(e) if locked.tasks().contains(task) => return Err(e),
+            Err(_) => continue,
+        }
+    }
+    if found { Ok(()) } else { Err("the task isn't on any runqueue") }
 }
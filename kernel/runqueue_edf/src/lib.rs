@@ -0,0 +1,237 @@
+//! Runqueue structures for an earliest deadline first (EDF) scheduler
+//! that serves each real-time task with a constant bandwidth server (CBS).
+//!
+//! Each `EdfTaskRef` element in the runqueue contains a `TaskRef` and, if the task is a real-time task,
+//! a [`Reservation`] of CPU time: up to `budget` of CPU time in each `period`,
+//! with a deadline of `deadline` after the start of each period.
+//! Tasks without a reservation are best-effort tasks, which only run when no real-time task can run.
+//!
+//! Reservations are subject to admission control: a task is only admitted if the total density
+//! of all reservations on the runqueue, i.e., the sum of `budget / deadline`, doesn't exceed 1.
+//! This guarantees that EDF can meet the deadlines of all admitted tasks that stay within their budgets.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use core::ops::{Deref, DerefMut};
+use runqueue::RealtimeParams;
+use task::TaskRef;
+use time::{Duration, Instant};
+
+/// The density of a reservation that uses an entire core, in parts per million.
+pub const MAX_DENSITY: u64 = 1_000_000;
+
+/// The CPU time reserved for a real-time task, along with the state of its current job.
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    /// The real-time parameters that were admitted for the task.
+    pub params: RealtimeParams,
+    /// The release time of the task's current job, i.e., the start of its current period.
+    pub release: Instant,
+    /// The budget that remains in the current period.
+    /// The task is throttled until its next period once this reaches zero.
+    pub remaining_budget: Duration,
+    /// The task's total CPU time, in TSC ticks, when its budget was last charged.
+    pub charged_cpu_ticks: u64,
+    /// Whether the task has been runnable since its current job was released.
+    pub job_started: bool,
+    /// Whether the task has blocked after its current job started, meaning that the job has completed.
+    pub job_completed: bool,
+    /// Whether a deadline miss or budget overrun has already been reported for the current job.
+    pub job_reported: bool,
+}
+
+impl Reservation {
+    /// Returns the absolute deadline of the task's current job.
+    pub fn absolute_deadline(&self) -> Instant {
+        self.release + self.params.deadline
+    }
+
+    /// Returns the start of the task's next period, when its budget is replenished.
+    pub fn next_release(&self) -> Instant {
+        self.release + self.params.period
+    }
+
+    /// Returns whether the task has exhausted its budget for the current period.
+    pub fn is_throttled(&self) -> bool {
+        self.remaining_budget.is_zero()
+    }
+
+    /// Releases a new job at the start of the latest period that began at or before `now`,
+    /// which replenishes the task's budget.
+    pub fn release_job(&mut self, now: Instant) {
+        let period = self.params.period.as_nanos().max(1);
+        let elapsed_periods = now.duration_since(self.release).as_nanos() / period;
+        let elapsed = (elapsed_periods * period).min(u64::MAX as u128) as u64;
+        self.release += Duration::from_nanos(elapsed);
+        self.remaining_budget = self.params.budget;
+        self.job_started = false;
+        self.job_completed = false;
+        self.job_reported = false;
+    }
+}
+
+/// Returns the density of a task with the given parameters, in parts per million, rounded up.
+pub fn density(params: &RealtimeParams) -> u64 {
+    let deadline = params.deadline.min(params.period).as_nanos().max(1);
+    let density = (params.budget.as_nanos() * MAX_DENSITY as u128 + deadline - 1) / deadline;
+    density.min(u64::MAX as u128) as u64
+}
+
+/// A reference to a task with its reservation for EDF scheduling.
+///
+/// `EdfTaskRef` implements `Deref` and `DerefMut` traits, which dereferences to `TaskRef`.
+#[derive(Debug, Clone)]
+pub struct EdfTaskRef {
+    /// `TaskRef` wrapped by `EdfTaskRef`
+    taskref: TaskRef,
+    /// `Some` if the task is a real-time task, `None` if it is a best-effort task.
+    pub reservation: Option<Reservation>,
+    /// Number of context switches the task has undergone. Not used in scheduling algorithm
+    context_switches: usize,
+}
+
+impl Deref for EdfTaskRef {
+    type Target = TaskRef;
+    fn deref(&self) -> &TaskRef {
+        &self.taskref
+    }
+}
+
+impl DerefMut for EdfTaskRef {
+    fn deref_mut(&mut self) -> &mut TaskRef {
+        &mut self.taskref
+    }
+}
+
+impl EdfTaskRef {
+    /// Creates a new best-effort `EdfTaskRef` that wraps the given `TaskRef`.
+    pub fn new(taskref: TaskRef) -> EdfTaskRef {
+        EdfTaskRef {
+            taskref,
+            reservation: None,
+            context_switches: 0,
+        }
+    }
+
+    /// Increment the number of times the task is picked
+    pub fn increment_context_switches(&mut self) {
+        self.context_switches = self.context_switches.saturating_add(1);
+    }
+}
+
+/// A list of `Task`s and their associated EDF scheduler data that may be run on a given CPU core.
+///
+/// `RunQueue` implements `Deref` and `DerefMut` traits, which dereferences to `VecDeque`.
+#[derive(Debug)]
+pub struct RunQueue {
+    core: u8,
+    queue: VecDeque<EdfTaskRef>,
+}
+
+impl Deref for RunQueue {
+    type Target = VecDeque<EdfTaskRef>;
+    fn deref(&self) -> &VecDeque<EdfTaskRef> {
+        &self.queue
+    }
+}
+
+impl DerefMut for RunQueue {
+    fn deref_mut(&mut self) -> &mut VecDeque<EdfTaskRef> {
+        &mut self.queue
+    }
+}
+
+impl RunQueue {
+    /// Creates a new empty `RunQueue` for the given core, which is an `apic_id`.
+    pub fn new(which_core: u8) -> RunQueue {
+        log::trace!("Created runqueue (edf) for core {}", which_core);
+        RunQueue {
+            core: which_core,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Moves the `EdfTaskRef` at the given `index` in this `RunQueue` to the end (back) of this `RunQueue`,
+    /// increments its number of context switches, and returns a cloned reference to its `TaskRef`.
+    ///
+    /// This is used to pick best-effort tasks in round robin fashion.
+    pub fn move_to_end(&mut self, index: usize) -> Option<TaskRef> {
+        self.remove(index).map(|mut edf_taskref| {
+            edf_taskref.increment_context_switches();
+            let taskref = edf_taskref.taskref.clone();
+            self.push_back(edf_taskref);
+            taskref
+        })
+    }
+
+    /// Adds a `TaskRef` to this RunQueue as a best-effort task.
+    pub fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {
+        #[cfg(not(any(rq_eval, downtime_eval)))]
+        log::debug!("Adding task to runqueue_edf {}, {:?}", self.core, task);
+        self.push_back(EdfTaskRef::new(task));
+        Ok(())
+    }
+
+    /// Removes a `TaskRef` from this RunQueue, which also releases its reservation.
+    pub fn remove_task(&mut self, task: &TaskRef) -> Result<(), &'static str> {
+        #[cfg(not(any(rq_eval, downtime_eval)))]
+        log::debug!("Removing task from runqueue_edf {}, {:?}", self.core, task);
+        self.retain(|x| &x.taskref != task);
+        Ok(())
+    }
+
+    /// Returns the total density of the reservations on this `RunQueue`, in parts per million.
+    ///
+    /// The reservation of the given `excluded` task, if any, is not counted.
+    pub fn total_density(&self, excluded: Option<&TaskRef>) -> u64 {
+        self.iter()
+            .filter(|t| Some(&t.taskref) != excluded)
+            .filter_map(|t| t.reservation.as_ref())
+            .map(|r| density(&r.params))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Reserves CPU time for the given task according to the given real-time `params`,
+    /// with its first period starting at `now`.
+    ///
+    /// Returns `Ok(true)` if the task was admitted, `Ok(false)` if the task isn't on this `RunQueue`,
+    /// or an error if the parameters are invalid or admitting the task would make
+    /// the real-time tasks on this `RunQueue` unschedulable.
+    pub fn set_realtime_params(
+        &mut self,
+        task: &TaskRef,
+        params: RealtimeParams,
+        now: Instant,
+    ) -> Result<bool, &'static str> {
+        params.validate()?;
+        let Some(index) = self.iter().position(|t| &t.taskref == task) else {
+            return Ok(false);
+        };
+        if self.total_density(Some(task)).saturating_add(density(&params)) > MAX_DENSITY {
+            return Err("admitting the task would make the real-time tasks on its core unschedulable");
+        }
+        let edf_taskref = &mut self[index];
+        edf_taskref.reservation = Some(Reservation {
+            params,
+            release: now,
+            remaining_budget: params.budget,
+            charged_cpu_ticks: edf_taskref.taskref.cpu_time_ticks(),
+            job_started: false,
+            job_completed: false,
+            job_reported: false,
+        });
+        Ok(true)
+    }
+
+    /// Returns the real-time parameters of the given task,
+    /// or `None` if the task isn't on this `RunQueue` or is a best-effort task.
+    pub fn get_realtime_params(&self, task: &TaskRef) -> Option<RealtimeParams> {
+        self.iter()
+            .find(|t| &t.taskref == task)
+            .and_then(|t| t.reservation.as_ref())
+            .map(|r| r.params)
+    }
+}
@@ -7,16 +7,12 @@
 
 extern crate alloc;
 #[macro_use] extern crate log;
-extern crate mutex_preemption;
-extern crate atomic_linked_list;
 extern crate task;
 
 #[cfg(single_simd_task_optimization)]
 extern crate single_simd_task_optimization;
 
 use alloc::collections::VecDeque;
-use mutex_preemption::RwLockPreempt;
-use atomic_linked_list::atomic_map::AtomicMap;
 use task::TaskRef;
 use core::ops::{Deref, DerefMut};
 
@@ -79,15 +75,10 @@ impl PriorityTaskRef {
     }
 }
 
-
-/// There is one runqueue per core, each core only accesses its own private runqueue
-/// and allows the scheduler to select a task from that runqueue to schedule in.
-static RUNQUEUES: AtomicMap<u8, RwLockPreempt<RunQueue>> = AtomicMap::new();
-
 /// A list of references to `Task`s (`PriorityTaskRef`s) 
 /// that is used to store the `Task`s (and associated scheduler related data) 
 /// that are runnable on a given core.
-/// A queue is used for the token based prioirty scheduler.
+/// A queue is used for the token based prioirty scheduler, which owns the `RunQueue` of its core.
 /// `Runqueue` implements `Deref` and `DerefMut` traits, which dereferences to `VecDeque`.
 #[derive(Debug)]
 pub struct RunQueue {
@@ -126,78 +117,18 @@ impl RunQueue {
         }
     }
 
-    /// Creates a new `RunQueue` for the given core, which is an `apic_id`
-    pub fn init(which_core: u8) -> Result<(), &'static str> {
+    /// Creates a new empty `RunQueue` for the given core, which is an `apic_id`
+    pub fn new(which_core: u8) -> RunQueue {
         #[cfg(not(loscd_eval))]
         trace!("Created runqueue (priority) for core {}", which_core);
-        let new_rq = RwLockPreempt::new(RunQueue {
+        RunQueue {
             core: which_core,
             queue: VecDeque::new(),
-        });
-
-        if RUNQUEUES.insert(which_core, new_rq).is_some() {
-            error!("BUG: RunQueue::init(): runqueue already exists for core {}!", which_core);
-            Err("runqueue already exists for this core")
-        }
-        else {
-            // there shouldn't already be a RunQueue for this core
-            Ok(())
         }
     }
 
-    /// Returns `RunQueue` for the given core, which is an `apic_id`.
-    pub fn get_runqueue(which_core: u8) -> Option<&'static RwLockPreempt<RunQueue>> {
-        RUNQUEUES.get(&which_core)
-    }
-
-
-    /// Returns the "least busy" core, which is currently very simple, based on runqueue size.
-    pub fn get_least_busy_core() -> Option<u8> {
-        Self::get_least_busy_runqueue().map(|rq| rq.read().core)
-    }
-
-
-    /// Returns the `RunQueue` for the "least busy" core.
-    /// See [`get_least_busy_core()`](#method.get_least_busy_core)
-    fn get_least_busy_runqueue() -> Option<&'static RwLockPreempt<RunQueue>> {
-        let mut min_rq: Option<(&'static RwLockPreempt<RunQueue>, usize)> = None;
-
-        for (_, rq) in RUNQUEUES.iter() {
-            let rq_size = rq.read().queue.len();
-
-            if let Some(min) = min_rq {
-                if rq_size < min.1 {
-                    min_rq = Some((rq, rq_size));
-                }
-            }
-            else {
-                min_rq = Some((rq, rq_size));
-            }
-        }
-
-        min_rq.map(|m| m.0)
-    }
-
-    /// Chooses the "least busy" core's runqueue (based on simple runqueue-size-based load balancing)
-    /// and adds the given `Task` reference to that core's runqueue.
-    pub fn add_task_to_any_runqueue(task: TaskRef) -> Result<(), &'static str> {
-        let rq = RunQueue::get_least_busy_runqueue()
-            .or_else(|| RUNQUEUES.iter().next().map(|r| r.1))
-            .ok_or("couldn't find any runqueues to add the task to!")?;
-
-        rq.write().add_task(task)
-    }
-
-    /// Convenience method that adds the given `Task` reference to given core's runqueue.
-    pub fn add_task_to_specific_runqueue(which_core: u8, task: TaskRef) -> Result<(), &'static str> {
-        RunQueue::get_runqueue(which_core)
-            .ok_or("Couldn't get RunQueue for the given core")?
-            .write()
-            .add_task(task)
-    }
-
     /// Adds a `TaskRef` to this RunQueue.
-    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {        
+    pub fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {        
         #[cfg(not(loscd_eval))]
         debug!("Adding task to runqueue_priority {}, {:?}", self.core, task);
         let priority_task_ref = PriorityTaskRef::new(task);
@@ -231,62 +162,25 @@ impl RunQueue {
         Ok(())
     }
 
-    /// Removes a `TaskRef` from all `RunQueue`s that exist on the entire system.
-    /// 
-    /// This is a brute force approach that iterates over all runqueues. 
-    pub fn remove_task_from_all(task: &TaskRef) -> Result<(), &'static str> {
-        for (_core, rq) in RUNQUEUES.iter() {
-            rq.write().remove_task(task)?;
-        }
-        Ok(())
-    }
-
-    /// The internal function that sets the priority of a given `Task` in a single `RunQueue`
-    fn set_priority_internal(&mut self, task: &TaskRef, priority: u8) -> Result<(), &'static str> {
-        // debug!("called_assign_priority_internal called per core");
+    /// Sets the priority of the given `Task` in this `RunQueue`.
+    ///
+    /// Returns `true` if the task is in this `RunQueue`.
+    pub fn set_priority(&mut self, task: &TaskRef, priority: u8) -> bool {
+        let mut found = false;
         for x in self.iter_mut() {
-            if &x.taskref == task{
+            if &x.taskref == task {
                 debug!("changed priority from {}  to {} ", x.priority, priority);
                 x.priority = priority;
+                found = true;
             }
         }
-        Ok(())
-    } 
-
-    /// Sets the priority of the given `Task` in all the `RunQueue` structures 
-    pub fn set_priority(task: &TaskRef, priority: u8) -> Result<(), &'static str> {
-        // debug!("assign priority wrapper. called once per call");
-        for (_core, rq) in RUNQUEUES.iter() {
-            rq.write().set_priority_internal(task, priority)?;
-        }
-        Ok(())
-    }
-
-    /// The internal function that outputs the priority of a given task.
-    /// The priority of the first task that matches is shown.
-    fn get_priority_internal(&self, task: &TaskRef) -> Option<u8> {
-        // debug!("called_assign_priority_internal called per core");
-        for x in self.iter() {
-            if &x.taskref == task {
-                return Some(x.priority);
-            }
-        }
-        None
+        found
     }
 
-    /// Output the priority of the given task.
-    /// Outputs None if the task is not found in any of the runqueues.
-    pub fn get_priority(task: &TaskRef) -> Option<u8> {
-        // debug!("assign priority wrapper. called once per call");
-        for (_core, rq) in RUNQUEUES.iter() {
-            let return_priority = rq.read().get_priority_internal(task);
-            match return_priority {
-                //If a matching task is found the iteration terminates
-                Some(x) => return Some(x),
-                None => continue,
-            }
-        }
-        None
+    /// Returns the priority of the given `Task`, or `None` if it isn't in this `RunQueue`.
+    pub fn get_priority(&self, task: &TaskRef) -> Option<u8> {
+        self.iter()
+            .find(|x| &x.taskref == task)
+            .map(|x| x.priority)
     }
-
 }
@@ -19,16 +19,12 @@
 #![no_std]
 
 extern crate task;
-extern crate mutex_preemption;
 extern crate alloc;
 #[macro_use] extern crate log;
-extern crate atomic_linked_list;
 
 use task::TaskRef;
-use mutex_preemption::RwLockPreempt;
 use alloc::collections::VecDeque;
 use core::ops::{Deref, DerefMut};
-use atomic_linked_list::atomic_map::AtomicMap;
 
 /// A reference to a task with its period for realtime scheduling.
 ///
@@ -93,10 +89,6 @@ impl RealtimeTaskRef {
     }
 }
 
-/// There is one runqueue per core, each core only accesses its own private runqueue
-/// and allows the scheduler to select a task from that runqueue to schedule in
-static RUNQUEUES: AtomicMap<u8, RwLockPreempt<RunQueue>> = AtomicMap::new();
-
 /// A list of `Task`s and their associated realtime scheduler data that may be run on a given CPU core.
 ///
 /// In rate monotonic scheduling, tasks are sorted in order of increasing periods.
@@ -145,72 +137,14 @@ impl RunQueue {
         }
     }
 
-    /// Creates a new `RunQueue` for the given core, which is an `apic_id`
-    pub fn init(which_core: u8) -> Result<(), &'static str> {
+    /// Creates a new empty `RunQueue` for the given core, which is an `apic_id`
+    pub fn new(which_core: u8) -> RunQueue {
         #[cfg(not(loscd_eval))]
         trace!("Created runqueue (realtime) for core {}", which_core);
-        let new_rq = RwLockPreempt::new(RunQueue {
+        RunQueue {
             core: which_core,
             queue: VecDeque::new(),
-        });
-
-        if RUNQUEUES.insert(which_core, new_rq).is_some() {
-            error!("BUG: RunQueue::init(): runqueue already exists for core {}!", which_core);
-            Err("runqueue already exists for this core")
-        }
-        else {
-            // there shouldn't already be a RunQueue for this core
-            Ok(())
-        }
-    }
-
-    /// Returns `RunQueue` for the given core, which is an `apic_id`.
-    pub fn get_runqueue(which_core: u8) -> Option<&'static RwLockPreempt<RunQueue>> {
-        RUNQUEUES.get(&which_core)
-    } 
-
-    /// Returns the "least busy" core, which is currently very simple, based on runqueue size.
-    pub fn get_least_busy_core() -> Option<u8> {
-        Self::get_least_busy_runqueue().map(|rq| rq.read().core)
-    }
-
-    /// Returns the `RunQueue` for the "least busy" core.
-    /// See [`get_least_busy_core()`](#method.get_least_busy_core)
-    fn get_least_busy_runqueue() -> Option<&'static RwLockPreempt<RunQueue>> {
-        let mut min_rq: Option<(&'static RwLockPreempt<RunQueue>, usize)> = None;
-
-        for (_, rq) in RUNQUEUES.iter() {
-            let rq_size = rq.read().queue.len();
-
-            if let Some(min) = min_rq {
-                if rq_size < min.1 {
-                    min_rq = Some((rq, rq_size));
-                }
-            }
-            else {
-                min_rq = Some((rq, rq_size));
-            }
         }
-
-        min_rq.map(|m| m.0)
-    }
-
-    /// Chooses the "least busy" core's runqueue (based on simple runqueue-size-based load balancing)
-    /// and adds the given `Task` reference to that core's runqueue.
-    pub fn add_task_to_any_runqueue(task: TaskRef) -> Result<(), &'static str> {
-        let rq = RunQueue::get_least_busy_runqueue()
-            .or_else(|| RUNQUEUES.iter().next().map(|r| r.1))
-            .ok_or("couldn't find any runqueues to add the task to!")?;
-
-        rq.write().add_task(task, None)
-    }
-
-    /// Convenience method that adds the given `Task` reference to given core's runqueue.
-    pub fn add_task_to_specific_runqueue(which_core: u8, task: TaskRef) -> Result<(), &'static str> {
-        RunQueue::get_runqueue(which_core)
-            .ok_or("Couldn't get RunQueue for the given core")?
-            .write()
-            .add_task(task, None)
     }
 
     /// Inserts a `RealtimeTaskRef` at its proper position in the queue.
@@ -243,7 +177,7 @@ impl RunQueue {
     }
 
     /// Adds a `TaskRef` to this runqueue with the given periodicity value
-    fn add_task(&mut self, task: TaskRef, period: Option<usize>) -> Result<(), &'static str> {
+    pub fn add_task(&mut self, task: TaskRef, period: Option<usize>) -> Result<(), &'static str> {
         debug!("Adding task to runqueue_realtime {}, {:?}", self.core, task);
         let realtime_taskref = RealtimeTaskRef::new(task, period);
         self.insert_realtime_taskref_at_proper_location(realtime_taskref);
@@ -264,37 +198,18 @@ impl RunQueue {
         self.remove_internal(task)
     }
 
-    /// Removes a `TaskRef` from all `RunQueue`s that exist on the entire system.
-    /// 
-    /// This is a brute force approach that iterates over all runqueues. 
-    pub fn remove_task_from_all(task: &TaskRef) -> Result<(), &'static str> {
-        for (_core, rq) in RUNQUEUES.iter() {
-            rq.write().remove_task(task)?;
-        }
-        Ok(())
-    }
-
-    /// The internal function that sets the periodicity of a given `Task` in a single `RunQueue`
-    /// then reinserts the `RealtimeTaskRef` at the proper location
-    fn set_periodicity_internal(
-        &mut self, 
-        task: &TaskRef, 
-        period: usize
-    ) -> Result<(), &'static str> {
+    /// Sets the periodicity of a given `Task` in this `RunQueue`,
+    /// then reinserts the `RealtimeTaskRef` at the proper location.
+    ///
+    /// Returns `true` if the task is in this `RunQueue`.
+    pub fn set_periodicity(&mut self, task: &TaskRef, period: usize) -> bool {
         if let Some(i) = self.iter().position(|rt| &rt.taskref == task) {
             if let Some(mut realtime_taskref) = self.remove(i) {
                 realtime_taskref.period = Some(period);
                 self.insert_realtime_taskref_at_proper_location(realtime_taskref);
+                return true;
             }
-        };
-        Ok(())
-    }
-}
-
-/// Set the periodicity of a given `Task` in all the `RunQueue` structures
-pub fn set_periodicity(task: &TaskRef, period: usize) -> Result<(), &'static str> {
-    for (_core, rq) in RUNQUEUES.iter() {
-        rq.write().set_periodicity_internal(task, period)?;
+        }
+        false
     }
-    Ok(())
 }
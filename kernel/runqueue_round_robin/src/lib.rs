@@ -7,16 +7,12 @@
 
 extern crate alloc;
 #[macro_use] extern crate log;
-extern crate mutex_preemption;
-extern crate atomic_linked_list;
 extern crate task;
 
 #[cfg(single_simd_task_optimization)]
 extern crate single_simd_task_optimization;
 
 use alloc::collections::VecDeque;
-use mutex_preemption::RwLockPreempt;
-use atomic_linked_list::atomic_map::AtomicMap;
 use task::TaskRef;
 use core::ops::{Deref, DerefMut};
 
@@ -75,14 +71,10 @@ impl RoundRobinTaskRef {
     }
 }
 
-/// There is one runqueue per core, each core only accesses its own private runqueue
-/// and allows the scheduler to select a task from that runqueue to schedule in.
-pub static RUNQUEUES: AtomicMap<u8, RwLockPreempt<RunQueue>> = AtomicMap::new();
-
 /// A list of references to `Task`s (`RoundRobinTaskRef`s). 
 /// This is used to store the `Task`s (and associated scheduler related data) 
 /// that are runnable on a given core.
-/// A queue is used for the round robin scheduler.
+/// A queue is used for the round robin scheduler, which owns the `RunQueue` of its core.
 /// `Runqueue` implements `Deref` and `DerefMut` traits, which dereferences to `VecDeque`.
 #[derive(Debug)]
 pub struct RunQueue {
@@ -120,77 +112,17 @@ impl RunQueue {
         })
     }
 
-    /// Creates a new `RunQueue` for the given core, which is an `apic_id`.
-    pub fn init(which_core: u8) -> Result<(), &'static str> {
+    /// Creates a new empty `RunQueue` for the given core, which is an `apic_id`.
+    pub fn new(which_core: u8) -> RunQueue {
         trace!("Created runqueue (round robin) for core {}", which_core);
-        let new_rq = RwLockPreempt::new(RunQueue {
+        RunQueue {
             core: which_core,
             queue: VecDeque::new(),
-        });
-
-        if RUNQUEUES.insert(which_core, new_rq).is_some() {
-            error!("BUG: RunQueue::init(): runqueue already exists for core {}!", which_core);
-            Err("runqueue already exists for this core")
         }
-        else {
-            // there shouldn't already be a RunQueue for this core
-            Ok(())
-        }
-    }
-
-    /// Returns the `RunQueue` for the given core, which is an `apic_id`.
-    pub fn get_runqueue(which_core: u8) -> Option<&'static RwLockPreempt<RunQueue>> {
-        RUNQUEUES.get(&which_core)
-    }
-
-
-    /// Returns the "least busy" core, which is currently very simple, based on runqueue size.
-    pub fn get_least_busy_core() -> Option<u8> {
-        Self::get_least_busy_runqueue().map(|rq| rq.read().core)
-    }
-
-
-    /// Returns the `RunQueue` for the "least busy" core.
-    /// See [`get_least_busy_core()`](#method.get_least_busy_core)
-    fn get_least_busy_runqueue() -> Option<&'static RwLockPreempt<RunQueue>> {
-        let mut min_rq: Option<(&'static RwLockPreempt<RunQueue>, usize)> = None;
-
-        for (_, rq) in RUNQUEUES.iter() {
-            let rq_size = rq.read().queue.len();
-
-            if let Some(min) = min_rq {
-                if rq_size < min.1 {
-                    min_rq = Some((rq, rq_size));
-                }
-            }
-            else {
-                min_rq = Some((rq, rq_size));
-            }
-        }
-
-        min_rq.map(|m| m.0)
-    }
-
-    /// Chooses the "least busy" core's runqueue (based on simple runqueue-size-based load balancing)
-    /// and adds the given `Task` reference to that core's runqueue.
-    pub fn add_task_to_any_runqueue(task: TaskRef) -> Result<(), &'static str> {
-        let rq = RunQueue::get_least_busy_runqueue()
-            .or_else(|| RUNQUEUES.iter().next().map(|r| r.1))
-            .ok_or("couldn't find any runqueues to add the task to!")?;
-
-        rq.write().add_task(task)
-    }
-
-    /// Convenience method that adds the given `Task` reference to given core's runqueue.
-    pub fn add_task_to_specific_runqueue(which_core: u8, task: TaskRef) -> Result<(), &'static str> {
-        RunQueue::get_runqueue(which_core)
-            .ok_or("Couldn't get RunQueue for the given core")?
-            .write()
-            .add_task(task)
     }
 
     /// Adds a `TaskRef` to this RunQueue.
-    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {        
+    pub fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {        
         #[cfg(not(any(rq_eval, downtime_eval)))]
         debug!("Adding task to runqueue_round_robin {}, {:?}", self.core, task);
 
@@ -225,15 +157,4 @@ impl RunQueue {
 
         Ok(())
     }
-
-
-    /// Removes a `TaskRef` from all `RunQueue`s that exist on the entire system.
-    /// 
-    /// This is a brute force approach that iterates over all runqueues. 
-    pub fn remove_task_from_all(task: &TaskRef) -> Result<(), &'static str> {
-        for (_core, rq) in RUNQUEUES.iter() {
-            rq.write().remove_task(task)?;
-        }
-        Ok(())
-    }
 }
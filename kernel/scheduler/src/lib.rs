@@ -1,16 +1,94 @@
+//! Provides scheduling functionality for selecting the next task and causing a task switch.
+//!
+//! Each core has its own instance of a [`Scheduler`] policy, which is stored in the `runqueue` crate.
+//! The policy that new cores use by default is chosen at build time,
+//! but each core's policy can be changed at runtime using [`set_policy()`].
+
 #![no_std]
 
-cfg_if::cfg_if! {
-    if #[cfg(priority_scheduler)] {
-        extern crate scheduler_priority as scheduler;
-    } else if #[cfg(realtime_scheduler)] {
-        extern crate scheduler_realtime as scheduler;
-    } else {
-        extern crate scheduler_round_robin as scheduler;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::str::FromStr;
+use runqueue::Scheduler;
+use scheduler_edf::EdfScheduler;
+use scheduler_priority::PriorityScheduler;
+use scheduler_realtime::RealtimeScheduler;
+use scheduler_round_robin::RoundRobinScheduler;
+use task::TaskRef;
+
+pub use runqueue::RealtimeParams;
+
+/// The scheduling policies that a core can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Tasks are picked in round robin fashion.
+    RoundRobin,
+    /// Tasks are picked based on tokens that are distributed according to their priority.
+    Priority,
+    /// Periodic tasks are picked using rate monotonic scheduling.
+    Realtime,
+    /// Real-time tasks with budgets and deadlines are picked using earliest deadline first scheduling,
+    /// subject to admission control.
+    Edf,
+}
+
+impl Policy {
+    /// The policy used by each core unless it is changed, which is chosen at build time.
+    #[cfg(priority_scheduler)]
+    pub const DEFAULT: Policy = Policy::Priority;
+    /// The policy used by each core unless it is changed, which is chosen at build time.
+    #[cfg(all(realtime_scheduler, not(priority_scheduler)))]
+    pub const DEFAULT: Policy = Policy::Realtime;
+    /// The policy used by each core unless it is changed, which is chosen at build time.
+    #[cfg(all(edf_scheduler, not(any(priority_scheduler, realtime_scheduler))))]
+    pub const DEFAULT: Policy = Policy::Edf;
+    /// The policy used by each core unless it is changed, which is chosen at build time.
+    #[cfg(not(any(priority_scheduler, realtime_scheduler, edf_scheduler)))]
+    pub const DEFAULT: Policy = Policy::RoundRobin;
+
+    /// Creates a new scheduler instance that implements this policy for the given core.
+    pub fn create_scheduler(self, apic_id: u8) -> Box<dyn Scheduler> {
+        match self {
+            Policy::RoundRobin => Box::new(RoundRobinScheduler::new(apic_id)),
+            Policy::Priority   => Box::new(PriorityScheduler::new(apic_id)),
+            Policy::Realtime   => Box::new(RealtimeScheduler::new(apic_id)),
+            Policy::Edf        => Box::new(EdfScheduler::new(apic_id)),
+        }
     }
 }
 
-use task::TaskRef;
+impl FromStr for Policy {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Policy, Self::Err> {
+        match s {
+            "round_robin" | "rr" => Ok(Policy::RoundRobin),
+            "priority"    | "prio" => Ok(Policy::Priority),
+            "realtime"    | "rt" => Ok(Policy::Realtime),
+            "edf" => Ok(Policy::Edf),
+            _ => Err("unknown scheduling policy"),
+        }
+    }
+}
+
+/// Creates the runqueue for the given core, which is an `apic_id`,
+/// using a scheduler that implements the default [`Policy`].
+pub fn init(apic_id: u8) -> Result<(), &'static str> {
+    runqueue::init(apic_id, Policy::DEFAULT.create_scheduler(apic_id))
+}
+
+/// Changes the scheduling policy of the given core, which is an `apic_id`,
+/// migrating all tasks on that core's runqueue to a new scheduler that implements the given `policy`.
+///
+/// Any policy-specific state of those tasks, e.g., their priority, is reset to its default value.
+pub fn set_policy(apic_id: u8, policy: Policy) -> Result<(), &'static str> {
+    runqueue::set_scheduler(apic_id, policy.create_scheduler(apic_id)).map(|_old_scheduler| ())
+}
+
+/// Returns the name of the scheduling policy used by the given core, which is an `apic_id`.
+pub fn policy_name(apic_id: u8) -> Option<&'static str> {
+    runqueue::get_scheduler(apic_id).map(|sched| sched.read().name())
+}
 
 /// Yields the current CPU by selecting a new `Task` to run 
 /// and then switching to that new `Task`.
@@ -33,10 +111,20 @@ pub fn schedule() -> bool {
 
     let cpu_id = preemption_guard.cpu_id();
 
-    let Some(next_task) = scheduler::select_next_task(cpu_id) else {
+    // Migrate tasks away from this core if it's busier than others.
+    runqueue::balance(cpu_id);
+
+    let Some(next_task) = runqueue::get_scheduler(cpu_id)
+        .and_then(|sched| sched.write().select_next_task())
+    else {
         return false; // keep running the same current task
     };
 
+    // Let busy cores know that this core has nothing else to run.
+    if next_task.is_an_idle_task {
+        runqueue::request_work(cpu_id);
+    }
+
     let (did_switch, recovered_preemption_guard) = task::task_switch(
         next_task,
         cpu_id,
@@ -51,32 +139,20 @@ pub fn schedule() -> bool {
 
 /// Changes the priority of the given task with the given priority level.
 /// Priority values must be between 40 (maximum priority) and 0 (minimum prriority).
-/// This function returns an error when a scheduler without priority is loaded. 
-pub fn set_priority(_task: &TaskRef, _priority: u8) -> Result<(), &'static str> {
-    #[cfg(priority_scheduler)] {
-        scheduler_priority::set_priority(_task, _priority)
-    }
-    #[cfg(not(priority_scheduler))] {
-        Err("no scheduler that uses task priority is currently loaded")
-    }
+/// This function returns an error when the task is on a core whose scheduler doesn't use priorities.
+pub fn set_priority(task: &TaskRef, priority: u8) -> Result<(), &'static str> {
+    runqueue::set_priority(task, priority)
 }
 
 /// Returns the priority of a given task.
-/// This function returns None when a scheduler without priority is loaded.
-pub fn get_priority(_task: &TaskRef) -> Option<u8> {
-    #[cfg(priority_scheduler)] {
-        scheduler_priority::get_priority(_task)
-    }
-    #[cfg(not(priority_scheduler))] {
-        None
-    }
+/// This function returns None when the task is on a core whose scheduler doesn't use priorities.
+pub fn get_priority(task: &TaskRef) -> Option<u8> {
+    runqueue::get_priority(task)
 }
 
-pub fn set_periodicity(_task: &TaskRef, _period: usize) -> Result<(), &'static str> {
-    #[cfg(realtime_scheduler)] {
-        scheduler_realtime::set_periodicity(_task, _period)
-    }
-    #[cfg(not(realtime_scheduler))] {
-        Err("no scheduler that supports periodic tasks is currently loaded")
-    }
+/// Sets the real-time parameters of the given task, i.e., its budget, period, and deadline.
+/// This function returns an error when the task is on a core whose scheduler doesn't support real-time tasks,
+/// or when that scheduler's admission control rejects the task because it would make its core unschedulable.
+pub fn set_realtime_params(task: &TaskRef, params: RealtimeParams) -> Result<(), &'static str> {
+    runqueue::set_realtime_params(task, params)
 }
@@ -0,0 +1,272 @@
+//! This scheduler implements earliest deadline first (EDF) scheduling
+//! of real-time tasks that are each served by a constant bandwidth server (CBS).
+//!
+//! A real-time task reserves up to `budget` of CPU time in each `period`
+//! (see [`RealtimeParams`]), and is only admitted if all real-time tasks on its core remain schedulable
+//! (see [`runqueue_edf`]). Among the real-time tasks that are runnable and have budget remaining,
+//! the one whose current job has the earliest absolute deadline is always chosen.
+//! A task that exhausts its budget is throttled until its next period,
+//! such that it cannot cause other real-time tasks to miss their deadlines.
+//! Best-effort tasks, i.e., those without real-time parameters, are picked in round robin fashion
+//! only if no real-time task can run.
+//!
+//! Whenever a real-time job misses its deadline or exhausts its budget before completing,
+//! this scheduler records a [`RealtimeEvent`], which can be retrieved using [`events()`].
+//! A job is considered to have completed once its task blocks, e.g., to sleep until its next period.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use irq_safety::MutexIrqSafe;
+use runqueue::{RealtimeParams, Scheduler};
+use runqueue_edf::RunQueue;
+use task::TaskRef;
+use time::{Instant, Monotonic};
+
+/// The maximum number of events that are retained; older events are discarded.
+const MAX_EVENTS: usize = 128;
+
+/// The kinds of real-time events recorded by the EDF scheduler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RealtimeEventKind {
+    /// A job didn't complete by its absolute deadline.
+    DeadlineMiss,
+    /// A job consumed its task's entire budget for the period without completing.
+    BudgetOverrun,
+}
+
+/// A deadline miss or budget overrun of a real-time task's job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RealtimeEvent {
+    pub kind: RealtimeEventKind,
+    /// The ID of the task whose job missed its deadline or overran its budget.
+    pub task_id: usize,
+    /// The core whose scheduler detected the event.
+    pub core: u8,
+    /// The absolute deadline of the job.
+    pub deadline: Instant,
+    /// The time at which the event was detected.
+    pub detected_at: Instant,
+}
+
+/// A bounded log of real-time events, which never allocates
+/// because events are recorded from within the scheduler.
+struct EventLog {
+    events: [Option<RealtimeEvent>; MAX_EVENTS],
+    /// The index at which the next event will be stored.
+    next: usize,
+    /// The number of deadline misses that have been recorded, including discarded ones.
+    total_deadline_misses: usize,
+}
+
+static EVENTS: MutexIrqSafe<EventLog> = MutexIrqSafe::new(EventLog {
+    events: [None; MAX_EVENTS],
+    next: 0,
+    total_deadline_misses: 0,
+});
+
+/// Records the given event, discarding the oldest retained event if the log is full.
+///
+/// This doesn't log the event, because the logger may be in use by the task that was interrupted.
+fn record_event(event: RealtimeEvent) {
+    let mut event_log = EVENTS.lock();
+    let next = event_log.next;
+    event_log.events[next] = Some(event);
+    event_log.next = (next + 1) % MAX_EVENTS;
+    if event.kind == RealtimeEventKind::DeadlineMiss {
+        event_log.total_deadline_misses += 1;
+    }
+}
+
+/// Returns the most recent real-time events recorded by the EDF scheduler on any core,
+/// in the order they were detected.
+pub fn events() -> Vec<RealtimeEvent> {
+    let event_log = EVENTS.lock();
+    let (newer, older) = event_log.events.split_at(event_log.next);
+    older.iter().chain(newer.iter()).flatten().copied().collect()
+}
+
+/// Returns the total number of deadline misses recorded by the EDF scheduler on any core,
+/// including those that are no longer retained by [`events()`].
+pub fn total_deadline_misses() -> usize {
+    EVENTS.lock().total_deadline_misses
+}
+
+/// Discards all recorded real-time events and resets the event counters.
+pub fn clear_events() {
+    let mut event_log = EVENTS.lock();
+    event_log.events = [None; MAX_EVENTS];
+    event_log.next = 0;
+    event_log.total_deadline_misses = 0;
+}
+
+
+/// The EDF scheduler of a single core, which owns that core's `RunQueue`.
+pub struct EdfScheduler {
+    apic_id: u8,
+    runqueue: RunQueue,
+}
+
+impl EdfScheduler {
+    /// Creates a new EDF scheduler for the given core, which is an `apic_id`.
+    pub fn new(apic_id: u8) -> EdfScheduler {
+        EdfScheduler {
+            apic_id,
+            runqueue: RunQueue::new(apic_id),
+        }
+    }
+
+    /// Charges each real-time task for the CPU time it consumed since it was last charged,
+    /// releases new jobs whose periods have begun, and records deadline misses and budget overruns.
+    fn update_reservations(&mut self, now: Instant) {
+        for t in self.runqueue.iter_mut() {
+            let task_id = t.id;
+            let runnable = t.is_runnable();
+            let cpu_ticks = t.cpu_time_ticks();
+            let Some(reservation) = t.reservation.as_mut() else {
+                continue;
+            };
+
+            let consumed = task::ticks_to_duration(cpu_ticks.saturating_sub(reservation.charged_cpu_ticks))
+                .unwrap_or_default();
+            reservation.charged_cpu_ticks = cpu_ticks;
+            reservation.remaining_budget = reservation.remaining_budget.saturating_sub(consumed);
+
+            if runnable {
+                reservation.job_started = true;
+            } else if reservation.job_started {
+                reservation.job_completed = true;
+            }
+
+            let pending = reservation.job_started && !reservation.job_completed;
+            if pending && !reservation.job_reported {
+                let kind = if now >= reservation.absolute_deadline() {
+                    Some(RealtimeEventKind::DeadlineMiss)
+                } else if reservation.is_throttled() {
+                    Some(RealtimeEventKind::BudgetOverrun)
+                } else {
+                    None
+                };
+                if let Some(kind) = kind {
+                    reservation.job_reported = true;
+                    record_event(RealtimeEvent {
+                        kind,
+                        task_id,
+                        core: self.apic_id,
+                        deadline: reservation.absolute_deadline(),
+                        detected_at: now,
+                    });
+                }
+            }
+
+            if now >= reservation.next_release() {
+                reservation.release_job(now);
+            }
+        }
+    }
+}
+
+impl Scheduler for EdfScheduler {
+    fn name(&self) -> &'static str {
+        "edf"
+    }
+
+    /// This defines the EDF scheduler policy.
+    /// Returns None if there is no schedule-able task
+    fn select_next_task(&mut self) -> Option<TaskRef> {
+        self.update_reservations(time::now::<Monotonic>());
+
+        let mut idle_task_index: Option<usize> = None;
+        let mut best_effort_index: Option<usize> = None;
+        let mut realtime_choice: Option<(usize, Instant)> = None;
+
+        for (i, t) in self.runqueue.iter().enumerate() {
+            // we skip the idle task, and only choose it if no other tasks are runnable
+            if t.is_an_idle_task {
+                idle_task_index = Some(i);
+                continue;
+            }
+
+            // must be runnable
+            if !t.is_runnable() {
+                continue;
+            }
+
+            // must be allowed to run on this core,
+            // otherwise it's waiting to be migrated away due to a change in its affinity
+            if !t.can_run_on(self.apic_id) {
+                continue;
+            }
+
+            match t.reservation {
+                // a real-time task must have budget remaining in its current period
+                Some(ref reservation) if !reservation.is_throttled() => {
+                    let deadline = reservation.absolute_deadline();
+                    if realtime_choice.map_or(true, |(_, earliest)| deadline < earliest) {
+                        realtime_choice = Some((i, deadline));
+                    }
+                }
+                Some(_) => continue,
+                None => {
+                    if best_effort_index.is_none() {
+                        best_effort_index = Some(i);
+                    }
+                }
+            }
+        }
+
+        if let Some((index, _deadline)) = realtime_choice {
+            let t = self.runqueue.get_mut(index)?;
+            t.increment_context_switches();
+            return Some(TaskRef::clone(t));
+        }
+
+        // best-effort tasks and the idle task are picked in round robin fashion
+        best_effort_index
+            .or(idle_task_index)
+            .and_then(|index| self.runqueue.move_to_end(index))
+    }
+
+    /// Adds the given task to this scheduler's `RunQueue` as a best-effort task.
+    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {
+        self.runqueue.add_task(task)
+    }
+
+    fn remove_task(&mut self, task: &TaskRef) -> bool {
+        let len = self.runqueue.len();
+        let _ = self.runqueue.remove_task(task);
+        self.runqueue.len() != len
+    }
+
+    fn busyness(&self) -> usize {
+        self.runqueue.len()
+    }
+
+    fn tasks(&self) -> Vec<TaskRef> {
+        self.runqueue.iter().map(|t| TaskRef::clone(t)).collect()
+    }
+
+    fn drain(&mut self) -> Vec<TaskRef> {
+        self.runqueue.drain(..).map(|t| TaskRef::clone(&t)).collect()
+    }
+
+    /// Admits the given task as a real-time task with the given parameters.
+    ///
+    /// Because admission control is performed per core, the task must be pinned to this core,
+    /// such that it is never migrated to another core.
+    fn set_realtime_params(&mut self, task: &TaskRef, params: RealtimeParams) -> Result<bool, &'static str> {
+        if !self.runqueue.iter().any(|t| &**t == task) {
+            return Ok(false);
+        }
+        if task.pinned_core() != Some(self.apic_id) {
+            return Err("a real-time task must be pinned to the core that it runs on");
+        }
+        self.runqueue.set_realtime_params(task, params, time::now::<Monotonic>())
+    }
+
+    fn realtime_params(&self, task: &TaskRef) -> Option<RealtimeParams> {
+        self.runqueue.get_realtime_params(task)
+    }
+}
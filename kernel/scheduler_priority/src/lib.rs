@@ -11,11 +11,13 @@
 #![no_std]
 
 extern crate alloc;
-#[macro_use] extern crate log;
 extern crate task;
+extern crate runqueue;
 extern crate runqueue_priority;
 
+use alloc::vec::Vec;
 use task::TaskRef;
+use runqueue::Scheduler;
 use runqueue_priority::{RunQueue, MAX_PRIORITY};
 
 
@@ -26,29 +28,76 @@ struct NextTaskResult{
     idle_task : bool,
 }
 
-/// Changes the priority of the given task with the given priority level.
-/// Priority values must be between 40 (maximum priority) and 0 (minimum prriority).
-pub fn set_priority(task: &TaskRef, priority: u8) -> Result<(), &'static str> {
-    let priority = core::cmp::min(priority, MAX_PRIORITY);
-    RunQueue::set_priority(task, priority)
+/// The priority scheduler of a single core, which owns that core's `RunQueue`.
+pub struct PriorityScheduler {
+    apic_id: u8,
+    runqueue: RunQueue,
 }
 
-/// Returns the priority of the given task.
-pub fn get_priority(task: &TaskRef) -> Option<u8> {
-    RunQueue::get_priority(task)
+impl PriorityScheduler {
+    /// Creates a new priority scheduler for the given core, which is an `apic_id`.
+    pub fn new(apic_id: u8) -> PriorityScheduler {
+        PriorityScheduler {
+            apic_id,
+            runqueue: RunQueue::new(apic_id),
+        }
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn select_next_task(&mut self) -> Option<TaskRef> {
+        select_next_task(self.apic_id, &mut self.runqueue)
+    }
+
+    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {
+        self.runqueue.add_task(task)
+    }
+
+    fn remove_task(&mut self, task: &TaskRef) -> bool {
+        let len = self.runqueue.len();
+        let _ = self.runqueue.remove_task(task);
+        self.runqueue.len() != len
+    }
+
+    fn busyness(&self) -> usize {
+        self.runqueue.len()
+    }
+
+    fn tasks(&self) -> Vec<TaskRef> {
+        self.runqueue.iter().map(|t| TaskRef::clone(t)).collect()
+    }
+
+    fn drain(&mut self) -> Vec<TaskRef> {
+        self.runqueue.drain(..).map(|t| TaskRef::clone(&t)).collect()
+    }
+
+    /// Changes the priority of the given task with the given priority level.
+    /// Priority values must be between 40 (maximum priority) and 0 (minimum prriority).
+    fn set_priority(&mut self, task: &TaskRef, priority: u8) -> Result<bool, &'static str> {
+        let priority = core::cmp::min(priority, MAX_PRIORITY);
+        Ok(self.runqueue.set_priority(task, priority))
+    }
+
+    fn priority(&self, task: &TaskRef) -> Option<u8> {
+        self.runqueue.get_priority(task)
+    }
 }
 
 /// This defines the priority scheduler policy.
 /// Returns None if there is no schedule-able task.
-pub fn select_next_task(apic_id: u8) -> Option<TaskRef>  {
-    let priority_taskref_with_result = select_next_task_priority(apic_id); 
+fn select_next_task(apic_id: u8, runqueue: &mut RunQueue) -> Option<TaskRef>  {
+    let priority_taskref_with_result = select_next_task_priority(apic_id, runqueue); 
     match priority_taskref_with_result {
         // A task has been selected
         Some(task) => {
             // If the selected task is idle task we begin a new scheduling epoch
             if task.idle_task {
-                assign_tokens(apic_id);
-                select_next_task_priority(apic_id).and_then(|m| m.taskref)
+                assign_tokens(apic_id, runqueue);
+                select_next_task_priority(apic_id, runqueue).and_then(|m| m.taskref)
             }
             // If the selected task is not idle we return the taskref
             else {
@@ -58,8 +107,8 @@ pub fn select_next_task(apic_id: u8) -> Option<TaskRef>  {
 
         // If no task is picked we pick a new scheduling epoch
         None    => {
-            assign_tokens(apic_id);
-            select_next_task_priority(apic_id).and_then(|m| m.taskref)
+            assign_tokens(apic_id, runqueue);
+            select_next_task_priority(apic_id, runqueue).and_then(|m| m.taskref)
         }
     }
 }
@@ -67,17 +116,7 @@ pub fn select_next_task(apic_id: u8) -> Option<TaskRef>  {
 /// this defines the priority scheduler policy.
 /// Returns None if there is no schedule-able task.
 /// Otherwise returns a task with a flag indicating whether its an idle task.
-fn select_next_task_priority(apic_id: u8) -> Option<NextTaskResult>  {
-
-    let mut runqueue_locked = match RunQueue::get_runqueue(apic_id) {
-        Some(rq) => rq.write(),
-        _ => {
-            // #[cfg(not(loscd_eval))]
-            // error!("BUG: select_next_task_priority(): couldn't get runqueue for core {}", apic_id); 
-            return None;
-        }
-    };
-    
+fn select_next_task_priority(apic_id: u8, runqueue_locked: &mut RunQueue) -> Option<NextTaskResult>  {
     let mut idle_task_index: Option<usize> = None;
     let mut chosen_task_index: Option<usize> = None;
     let mut idle_task = true;
@@ -94,13 +133,10 @@ fn select_next_task_priority(apic_id: u8) -> Option<NextTaskResult>  {
             continue;
         }
 
-        // if this task is pinned, it must not be pinned to a different core
-        if let Some(pinned) = t.pinned_core() {
-            if pinned != apic_id {
-                // with per-core runqueues, this should never happen!
-                error!("select_next_task() (AP {}) found a task pinned to a different core: {:?}", apic_id, t);
-                return None;
-            }
+        // this task must be allowed to run on this core,
+        // otherwise it's waiting to be migrated away due to a change in its affinity
+        if !t.can_run_on(apic_id) {
+            continue;
         }
 
         // if the task has no remaining tokens we ignore the task
@@ -137,17 +173,7 @@ fn select_next_task_priority(apic_id: u8) -> Option<NextTaskResult>  {
 /// This assigns tokens between tasks.
 /// Returns true if successful.
 /// Tokens are assigned based on  (prioirty of each task / prioirty of all tasks).
-fn assign_tokens(apic_id: u8) -> bool  {
-
-    let mut runqueue_locked = match RunQueue::get_runqueue(apic_id) {
-        Some(rq) => rq.write(),
-        _ => {
-            // #[cfg(not(loscd_eval))]
-            // error!("BUG: assign_tokens(): couldn't get runqueue for core {}", apic_id); 
-            return false;
-        }
-    };
-    
+fn assign_tokens(apic_id: u8, runqueue_locked: &mut RunQueue) -> bool  {
 
     // We begin with total priorities = 1 to avoid division by zero 
     let mut total_priorities :usize = 1;
@@ -164,13 +190,10 @@ fn assign_tokens(apic_id: u8) -> bool  {
             continue;
         }
 
-        // if this task is pinned, it must not be pinned to a different core
-        if let Some(pinned) = t.pinned_core() {
-            if pinned != apic_id {
-                // with per-core runqueues, this should never happen!
-                error!("select_next_task() (AP {}) found a task pinned to a different core: {:?}", apic_id, t);
-                return false;
-            }
+        // this task must be allowed to run on this core,
+        // otherwise it's waiting to be migrated away due to a change in its affinity
+        if !t.can_run_on(apic_id) {
+            continue;
         }
             
         // found a runnable task!
@@ -204,13 +227,10 @@ fn assign_tokens(apic_id: u8) -> bool  {
             continue;
         }
 
-        // if this task is pinned, it must not be pinned to a different core
-        if let Some(pinned) = t.pinned_core() {
-            if pinned != apic_id {
-                // with per-core runqueues, this should never happen!
-                error!("select_next_task() (AP {}) found a task pinned to a different core: {:?}", apic_id, &*t);
-                return false;
-            }
+        // this task must be allowed to run on this core,
+        // otherwise it's waiting to be migrated away due to a change in its affinity
+        if !t.can_run_on(apic_id) {
+            continue;
         }
         // task_tokens = epoch * (taskref + 1) / total_priorities;
         let task_tokens = epoch.saturating_mul((t.priority as usize).saturating_add(1)).wrapping_div(total_priorities);
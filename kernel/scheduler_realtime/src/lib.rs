@@ -6,52 +6,101 @@
 #![no_std]
 
 extern crate alloc;
-#[macro_use] extern crate log;
 extern crate task;
+extern crate runqueue;
 extern crate runqueue_realtime;
 
+use alloc::vec::Vec;
 use task::TaskRef;
+use runqueue::{RealtimeParams, Scheduler};
 use runqueue_realtime::RunQueue;
 
-/// Set the periodicity of a given `Task` in all `RunQueue` structures.
-/// A reexport of the set_periodicity function from runqueue_realtime
-pub use runqueue_realtime::set_periodicity;
-
-/// This defines the realtime scheduler policy.
-/// Returns None if there is no schedule-able task
-pub fn select_next_task(apic_id: u8) -> Option<TaskRef> {
-    let mut runqueue_locked = match RunQueue::get_runqueue(apic_id) {
-        Some(rq) => rq.write(),
-        _ => {
-            error!("BUG: select_next_task_round_robin(): couldn't get runqueue for core {}", apic_id);
-            return None;
-        }
-    };
-
-    let mut idle_task_index: Option<usize> = None;
-    let mut chosen_task_index: Option<usize> = None;
-    
-    for (i, taskref) in runqueue_locked.iter().enumerate() {
-        let t = taskref;
-
-        // we skip the idle task, and only choose it if no other tasks are runnable
-        if t.is_an_idle_task {
-            idle_task_index = Some(i);
-            continue;
+
+/// The realtime scheduler of a single core, which owns that core's `RunQueue`.
+pub struct RealtimeScheduler {
+    apic_id: u8,
+    runqueue: RunQueue,
+}
+
+impl RealtimeScheduler {
+    /// Creates a new realtime scheduler for the given core, which is an `apic_id`.
+    pub fn new(apic_id: u8) -> RealtimeScheduler {
+        RealtimeScheduler {
+            apic_id,
+            runqueue: RunQueue::new(apic_id),
         }
+    }
+}
+
+impl Scheduler for RealtimeScheduler {
+    fn name(&self) -> &'static str {
+        "realtime"
+    }
+
+    /// This defines the realtime scheduler policy.
+    /// Returns None if there is no schedule-able task
+    fn select_next_task(&mut self) -> Option<TaskRef> {
+        let mut idle_task_index: Option<usize> = None;
+        let mut chosen_task_index: Option<usize> = None;
+        
+        for (i, taskref) in self.runqueue.iter().enumerate() {
+            let t = taskref;
+
+            // we skip the idle task, and only choose it if no other tasks are runnable
+            if t.is_an_idle_task {
+                idle_task_index = Some(i);
+                continue;
+            }
+
+            // must be runnable
+            if !t.is_runnable() {
+                continue;
+            }
+
+            // must be allowed to run on this core,
+            // otherwise it's waiting to be migrated away due to a change in its affinity
+            if !t.can_run_on(self.apic_id) {
+                continue;
+            }
 
-        // must be runnable
-        if !t.is_runnable() {
-            continue;
+            // found a runnable task
+            chosen_task_index = Some(i);
+            break;
         }
 
-        // found a runnable task
-        chosen_task_index = Some(i);
-        break;
+        // idle task is backup iff no other task has been chosen
+        chosen_task_index
+            .or(idle_task_index)
+            .and_then(|index| self.runqueue.update_and_reinsert(index))
     }
 
-    // idle task is backup iff no other task has been chosen
-    chosen_task_index
-        .or(idle_task_index)
-        .and_then(|index| runqueue_locked.update_and_reinsert(index))
+    /// Adds the given task to this scheduler's `RunQueue` as an aperiodic task.
+    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {
+        self.runqueue.add_task(task, None)
+    }
+
+    fn remove_task(&mut self, task: &TaskRef) -> bool {
+        let len = self.runqueue.len();
+        let _ = self.runqueue.remove_task(task);
+        self.runqueue.len() != len
+    }
+
+    fn busyness(&self) -> usize {
+        self.runqueue.len()
+    }
+
+    fn tasks(&self) -> Vec<TaskRef> {
+        self.runqueue.iter().map(|t| TaskRef::clone(t)).collect()
+    }
+
+    fn drain(&mut self) -> Vec<TaskRef> {
+        self.runqueue.drain(..).map(|t| TaskRef::clone(&t)).collect()
+    }
+
+    /// Only the period of the task is used, in milliseconds,
+    /// because rate monotonic scheduling orders tasks by their periods.
+    fn set_realtime_params(&mut self, task: &TaskRef, params: RealtimeParams) -> Result<bool, &'static str> {
+        let period = params.period.as_millis().min(usize::MAX as u128) as usize;
+        Ok(self.runqueue.set_periodicity(task, period))
+    }
 }
@@ -5,50 +5,92 @@
 #![no_std]
 
 extern crate alloc;
-#[macro_use] extern crate log;
 extern crate task;
 extern crate runqueue;
 extern crate runqueue_round_robin;
 
+use alloc::vec::Vec;
 use task::TaskRef;
+use runqueue::Scheduler;
 use runqueue_round_robin::RunQueue;
 
 
-/// This defines the round robin scheduler policy.
-/// Returns None if there is no schedule-able task
-pub fn select_next_task(apic_id: u8) -> Option<TaskRef> {
+/// The round robin scheduler of a single core, which owns that core's `RunQueue`.
+pub struct RoundRobinScheduler {
+    apic_id: u8,
+    runqueue: RunQueue,
+}
 
-    let mut runqueue_locked = match RunQueue::get_runqueue(apic_id) {
-        Some(rq) => rq.write(),
-        _ => {
-            error!("BUG: select_next_task_round_robin(): couldn't get runqueue for core {}", apic_id); 
-            return None;
-        }
-    };
-    
-    let mut idle_task_index: Option<usize> = None;
-    let mut chosen_task_index: Option<usize> = None;
-
-    for (i, t) in runqueue_locked.iter().enumerate() {
-        // we skip the idle task, and only choose it if no other tasks are runnable
-        if t.is_an_idle_task {
-            idle_task_index = Some(i);
-            continue;
+impl RoundRobinScheduler {
+    /// Creates a new round robin scheduler for the given core, which is an `apic_id`.
+    pub fn new(apic_id: u8) -> RoundRobinScheduler {
+        RoundRobinScheduler {
+            apic_id,
+            runqueue: RunQueue::new(apic_id),
         }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self) -> &'static str {
+        "round robin"
+    }
+
+    /// This defines the round robin scheduler policy.
+    /// Returns None if there is no schedule-able task
+    fn select_next_task(&mut self) -> Option<TaskRef> {
+        let mut idle_task_index: Option<usize> = None;
+        let mut chosen_task_index: Option<usize> = None;
+
+        for (i, t) in self.runqueue.iter().enumerate() {
+            // we skip the idle task, and only choose it if no other tasks are runnable
+            if t.is_an_idle_task {
+                idle_task_index = Some(i);
+                continue;
+            }
 
-        // must be runnable
-        if !t.is_runnable() {
-            continue;
+            // must be runnable
+            if !t.is_runnable() {
+                continue;
+            }
+
+            // must be allowed to run on this core,
+            // otherwise it's waiting to be migrated away due to a change in its affinity
+            if !t.can_run_on(self.apic_id) {
+                continue;
+            }
+                
+            // found a runnable task!
+            chosen_task_index = Some(i);
+            // debug!("select_next_task(): AP {} chose Task {:?}", apic_id, &*t);
+            break;
         }
-            
-        // found a runnable task!
-        chosen_task_index = Some(i);
-        // debug!("select_next_task(): AP {} chose Task {:?}", apic_id, &*t);
-        break;
+
+        // idle task is a backup iff no other task has been chosen
+        chosen_task_index
+            .or(idle_task_index)
+            .and_then(|index| self.runqueue.move_to_end(index))
     }
 
-    // idle task is a backup iff no other task has been chosen
-    chosen_task_index
-        .or(idle_task_index)
-        .and_then(|index| runqueue_locked.move_to_end(index))
+    fn add_task(&mut self, task: TaskRef) -> Result<(), &'static str> {
+        self.runqueue.add_task(task)
+    }
+
+    fn remove_task(&mut self, task: &TaskRef) -> bool {
+        let len = self.runqueue.len();
+        let _ = self.runqueue.remove_task(task);
+        self.runqueue.len() != len
+    }
+
+    fn busyness(&self) -> usize {
+        self.runqueue.len()
+    }
+
+    fn tasks(&self) -> Vec<TaskRef> {
+        self.runqueue.iter().map(|t| TaskRef::clone(t)).collect()
+    }
+
+    fn drain(&mut self) -> Vec<TaskRef> {
+        self.runqueue.drain(..).map(|t| TaskRef::clone(&t)).collect()
+    }
 }
@@ -0,0 +1,260 @@
+//! IPv6 stateless address autoconfiguration (SLAAC), as described in RFC 4862.
+//!
+//! Each interface gets its own client task, which assigns the interface a link-local address
+//! derived from its MAC address and then solicits advertisements from the routers on its link.
+//! For each advertised prefix that allows autoconfiguration, the interface is assigned
+//! an additional address within that prefix, which is removed once its valid lifetime expires.
+//! A router that advertises itself as a default router becomes the interface's default IPv6 gateway.
+//!
+//! Duplicate address detection isn't performed, as all addresses are derived
+//! from the interface's MAC address, which is assumed to be unique on its link.
+
+#![no_std]
+
+extern crate alloc;
+
+mod message;
+
+use alloc::{format, sync::Arc, vec, vec::Vec};
+use log::{error, info, warn};
+use message::RouterAdvertisement;
+use net::{
+    raw,
+    wire::{IpCidr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr},
+    NetworkInterface,
+};
+use task::JoinableTaskRef;
+use time::{Duration, Instant, Monotonic};
+
+/// The length of the prefixes that addresses can be autoconfigured from,
+/// as the remaining 64 bits of each address are its interface identifier.
+pub const PREFIX_LEN: u8 = 64;
+
+/// How many router solicitations are sent before waiting for unsolicited advertisements.
+const MAX_ROUTER_SOLICITATIONS: usize = 3;
+/// How long to wait for an advertisement before sending another router solicitation.
+const ROUTER_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+/// An unauthenticated advertisement can't shorten the remaining lifetime of an address below this.
+const MIN_UPDATED_VALID_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// How often the client checks its socket for advertisements and lifetimes for expiry.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The maximum number of packets queued in the socket's receive buffer.
+const RX_PACKET_CAPACITY: usize = 8;
+/// The size of the socket's receive buffer, which fits the maximum number of full-sized packets.
+const RX_BUFFER_SIZE: usize = RX_PACKET_CAPACITY * 1500;
+/// The size of the socket's transmit buffer, which fits a router solicitation.
+const TX_BUFFER_SIZE: usize = 64;
+
+/// An address that was autoconfigured from an advertised prefix.
+struct AutoconfiguredAddress {
+    address: Ipv6Cidr,
+    /// When the address is no longer valid, or `None` if it never expires.
+    expires: Option<Instant>,
+}
+
+/// The router that the interface uses as its default IPv6 gateway.
+struct DefaultRouter {
+    address: Ipv6Address,
+    expires: Instant,
+}
+
+/// The autoconfiguration state of an interface.
+struct Client {
+    interface: Arc<NetworkInterface>,
+    mac: [u8; 6],
+    addresses: Vec<AutoconfiguredAddress>,
+    router: Option<DefaultRouter>,
+}
+
+/// Returns the link-local address of the interface with the given `mac` address.
+pub fn link_local_address(mac: [u8; 6]) -> Ipv6Cidr {
+    let prefix = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+    Ipv6Cidr::new(address_in_prefix(prefix, mac), PREFIX_LEN)
+}
+
+/// Returns the address with the given `prefix` and the interface identifier derived from `mac`.
+fn address_in_prefix(prefix: Ipv6Address, mac: [u8; 6]) -> Ipv6Address {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&prefix.as_bytes()[..8]);
+    bytes[8..].copy_from_slice(&interface_identifier(mac));
+    Ipv6Address::from_bytes(&bytes)
+}
+
+/// Returns the modified EUI-64 interface identifier derived from `mac`, as defined in RFC 4291.
+fn interface_identifier(mac: [u8; 6]) -> [u8; 8] {
+    // The universal/local bit is inverted, so that locally-administered MAC addresses
+    // like `02:00:00:00:00:01` result in short identifiers like `::ff:fe00:1`.
+    [mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+}
+
+/// Spawns a SLAAC client task for the given `interface`.
+///
+/// The task runs for as long as the interface exists.
+pub fn start(interface: Arc<NetworkInterface>) -> Result<JoinableTaskRef, &'static str> {
+    let name = format!("slaac_{:#X}", Arc::as_ptr(&interface) as usize);
+    spawn::new_task_builder(slaac_task, interface)
+        .name(name)
+        .spawn()
+}
+
+/// Spawns a SLAAC client task for every network interface registered with the `net` crate.
+pub fn start_all() -> Result<Vec<JoinableTaskRef>, &'static str> {
+    let interfaces = net::get_interfaces().lock().clone();
+    interfaces.into_iter().map(start).collect()
+}
+
+/// The entry point of a SLAAC client task.
+fn slaac_task(interface: Arc<NetworkInterface>) {
+    let mac = interface.mac_address();
+    let link_local = link_local_address(mac);
+    if let Err(e) = interface.add_ip_addr(IpCidr::Ipv6(link_local)) {
+        error!("slaac: couldn't assign link-local address {}: {}", link_local, e);
+        return;
+    }
+    info!("slaac: assigned link-local address {}", link_local);
+
+    let socket = interface.add_socket(raw::Socket::new(
+        IpVersion::Ipv6,
+        IpProtocol::Icmpv6,
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; RX_PACKET_CAPACITY], vec![0; RX_BUFFER_SIZE]),
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY], vec![0; TX_BUFFER_SIZE]),
+    ));
+    let mut client = Client {
+        interface: interface.clone(),
+        mac,
+        addresses: Vec::new(),
+        router: None,
+    };
+    let mut solicitations_sent = 0;
+    let mut next_solicitation = time::now::<Monotonic>();
+
+    loop {
+        let now = time::now::<Monotonic>();
+        if client.router.is_none() && solicitations_sent < MAX_ROUTER_SOLICITATIONS && now >= next_solicitation {
+            let packet = message::router_solicitation(link_local.address(), mac);
+            if socket.lock().send_slice(&packet).is_err() {
+                warn!("slaac: couldn't send router solicitation");
+            }
+            solicitations_sent += 1;
+            next_solicitation = now + ROUTER_SOLICITATION_INTERVAL;
+        }
+
+        // The interface's worker task polls the interface once the socket is unlocked,
+        // so received packets are copied out of the socket before they're processed.
+        let mut packets = Vec::new();
+        {
+            let mut socket = socket.lock();
+            while let Ok(packet) = socket.recv() {
+                packets.push(packet.to_vec());
+            }
+        }
+        for packet in packets {
+            if let Some(advertisement) = message::parse_router_advertisement(&packet) {
+                client.process_advertisement(&advertisement, now);
+            }
+        }
+
+        if client.expire(now) {
+            // The default router is gone, so solicit a new one.
+            solicitations_sent = 0;
+        }
+
+        if let Err(e) = sleep::sleep(POLL_INTERVAL) {
+            error!("slaac: couldn't sleep, run state: {:?}", e);
+            return;
+        }
+    }
+}
+
+impl Client {
+    /// Updates the interface's default router and addresses according to the given `advertisement`.
+    fn process_advertisement(&mut self, advertisement: &RouterAdvertisement, now: Instant) {
+        let is_current_router = self
+            .router
+            .as_ref()
+            .map_or(false, |router| router.address == advertisement.router);
+        if advertisement.router_lifetime.is_zero() {
+            if is_current_router {
+                info!("slaac: {} is no longer a default router", advertisement.router);
+                self.interface.set_ipv6_gateway(None);
+                self.router = None;
+            }
+        } else if is_current_router || self.router.is_none() {
+            if !is_current_router {
+                info!("slaac: using default router {}", advertisement.router);
+                self.interface.set_ipv6_gateway(Some(advertisement.router));
+            }
+            self.router = Some(DefaultRouter {
+                address: advertisement.router,
+                expires: now + advertisement.router_lifetime,
+            });
+        }
+
+        for prefix in &advertisement.prefixes {
+            if !prefix.autonomous || prefix.prefix_len != PREFIX_LEN || prefix.prefix.is_link_local() {
+                continue;
+            }
+            let address = Ipv6Cidr::new(address_in_prefix(prefix.prefix, self.mac), PREFIX_LEN);
+            match self.addresses.iter_mut().find(|a| a.address == address) {
+                Some(existing) => {
+                    existing.expires = updated_expiry(existing.expires, prefix.valid_lifetime, now);
+                }
+                None if prefix.valid_lifetime.map_or(true, |lifetime| !lifetime.is_zero()) => {
+                    if let Err(e) = self.interface.add_ip_addr(IpCidr::Ipv6(address)) {
+                        warn!("slaac: couldn't assign address {}: {}", address, e);
+                        continue;
+                    }
+                    info!("slaac: assigned address {}", address);
+                    self.addresses.push(AutoconfiguredAddress {
+                        address,
+                        expires: prefix.valid_lifetime.map(|lifetime| now + lifetime),
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Removes the addresses and default router whose lifetimes have expired.
+    ///
+    /// Returns `true` if the default router was removed.
+    fn expire(&mut self, now: Instant) -> bool {
+        let interface = &self.interface;
+        self.addresses.retain(|a| match a.expires {
+            Some(expires) if now >= expires => {
+                info!("slaac: address {} expired", a.address);
+                interface.remove_ip_addr(IpCidr::Ipv6(a.address));
+                false
+            }
+            _ => true,
+        });
+
+        match self.router {
+            Some(ref router) if now >= router.expires => {
+                info!("slaac: default router {} expired", router.address);
+                self.interface.set_ipv6_gateway(None);
+                self.router = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns when an existing address expires after an advertisement with the given `valid_lifetime`.
+///
+/// As required by RFC 4862 section 5.5.3, an advertisement can't shorten
+/// the address's remaining lifetime below [`MIN_UPDATED_VALID_LIFETIME`],
+/// so that forged advertisements can't invalidate addresses.
+fn updated_expiry(expires: Option<Instant>, valid_lifetime: Option<Duration>, now: Instant) -> Option<Instant> {
+    let valid_lifetime = valid_lifetime?;
+    let remaining = expires.map(|expires| expires.duration_since(now));
+    match remaining {
+        _ if valid_lifetime > MIN_UPDATED_VALID_LIFETIME => Some(now + valid_lifetime),
+        Some(remaining) if valid_lifetime > remaining => Some(now + valid_lifetime),
+        Some(remaining) if remaining <= MIN_UPDATED_VALID_LIFETIME => expires,
+        _ => Some(now + MIN_UPDATED_VALID_LIFETIME),
+    }
+}
@@ -0,0 +1,158 @@
+//! Building router solicitations and parsing router advertisements,
+//! the neighbor discovery messages defined in RFC 4861 that SLAAC relies on.
+
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+use net::wire::Ipv6Address;
+
+const PROTOCOL_ICMPV6: u8 = 58;
+const IPV6_HEADER_LEN: usize = 40;
+
+const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+/// The length of a router advertisement without its options.
+const ROUTER_ADVERTISEMENT_LEN: usize = 16;
+
+const OPTION_SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const PREFIX_INFORMATION_LEN: usize = 32;
+/// The flag of a prefix information option that allows addresses to be autoconfigured from it.
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// Neighbor discovery messages must be sent with this hop limit,
+/// which shows that they weren't forwarded by a router.
+const NDISC_HOP_LIMIT: u8 = 255;
+/// The lifetime that never expires.
+const INFINITE_LIFETIME: u32 = 0xFFFF_FFFF;
+
+/// The contents of a router advertisement that are used for autoconfiguration.
+pub(crate) struct RouterAdvertisement {
+    /// The link-local address of the router that sent the advertisement.
+    pub(crate) router: Ipv6Address,
+    /// How long the router can be used as a default router, which is zero if it isn't one.
+    pub(crate) router_lifetime: Duration,
+    pub(crate) prefixes: Vec<PrefixInformation>,
+}
+
+/// A prefix advertised by a router.
+pub(crate) struct PrefixInformation {
+    pub(crate) prefix: Ipv6Address,
+    pub(crate) prefix_len: u8,
+    /// Whether addresses can be autoconfigured from this prefix.
+    pub(crate) autonomous: bool,
+    /// How long addresses from this prefix remain valid, or `None` if they never expire.
+    pub(crate) valid_lifetime: Option<Duration>,
+}
+
+/// Returns an IPv6 packet that solicits advertisements from all routers on the link.
+pub(crate) fn router_solicitation(source: Ipv6Address, mac: [u8; 6]) -> Vec<u8> {
+    let destination = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+    // The type, code, checksum, and reserved fields, followed by the source link-layer address option.
+    let mut message = vec![ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&[OPTION_SOURCE_LINK_LAYER_ADDRESS, 1]);
+    message.extend_from_slice(&mac);
+    let checksum = checksum(&source, &destination, &message);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut packet = Vec::with_capacity(IPV6_HEADER_LEN + message.len());
+    packet.extend_from_slice(&[0x60, 0, 0, 0]); // version 6, without a traffic class or flow label
+    packet.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    packet.push(PROTOCOL_ICMPV6);
+    packet.push(NDISC_HOP_LIMIT);
+    packet.extend_from_slice(source.as_bytes());
+    packet.extend_from_slice(destination.as_bytes());
+    packet.extend_from_slice(&message);
+    packet
+}
+
+/// Parses the given IPv6 `packet` as a router advertisement.
+///
+/// Returns `None` if the packet isn't a valid router advertisement, as defined in RFC 4861 section 6.1.2.
+pub(crate) fn parse_router_advertisement(packet: &[u8]) -> Option<RouterAdvertisement> {
+    let header = packet.get(..IPV6_HEADER_LEN)?;
+    let payload_len = usize::from(read_u16(header, 4)?);
+    let message = packet.get(IPV6_HEADER_LEN..IPV6_HEADER_LEN + payload_len)?;
+    let source = Ipv6Address::from_bytes(&header[8..24]);
+    let destination = Ipv6Address::from_bytes(&header[24..40]);
+
+    if header[0] >> 4 != 6 || header[6] != PROTOCOL_ICMPV6 || header[7] != NDISC_HOP_LIMIT || !source.is_link_local() {
+        return None;
+    }
+    if message.len() < ROUTER_ADVERTISEMENT_LEN
+        || message[0] != ICMPV6_ROUTER_ADVERTISEMENT
+        || message[1] != 0
+        || checksum(&source, &destination, message) != 0
+    {
+        return None;
+    }
+
+    let router_lifetime = Duration::from_secs(read_u16(message, 6)?.into());
+    let mut prefixes = Vec::new();
+    let mut options = &message[ROUTER_ADVERTISEMENT_LEN..];
+    while !options.is_empty() {
+        // An option's length is given in units of 8 bytes, and options without a length are invalid.
+        let len = usize::from(*options.get(1)?) * 8;
+        if len == 0 || len > options.len() {
+            return None;
+        }
+        let (option, rest) = options.split_at(len);
+        if option[0] == OPTION_PREFIX_INFORMATION && len == PREFIX_INFORMATION_LEN {
+            let valid_lifetime = read_u32(option, 4)?;
+            let preferred_lifetime = read_u32(option, 8)?;
+            // Prefixes whose addresses would be preferred after they're no longer valid are ignored.
+            if preferred_lifetime <= valid_lifetime {
+                prefixes.push(PrefixInformation {
+                    prefix: Ipv6Address::from_bytes(&option[16..32]),
+                    prefix_len: option[2],
+                    autonomous: option[3] & PREFIX_FLAG_AUTONOMOUS != 0,
+                    valid_lifetime: (valid_lifetime != INFINITE_LIFETIME)
+                        .then(|| Duration::from_secs(valid_lifetime.into())),
+                });
+            }
+        }
+        options = rest;
+    }
+
+    Some(RouterAdvertisement {
+        router: source,
+        router_lifetime,
+        prefixes,
+    })
+}
+
+/// Computes the ICMPv6 checksum of `message`, including the IPv6 pseudo-header.
+///
+/// The result is zero if `message` already contains a valid checksum.
+fn checksum(source: &Ipv6Address, destination: &Ipv6Address, message: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut add = |bytes: &[u8]| {
+        for chunk in bytes.chunks(2) {
+            let word = match *chunk {
+                [high, low] => u16::from_be_bytes([high, low]),
+                [high] => u16::from_be_bytes([high, 0]),
+                _ => 0,
+            };
+            sum += u32::from(word);
+        }
+    };
+    add(source.as_bytes());
+    add(destination.as_bytes());
+    add(&(message.len() as u32).to_be_bytes());
+    add(&[0, 0, 0, PROTOCOL_ICMPV6]);
+    add(message);
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
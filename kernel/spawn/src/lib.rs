@@ -37,6 +37,7 @@ use path::Path;
 use fs_node::FileOrDir;
 use preemption::{hold_preemption, PreemptionGuard};
 use no_drop::NoDrop;
+use cpu::CpuSet;
 
 #[cfg(simd_personality)]
 use task::SimdExt;
@@ -49,7 +50,7 @@ pub fn init(
     apic_id: u8,
     stack: NoDrop<Stack>,
 ) -> Result<BootstrapTaskRef, &'static str> {
-    runqueue::init(apic_id)?;
+    scheduler::init(apic_id)?;
     
     let (joinable_bootstrap_task, exitable_bootstrap_task) =
         task::bootstrap_task(apic_id, stack, kernel_mmi_ref)?;
@@ -266,7 +267,7 @@ pub struct TaskBuilder<F, A, R> {
     name: Option<String>,
     stack: Option<Stack>,
     parent: Option<TaskRef>,
-    pin_on_core: Option<u8>,
+    affinity: Option<CpuSet>,
     blocked: bool,
     idle: bool,
     post_build_function: Option<Box< dyn FnOnce(&mut Task) -> Result<(), &'static str> >>,
@@ -290,7 +291,7 @@ impl<F, A, R> TaskBuilder<F, A, R>
             name: None,
             stack: None,
             parent: None,
-            pin_on_core: None,
+            affinity: None,
             blocked: false,
             idle: false,
             post_build_function: None,
@@ -328,8 +329,18 @@ impl<F, A, R> TaskBuilder<F, A, R>
     }
 
     /// Pin the new Task to a specific core.
+    ///
+    /// This is equivalent to setting the new Task's [`affinity`](Self::affinity) to only that core.
     pub fn pin_on_core(mut self, core_apic_id: u8) -> TaskBuilder<F, A, R> {
-        self.pin_on_core = Some(core_apic_id);
+        self.affinity = Some(CpuSet::single(core_apic_id));
+        self
+    }
+
+    /// Set the set of cores that the new Task may run on, i.e., its CPU affinity.
+    ///
+    /// By default, a new Task may run on any core.
+    pub fn affinity(mut self, affinity: CpuSet) -> TaskBuilder<F, A, R> {
+        self.affinity = Some(affinity);
         self
     }
 
@@ -391,6 +402,14 @@ impl<F, A, R> TaskBuilder<F, A, R>
             new_task.is_an_idle_task = true;
         }
 
+        // Record the cores that the new task may run on, such that it is never migrated to any other core.
+        if let Some(affinity) = self.affinity {
+            if affinity.is_empty() {
+                return Err("the new task's CPU affinity doesn't contain any cores");
+            }
+            new_task.inner_mut().affinity = affinity;
+        }
+
         // If there is a post-build function, invoke it now
         // before finalizing the task and adding it to runqueues.
         if let Some(pb_func) = self.post_build_function {
@@ -418,11 +437,8 @@ impl<F, A, R> TaskBuilder<F, A, R>
         // (in `spawn::task_cleanup_final_internal()`).
         fence(Ordering::Release);
         
-        if let Some(core) = self.pin_on_core {
-            runqueue::add_task_to_specific_runqueue(core, task_ref.clone())?;
-        } else {
-            runqueue::add_task_to_any_runqueue(task_ref.clone())?;
-        }
+        // This chooses a runqueue of a core within the task's affinity.
+        runqueue::add_task_to_any_runqueue(task_ref.clone())?;
 
         Ok(task_ref)
 
@@ -951,12 +967,10 @@ where
         });
 
         if let Some((func, arg)) = restartable_info {
-            let mut new_task = new_task_builder(func, arg)
-                .name(current_task.name.clone());
-            if let Some(core) = current_task.pinned_core() {
-                new_task = new_task.pin_on_core(core);
-            }
-            new_task.spawn_restartable(None)
+            new_task_builder(func, arg)
+                .name(current_task.name.clone())
+                .affinity(current_task.affinity())
+                .spawn_restartable(None)
                 .expect("Failed to respawn the restartable task");
         } else {
             error!("BUG: Restartable task has no restart information available");
@@ -982,13 +996,10 @@ fn remove_current_task_from_runqueue(current_task: &ExitableTaskRef) {
         runqueue::remove_task_from_all(current_task).unwrap();
     }
 
-    // In the regular case, we do not perform task migration between cores,
-    // so we can use the heuristic that the task is only on the current core's runqueue.
+    // In the regular case, a task is only migrated between cores while it isn't running,
+    // so the current task can only be on the current core's runqueue.
     #[cfg(not(rq_eval))] {
-        if let Err(e) = runqueue::get_runqueue(cpu::current_cpu())
-            .ok_or("couldn't get this core's ID or runqueue to remove exited task from it")
-            .and_then(|rq| rq.write().remove_task(current_task)) 
-        {
+        if let Err(e) = runqueue::remove_task_from_specific_runqueue(cpu::current_cpu(), current_task) {
             error!("BUG: couldn't remove exited task from runqueue: {}", e);
         }
     }
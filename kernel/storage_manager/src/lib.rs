@@ -3,26 +3,39 @@
 
 #![no_std]
 
-extern crate alloc;
+#[macro_use] extern crate alloc;
 #[macro_use] extern crate log;
 extern crate spin;
 extern crate pci;
 extern crate ata;
+extern crate ahci;
+extern crate virtio_blk;
 extern crate storage_device;
+extern crate block_cache;
 
 use alloc::{
+    string::String,
     vec::Vec,
     sync::Arc,
 };
 use spin::Mutex;
 use pci::PciDevice;
 use storage_device::StorageControllerRef;
+use block_cache::{BlockCache, BlockCacheRef, CachedStorageDevice};
 
 pub use storage_device::*;
 
+/// The prefix of the names given to storage devices, e.g., `disk0`, `disk1`.
+/// The number following this prefix is the index of that device in [`storage_devices()`].
+pub const STORAGE_DEVICE_NAME_PREFIX: &str = "disk";
+
 /// A list of all of the available and initialized storage controllers that exist on this system.
 static STORAGE_CONTROLLERS: Mutex<Vec<StorageControllerRef>> = Mutex::new(Vec::new());
 
+/// The block caches of storage devices, at most one per device,
+/// which are shared by all users of [`cached_storage_device()`].
+static BLOCK_CACHES: Mutex<Vec<(StorageDeviceRef, BlockCacheRef)>> = Mutex::new(Vec::new());
+
 /// Returns an iterator over all initialized storage controllers on this system.
 /// 
 /// This function requires allocation, as it currently clones the list of storage controllers,\
@@ -44,6 +57,65 @@ pub fn storage_devices() -> impl Iterator<Item = StorageDeviceRef> {
     )
 }
 
+/// Returns the storage device with the given `name`, e.g., `disk0`.
+///
+/// See [`STORAGE_DEVICE_NAME_PREFIX`] for how storage devices are named.
+pub fn storage_device_by_name(name: &str) -> Option<StorageDeviceRef> {
+    let index = name.strip_prefix(STORAGE_DEVICE_NAME_PREFIX)?
+        .parse::<usize>()
+        .ok()?;
+    storage_devices().nth(index)
+}
+
+
+/// Returns the block cache of the given storage device, creating it if it doesn't yet exist.
+///
+/// All callers that pass the same device receive the same cache.
+pub fn block_cache(storage_device: &StorageDeviceRef) -> BlockCacheRef {
+    let mut caches = BLOCK_CACHES.lock();
+    if let Some((_, cache)) = caches.iter().find(|(sd, _)| Arc::ptr_eq(sd, storage_device)) {
+        return Arc::clone(cache);
+    }
+    let cache = Arc::new(Mutex::new(BlockCache::new(Arc::clone(storage_device))));
+    caches.push((Arc::clone(storage_device), Arc::clone(&cache)));
+    cache
+}
+
+/// Returns a `StorageDevice` that accesses the given storage device through its shared block cache.
+///
+/// This should be preferred over accessing the storage device directly,
+/// as direct accesses bypass (and may be inconsistent with) the cache.
+pub fn cached_storage_device(storage_device: &StorageDeviceRef) -> StorageDeviceRef {
+    Arc::new(Mutex::new(CachedStorageDevice::new(block_cache(storage_device))))
+}
+
+/// Returns all existing block caches along with the name of their storage device, e.g., `disk0`.
+pub fn block_caches() -> Vec<(String, BlockCacheRef)> {
+    let caches = BLOCK_CACHES.lock().clone();
+    storage_devices()
+        .enumerate()
+        .filter_map(|(i, sd)| caches.iter()
+            .find(|(cached_sd, _)| Arc::ptr_eq(cached_sd, &sd))
+            .map(|(_, cache)| (format!("{STORAGE_DEVICE_NAME_PREFIX}{i}"), Arc::clone(cache)))
+        )
+        .collect()
+}
+
+/// Writes back all dirty blocks in every block cache to their storage devices.
+///
+/// All caches are flushed even if flushing one of them fails, in which case the last error is returned.
+pub fn flush_block_caches() -> Result<(), &'static str> {
+    let caches: Vec<BlockCacheRef> = BLOCK_CACHES.lock().iter().map(|(_, cache)| Arc::clone(cache)).collect();
+    let mut result = Ok(());
+    for cache in caches {
+        if let Err(e) = cache.lock().flush(None) {
+            error!("Failed to flush block cache: {}", e);
+            result = Err(e);
+        }
+    }
+    result
+}
+
 
 /// Attempts to handle the initialization of the given `PciDevice`,
 /// if it is a recognized storage device.
@@ -53,7 +125,8 @@ pub fn storage_devices() -> impl Iterator<Item = StorageDeviceRef> {
 /// * `Ok(None)` if the given `PciDevice` isn't a supported storage device,
 /// * An error if it fails to initialize a supported storage device.
 pub fn init_device(pci_device: &PciDevice) -> Result<Option<StorageControllerRef>, &'static str> {
-    // We currently only support IDE controllers for ATA drives (aka PATA).
+    // We currently support IDE controllers for ATA drives (aka PATA), AHCI controllers for SATA drives,
+    // and virtio block devices.
     let storage_controller = if pci_device.class == 0x01 && pci_device.subclass == 0x01 {
         info!("IDE controller PCI device found at: {:?}", pci_device.location);
         let ide_controller = ata::IdeController::new(pci_device)?;
@@ -61,6 +134,23 @@ pub fn init_device(pci_device: &PciDevice) -> Result<Option<StorageControllerRef
         STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
         Some(storage_controller_ref)
     } 
+    else if pci_device.class == ahci::AHCI_PCI_CLASS
+        && pci_device.subclass == ahci::AHCI_PCI_SUBCLASS
+        && pci_device.prog_if == ahci::AHCI_PCI_PROG_IF
+    {
+        info!("AHCI controller PCI device found at: {:?}", pci_device.location);
+        let ahci_controller = ahci::AhciController::new(pci_device)?;
+        let storage_controller_ref: StorageControllerRef = Arc::new(Mutex::new(ahci_controller));
+        STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
+        Some(storage_controller_ref)
+    }
+    else if virtio_blk::is_virtio_blk(pci_device) {
+        info!("virtio block PCI device found at: {:?}", pci_device.location);
+        let virtio_blk_controller = virtio_blk::VirtioBlkController::new(pci_device)?;
+        let storage_controller_ref: StorageControllerRef = Arc::new(Mutex::new(virtio_blk_controller));
+        STORAGE_CONTROLLERS.lock().push(Arc::clone(&storage_controller_ref));
+        Some(storage_controller_ref)
+    }
     // Here: in the future, handle other supported storage devices
     else {
         None
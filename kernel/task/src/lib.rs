@@ -48,6 +48,7 @@ extern crate spin;
 extern crate kernel_config;
 extern crate crossbeam_utils;
 extern crate no_drop;
+extern crate tsc;
 
 
 use core::{
@@ -56,8 +57,9 @@ use core::{
     hash::{Hash, Hasher},
     ops::Deref,
     panic::PanicInfo,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, fence},
     task::Waker,
+    time::Duration,
 };
 use alloc::{
     boxed::Box,
@@ -76,6 +78,7 @@ use spin::Mutex;
 use x86_64::registers::model_specific::FsBase;
 use preemption::PreemptionGuard;
 use no_drop::NoDrop;
+use cpu::CpuSet;
 
 /// The function signature of the callback that will be invoked
 /// when a given Task panics or otherwise fails, e.g., a machine exception occurs.
@@ -298,9 +301,9 @@ pub struct TaskInner {
     drop_after_task_switch: Option<TaskRef>,
     /// The kernel stack, which all `Task`s must have in order to execute.
     pub kstack: Stack,
-    /// Whether or not this task is pinned to a certain core.
+    /// The set of cores that this task may run on, i.e., its CPU affinity.
     /// The idle tasks are always pinned to their respective cores.
-    pub pinned_core: Option<u8>,
+    pub affinity: CpuSet,
     /// The function that will be called when this `Task` panics or fails due to a machine exception.
     /// It will be invoked before the task is cleaned up via stack unwinding.
     /// This is similar to Rust's built-in panic hook, but is also called upon a machine exception, not just a panic.
@@ -360,6 +363,21 @@ pub struct Task {
     /// 
     /// This is not public because it permits interior mutability.
     joinable: AtomicBool,
+    /// The total number of TSC ticks that this task has run for,
+    /// excluding its current run if it is currently running.
+    ///
+    /// This is not public because it permits interior mutability.
+    cpu_time: AtomicU64,
+    /// The TSC value at which this task last started running on a CPU,
+    /// or zero if it has never run.
+    ///
+    /// This is not public because it permits interior mutability.
+    last_switched_in: AtomicU64,
+    /// A sequence number that is odd while `cpu_time`, `last_switched_in`, and `running_on_cpu`
+    /// are being updated as this task is switched in or out, see [`Task::update_run_time()`].
+    ///
+    /// This is not public because it permits interior mutability.
+    run_time_seq: AtomicU64,
     /// Memory management details: page tables, mappings, allocators, etc.
     /// This is shared among all other tasks in the same address space.
     pub mmi: MmiRef, 
@@ -399,9 +417,9 @@ impl fmt::Debug for Task {
             .field("running_on", &self.running_on_cpu())
             .field("runstate", &self.runstate());
         if let Some(inner) = self.inner.try_lock() {
-            ds.field("pinned", &inner.pinned_core);
+            ds.field("affinity", &inner.affinity);
         } else {
-            ds.field("pinned", &"<Locked>");
+            ds.field("affinity", &"<Locked>");
         }
         ds.finish()
     }
@@ -491,7 +509,7 @@ impl Task {
                 preemption_guard: None,
                 drop_after_task_switch: None,
                 kstack,
-                pinned_core: None,
+                affinity: CpuSet::all(),
                 kill_handler: None,
                 env,
                 restart_info: None,
@@ -504,6 +522,9 @@ impl Task {
             suspended: AtomicBool::new(false),
             // Tasks are not considered "joinable" until passed to `TaskRef::new()`
             joinable: AtomicBool::new(false),
+            cpu_time: AtomicU64::new(0),
+            last_switched_in: AtomicU64::new(0),
+            run_time_seq: AtomicU64::new(0),
             mmi,
             is_an_idle_task: false,
             app_crate,
@@ -559,9 +580,94 @@ impl Task {
     }
 
     /// Returns the APIC ID of the CPU this `Task` is pinned on,
-    /// or `None` if it is not pinned.
+    /// or `None` if it is not pinned, i.e., if its affinity isn't a single CPU.
     pub fn pinned_core(&self) -> Option<u8> {
-        self.inner.lock().pinned_core
+        self.inner.lock().affinity.single_cpu()
+    }
+
+    /// Returns the set of CPUs that this `Task` may run on.
+    pub fn affinity(&self) -> CpuSet {
+        self.inner.lock().affinity
+    }
+
+    /// Returns whether this `Task` may run on the CPU with the given APIC ID.
+    pub fn can_run_on(&self, apic_id: u8) -> bool {
+        self.inner.lock().affinity.contains(apic_id)
+    }
+
+    /// Sets the set of CPUs that this `Task` may run on.
+    ///
+    /// This does not move the task to a runqueue of a CPU in the new set;
+    /// use `runqueue::set_affinity()` to change the affinity of a task that has already been spawned.
+    pub fn set_affinity(&self, affinity: CpuSet) {
+        self.inner.lock().affinity = affinity;
+    }
+
+    /// Returns the total CPU time that this `Task` has consumed, in TSC ticks.
+    ///
+    /// If this `Task` is currently running, its current run is included.
+    pub fn cpu_time_ticks(&self) -> u64 {
+        // Retry until the fields weren't changed by a task switch while they were read,
+        // so that the current run is neither counted twice nor counted from an earlier switch-in time.
+        loop {
+            let seq = self.run_time_seq.load(Ordering::Acquire);
+            if seq % 2 == 0 {
+                let cpu_time = self.cpu_time.load(Ordering::Relaxed);
+                let last_switched_in = self.last_switched_in.load(Ordering::Relaxed);
+                let is_running = self.is_running();
+                fence(Ordering::Acquire);
+                if self.run_time_seq.load(Ordering::Relaxed) == seq {
+                    return if is_running && last_switched_in != 0 {
+                        cpu_time + current_tsc().saturating_sub(last_switched_in)
+                    } else {
+                        cpu_time
+                    };
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Returns the total CPU time that this `Task` has consumed.
+    ///
+    /// If this `Task` is currently running, its current run is included.
+    /// Returns `None` if the TSC frequency is unknown.
+    pub fn cpu_time(&self) -> Option<Duration> {
+        ticks_to_duration(self.cpu_time_ticks())
+    }
+
+    /// Marks this `Task` as no longer running and adds the time since it was last switched in
+    /// to its total CPU time, as it's being switched out at the given `now` TSC value.
+    fn switch_out(&self, now: u64) {
+        self.update_run_time(|| {
+            let last_switched_in = self.last_switched_in.load(Ordering::Relaxed);
+            // A task that never started running has no run time to account for.
+            if last_switched_in != 0 {
+                self.cpu_time.fetch_add(now.saturating_sub(last_switched_in), Ordering::Relaxed);
+            }
+            self.running_on_cpu.store(None.into());
+        });
+    }
+
+    /// Marks this `Task` as running on the given CPU since the given `now` TSC value.
+    fn switch_in(&self, apic_id: u8, now: u64) {
+        self.update_run_time(|| {
+            self.last_switched_in.store(now, Ordering::Relaxed);
+            self.running_on_cpu.store(Some(apic_id).into());
+        });
+    }
+
+    /// Performs the given `update` of the fields that [`Task::cpu_time_ticks()`] reads,
+    /// such that readers never observe only part of it.
+    ///
+    /// Interrupts must be disabled, as readers on the same CPU would otherwise spin forever.
+    /// A task is only ever switched in or out by one CPU at a time, so there is only one writer.
+    fn update_run_time<F: FnOnce()>(&self, update: F) {
+        let seq = self.run_time_seq.load(Ordering::Relaxed);
+        self.run_time_seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        update();
+        self.run_time_seq.store(seq.wrapping_add(2), Ordering::Release);
     }
 
     /// Returns the current [`RunState`] of this `Task`.
@@ -1055,8 +1161,12 @@ fn task_switch_inner(
         inner.saved_sp
     };
 
-    // Mark the current task as no longer running
-    curr.running_on_cpu.store(None.into());
+    // Charge the current task for the time it has run and mark it as no longer running.
+    let now = current_tsc();
+    {
+        let _held_interrupts = hold_interrupts();
+        curr.switch_out(now);
+    }
 
     // After this point, we may need to mutate the `curr_task_tls_slot` (if curr has exited),
     // so we use local variables to store some necessary info about the curr task
@@ -1088,7 +1198,8 @@ fn task_switch_inner(
     // in task runstates, e.g., when an interrupt handler accesses the current task context.
     {
         let _held_interrupts = hold_interrupts();
-        next.running_on_cpu.store(Some(apic_id).into());
+        // Start the next task's run at the same time the current task's run ended.
+        next.switch_in(apic_id, now);
         next.set_as_current_task();
         drop(_held_interrupts);
     }
@@ -1439,6 +1550,37 @@ impl Deref for TaskRef {
 // }
 
 
+/// Returns the total time that the given CPU has spent running its idle task, in TSC ticks.
+///
+/// Returns `None` if the given CPU has no idle task.
+pub fn cpu_idle_time_ticks(apic_id: u8) -> Option<u64> {
+    TASKLIST.lock()
+        .values()
+        .find(|t| t.is_an_idle_task && t.pinned_core() == Some(apic_id))
+        .map(|t| t.cpu_time_ticks())
+}
+
+/// Returns the total time that the given CPU has spent running its idle task.
+///
+/// Returns `None` if the given CPU has no idle task or if the TSC frequency is unknown.
+pub fn cpu_idle_time(apic_id: u8) -> Option<Duration> {
+    cpu_idle_time_ticks(apic_id).and_then(ticks_to_duration)
+}
+
+/// Converts the given number of TSC ticks into a `Duration`.
+///
+/// Returns `None` if the TSC frequency is unknown.
+pub fn ticks_to_duration(ticks: u64) -> Option<Duration> {
+    let freq = tsc::get_tsc_frequency().ok()?;
+    let nanos = u128::from(ticks) * 1_000_000_000 / freq;
+    Some(Duration::from_nanos(nanos as u64))
+}
+
+fn current_tsc() -> u64 {
+    u128::from(tsc::tsc_ticks()) as u64
+}
+
+
 /// Bootstrap a new task from the current thread of execution.
 ///
 /// Returns a tuple of:
@@ -1470,7 +1612,8 @@ pub fn bootstrap_task(
     bootstrap_task.name = format!("bootstrap_task_core_{apic_id}");
     bootstrap_task.runstate.store(RunState::Runnable);
     bootstrap_task.running_on_cpu.store(Some(apic_id).into()); 
-    bootstrap_task.inner.get_mut().pinned_core = Some(apic_id); // can only run on this CPU core
+    bootstrap_task.last_switched_in.store(current_tsc(), Ordering::Relaxed);
+    bootstrap_task.inner.get_mut().affinity = CpuSet::single(apic_id); // can only run on this CPU core
     let bootstrap_task_id = bootstrap_task.id;
     let joinable_taskref = TaskRef::create(bootstrap_task);
 
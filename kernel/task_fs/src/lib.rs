@@ -10,6 +10,8 @@
 //!     about the task's memory management information
 //! 5) MmiFile: lazily computed file that contains information about the task's
 //!     memory management information
+//! 6) CpusDir: lazily computed directory (`/tasks/cpus`) that contains a CpuFile for each CPU
+//! 7) CpuFile: lazily computed file that contains information about a CPU, e.g., its idle time
 //! 
 //! * Note that all the structs here are NOT persistent in the filesystem EXCEPT
 //! for the TaskFs struct, which contains all the individual TaskDirs. This means 
@@ -19,8 +21,9 @@
 //! 
 //! The hierarchy (tree) is as follows:
 //! 
-//!             TaskDir
-//!         TaskFile    MmiDir
+//!                     TaskFs
+//!             TaskDir                 CpusDir
+//!         TaskFile    MmiDir          CpuFile
 //!                         MmiFile
 //! 
 
@@ -33,13 +36,14 @@ extern crate memory;
 extern crate task;
 extern crate path;
 extern crate root;
+extern crate mount_table;
 extern crate io;
 
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use spin::Mutex;
 use alloc::sync::Arc;
-use fs_node::{DirRef, WeakDirRef, Directory, FileOrDir, File, FileRef, FsNode};
+use fs_node::{DirRef, WeakDirRef, Directory, FileOrDir, File, FileRef, FsNode, Metadata, NodeType, Permissions};
 use memory::MappedPages;
 use task::{TaskRef, TASKLIST};
 use path::Path;
@@ -50,9 +54,15 @@ use io::{ByteReader, ByteWriter, KnownLength, IoError};
 pub const TASKS_DIRECTORY_NAME: &str = "tasks";
 /// The absolute path of the tasks directory, which is currently below the root
 pub const TASKS_DIRECTORY_PATH: &str = "/tasks"; 
+/// The name of the directory within the tasks directory that contains a file for each CPU.
+pub const CPUS_DIRECTORY_NAME: &str = "cpus";
 
 
-/// Initializes the tasks virtual filesystem directory within the root directory.
+/// The filesystem type of the tasks virtual filesystem, as shown in the `mount_table`.
+pub const TASK_FS_TYPE: &str = "taskfs";
+
+
+/// Initializes the tasks virtual filesystem at [`TASKS_DIRECTORY_PATH`] and records it in the mount table.
 pub fn init() -> Result<(), &'static str> {
     TaskFs::create()?;
     Ok(())
@@ -65,10 +75,17 @@ pub fn init() -> Result<(), &'static str> {
 pub struct TaskFs { }
 
 impl TaskFs {
+    /// Inserts the tasks directory into the root directory, replacing any existing `tasks` directory,
+    /// and records it in the `mount_table`.
+    ///
+    /// The tasks directory is a real child of the root directory rather than being mounted
+    /// over a placeholder, such that it is also found by lookups that don't go through a `Path`.
+    /// It serves as its own mount point, so it is still listed alongside other mounted filesystems.
     fn create() -> Result<DirRef, &'static str> {
         let root = root::get_root();
         let dir_ref = Arc::new(Mutex::new(TaskFs { })) as DirRef;
         root.lock().insert(FileOrDir::Dir(dir_ref.clone()))?;
+        mount_table::mount(dir_ref.clone(), &dir_ref, TASK_FS_TYPE, "none")?;
         Ok(dir_ref)
     }
 
@@ -77,6 +94,9 @@ impl TaskFs {
     }
 
     fn get_internal(&self, node: &str) -> Result<FileOrDir, &'static str> {
+        if node == CPUS_DIRECTORY_NAME {
+            return Ok(FileOrDir::Dir(Arc::new(Mutex::new(CpusDir { })) as DirRef));
+        }
         let id = node.parse::<usize>().map_err(|_e| "could not parse Task id as usize")?;
         let task_ref = task::get_task(id).ok_or("could not get taskref from TASKLIST")?;
         let parent_dir = self.get_self_pointer().ok_or("BUG: tasks directory wasn't in root")?;
@@ -112,6 +132,10 @@ impl Directory for TaskFs {
         Err("cannot insert node into read-only TaskFs")
     }
 
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot rename node in read-only TaskFs")
+    }
+
     fn get(&self, node: &str) -> Option<FileOrDir> {
         match self.get_internal(node) {
             Ok(d) => Some(d),
@@ -124,7 +148,7 @@ impl Directory for TaskFs {
 
     /// Returns a string listing all the children in the directory
     fn list(&self) -> Vec<String> {
-        let mut tasks_string = Vec::new();
+        let mut tasks_string = vec![CPUS_DIRECTORY_NAME.to_string()];
         for (id, _taskref) in TASKLIST.lock().iter() {
             tasks_string.push(format!("{id}"));
         }
@@ -135,6 +159,9 @@ impl Directory for TaskFs {
         None
     }
 
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::Directory, 0)
+    }
 }
 
 
@@ -171,6 +198,10 @@ impl Directory for TaskDir {
         Err("cannot insert node into read-only TaskFs")
     }
 
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot rename node in read-only TaskFs")
+    }
+
     fn get(&self, child_name: &str) -> Option<FileOrDir> {
         if child_name == "taskInfo" {
             let task_file = TaskFile::new(self.taskref.clone());
@@ -194,6 +225,10 @@ impl Directory for TaskDir {
     fn remove(&mut self, _: &FileOrDir) -> Option<FileOrDir> { 
         None
     }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::Directory, 0)
+    }
 }
 
 impl FsNode for TaskDir {
@@ -238,7 +273,7 @@ impl TaskFile {
     fn generate(&self) -> String {
         // Print all tasks
         let cpu = self.taskref.running_on_cpu().map(|cpu| format!("{cpu}")).unwrap_or_else(|| String::from("-"));
-        let pinned = &self.taskref.pinned_core().map(|pin| format!("{pin}")).unwrap_or_else(|| String::from("-"));
+        let affinity = self.taskref.affinity();
         let task_type = if self.taskref.is_an_idle_task {
             "I"
         } else if self.taskref.is_application() {
@@ -247,13 +282,16 @@ impl TaskFile {
             " "
         };  
 
-        format!("{0:<10} {1}\n{2:<10} {3}\n{4:<10} {5:?}\n{6:<10} {7}\n{8:<10} {9}\n{10:<10} {11:<10}", 
+        let cpu_time = format_cpu_time(self.taskref.cpu_time_ticks());
+
+        format!("{0:<10} {1}\n{2:<10} {3}\n{4:<10} {5:?}\n{6:<10} {7}\n{8:<10} {9}\n{10:<10} {11:<10}\n{12:<10} {13}", 
             "name", self.taskref.name,
             "task id", self.taskref.id,
             "runstate", self.taskref.runstate(),
             "cpu", cpu,
-            "pinned", pinned,
-            "task type", task_type
+            "affinity", affinity,
+            "task type", task_type,
+            "cpu time", cpu_time
         )
     }
 }
@@ -309,6 +347,10 @@ impl File for TaskFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("task files are autogenerated, cannot be memory mapped")
     }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::File, self.len())
+    }
 }
 
 
@@ -341,6 +383,10 @@ impl Directory for MmiDir {
         Err("cannot insert node into read-only TaskFs")
     }
 
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot rename node in read-only TaskFs")
+    }
+
     fn get(&self, child_name: &str) -> Option<FileOrDir> {
         if child_name == "MmiInfo" {
             // create the new mmi dir here on demand
@@ -360,6 +406,10 @@ impl Directory for MmiDir {
     fn remove(&mut self, _: &FileOrDir) -> Option<FileOrDir> {
         None
     }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::Directory, 0)
+    }
 }
 
 impl FsNode for MmiDir {
@@ -461,5 +511,170 @@ impl File for MmiFile {
     fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
         Err("task files are autogenerated, cannot be memory mapped")
     }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::File, self.len())
+    }
+}
+
+
+/// Lazily computed directory that contains a `CpuFile` for each CPU,
+/// i.e., each CPU that has an idle task.
+pub struct CpusDir { }
+
+impl Directory for CpusDir {
+    fn insert(&mut self, _node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot insert node into read-only TaskFs")
+    }
+
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        Err("cannot rename node in read-only TaskFs")
+    }
+
+    fn get(&self, child_name: &str) -> Option<FileOrDir> {
+        let apic_id = child_name.parse::<u8>().ok()?;
+        task::cpu_idle_time_ticks(apic_id)?;
+        Some(FileOrDir::File(Arc::new(Mutex::new(CpuFile::new(apic_id))) as FileRef))
+    }
+
+    /// Returns a string listing all the children in the directory
+    fn list(&self) -> Vec<String> {
+        let mut cpus: Vec<u8> = TASKLIST.lock().values()
+            .filter(|t| t.is_an_idle_task)
+            .filter_map(|t| t.pinned_core())
+            .collect();
+        cpus.sort_unstable();
+        cpus.into_iter().map(|cpu| cpu.to_string()).collect()
+    }
+
+    fn remove(&mut self, _: &FileOrDir) -> Option<FileOrDir> {
+        None
+    }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::Directory, 0)
+    }
+}
+
+impl FsNode for CpusDir {
+    fn get_absolute_path(&self) -> String {
+        format!("{TASKS_DIRECTORY_PATH}/{CPUS_DIRECTORY_NAME}")
+    }
+
+    fn get_name(&self) -> String {
+        CPUS_DIRECTORY_NAME.to_string()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        root::get_root().lock().get_dir(TASKS_DIRECTORY_NAME)
+    }
+
+    fn set_parent_dir(&mut self, _: WeakDirRef) {
+        // do nothing
+    }
+}
+
+
+
+/// Lazily computed file that contains information about a CPU,
+/// such as how long it has spent running its idle task.
+pub struct CpuFile {
+    apic_id: u8,
+    path: Path,
+}
+
+impl CpuFile {
+    pub fn new(apic_id: u8) -> CpuFile {
+        CpuFile {
+            apic_id,
+            path: Path::new(format!("{TASKS_DIRECTORY_PATH}/{CPUS_DIRECTORY_NAME}/{apic_id}")),
+        }
+    }
+
+    /// Generates the CPU info string.
+    fn generate(&self) -> String {
+        let idle_time = match task::cpu_idle_time_ticks(self.apic_id) {
+            Some(ticks) => format_cpu_time(ticks),
+            None => String::from("-"),
+        };
+        format!("{0:<10} {1}
+{2:<10} {3}",
+            "cpu", self.apic_id,
+            "idle time", idle_time
+        )
+    }
+}
+
+impl FsNode for CpuFile {
+    fn get_absolute_path(&self) -> String {
+        self.path.clone().into()
+    }
+
+    fn get_name(&self) -> String {
+        self.apic_id.to_string()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        let path = Path::new(format!("{TASKS_DIRECTORY_PATH}/{CPUS_DIRECTORY_NAME}"));
+        match Path::get_absolute(&path) {
+            Some(FileOrDir::Dir(d)) => Some(d),
+            _ => None,
+        }
+    }
+
+    fn set_parent_dir(&mut self, _: WeakDirRef) {
+        // do nothing
+    }
+}
+
+impl ByteReader for CpuFile {
+    fn read_at(&mut self, buf: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        let output = self.generate();
+        if offset > output.len() {
+            return Err(IoError::InvalidInput);
+        }
+        let count = core::cmp::min(buf.len(), output.len() - offset);
+        buf[..count].copy_from_slice(&output.as_bytes()[offset..(offset + count)]);
+        Ok(count)
+    }
+}
+
+impl ByteWriter for CpuFile {
+    fn write_at(&mut self, _buffer: &[u8], _offset: usize) -> Result<usize, IoError> {
+        Err(IoError::from("not permitted to write CPU contents through the task VFS"))
+    }
+    fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl KnownLength for CpuFile {
+    fn len(&self) -> usize {
+        self.generate().len()
+    }
 }
 
+impl File for CpuFile {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("CPU files are autogenerated, cannot be memory mapped")
+    }
+
+    fn metadata(&self) -> Metadata {
+        read_only_metadata(NodeType::File, self.len())
+    }
+}
+
+
+/// Formats the given number of TSC ticks as seconds, or as ticks if the TSC frequency is unknown.
+fn format_cpu_time(ticks: u64) -> String {
+    match task::ticks_to_duration(ticks) {
+        Some(t) => format!("{}.{:06} s", t.as_secs(), t.subsec_micros()),
+        None => format!("{ticks} ticks"),
+    }
+}
+
+/// Returns the metadata of a node in the task VFS, all of which are read-only.
+fn read_only_metadata(node_type: NodeType, size: usize) -> Metadata {
+    Metadata {
+        permissions: Permissions { read_only: true, executable: false },
+        ..Metadata::new(node_type, size)
+    }
+}
@@ -2,11 +2,16 @@
 
 #![no_std]
 
-use core::ops;
+use core::{
+    ops,
+    sync::atomic::{AtomicU64, Ordering},
+};
 use crossbeam_utils::atomic::AtomicCell;
 
+mod civil;
 mod dummy;
 
+pub use civil::{civil_from_days, days_from_civil};
 pub use core::time::Duration;
 
 const FEMTOS_TO_NANOS: u128 = 1_000_000;
@@ -20,6 +25,11 @@ static MONOTONIC_PERIOD: AtomicCell<Period> = AtomicCell::new(Period::MAX);
 static WALL_TIME_NOW_FUNCTION: AtomicCell<fn() -> Duration> = AtomicCell::new(dummy::wall_time_now);
 static WALL_TIME_PERIOD: AtomicCell<Period> = AtomicCell::new(Period::MAX);
 
+/// The wall time at which the monotonic clock read [`Instant::ZERO`], in nanoseconds since the Unix epoch,
+/// or [`UNKNOWN_OFFSET`] if it hasn't been calculated since a clock source was last registered.
+static WALL_TIME_OFFSET_NANOS: AtomicU64 = AtomicU64::new(UNKNOWN_OFFSET);
+const UNKNOWN_OFFSET: u64 = u64::MAX;
+
 /// A measurement of a monotonically nondecreasing clock.
 ///
 /// The inner value usually represents the internal counter value but the type
@@ -207,6 +217,8 @@ where
     {
         let now_fn = T::ClockType::now_fn();
         now_fn.store(T::now);
+        // The offset between the monotonic clock and wall time depends on both clock sources.
+        WALL_TIME_OFFSET_NANOS.store(UNKNOWN_OFFSET, Ordering::Release);
 
         true
     } else {
@@ -214,6 +226,15 @@ where
     }
 }
 
+/// Returns whether a clock source of the specified type has been registered
+/// using [`register_clock_source`].
+pub fn has_clock_source<T>() -> bool
+where
+    T: ClockType,
+{
+    T::period_atomic().load() != Period::MAX
+}
+
 /// Returns the current time.
 ///
 /// Monotonic clocks return an [`Instant`] whereas wall time clocks return a
@@ -230,6 +251,32 @@ where
     f()
 }
 
+/// Returns the current wall time, i.e., the time since the Unix epoch,
+/// derived from the monotonic clock.
+///
+/// Unlike `now::<WallTime>()`, this only reads the wall time clock source once
+/// after the clock sources were registered, because wall time clocks such as the RTC
+/// are typically slow to read and have a coarse resolution.
+/// Subsequent calls are as cheap and as precise as reading the monotonic clock.
+///
+/// Returns `None` if a monotonic or wall time clock source hasn't been registered.
+pub fn approximate_wall_time() -> Option<Duration> {
+    if !has_clock_source::<Monotonic>() || !has_clock_source::<WallTime>() {
+        return None;
+    }
+    let mut offset = WALL_TIME_OFFSET_NANOS.load(Ordering::Acquire);
+    if offset == UNKNOWN_OFFSET {
+        let wall_time = now::<WallTime>();
+        let since_zero = now::<Monotonic>().duration_since(Instant::ZERO);
+        offset = wall_time
+            .saturating_sub(since_zero)
+            .as_nanos()
+            .min(u128::from(UNKNOWN_OFFSET - 1)) as u64;
+        WALL_TIME_OFFSET_NANOS.store(offset, Ordering::Release);
+    }
+    Some(Duration::from_nanos(offset) + now::<Monotonic>().duration_since(Instant::ZERO))
+}
+
 /// A clock source.
 pub trait ClockSource {
     /// The type of clock (either [`Monotonic`] or [`WallTime`]).
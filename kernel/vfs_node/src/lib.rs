@@ -5,19 +5,24 @@
 //! The VFSDirectory and VFSFile are intended to be used as regular nodes within the filesystem
 //! that require no special functionality as well as for inspiration for creating other concrete implementations
 //!s of the Directory and File traits. 
+//! This crate also offers [`VFSSymlink`] and [`VFSHardLink`], which implement symbolic links and hard links.
 
-// #[macro_use] extern crate log;
+#[macro_use] extern crate log;
 extern crate alloc;
 extern crate spin;
 extern crate fs_node;
 extern crate memory;
+extern crate io;
 
-use alloc::string::String;
+mod link;
+pub use link::{VFSSymlink, VFSHardLink};
+
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use spin::Mutex;
 use alloc::sync::{Arc, Weak};
 use alloc::collections::BTreeMap;
-use fs_node::{DirRef, WeakDirRef, Directory, FileOrDir, FsNode};
+use fs_node::{Attributes, DirRef, WeakDirRef, Directory, FileOrDir, FsNode, Metadata, NodeType, Permissions};
 
 
 /// A struct that represents a node in the VFS 
@@ -28,17 +33,25 @@ pub struct VFSDirectory {
     pub children: BTreeMap<String, FileOrDir>,
     /// A weak reference to the parent directory
     pub parent: WeakDirRef,
+    /// The timestamps and permissions of this directory
+    pub attributes: Attributes,
 }
 
 impl VFSDirectory {
+    /// Creates a new empty directory with the given parent, without inserting it into that parent.
+    pub fn new(name: String, parent: WeakDirRef) -> VFSDirectory {
+        VFSDirectory {
+            name,
+            children: BTreeMap::new(),
+            parent,
+            attributes: Attributes::new(),
+        }
+    }
+
     /// Creates a new directory and passes a pointer to the new directory created as output
     pub fn create(name: String, parent: &DirRef)  -> Result<DirRef, &'static str> {
         // creates a copy of the parent pointer so that we can add the newly created folder to the parent's children later
-        let directory = VFSDirectory {
-            name,
-            children: BTreeMap::new(),
-            parent: Arc::downgrade(parent),
-        };
+        let directory = VFSDirectory::new(name, Arc::downgrade(parent));
         let dir_ref = Arc::new(Mutex::new(directory)) as DirRef;
         parent.lock().insert(FileOrDir::Dir(dir_ref.clone()))?;
         Ok(dir_ref)
@@ -47,6 +60,8 @@ impl VFSDirectory {
 
 impl Directory for VFSDirectory {
     fn insert(&mut self, node: FileOrDir) -> Result<Option<FileOrDir>, &'static str> {
+        self.attributes.check_writable()?;
+        self.attributes.touch();
         let name = node.get_name();
         if let Some(mut old_node) = self.children.insert(name, node) {
             old_node.set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
@@ -66,13 +81,38 @@ impl Directory for VFSDirectory {
     }
 
     fn remove(&mut self, node: &FileOrDir) -> Option<FileOrDir> {
+        if let Err(e) = self.attributes.check_writable() {
+            warn!("VFSDirectory::remove(): refusing to remove {:?} from {:?}: {}", node.get_name(), self.name, e);
+            return None;
+        }
         if let Some(mut old_node) = self.children.remove(&node.get_name()) {
+            self.attributes.touch();
             old_node.set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
             Some(old_node)
         } else {
             None
         }
     }
+
+    fn rename(&mut self, old_name: &str, new_name: &str) -> Result<Option<FileOrDir>, &'static str> {
+        self.attributes.check_writable()?;
+        let replaced = rename_child(&mut self.children, old_name, new_name)?;
+        self.attributes.touch();
+        Ok(replaced)
+    }
+
+    fn supports_move(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.attributes.metadata(NodeType::Directory, 0)
+    }
+
+    fn set_permissions(&mut self, permissions: Permissions) -> Result<(), &'static str> {
+        self.attributes.permissions = permissions;
+        Ok(())
+    }
 }
 
 impl FsNode for VFSDirectory {
@@ -88,4 +128,37 @@ impl FsNode for VFSDirectory {
     fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
         self.parent = new_parent;
     }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        self.name = new_name;
+        Ok(())
+    }
+}
+
+/// Renames the node called `old_name` in the given map of `children` to `new_name`,
+/// returning the replaced node, if any, whose parent directory is cleared.
+///
+/// This can be used by other `Directory` implementations that store their children in a map.
+pub fn rename_child(
+    children: &mut BTreeMap<String, FileOrDir>,
+    old_name: &str,
+    new_name: &str,
+) -> Result<Option<FileOrDir>, &'static str> {
+    if !children.contains_key(old_name) {
+        return Err("no node with that name exists in this directory");
+    }
+    if old_name == new_name {
+        return Ok(None);
+    }
+    let mut node = children.remove(old_name).ok_or("no node with that name exists in this directory")?;
+    if let Err(e) = node.set_name(new_name.to_string()) {
+        children.insert(old_name.to_string(), node);
+        return Err(e);
+    }
+    if let Some(mut old_node) = children.insert(new_name.to_string(), node) {
+        old_node.set_parent_dir(Weak::<Mutex<VFSDirectory>>::new());
+        Ok(Some(old_node))
+    } else {
+        Ok(None)
+    }
 }
@@ -0,0 +1,215 @@
+//! Symbolic links and hard links, which allow a node to be reached via multiple paths.
+//!
+//! A [`VFSSymlink`] stores the path of another node, which is resolved by `path::Path::get()`
+//! each time the link is traversed; thus, the target need not exist when the link is created.
+//! Because inserting a node replaces any existing node with the same name,
+//! a symbolic link can be atomically redirected by creating a new link with the same name.
+//!
+//! A [`VFSHardLink`] gives an additional name to an existing file:
+//! it forwards all operations except mapping directly to that file, so both names share the same contents.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use spin::Mutex;
+use fs_node::{Attributes, DirRef, WeakDirRef, File, FileOrDir, FileRef, FsNode, Metadata, NodeType, Permissions};
+use io::{ByteReader, ByteWriter, IoError, KnownLength};
+use memory::MappedPages;
+
+
+/// A symbolic link, i.e., a file whose contents are the path of another node.
+///
+/// Reading a symbolic link returns the path that it points to.
+pub struct VFSSymlink {
+    /// The name of this link.
+    name: String,
+    /// The path of the node that this link points to, which may be absolute or
+    /// relative to the directory that contains this link.
+    target: String,
+    /// The parent directory that contains this link.
+    parent: WeakDirRef,
+    /// The timestamps and permissions of this link.
+    attributes: Attributes,
+}
+
+impl VFSSymlink {
+    /// Creates a new symbolic link called `name` within the given `parent` directory
+    /// that points to the given `target` path.
+    ///
+    /// If a node called `name` already exists in the `parent` directory, it is replaced.
+    pub fn create(name: String, target: String, parent: &DirRef) -> Result<FileRef, &'static str> {
+        if target.is_empty() {
+            return Err("the target of a symbolic link cannot be empty");
+        }
+        let symlink = VFSSymlink {
+            name,
+            target,
+            parent: Arc::downgrade(parent),
+            attributes: Attributes::new(),
+        };
+        let file_ref = Arc::new(Mutex::new(symlink)) as FileRef;
+        parent.lock().insert(FileOrDir::File(file_ref.clone()))?;
+        Ok(file_ref)
+    }
+}
+
+impl ByteReader for VFSSymlink {
+    fn read_at(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        let target = self.target.as_bytes();
+        if offset > target.len() {
+            return Err(IoError::InvalidInput);
+        }
+        let count = core::cmp::min(buffer.len(), target.len() - offset);
+        buffer[..count].copy_from_slice(&target[offset..(offset + count)]);
+        Ok(count)
+    }
+}
+
+impl ByteWriter for VFSSymlink {
+    fn write_at(&mut self, _buffer: &[u8], _offset: usize) -> Result<usize, IoError> {
+        Err(IoError::from("cannot write to a symbolic link; replace it with a new link instead"))
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl KnownLength for VFSSymlink {
+    fn len(&self) -> usize {
+        self.target.len()
+    }
+}
+
+impl File for VFSSymlink {
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("cannot map a symbolic link as a MappedPages object")
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.attributes.metadata(NodeType::Symlink, self.target.len())
+    }
+
+    fn set_permissions(&mut self, permissions: Permissions) -> Result<(), &'static str> {
+        self.attributes.permissions = permissions;
+        Ok(())
+    }
+
+    fn symlink_target(&self) -> Option<String> {
+        Some(self.target.clone())
+    }
+}
+
+impl FsNode for VFSSymlink {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        self.name = new_name;
+        Ok(())
+    }
+}
+
+
+/// A hard link, i.e., an additional name for an existing file.
+///
+/// All file operations except mapping are forwarded to the linked file,
+/// which remains alive as long as any of its links exist.
+/// Only files can be hard linked; directories cannot.
+pub struct VFSHardLink {
+    /// The name of this link.
+    name: String,
+    /// The file that this link refers to.
+    file: FileRef,
+    /// The parent directory that contains this link.
+    parent: WeakDirRef,
+}
+
+impl VFSHardLink {
+    /// Creates a new hard link called `name` within the given `parent` directory
+    /// that refers to the given `file`.
+    ///
+    /// If a node called `name` already exists in the `parent` directory, it is replaced.
+    pub fn create(name: String, file: FileRef, parent: &DirRef) -> Result<FileRef, &'static str> {
+        let link = VFSHardLink {
+            name,
+            file,
+            parent: Arc::downgrade(parent),
+        };
+        let file_ref = Arc::new(Mutex::new(link)) as FileRef;
+        parent.lock().insert(FileOrDir::File(file_ref.clone()))?;
+        Ok(file_ref)
+    }
+
+    /// Returns the file that this link refers to.
+    pub fn file(&self) -> &FileRef {
+        &self.file
+    }
+}
+
+impl ByteReader for VFSHardLink {
+    fn read_at(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, IoError> {
+        self.file.lock().read_at(buffer, offset)
+    }
+}
+
+impl ByteWriter for VFSHardLink {
+    fn write_at(&mut self, buffer: &[u8], offset: usize) -> Result<usize, IoError> {
+        self.file.lock().write_at(buffer, offset)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.file.lock().flush()
+    }
+}
+
+impl KnownLength for VFSHardLink {
+    fn len(&self) -> usize {
+        self.file.lock().len()
+    }
+}
+
+impl File for VFSHardLink {
+    /// A hard link cannot lend out the linked file's mapping, as it can't hold the lock on that file
+    /// for as long as the returned reference is used; map the linked file via [`VFSHardLink::file()`] instead.
+    fn as_mapping(&self) -> Result<&MappedPages, &'static str> {
+        Err("cannot map a hard link as a MappedPages object; map the linked file instead")
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.file.lock().metadata()
+    }
+
+    fn set_permissions(&mut self, permissions: Permissions) -> Result<(), &'static str> {
+        self.file.lock().set_permissions(permissions)
+    }
+
+    fn symlink_target(&self) -> Option<String> {
+        self.file.lock().symlink_target()
+    }
+}
+
+impl FsNode for VFSHardLink {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_parent_dir(&self) -> Option<DirRef> {
+        self.parent.upgrade()
+    }
+
+    fn set_parent_dir(&mut self, new_parent: WeakDirRef) {
+        self.parent = new_parent;
+    }
+
+    fn set_name(&mut self, new_name: String) -> Result<(), &'static str> {
+        self.name = new_name;
+        Ok(())
+    }
+}
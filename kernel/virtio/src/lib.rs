@@ -0,0 +1,552 @@
+//! Support for virtio devices attached via PCI, which is shared by all virtio device drivers.
+//!
+//! A virtio device is accessed through a [`Transport`], which is either:
+//! * the *modern* transport from virtio 1.0, whose configuration structures are
+//!   memory-mapped regions located by vendor-specific PCI capabilities, or
+//! * the *legacy* transport from virtio 0.9.5, whose registers are in an I/O port BAR.
+//!
+//! Transitional devices, e.g., QEMU's default virtio devices on the `pc` machine, support both;
+//! we prefer the modern transport if it is available.
+//!
+//! Buffers are exchanged with the device through one or more [`Virtqueue`]s.
+
+#![no_std]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+
+mod queue;
+pub use queue::{Virtqueue, VirtqueueBuffer, MAX_QUEUE_SIZE};
+
+use alloc::vec::Vec;
+use port_io::Port;
+use memory::{
+    MappedPages, PteFlags,
+    allocate_pages_by_bytes, allocate_frames_by_bytes_at, get_kernel_mmi_ref,
+};
+use pci::{PciDevice, PCI_CAPABILITIES, PCI_STATUS};
+use volatile::{Volatile, ReadOnly};
+use zerocopy::FromBytes;
+
+
+/// The PCI vendor ID of all virtio devices.
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+/// The virtio device type of network cards.
+pub const DEVICE_TYPE_NET: u16 = 1;
+/// The virtio device type of block devices.
+pub const DEVICE_TYPE_BLOCK: u16 = 2;
+
+/// The device status bit indicating that the driver has noticed the device.
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+/// The device status bit indicating that the driver knows how to drive the device.
+pub const STATUS_DRIVER: u8 = 2;
+/// The device status bit indicating that the driver is ready to drive the device.
+pub const STATUS_DRIVER_OK: u8 = 4;
+/// The device status bit indicating that feature negotiation is complete.
+pub const STATUS_FEATURES_OK: u8 = 8;
+/// The device status bit indicating that the driver has given up on the device.
+pub const STATUS_FAILED: u8 = 128;
+
+/// The feature bit indicating compliance with virtio 1.0 or later,
+/// which must be negotiated when using the modern transport.
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// The ISR status bit indicating that a virtqueue has been updated.
+pub const ISR_QUEUE: u8 = 1 << 0;
+/// The ISR status bit indicating that the device configuration has changed.
+pub const ISR_CONFIG: u8 = 1 << 1;
+
+/// The mapping flags used for virtio device registers and DMA memory.
+pub const VIRTIO_MAPPING_FLAGS: PteFlags = PteFlags::from_bits_truncate(
+    PteFlags::new().bits()
+    | PteFlags::VALID.bits()
+    | PteFlags::WRITABLE.bits()
+    | PteFlags::DEVICE_MEMORY.bits()
+);
+
+/// Returns the virtio device type of the given PCI device,
+/// or `None` if it isn't a virtio device whose type we can determine.
+pub fn device_type(pci_device: &PciDevice) -> Option<u16> {
+    if pci_device.vendor_id != VIRTIO_VENDOR_ID {
+        return None;
+    }
+    match pci_device.device_id {
+        // Transitional devices have fixed device IDs.
+        0x1000 => Some(DEVICE_TYPE_NET),
+        0x1001 => Some(DEVICE_TYPE_BLOCK),
+        // Modern devices' IDs are the device type plus 0x1040.
+        id @ 0x1040 ..= 0x107F => Some(id - 0x1040),
+        _ => None,
+    }
+}
+
+
+/// The means of accessing a virtio device's configuration and notifying it of new buffers.
+pub enum Transport {
+    Legacy(LegacyTransport),
+    Modern(ModernTransport),
+}
+
+impl Transport {
+    /// Creates the transport for the given virtio PCI device,
+    /// preferring the modern transport over the legacy transport if both are available.
+    ///
+    /// This also enables bus mastering for the device, which allows it to use DMA.
+    pub fn new(pci_device: &PciDevice) -> Result<Transport, &'static str> {
+        pci_device.pci_set_command_bus_master_bit();
+
+        match ModernTransport::new(pci_device) {
+            Ok(Some(modern)) => return Ok(Transport::Modern(modern)),
+            Ok(None) => { }
+            Err(e) => warn!("virtio device at {}: couldn't use modern transport: {}", pci_device.location, e),
+        }
+        if pci_device.device_id >= 0x1040 {
+            return Err("modern-only virtio device did not have usable virtio PCI capabilities");
+        }
+        LegacyTransport::new(pci_device).map(Transport::Legacy)
+    }
+
+    /// Returns `true` if this is the modern (virtio 1.0) transport.
+    pub fn is_modern(&self) -> bool {
+        matches!(self, Transport::Modern(_))
+    }
+
+    /// Reads the device status.
+    pub fn status(&self) -> u8 {
+        match self {
+            Transport::Legacy(t) => t.port::<u8>(LEGACY_DEVICE_STATUS).read(),
+            Transport::Modern(t) => t.common().map(|c| c.device_status.read()).unwrap_or(STATUS_FAILED),
+        }
+    }
+
+    /// Writes the device status.
+    pub fn set_status(&mut self, status: u8) {
+        match self {
+            Transport::Legacy(t) => unsafe { t.port::<u8>(LEGACY_DEVICE_STATUS).write(status) },
+            Transport::Modern(t) => if let Ok(c) = t.common_mut() { c.device_status.write(status) },
+        }
+    }
+
+    /// Sets the given bits in the device status, in addition to those already set.
+    pub fn add_status(&mut self, status: u8) {
+        let current = self.status();
+        self.set_status(current | status);
+    }
+
+    /// Resets the device, which returns it to its initial state and stops all DMA.
+    pub fn reset(&mut self) -> Result<(), &'static str> {
+        self.set_status(0);
+        // The device has finished resetting once it reads back a status of 0.
+        for _ in 0 .. RESET_TIMEOUT_POLLS {
+            if self.status() == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("virtio device did not finish resetting")
+    }
+
+    /// Returns all feature bits that the device offers.
+    pub fn device_features(&mut self) -> u64 {
+        match self {
+            Transport::Legacy(t) => t.port::<u32>(LEGACY_DEVICE_FEATURES).read() as u64,
+            Transport::Modern(t) => {
+                let mut features = 0;
+                if let Ok(c) = t.common_mut() {
+                    c.device_feature_select.write(0);
+                    features |= c.device_feature.read() as u64;
+                    c.device_feature_select.write(1);
+                    features |= (c.device_feature.read() as u64) << 32;
+                }
+                features
+            }
+        }
+    }
+
+    /// Informs the device of the feature bits that the driver accepts.
+    pub fn set_driver_features(&mut self, features: u64) {
+        match self {
+            Transport::Legacy(t) => unsafe { t.port::<u32>(LEGACY_DRIVER_FEATURES).write(features as u32) },
+            Transport::Modern(t) => if let Ok(c) = t.common_mut() {
+                c.driver_feature_select.write(0);
+                c.driver_feature.write(features as u32);
+                c.driver_feature_select.write(1);
+                c.driver_feature.write((features >> 32) as u32);
+            },
+        }
+    }
+
+    /// Performs the first steps of device initialization: resets the device,
+    /// acknowledges it, and negotiates the features supported by both the device and the driver,
+    /// the latter of which are given by `supported_features`.
+    ///
+    /// Afterwards, the driver should set up its virtqueues and then call [`driver_ok()`](#method.driver_ok).
+    ///
+    /// Returns the negotiated features.
+    pub fn begin_init(&mut self, supported_features: u64) -> Result<u64, &'static str> {
+        self.reset()?;
+        self.add_status(STATUS_ACKNOWLEDGE);
+        self.add_status(STATUS_DRIVER);
+
+        let device_features = self.device_features();
+        let mut features = device_features & supported_features;
+        if self.is_modern() {
+            if device_features & VIRTIO_F_VERSION_1 == 0 {
+                self.add_status(STATUS_FAILED);
+                return Err("modern virtio device did not offer VIRTIO_F_VERSION_1");
+            }
+            features |= VIRTIO_F_VERSION_1;
+        } else {
+            // The legacy transport only supports the lower 32 feature bits.
+            features &= u32::MAX as u64;
+        }
+        self.set_driver_features(features);
+
+        // Only the modern transport has the FEATURES_OK handshake.
+        if self.is_modern() {
+            self.add_status(STATUS_FEATURES_OK);
+            if self.status() & STATUS_FEATURES_OK == 0 {
+                self.add_status(STATUS_FAILED);
+                return Err("virtio device did not accept the negotiated features");
+            }
+        }
+        Ok(features)
+    }
+
+    /// Completes device initialization, after which the device may be used.
+    pub fn driver_ok(&mut self) {
+        self.add_status(STATUS_DRIVER_OK);
+    }
+
+    /// Returns the largest number of descriptors that the queue with the given `index` supports,
+    /// or `0` if that queue doesn't exist.
+    pub fn max_queue_size(&mut self, index: u16) -> u16 {
+        match self {
+            Transport::Legacy(t) => unsafe {
+                t.port::<u16>(LEGACY_QUEUE_SELECT).write(index);
+                t.port::<u16>(LEGACY_QUEUE_SIZE).read()
+            },
+            Transport::Modern(t) => t.common_mut().map(|c| {
+                c.queue_select.write(index);
+                c.queue_size.read()
+            }).unwrap_or(0),
+        }
+    }
+
+    /// Returns the queue size that a driver should use for the queue with the given `index`.
+    ///
+    /// The legacy transport requires the device's queue size to be used as is,
+    /// whereas the modern transport permits using a smaller queue.
+    pub fn preferred_queue_size(&mut self, index: u16) -> u16 {
+        let max = self.max_queue_size(index);
+        if self.is_modern() {
+            core::cmp::min(max, MAX_QUEUE_SIZE)
+        } else {
+            max
+        }
+    }
+
+    /// Informs the device of the location and size of the given virtqueue and enables it.
+    pub fn setup_queue(&mut self, queue: &Virtqueue) -> Result<(), &'static str> {
+        let index = queue.index();
+        match self {
+            Transport::Legacy(t) => unsafe {
+                t.port::<u16>(LEGACY_QUEUE_SELECT).write(index);
+                if t.port::<u16>(LEGACY_QUEUE_SIZE).read() != queue.size() {
+                    return Err("legacy virtio queues must have the size chosen by the device");
+                }
+                let pfn = queue.descriptor_table_address().value() / LEGACY_QUEUE_ALIGNMENT;
+                if pfn > u32::MAX as usize {
+                    return Err("legacy virtio queue memory must be below 16 TiB");
+                }
+                t.port::<u32>(LEGACY_QUEUE_ADDRESS).write(pfn as u32);
+                Ok(())
+            },
+            Transport::Modern(t) => {
+                let notify_off = {
+                    let c = t.common_mut()?;
+                    c.queue_select.write(index);
+                    c.queue_size.write(queue.size());
+                    let desc = queue.descriptor_table_address().value() as u64;
+                    let avail = queue.available_ring_address().value() as u64;
+                    let used = queue.used_ring_address().value() as u64;
+                    c.queue_desc_lo.write(desc as u32);
+                    c.queue_desc_hi.write((desc >> 32) as u32);
+                    c.queue_driver_lo.write(avail as u32);
+                    c.queue_driver_hi.write((avail >> 32) as u32);
+                    c.queue_device_lo.write(used as u32);
+                    c.queue_device_hi.write((used >> 32) as u32);
+                    c.queue_enable.write(1);
+                    c.queue_notify_off.read()
+                };
+                let index = index as usize;
+                if t.queue_notify_offs.len() <= index {
+                    t.queue_notify_offs.resize(index + 1, 0);
+                }
+                t.queue_notify_offs[index] = notify_off;
+                Ok(())
+            }
+        }
+    }
+
+    /// Notifies the device that new buffers are available in the queue with the given `index`.
+    pub fn notify(&mut self, index: u16) {
+        match self {
+            Transport::Legacy(t) => unsafe { t.port::<u16>(LEGACY_QUEUE_NOTIFY).write(index) },
+            Transport::Modern(t) => {
+                let notify_off = t.queue_notify_offs.get(index as usize).copied().unwrap_or(0) as usize;
+                let offset = t.notify_offset + notify_off * t.notify_off_multiplier as usize;
+                if let Ok(notify) = t.mapped_bar.as_type_mut::<Volatile<u16>>(offset) {
+                    notify.write(index);
+                }
+            }
+        }
+    }
+
+    /// Reads and thereby clears the ISR status, which indicates why the device raised an interrupt.
+    ///
+    /// See [`ISR_QUEUE`] and [`ISR_CONFIG`].
+    pub fn read_isr(&self) -> u8 {
+        match self {
+            Transport::Legacy(t) => t.port::<u8>(LEGACY_ISR_STATUS).read(),
+            Transport::Modern(t) => t.mapped_bar.as_type::<ReadOnly<u8>>(t.isr_offset)
+                .map(|isr| isr.read())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Reads a byte from the device-specific configuration at the given `offset`.
+    pub fn read_config_u8(&self, offset: usize) -> u8 {
+        match self {
+            Transport::Legacy(t) => t.port::<u8>(LEGACY_DEVICE_CONFIG + offset as u16).read(),
+            Transport::Modern(t) => t.mapped_bar.as_type::<ReadOnly<u8>>(t.device_offset + offset)
+                .map(|v| v.read())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Reads a 16-bit value from the device-specific configuration at the given `offset`.
+    pub fn read_config_u16(&self, offset: usize) -> u16 {
+        match self {
+            Transport::Legacy(t) => t.port::<u16>(LEGACY_DEVICE_CONFIG + offset as u16).read(),
+            Transport::Modern(t) => t.mapped_bar.as_type::<ReadOnly<u16>>(t.device_offset + offset)
+                .map(|v| v.read())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Reads a 32-bit value from the device-specific configuration at the given `offset`.
+    pub fn read_config_u32(&self, offset: usize) -> u32 {
+        match self {
+            Transport::Legacy(t) => t.port::<u32>(LEGACY_DEVICE_CONFIG + offset as u16).read(),
+            Transport::Modern(t) => t.mapped_bar.as_type::<ReadOnly<u32>>(t.device_offset + offset)
+                .map(|v| v.read())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Reads a 64-bit value from the device-specific configuration at the given `offset`,
+    /// as two 32-bit accesses.
+    pub fn read_config_u64(&self, offset: usize) -> u64 {
+        self.read_config_u32(offset) as u64 | ((self.read_config_u32(offset + 4) as u64) << 32)
+    }
+}
+
+
+/// How many times to poll the device status before giving up on a reset.
+const RESET_TIMEOUT_POLLS: usize = 1_000_000;
+
+/// Offsets of the legacy transport's registers within its I/O port BAR.
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_ADDRESS:   u16 = 0x08;
+const LEGACY_QUEUE_SIZE:      u16 = 0x0C;
+const LEGACY_QUEUE_SELECT:    u16 = 0x0E;
+const LEGACY_QUEUE_NOTIFY:    u16 = 0x10;
+const LEGACY_DEVICE_STATUS:   u16 = 0x12;
+const LEGACY_ISR_STATUS:      u16 = 0x13;
+/// The device-specific configuration, assuming MSI-X is disabled.
+const LEGACY_DEVICE_CONFIG:   u16 = 0x14;
+/// Legacy queue addresses are given as page frame numbers of this size.
+const LEGACY_QUEUE_ALIGNMENT: usize = 4096;
+
+/// To use a BAR as a Port address, you must mask out the lowest 2 bits.
+const PCI_BAR_PORT_MASK: u16 = 0xFFFC;
+
+/// The legacy (virtio 0.9.5) transport, which uses the registers in the device's I/O port BAR0.
+pub struct LegacyTransport {
+    io_base: u16,
+}
+
+impl LegacyTransport {
+    fn new(pci_device: &PciDevice) -> Result<LegacyTransport, &'static str> {
+        let bar0 = pci_device.bars[0];
+        if bar0 & 0x1 == 0 {
+            return Err("legacy virtio device's BAR0 was not an I/O port BAR");
+        }
+        Ok(LegacyTransport { io_base: bar0 as u16 & PCI_BAR_PORT_MASK })
+    }
+
+    fn port<T: port_io::PortIn + port_io::PortOut>(&self, offset: u16) -> Port<T> {
+        Port::new(self.io_base + offset)
+    }
+}
+
+
+/// The PCI capability ID of vendor-specific capabilities, which describe virtio structures.
+const PCI_CAPABILITY_VENDOR_SPECIFIC: u8 = 0x09;
+/// The virtio PCI capability types.
+const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+const VIRTIO_PCI_CAP_NOTIFY_CFG: u8 = 2;
+const VIRTIO_PCI_CAP_ISR_CFG:    u8 = 3;
+const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
+
+/// The layout in memory of the modern transport's common configuration structure.
+#[allow(dead_code)] // not all fields are used, e.g., those for MSI-X
+#[derive(FromBytes)]
+#[repr(C)]
+struct CommonConfig {
+    device_feature_select:  Volatile<u32>,  // 0x00
+    device_feature:         ReadOnly<u32>,  // 0x04
+    driver_feature_select:  Volatile<u32>,  // 0x08
+    driver_feature:         Volatile<u32>,  // 0x0C
+    msix_config:            Volatile<u16>,  // 0x10
+    num_queues:             ReadOnly<u16>,  // 0x12
+    device_status:          Volatile<u8>,   // 0x14
+    config_generation:      ReadOnly<u8>,   // 0x15
+    queue_select:           Volatile<u16>,  // 0x16
+    queue_size:             Volatile<u16>,  // 0x18
+    queue_msix_vector:      Volatile<u16>,  // 0x1A
+    queue_enable:           Volatile<u16>,  // 0x1C
+    queue_notify_off:       ReadOnly<u16>,  // 0x1E
+    // The 64-bit queue addresses are split in two because devices needn't support 64-bit accesses.
+    queue_desc_lo:          Volatile<u32>,  // 0x20
+    queue_desc_hi:          Volatile<u32>,  // 0x24
+    queue_driver_lo:        Volatile<u32>,  // 0x28
+    queue_driver_hi:        Volatile<u32>,  // 0x2C
+    queue_device_lo:        Volatile<u32>,  // 0x30
+    queue_device_hi:        Volatile<u32>,  // 0x34
+}
+
+const _: () = assert!(core::mem::size_of::<CommonConfig>() == 0x38);
+
+/// The modern (virtio 1.0) transport, whose configuration structures are in a memory BAR.
+pub struct ModernTransport {
+    /// The BAR that contains all of the configuration structures.
+    mapped_bar: MappedPages,
+    common_offset: usize,
+    notify_offset: usize,
+    notify_off_multiplier: u32,
+    isr_offset: usize,
+    device_offset: usize,
+    /// The notification offset of each queue, indexed by queue index.
+    queue_notify_offs: Vec<u16>,
+}
+
+impl ModernTransport {
+    /// Locates the virtio configuration structures via the device's PCI capabilities and maps them.
+    ///
+    /// Returns `Ok(None)` if the device doesn't have the capabilities required by the modern transport.
+    fn new(pci_device: &PciDevice) -> Result<Option<ModernTransport>, &'static str> {
+        let mut bar = None;
+        let mut common_offset = None;
+        let mut notify = None;
+        let mut isr_offset = None;
+        let mut device_offset = None;
+
+        for cap in pci_capabilities(pci_device, PCI_CAPABILITY_VENDOR_SPECIFIC) {
+            let cfg_type = pci_device.pci_read_8(cap + 3);
+            let cap_bar = pci_device.pci_read_8(cap + 4) as usize;
+            let offset = pci_device.pci_read_32(cap + 8) as usize;
+            let slot = match cfg_type {
+                VIRTIO_PCI_CAP_COMMON_CFG => &mut common_offset,
+                VIRTIO_PCI_CAP_ISR_CFG    => &mut isr_offset,
+                VIRTIO_PCI_CAP_DEVICE_CFG => &mut device_offset,
+                VIRTIO_PCI_CAP_NOTIFY_CFG => {
+                    if notify.is_none() {
+                        notify = Some((cap_bar, offset, pci_device.pci_read_32(cap + 16)));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            // Use the first capability of each type, as recommended by the specification.
+            if slot.is_none() {
+                *slot = Some((cap_bar, offset));
+            }
+        }
+
+        let (common, (notify_bar, notify_offset, notify_off_multiplier), isr, device) =
+            match (common_offset, notify, isr_offset, device_offset) {
+                (Some(c), Some(n), Some(i), Some(d)) => (c, n, i, d),
+                _ => return Ok(None),
+            };
+        for cap_bar in [common.0, notify_bar, isr.0, device.0] {
+            match bar {
+                None => bar = Some(cap_bar),
+                Some(b) if b == cap_bar => { }
+                Some(_) => return Err("virtio configuration structures spanning multiple BARs are unsupported"),
+            }
+        }
+        let bar = bar.ok_or("virtio device had no configuration BAR")?;
+        if bar > 5 {
+            return Err("virtio capability referred to an invalid BAR");
+        }
+
+        let mem_base = pci_device.determine_mem_base(bar)?;
+        let mem_size = pci_device.determine_mem_size(bar) as usize;
+        let mapped_bar = map_bar(mem_base, mem_size)?;
+
+        Ok(Some(ModernTransport {
+            mapped_bar,
+            common_offset: common.1,
+            notify_offset,
+            notify_off_multiplier,
+            isr_offset: isr.1,
+            device_offset: device.1,
+            queue_notify_offs: Vec::new(),
+        }))
+    }
+
+    fn common(&self) -> Result<&CommonConfig, &'static str> {
+        self.mapped_bar.as_type(self.common_offset)
+    }
+
+    fn common_mut(&mut self) -> Result<&mut CommonConfig, &'static str> {
+        self.mapped_bar.as_type_mut(self.common_offset)
+    }
+}
+
+/// Returns the config space offsets of all of the given PCI device's capabilities with the given ID.
+fn pci_capabilities(pci_device: &PciDevice, capability_id: u8) -> Vec<u8> {
+    // capabilities are only valid if bit 4 of status register is set
+    const CAPABILITIES_VALID: u16 = 1 << 4;
+    let mut capabilities = Vec::new();
+    if pci_device.pci_read_16(PCI_STATUS) & CAPABILITIES_VALID == 0 {
+        return capabilities;
+    }
+    let mut cap = pci_device.pci_read_8(PCI_CAPABILITIES) & 0xFC;
+    // Bound the number of iterations in case of a malformed (cyclic) capability list.
+    for _ in 0 .. 48 {
+        if cap == 0 {
+            break;
+        }
+        if pci_device.pci_read_8(cap) == capability_id {
+            capabilities.push(cap);
+        }
+        cap = pci_device.pci_read_8(cap + 1) & 0xFC;
+    }
+    capabilities
+}
+
+/// Maps the memory BAR of `size_in_bytes` at the given physical address.
+fn map_bar(mem_base: memory::PhysicalAddress, size_in_bytes: usize) -> Result<MappedPages, &'static str> {
+    let pages = allocate_pages_by_bytes(size_in_bytes)
+        .ok_or("virtio::map_bar(): couldn't allocate virtual pages")?;
+    let frames = allocate_frames_by_bytes_at(mem_base, size_in_bytes)
+        .map_err(|_e| "virtio::map_bar(): couldn't allocate physical frames")?;
+    let kernel_mmi_ref = get_kernel_mmi_ref().ok_or("virtio::map_bar(): KERNEL_MMI was not yet initialized!")?;
+    let mp = kernel_mmi_ref.lock().page_table.map_allocated_pages_to(pages, frames, VIRTIO_MAPPING_FLAGS)?;
+    Ok(mp)
+}
@@ -0,0 +1,259 @@
+//! Split virtqueues, the rings through which a driver and a virtio device exchange buffers.
+//!
+//! A virtqueue consists of three parts, which are placed contiguously in DMA memory
+//! using the legacy layout so that the same queue works with both transports:
+//! * The descriptor table, in which each descriptor describes one physically-contiguous buffer.
+//!   Descriptors are chained together to form a single request.
+//! * The available ring, into which the driver places the head descriptor of each new request.
+//! * The used ring, into which the device places the head descriptor of each completed request.
+
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, Ordering},
+};
+use memory::{MappedPages, PhysicalAddress, create_contiguous_mapping};
+use volatile::Volatile;
+use zerocopy::FromBytes;
+use crate::VIRTIO_MAPPING_FLAGS;
+
+/// The descriptor continues via its `next` field.
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+/// The descriptor's buffer is written by the device (otherwise it is read by the device).
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// The alignment of the used ring in the legacy virtqueue layout.
+const USED_RING_ALIGNMENT: usize = 4096;
+
+/// The largest queue size that we will use, even if a device supports larger queues.
+pub const MAX_QUEUE_SIZE: u16 = 256;
+
+
+/// An entry in the descriptor table.
+#[derive(FromBytes)]
+#[repr(C)]
+struct Descriptor {
+    addr:  Volatile<u64>,
+    len:   Volatile<u32>,
+    flags: Volatile<u16>,
+    next:  Volatile<u16>,
+}
+
+/// An entry in the used ring.
+#[derive(FromBytes)]
+#[repr(C)]
+struct UsedElement {
+    /// The head descriptor of the completed request.
+    id:  Volatile<u32>,
+    /// The number of bytes that the device wrote into the request's buffers.
+    len: Volatile<u32>,
+}
+
+/// A buffer that is part of a request submitted to a virtqueue.
+#[derive(Copy, Clone, Debug)]
+pub struct VirtqueueBuffer {
+    /// The physical address of the start of the buffer.
+    pub phys_addr: PhysicalAddress,
+    /// The length of the buffer in bytes.
+    pub len: u32,
+    /// Whether the device writes to this buffer (`true`) or reads from it (`false`).
+    pub device_writable: bool,
+}
+
+/// A split virtqueue in DMA memory.
+pub struct Virtqueue {
+    /// The index of this queue within its device.
+    index: u16,
+    /// The number of descriptors in this queue, which is always a power of two.
+    size: u16,
+    /// The memory that holds the descriptor table, available ring, and used ring.
+    memory: MappedPages,
+    phys_addr: PhysicalAddress,
+    /// The offset of the available ring within `memory`.
+    avail_offset: usize,
+    /// The offset of the used ring within `memory`.
+    used_offset: usize,
+    /// The first descriptor in the list of free descriptors, which are chained by their `next` field.
+    free_head: u16,
+    num_free: u16,
+    /// The index of the next entry in the available ring, which is published to the device.
+    next_avail_idx: u16,
+    /// The index of the next entry in the used ring that we have not yet processed.
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    /// Allocates a new virtqueue with the given `index` and number of descriptors (`size`),
+    /// which must be a power of two.
+    pub fn new(index: u16, size: u16) -> Result<Virtqueue, &'static str> {
+        if size == 0 || !size.is_power_of_two() {
+            return Err("virtqueue size must be a nonzero power of two");
+        }
+        let num = size as usize;
+        let avail_offset = num * size_of::<Descriptor>();
+        // The available ring has a flags field, an index field, the ring, and a `used_event` field.
+        let avail_size = 2 + 2 + 2 * num + 2;
+        let used_offset = align_up(avail_offset + avail_size, USED_RING_ALIGNMENT);
+        // The used ring has a flags field, an index field, the ring, and an `avail_event` field.
+        let used_size = 2 + 2 + num * size_of::<UsedElement>() + 2;
+        let total_size = align_up(used_offset + used_size, USED_RING_ALIGNMENT);
+
+        let (mut memory, phys_addr) = create_contiguous_mapping(total_size, VIRTIO_MAPPING_FLAGS)?;
+        memory.as_slice_mut::<u8>(0, total_size)?.fill(0);
+
+        let mut queue = Virtqueue {
+            index,
+            size,
+            memory,
+            phys_addr,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            next_avail_idx: 0,
+            last_used_idx: 0,
+        };
+        // Chain all of the descriptors into the free list.
+        for i in 0 .. size - 1 {
+            queue.descriptor(i)?.next.write(i + 1);
+        }
+        Ok(queue)
+    }
+
+    /// Returns the index of this queue within its device.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Returns the number of descriptors in this queue.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Returns the number of descriptors that are not currently part of a submitted request.
+    pub fn num_free(&self) -> usize {
+        self.num_free as usize
+    }
+
+    /// Returns the physical address of the descriptor table.
+    pub fn descriptor_table_address(&self) -> PhysicalAddress {
+        self.phys_addr
+    }
+
+    /// Returns the physical address of the available ring.
+    pub fn available_ring_address(&self) -> PhysicalAddress {
+        self.phys_addr + self.avail_offset
+    }
+
+    /// Returns the physical address of the used ring.
+    pub fn used_ring_address(&self) -> PhysicalAddress {
+        self.phys_addr + self.used_offset
+    }
+
+    /// Submits a request consisting of the given chain of `buffers` to the device.
+    ///
+    /// The device isn't aware of the new request until it is notified via `Transport::notify()`.
+    ///
+    /// Returns the head descriptor of the request, which identifies it when it is later
+    /// returned from [`pop_used()`](#method.pop_used).
+    pub fn add(&mut self, buffers: &[VirtqueueBuffer]) -> Result<u16, &'static str> {
+        if buffers.is_empty() {
+            return Err("cannot add an empty request to a virtqueue");
+        }
+        if buffers.len() > self.num_free as usize {
+            return Err("not enough free descriptors in the virtqueue");
+        }
+
+        let head = self.free_head;
+        let mut idx = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let is_last = i == buffers.len() - 1;
+            let desc = self.descriptor(idx)?;
+            desc.addr.write(buffer.phys_addr.value() as u64);
+            desc.len.write(buffer.len);
+            let mut flags = if buffer.device_writable { VIRTQ_DESC_F_WRITE } else { 0 };
+            if !is_last {
+                flags |= VIRTQ_DESC_F_NEXT;
+            }
+            desc.flags.write(flags);
+            // Free descriptors are already chained via their `next` field,
+            // so the next free descriptor is also the next descriptor in this request.
+            let next = desc.next.read();
+            if is_last {
+                self.free_head = next;
+            } else {
+                idx = next;
+            }
+        }
+        self.num_free -= buffers.len() as u16;
+
+        let ring_slot = (self.next_avail_idx % self.size) as usize;
+        let avail_offset = self.avail_offset;
+        self.memory.as_type_mut::<Volatile<u16>>(avail_offset + 4 + 2 * ring_slot)?.write(head);
+        // The device must see the ring entry before it sees the updated index.
+        fence(Ordering::SeqCst);
+        self.next_avail_idx = self.next_avail_idx.wrapping_add(1);
+        self.memory.as_type_mut::<Volatile<u16>>(avail_offset + 2)?.write(self.next_avail_idx);
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Returns `true` if the device has completed a request that we haven't yet popped.
+    pub fn has_used(&self) -> bool {
+        self.memory.as_type::<Volatile<u16>>(self.used_offset + 2)
+            .map(|used_idx| used_idx.read() != self.last_used_idx)
+            .unwrap_or(false)
+    }
+
+    /// Removes and returns the next request completed by the device, if any,
+    /// and frees its descriptors.
+    ///
+    /// Returns the request's head descriptor and the number of bytes that the device wrote into its buffers.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        // Don't read the used element until after we've observed the device's updated index.
+        fence(Ordering::SeqCst);
+        let ring_slot = (self.last_used_idx % self.size) as usize;
+        let elem_offset = self.used_offset + 4 + ring_slot * size_of::<UsedElement>();
+        let (id, len) = {
+            let elem = self.memory.as_type::<UsedElement>(elem_offset).ok()?;
+            (elem.id.read() as u16, elem.len.read())
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        if let Err(e) = self.free_chain(id) {
+            error!("virtqueue {}: failed to free descriptors of completed request: {}", self.index, e);
+        }
+        Some((id, len))
+    }
+
+    /// Returns the chain of descriptors starting at `head` to the free list.
+    fn free_chain(&mut self, head: u16) -> Result<(), &'static str> {
+        let mut idx = head;
+        loop {
+            let free_head = self.free_head;
+            let desc = self.descriptor(idx)?;
+            let flags = desc.flags.read();
+            desc.flags.write(0);
+            self.num_free += 1;
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                desc.next.write(free_head);
+                break;
+            }
+            idx = desc.next.read();
+        }
+        self.free_head = head;
+        Ok(())
+    }
+
+    fn descriptor(&mut self, idx: u16) -> Result<&mut Descriptor, &'static str> {
+        if idx >= self.size {
+            return Err("virtqueue descriptor index out of bounds");
+        }
+        self.memory.as_type_mut(idx as usize * size_of::<Descriptor>())
+    }
+}
+
+const fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
@@ -0,0 +1,468 @@
+//! Driver for virtio block devices, e.g., QEMU's `virtio-blk-pci` disks.
+//!
+//! The primary struct of interest is [`VirtioBlk`], which implements `StorageDevice`.
+//! It supports both the modern and legacy virtio PCI transports.
+//!
+//! Each request is a chain of three buffers in the device's request queue:
+//! a header that specifies the request type and starting sector, the data buffer,
+//! and a status byte written by the device upon completion.
+//! Large requests are split across multiple request slots that are submitted together,
+//! and the requesting task sleeps until the device raises an interrupt to signal their completion.
+//! If the device's interrupt line can't be used, completions are polled for instead.
+
+#![no_std]
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+
+use core::mem::size_of;
+use alloc::{
+    boxed::Box,
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex;
+use irq_safety::MutexIrqSafe;
+use memory::{MappedPages, PhysicalAddress, create_contiguous_mapping};
+use pci::{PciDevice, PCI_INTERRUPT_LINE};
+use interrupts::{eoi, IRQ_BASE_OFFSET};
+use wait_queue::WaitQueue;
+use virtio::{Transport, Virtqueue, VirtqueueBuffer, VIRTIO_MAPPING_FLAGS};
+use storage_device::{StorageController, StorageDevice, StorageDeviceRef};
+use io::{BlockIo, BlockReader, BlockWriter, IoError, KnownLength};
+use volatile::Volatile;
+use zerocopy::FromBytes;
+use x86_64::structures::idt::InterruptStackFrame;
+
+
+/// Virtio block devices always address data in units of 512-byte sectors,
+/// regardless of their optimal block size.
+const SECTOR_SIZE_IN_BYTES: usize = 512;
+
+/// The size of the data buffer for each request slot, which is the most data transferred by a single request.
+const SLOT_BUFFER_SIZE_IN_BYTES: usize = 64 * SECTOR_SIZE_IN_BYTES;
+/// The maximum number of requests that may be in flight at once.
+const MAX_REQUEST_SLOTS: usize = 16;
+/// Each request uses a header descriptor, a data descriptor, and a status descriptor.
+const DESCRIPTORS_PER_REQUEST: usize = 3;
+
+/// The index of the request queue, which is the only queue used by this driver.
+const REQUEST_QUEUE_INDEX: u16 = 0;
+
+/// Feature bits of virtio block devices.
+const VIRTIO_BLK_F_SIZE_MAX: u64 = 1 << 1;
+const VIRTIO_BLK_F_RO:       u64 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH:    u64 = 1 << 9;
+/// The features supported by this driver.
+const SUPPORTED_FEATURES: u64 = VIRTIO_BLK_F_SIZE_MAX | VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH;
+
+/// Offsets of fields in the device-specific configuration.
+const CONFIG_CAPACITY: usize = 0;
+const CONFIG_SIZE_MAX: usize = 8;
+
+/// Request types.
+const VIRTIO_BLK_T_IN:    u32 = 0;
+const VIRTIO_BLK_T_OUT:   u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+/// Request status values written by the device.
+const VIRTIO_BLK_S_OK:     u8 = 0;
+const VIRTIO_BLK_S_IOERR:  u8 = 1;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+/// The value of a request's status byte before the device has completed it.
+const STATUS_PENDING: u8 = 0xFF;
+
+/// The header of each request, which is read by the device.
+#[derive(FromBytes)]
+#[repr(C)]
+struct RequestHeader {
+    request_type: Volatile<u32>,
+    _reserved:    Volatile<u32>,
+    sector:       Volatile<u64>,
+}
+
+/// The request headers of all slots are at the beginning of the request memory,
+/// followed by the status bytes of all slots.
+const STATUS_OFFSET: usize = MAX_REQUEST_SLOTS * size_of::<RequestHeader>();
+const REQUEST_MEMORY_SIZE: usize = STATUS_OFFSET + MAX_REQUEST_SLOTS;
+
+
+/// Returns `true` if the given PCI device is a virtio block device.
+pub fn is_virtio_blk(pci_device: &PciDevice) -> bool {
+    virtio::device_type(pci_device) == Some(virtio::DEVICE_TYPE_BLOCK)
+}
+
+
+/// A virtio block device.
+pub struct VirtioBlk {
+    /// The transport, which is shared with the interrupt handler so it can read the ISR status.
+    transport: Arc<MutexIrqSafe<Transport>>,
+    /// The queue through which all requests are submitted.
+    queue: Virtqueue,
+    /// The request headers and status bytes, one of each per request slot.
+    requests: MappedPages,
+    requests_phys: PhysicalAddress,
+    /// The data buffers, one per request slot, each of size `SLOT_BUFFER_SIZE_IN_BYTES`.
+    buffers: MappedPages,
+    buffers_phys: PhysicalAddress,
+    /// The number of request slots that can be in flight at once.
+    num_slots: usize,
+    /// The most bytes that a single request may transfer.
+    max_transfer_size: usize,
+    /// The size of the device in sectors.
+    capacity_in_sectors: usize,
+    read_only: bool,
+    supports_flush: bool,
+    /// The state used to wait for completion interrupts, if interrupts are enabled.
+    interrupt: Option<Arc<InterruptState>>,
+}
+
+impl VirtioBlk {
+    /// Initializes the virtio block device that is connected as the given PCI device.
+    pub fn new(pci_device: &PciDevice) -> Result<VirtioBlk, &'static str> {
+        let transport = Arc::new(MutexIrqSafe::new(Transport::new(pci_device)?));
+        Self::init(pci_device, transport.clone()).map_err(|e| {
+            transport.lock().add_status(virtio::STATUS_FAILED);
+            e
+        })
+    }
+
+    fn init(pci_device: &PciDevice, transport: Arc<MutexIrqSafe<Transport>>) -> Result<VirtioBlk, &'static str> {
+        let features = transport.lock().begin_init(SUPPORTED_FEATURES)?;
+
+        let queue_size = transport.lock().preferred_queue_size(REQUEST_QUEUE_INDEX);
+        if (queue_size as usize) < DESCRIPTORS_PER_REQUEST {
+            return Err("virtio-blk request queue was too small");
+        }
+        let queue = Virtqueue::new(REQUEST_QUEUE_INDEX, queue_size)?;
+        transport.lock().setup_queue(&queue)?;
+
+        let (capacity_in_sectors, size_max) = {
+            let t = transport.lock();
+            (t.read_config_u64(CONFIG_CAPACITY) as usize, t.read_config_u32(CONFIG_SIZE_MAX) as usize)
+        };
+        let mut max_transfer_size = SLOT_BUFFER_SIZE_IN_BYTES;
+        if features & VIRTIO_BLK_F_SIZE_MAX != 0 && size_max < max_transfer_size {
+            max_transfer_size = size_max - (size_max % SECTOR_SIZE_IN_BYTES);
+            if max_transfer_size == 0 {
+                return Err("virtio-blk device's maximum segment size was smaller than a sector");
+            }
+        }
+
+        let num_slots = core::cmp::min(MAX_REQUEST_SLOTS, queue_size as usize / DESCRIPTORS_PER_REQUEST);
+        let (requests, requests_phys) = create_contiguous_mapping(REQUEST_MEMORY_SIZE, VIRTIO_MAPPING_FLAGS)?;
+        let (buffers, buffers_phys) = create_contiguous_mapping(num_slots * SLOT_BUFFER_SIZE_IN_BYTES, VIRTIO_MAPPING_FLAGS)?;
+
+        let interrupt = enable_interrupts(pci_device, &transport);
+        transport.lock().driver_ok();
+
+        let device = VirtioBlk {
+            transport,
+            queue,
+            requests,
+            requests_phys,
+            buffers,
+            buffers_phys,
+            num_slots,
+            max_transfer_size,
+            capacity_in_sectors,
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+            supports_flush: features & VIRTIO_BLK_F_FLUSH != 0,
+            interrupt,
+        };
+        info!("virtio-blk device at {:?}: {} sectors, {} transport, read-only: {}, interrupt: {:?}",
+            pci_device.location,
+            device.capacity_in_sectors,
+            if device.transport.lock().is_modern() { "modern" } else { "legacy" },
+            device.read_only,
+            device.interrupt.as_ref().map(|i| i.interrupt_num),
+        );
+        Ok(device)
+    }
+
+    /// Reads data from this device starting at the given `offset_in_sectors` into the provided `buffer`.
+    /// The length of the given `buffer` determines the number of bytes to be read.
+    ///
+    /// As content is read from the device at sector granularity,
+    /// the buffer length must be a multiple of the sector size (512 bytes),
+    /// and the offset is specified in number of sectors (not number of bytes) from the beginning of the device.
+    ///
+    /// Returns the number of sectors (*not bytes*) that were successfully read from the device.
+    pub fn read_sectors(&mut self, buffer: &mut [u8], offset_in_sectors: usize) -> Result<usize, &'static str> {
+        let sector_count = self.check_request(buffer.len(), offset_in_sectors)?;
+        let max_transfer_size = self.max_transfer_size;
+
+        let mut sector = offset_in_sectors;
+        for batch in buffer.chunks_mut(self.num_slots * max_transfer_size) {
+            let mut submitted = 0;
+            for (slot, chunk) in batch.chunks(max_transfer_size).enumerate() {
+                self.submit(slot, VIRTIO_BLK_T_IN, sector, chunk.len())?;
+                submitted += 1;
+                sector += chunk.len() / SECTOR_SIZE_IN_BYTES;
+            }
+            self.complete(submitted)?;
+
+            for (slot, chunk) in batch.chunks_mut(max_transfer_size).enumerate() {
+                let len = chunk.len();
+                chunk.copy_from_slice(&self.slot_buffer(slot)?[..len]);
+            }
+        }
+        Ok(sector_count)
+    }
+
+    /// Writes data from the provided `buffer` to this device, starting at the given `offset_in_sectors` into the device.
+    /// The length of the given `buffer` determines the number of bytes to be written.
+    ///
+    /// As content is written to the device at sector granularity,
+    /// the buffer length must be a multiple of the sector size (512 bytes),
+    /// and the offset is specified in number of sectors (not number of bytes) from the beginning of the device.
+    ///
+    /// Returns the number of sectors (*not bytes*) that were successfully written to the device.
+    pub fn write_sectors(&mut self, buffer: &[u8], offset_in_sectors: usize) -> Result<usize, &'static str> {
+        if self.read_only {
+            return Err("virtio-blk device is read-only");
+        }
+        let sector_count = self.check_request(buffer.len(), offset_in_sectors)?;
+        let max_transfer_size = self.max_transfer_size;
+
+        let mut sector = offset_in_sectors;
+        for batch in buffer.chunks(self.num_slots * max_transfer_size) {
+            let mut submitted = 0;
+            for (slot, chunk) in batch.chunks(max_transfer_size).enumerate() {
+                self.slot_buffer(slot)?[..chunk.len()].copy_from_slice(chunk);
+                self.submit(slot, VIRTIO_BLK_T_OUT, sector, chunk.len())?;
+                submitted += 1;
+                sector += chunk.len() / SECTOR_SIZE_IN_BYTES;
+            }
+            self.complete(submitted)?;
+        }
+        Ok(sector_count)
+    }
+
+    /// Instructs the device to write its volatile write cache to persistent media.
+    ///
+    /// This does nothing if the device doesn't support flushing, i.e., it has no volatile write cache.
+    pub fn flush_cache(&mut self) -> Result<(), &'static str> {
+        if !self.supports_flush {
+            return Ok(());
+        }
+        self.submit(0, VIRTIO_BLK_T_FLUSH, 0, 0)?;
+        self.complete(1)
+    }
+
+    /// Returns `true` if this device cannot be written to.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns `true` if request completions are signaled by interrupts rather than polled for.
+    pub fn uses_interrupts(&self) -> bool {
+        self.interrupt.is_some()
+    }
+
+    /// Checks that a request of `length_in_bytes` at `offset_in_sectors` is valid for this device,
+    /// and returns the number of sectors that it covers.
+    fn check_request(&self, length_in_bytes: usize, offset_in_sectors: usize) -> Result<usize, &'static str> {
+        if length_in_bytes % SECTOR_SIZE_IN_BYTES != 0 {
+            return Err("The buffer length must be a multiple of sector size (512) bytes. Virtio block devices can only transfer at sector granularity.");
+        }
+        let sector_count = length_in_bytes / SECTOR_SIZE_IN_BYTES;
+        if offset_in_sectors + sector_count > self.capacity_in_sectors {
+            return Err("offset_in_sectors was out of bounds");
+        }
+        Ok(sector_count)
+    }
+
+    /// Adds a request of the given type to the request queue using the given request slot,
+    /// which transfers `data_len` bytes to or from that slot's buffer.
+    ///
+    /// The device isn't notified of the request until [`complete()`](#method.complete) is invoked.
+    fn submit(&mut self, slot: usize, request_type: u32, sector: usize, data_len: usize) -> Result<(), &'static str> {
+        let header_offset = slot * size_of::<RequestHeader>();
+        let status_offset = STATUS_OFFSET + slot;
+        {
+            let header: &mut RequestHeader = self.requests.as_type_mut(header_offset)?;
+            header.request_type.write(request_type);
+            header.sector.write(sector as u64);
+        }
+        self.requests.as_type_mut::<Volatile<u8>>(status_offset)?.write(STATUS_PENDING);
+
+        let header = VirtqueueBuffer {
+            phys_addr: self.requests_phys + header_offset,
+            len: size_of::<RequestHeader>() as u32,
+            device_writable: false,
+        };
+        let status = VirtqueueBuffer {
+            phys_addr: self.requests_phys + status_offset,
+            len: 1,
+            device_writable: true,
+        };
+        if data_len == 0 {
+            self.queue.add(&[header, status])?;
+        } else {
+            let data = VirtqueueBuffer {
+                phys_addr: self.buffers_phys + slot * SLOT_BUFFER_SIZE_IN_BYTES,
+                len: data_len as u32,
+                device_writable: request_type == VIRTIO_BLK_T_IN,
+            };
+            self.queue.add(&[header, data, status])?;
+        }
+        Ok(())
+    }
+
+    /// Notifies the device of the requests that were submitted in the first `num_requests` slots,
+    /// waits for all of them to complete, and then checks whether they succeeded.
+    fn complete(&mut self, num_requests: usize) -> Result<(), &'static str> {
+        self.transport.lock().notify(REQUEST_QUEUE_INDEX);
+
+        let mut remaining = num_requests;
+        while remaining > 0 {
+            while remaining > 0 && self.queue.pop_used().is_some() {
+                remaining -= 1;
+            }
+            if remaining == 0 {
+                break;
+            }
+            match self.interrupt.as_ref() {
+                Some(interrupt) => {
+                    let queue = &self.queue;
+                    let condition = || if queue.has_used() { Some(()) } else { None };
+                    // If the current task can't sleep, e.g., during early initialization, just poll instead.
+                    if interrupt.completions.wait_until(&condition).is_err() {
+                        core::hint::spin_loop();
+                    }
+                }
+                None => core::hint::spin_loop(),
+            }
+        }
+
+        for slot in 0 .. num_requests {
+            match self.requests.as_type::<Volatile<u8>>(STATUS_OFFSET + slot)?.read() {
+                VIRTIO_BLK_S_OK     => { }
+                VIRTIO_BLK_S_IOERR  => return Err("virtio-blk device reported an I/O error"),
+                VIRTIO_BLK_S_UNSUPP => return Err("virtio-blk device doesn't support the request"),
+                _                   => return Err("virtio-blk device returned an unknown request status"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the data buffer of the given request slot.
+    fn slot_buffer(&mut self, slot: usize) -> Result<&mut [u8], &'static str> {
+        self.buffers.as_slice_mut(slot * SLOT_BUFFER_SIZE_IN_BYTES, SLOT_BUFFER_SIZE_IN_BYTES)
+    }
+}
+
+impl StorageDevice for VirtioBlk {
+    fn size_in_blocks(&self) -> usize {
+        self.capacity_in_sectors
+    }
+}
+impl BlockIo for VirtioBlk {
+    fn block_size(&self) -> usize { SECTOR_SIZE_IN_BYTES }
+}
+impl KnownLength for VirtioBlk {
+    fn len(&self) -> usize { self.block_size() * self.size_in_blocks() }
+}
+impl BlockReader for VirtioBlk {
+    fn read_blocks(&mut self, buffer: &mut [u8], block_offset: usize) -> Result<usize, IoError> {
+        self.read_sectors(buffer, block_offset).map_err(IoError::from)
+    }
+}
+impl BlockWriter for VirtioBlk {
+    fn write_blocks(&mut self, buffer: &[u8], block_offset: usize) -> Result<usize, IoError> {
+        self.write_sectors(buffer, block_offset).map_err(IoError::from)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.flush_cache().map_err(IoError::from)
+    }
+}
+
+pub type VirtioBlkRef = Arc<Mutex<VirtioBlk>>;
+
+
+/// A virtio block PCI device acts as its own storage controller with exactly one storage device.
+pub struct VirtioBlkController {
+    device: VirtioBlkRef,
+}
+
+impl VirtioBlkController {
+    /// Creates a new controller for the virtio block device that is connected as the given PCI device.
+    pub fn new(pci_device: &PciDevice) -> Result<VirtioBlkController, &'static str> {
+        Ok(VirtioBlkController {
+            device: Arc::new(Mutex::new(VirtioBlk::new(pci_device)?)),
+        })
+    }
+
+    /// Returns the virtio block device of this controller.
+    pub fn device(&self) -> &VirtioBlkRef {
+        &self.device
+    }
+}
+
+impl StorageController for VirtioBlkController {
+    fn devices<'c>(&'c self) -> Box<(dyn Iterator<Item = StorageDeviceRef> + 'c)> {
+        Box::new(core::iter::once(Arc::clone(&self.device) as StorageDeviceRef))
+    }
+}
+
+
+/// The state used by a virtio block device's interrupt handler to wake up tasks awaiting completions.
+struct InterruptState {
+    transport: Arc<MutexIrqSafe<Transport>>,
+    interrupt_num: u8,
+    completions: WaitQueue,
+}
+
+/// The interrupt states of all virtio block devices that use interrupts.
+///
+/// Multiple devices may share the same interrupt line, so the handler checks all of them.
+static INTERRUPT_STATES: MutexIrqSafe<Vec<Arc<InterruptState>>> = MutexIrqSafe::new(Vec::new());
+
+/// Registers the interrupt handler for the given device's legacy interrupt line.
+///
+/// Returns `None` if the interrupt line cannot be used, in which case completions must be polled for.
+fn enable_interrupts(pci_device: &PciDevice, transport: &Arc<MutexIrqSafe<Transport>>) -> Option<Arc<InterruptState>> {
+    let interrupt_line = pci_device.pci_read_8(PCI_INTERRUPT_LINE);
+    if interrupt_line == 0xFF {
+        warn!("virtio-blk device at {:?} has no interrupt line; polling for completions instead.", pci_device.location);
+        return None;
+    }
+    let interrupt_num = interrupt_line + IRQ_BASE_OFFSET;
+
+    let state = Arc::new(InterruptState {
+        transport: Arc::clone(transport),
+        interrupt_num,
+        completions: WaitQueue::new(),
+    });
+    INTERRUPT_STATES.lock().push(Arc::clone(&state));
+
+    match interrupts::register_interrupt(interrupt_num, virtio_blk_handler) {
+        Ok(()) => Some(state),
+        // Another virtio block device on the same interrupt line already registered this handler.
+        Err(existing) if existing == virtio_blk_handler as usize => Some(state),
+        Err(existing) => {
+            warn!("virtio-blk interrupt {:#X} was already in use by handler {:#X}! Sharing IRQs with other devices \
+                is currently unsupported, so polling for completions instead.", interrupt_num, existing,
+            );
+            INTERRUPT_STATES.lock().retain(|s| !Arc::ptr_eq(s, &state));
+            None
+        }
+    }
+}
+
+/// The interrupt handler for all virtio block devices.
+extern "x86-interrupt" fn virtio_blk_handler(_stack_frame: InterruptStackFrame) {
+    let states = INTERRUPT_STATES.lock();
+    let mut interrupt_num = states.first().map(|s| s.interrupt_num);
+    for state in states.iter() {
+        // Reading the ISR status acknowledges the interrupt, which deasserts the interrupt line.
+        if state.transport.lock().read_isr() & virtio::ISR_QUEUE != 0 {
+            state.completions.notify_all();
+            interrupt_num = Some(state.interrupt_num);
+        }
+    }
+    eoi(interrupt_num);
+}
@@ -0,0 +1,514 @@
+//! Driver for virtio-net network interface cards, e.g., QEMU's `virtio-net-pci` NIC.
+//!
+//! The NIC has one or more pairs of receive and transmit virtqueues.
+//! If the device supports multiple queue pairs, we use up to one pair per CPU,
+//! and each packet is transmitted on the queue of the CPU that sent it.
+//!
+//! Every packet is preceded by a `virtio_net_hdr`, which we place in its own descriptor
+//! so that received frames can be handed to higher layers without copying.
+//! We don't negotiate any offloads, so the header of transmitted packets is always zeroed
+//! and the header of received packets is ignored.
+
+#![no_std]
+#![feature(abi_x86_interrupt)]
+
+#[macro_use] extern crate log;
+#[macro_use] extern crate lazy_static;
+extern crate alloc;
+
+use alloc::{format, sync::Arc, vec, vec::Vec};
+use spin::Once;
+use irq_safety::MutexIrqSafe;
+use memory::{MappedPages, PhysicalAddress, create_contiguous_mapping};
+use pci::{PciDevice, PCI_INTERRUPT_LINE};
+use interrupts::{eoi, IRQ_BASE_OFFSET};
+use x86_64::structures::idt::InterruptStackFrame;
+use virtio::{Transport, Virtqueue, VirtqueueBuffer, VIRTIO_MAPPING_FLAGS};
+use network_interface_card::NetworkInterfaceCard;
+use nic_buffers::{ReceiveBuffer, ReceivedFrame, TransmitBuffer};
+use nic_initialization::init_rx_buf_pool;
+use nic_queues::{BufferQueue, BufferRxQueue, BufferTxQueue};
+use volatile::Volatile;
+
+
+/// Feature bits of virtio-net devices.
+const VIRTIO_NET_F_MAC:     u64 = 1 << 5;
+const VIRTIO_NET_F_STATUS:  u64 = 1 << 16;
+const VIRTIO_NET_F_CTRL_VQ: u64 = 1 << 17;
+const VIRTIO_NET_F_MQ:      u64 = 1 << 22;
+/// The features supported by this driver.
+const SUPPORTED_FEATURES: u64 = VIRTIO_NET_F_MAC | VIRTIO_NET_F_STATUS | VIRTIO_NET_F_CTRL_VQ | VIRTIO_NET_F_MQ;
+
+/// Offsets of fields in the device-specific configuration.
+const CONFIG_MAC:                 usize = 0;
+const CONFIG_STATUS:              usize = 6;
+const CONFIG_MAX_VIRTQUEUE_PAIRS: usize = 8;
+
+/// The link status bit of the `status` configuration field.
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
+
+/// The size of the `virtio_net_hdr` that precedes every packet.
+/// The modern transport always includes the trailing `num_buffers` field.
+const NET_HEADER_SIZE_LEGACY: usize = 10;
+const NET_HEADER_SIZE_MODERN: usize = 12;
+/// The distance between the headers of consecutive receive buffers.
+const NET_HEADER_STRIDE: usize = 16;
+
+/// The control queue class and command that set the number of queue pairs in use.
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+const VIRTIO_NET_OK: u8 = 0;
+/// How many times to poll the control queue before giving up on the device answering a command.
+const CONTROL_TIMEOUT_POLLS: usize = 1_000_000;
+
+/// The maximum number of receive/transmit queue pairs that we use.
+const MAX_QUEUE_PAIRS: usize = 4;
+/// The maximum number of receive buffers that are given to each receive queue.
+const MAX_RX_BUFFERS_PER_QUEUE: usize = 64;
+/// Each packet uses a header descriptor and a data descriptor.
+const DESCRIPTORS_PER_PACKET: usize = 2;
+
+/// Each receive buffer can hold a full Ethernet frame.
+const RX_BUFFER_SIZE_IN_BYTES: u16 = 2048;
+
+/// How many ReceiveBuffers are preallocated for this driver to use.
+const RX_BUFFER_POOL_SIZE: usize = MAX_QUEUE_PAIRS * MAX_RX_BUFFERS_PER_QUEUE;
+lazy_static! {
+    /// The pool of pre-allocated receive buffers that are used by the virtio-net NIC
+    /// and temporarily given to higher layers in the networking stack.
+    static ref RX_BUFFER_POOL: mpmc::Queue<ReceiveBuffer> = mpmc::Queue::with_capacity(RX_BUFFER_POOL_SIZE);
+}
+
+/// The single instance of the virtio-net NIC.
+/// TODO: in the future, we should support multiple NICs.
+static VIRTIO_NET_NIC: Once<MutexIrqSafe<VirtioNetNic>> = Once::new();
+
+/// Returns a reference to the VirtioNetNic wrapped in a MutexIrqSafe,
+/// if it exists and has been initialized.
+pub fn get_virtio_net_nic() -> Option<&'static MutexIrqSafe<VirtioNetNic>> {
+    VIRTIO_NET_NIC.get()
+}
+
+/// Returns `true` if the given PCI device is a virtio-net NIC.
+pub fn is_virtio_net(pci_device: &PciDevice) -> bool {
+    virtio::device_type(pci_device) == Some(virtio::DEVICE_TYPE_NET)
+}
+
+
+/// A receive virtqueue, in which each receive buffer is preceded by its own `virtio_net_hdr`.
+///
+/// This implements the hooks needed by a `nic_queues::BufferRxQueue`,
+/// in which each receive buffer is identified by the head descriptor of its request.
+struct RxVirtqueue {
+    queue: Virtqueue,
+    /// The `virtio_net_hdr` of each receive buffer, one slot per buffer.
+    /// These are written by the device but never read by us.
+    _headers: MappedPages,
+    headers_phys: PhysicalAddress,
+    header_len: usize,
+    /// The header slots that are not currently in use by a receive buffer.
+    free_header_slots: Vec<usize>,
+    /// The header slot used by each request, indexed by its head descriptor.
+    header_slots_in_use: Vec<Option<usize>>,
+}
+
+impl RxVirtqueue {
+    fn new(queue: Virtqueue, header_len: usize) -> Result<RxVirtqueue, &'static str> {
+        let num_buffers = core::cmp::min(MAX_RX_BUFFERS_PER_QUEUE, queue.size() as usize / DESCRIPTORS_PER_PACKET);
+        let (headers, headers_phys) = create_contiguous_mapping(num_buffers * NET_HEADER_STRIDE, VIRTIO_MAPPING_FLAGS)?;
+        let header_slots_in_use = vec![None; queue.size() as usize];
+        Ok(RxVirtqueue {
+            queue,
+            _headers: headers,
+            headers_phys,
+            header_len,
+            free_header_slots: (0 .. num_buffers).collect(),
+            header_slots_in_use,
+        })
+    }
+}
+
+impl BufferQueue for RxVirtqueue {
+    fn num_tokens(&self) -> u16 {
+        self.queue.size()
+    }
+
+    fn can_add_buffer(&self) -> bool {
+        self.queue.num_free() >= DESCRIPTORS_PER_PACKET && !self.free_header_slots.is_empty()
+    }
+
+    fn add_buffer(&mut self, phys_addr: PhysicalAddress, len: u16, device_writable: bool) -> Result<u16, &'static str> {
+        let header_slot = self.free_header_slots.pop().ok_or("virtio-net rx queue has no free header slots")?;
+        let header = VirtqueueBuffer {
+            phys_addr: self.headers_phys + header_slot * NET_HEADER_STRIDE,
+            len: self.header_len as u32,
+            device_writable: true,
+        };
+        let data = VirtqueueBuffer { phys_addr, len: len as u32, device_writable };
+        match self.queue.add(&[header, data]) {
+            Ok(head) => {
+                self.header_slots_in_use[head as usize] = Some(header_slot);
+                Ok(head)
+            }
+            Err(e) => {
+                self.free_header_slots.push(header_slot);
+                Err(e)
+            }
+        }
+    }
+
+    fn pop_completed(&mut self) -> Option<(u16, usize)> {
+        let (head, len) = self.queue.pop_used()?;
+        if let Some(header_slot) = self.header_slots_in_use.get_mut(head as usize).and_then(Option::take) {
+            self.free_header_slots.push(header_slot);
+        }
+        Some((head, (len as usize).saturating_sub(self.header_len)))
+    }
+}
+
+
+/// A transmit virtqueue, in which every packet is preceded by the same zeroed `virtio_net_hdr`.
+///
+/// This implements the hooks needed by a `nic_queues::BufferTxQueue`.
+struct TxVirtqueue {
+    queue: Virtqueue,
+    header_phys: PhysicalAddress,
+    header_len: usize,
+}
+
+impl BufferQueue for TxVirtqueue {
+    fn num_tokens(&self) -> u16 {
+        self.queue.size()
+    }
+
+    fn can_add_buffer(&self) -> bool {
+        self.queue.num_free() >= DESCRIPTORS_PER_PACKET
+    }
+
+    fn add_buffer(&mut self, phys_addr: PhysicalAddress, len: u16, device_writable: bool) -> Result<u16, &'static str> {
+        let header = VirtqueueBuffer {
+            phys_addr: self.header_phys,
+            len: self.header_len as u32,
+            device_writable: false,
+        };
+        let data = VirtqueueBuffer { phys_addr, len: len as u32, device_writable };
+        self.queue.add(&[header, data])
+    }
+
+    fn pop_completed(&mut self) -> Option<(u16, usize)> {
+        self.queue.pop_used().map(|(head, len)| (head, len as usize))
+    }
+}
+
+
+/// Struct representing a virtio-net network interface card.
+pub struct VirtioNetNic {
+    transport: Transport,
+    /// The interrupt number, if the device's interrupt line could be determined.
+    interrupt_num: Option<u8>,
+    /// The MAC address of this NIC as provided by the device.
+    mac_hardware: [u8; 6],
+    /// The optional spoofed MAC address to use in place of `mac_hardware` when transmitting.
+    mac_spoofed: Option<[u8; 6]>,
+    /// Whether the device reports its link status.
+    has_link_status: bool,
+    rx_queues: Vec<BufferRxQueue<RxVirtqueue>>,
+    tx_queues: Vec<BufferTxQueue<TxVirtqueue>>,
+    /// The control queue, if the device has one.
+    /// It must live as long as the NIC, because the device keeps using its memory once it's set up.
+    control_queue: Option<Virtqueue>,
+    /// The zeroed `virtio_net_hdr` shared by all transmitted packets.
+    _tx_header: MappedPages,
+    tx_header_phys: PhysicalAddress,
+    header_len: usize,
+    deferred_task: Option<task::JoinableTaskRef>,
+}
+
+impl NetworkInterfaceCard for VirtioNetNic {
+    fn send_packet(&mut self, transmit_buffer: TransmitBuffer) -> Result<(), &'static str> {
+        let queue_pair = cpu::current_cpu() as usize % self.tx_queues.len();
+        let tx_queue = &mut self.tx_queues[queue_pair];
+        tx_queue.send_on_queue(transmit_buffer)?;
+        self.transport.notify(tx_queue.queue.queue.index());
+        Ok(())
+    }
+
+    fn get_received_frame(&mut self) -> Option<ReceivedFrame> {
+        self.rx_queues.iter_mut().find_map(|rx_queue| rx_queue.return_frame())
+    }
+
+    fn poll_receive(&mut self) -> Result<(), &'static str> {
+        for rx_queue in self.rx_queues.iter_mut() {
+            if rx_queue.poll_queue_and_store_received_packets()? {
+                self.transport.notify(rx_queue.queue.queue.index());
+            }
+        }
+        Ok(())
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac_spoofed.unwrap_or(self.mac_hardware)
+    }
+}
+
+impl VirtioNetNic {
+    /// Initializes the new virtio-net network interface card that is connected as the given PciDevice.
+    ///
+    /// `init_interrupts` must be called after the NIC has been registered with the `net` subsystem.
+    pub fn init(pci_device: &PciDevice) -> Result<&'static MutexIrqSafe<VirtioNetNic>, &'static str> {
+        let transport = Transport::new(pci_device)?;
+        let (mut tx_header, tx_header_phys) = create_contiguous_mapping(NET_HEADER_STRIDE, VIRTIO_MAPPING_FLAGS)?;
+        tx_header.as_slice_mut::<u8>(0, NET_HEADER_STRIDE)?.fill(0);
+
+        let interrupt_line = pci_device.pci_read_8(PCI_INTERRUPT_LINE);
+        let mut nic = VirtioNetNic {
+            transport,
+            interrupt_num: if interrupt_line == 0xFF { None } else { Some(interrupt_line + IRQ_BASE_OFFSET) },
+            mac_hardware: [0; 6],
+            mac_spoofed: None,
+            has_link_status: false,
+            rx_queues: Vec::new(),
+            tx_queues: Vec::new(),
+            control_queue: None,
+            _tx_header: tx_header,
+            tx_header_phys,
+            header_len: NET_HEADER_SIZE_LEGACY,
+            deferred_task: None,
+        };
+        if let Err(e) = nic.init_device() {
+            nic.transport.add_status(virtio::STATUS_FAILED);
+            // The device may already use the queues, so it must be stopped before their memory is freed.
+            if nic.transport.reset().is_err() {
+                error!("virtio-net device couldn't be reset, leaking its queues");
+                core::mem::forget(nic);
+            }
+            return Err(e);
+        }
+
+        info!("virtio-net NIC at {:?}: MAC {:02x?}, {} queue pair(s), interrupt: {:?}",
+            pci_device.location, nic.mac_hardware, nic.num_queue_pairs(), nic.interrupt_num,
+        );
+        Ok(VIRTIO_NET_NIC.call_once(|| MutexIrqSafe::new(nic)))
+    }
+
+    /// Negotiates features with the device, reads its configuration, and sets up its queues.
+    fn init_device(&mut self) -> Result<(), &'static str> {
+        let features = self.transport.begin_init(SUPPORTED_FEATURES)?;
+        if features & VIRTIO_NET_F_MAC == 0 {
+            return Err("virtio-net device doesn't provide a MAC address");
+        }
+        for (i, byte) in self.mac_hardware.iter_mut().enumerate() {
+            *byte = self.transport.read_config_u8(CONFIG_MAC + i);
+        }
+        self.has_link_status = features & VIRTIO_NET_F_STATUS != 0;
+        self.header_len = if self.transport.is_modern() { NET_HEADER_SIZE_MODERN } else { NET_HEADER_SIZE_LEGACY };
+
+        // Multiple queue pairs can only be enabled through the control queue.
+        let multiqueue = features & VIRTIO_NET_F_MQ != 0 && features & VIRTIO_NET_F_CTRL_VQ != 0;
+        let max_queue_pairs = if multiqueue {
+            self.transport.read_config_u16(CONFIG_MAX_VIRTQUEUE_PAIRS) as usize
+        } else {
+            1
+        };
+        let num_queue_pairs = max_queue_pairs.min(cpu::cpu_count() as usize).min(MAX_QUEUE_PAIRS).max(1);
+
+        init_rx_buf_pool(num_queue_pairs * MAX_RX_BUFFERS_PER_QUEUE, RX_BUFFER_SIZE_IN_BYTES, &RX_BUFFER_POOL)?;
+
+        for pair in 0 .. num_queue_pairs {
+            let rx_queue = RxVirtqueue::new(self.setup_queue((2 * pair) as u16)?, self.header_len)?;
+            self.rx_queues.push(BufferRxQueue::new(pair as u8, rx_queue, RX_BUFFER_SIZE_IN_BYTES, &RX_BUFFER_POOL)?);
+            let tx_queue = TxVirtqueue {
+                queue: self.setup_queue((2 * pair + 1) as u16)?,
+                header_phys: self.tx_header_phys,
+                header_len: self.header_len,
+            };
+            self.tx_queues.push(BufferTxQueue::new(pair as u8, tx_queue));
+        }
+        // The control queue comes after the maximum number of queue pairs, not just the ones we use.
+        if multiqueue {
+            self.control_queue = Some(self.setup_queue((2 * max_queue_pairs) as u16)?);
+        }
+
+        self.transport.driver_ok();
+
+        // The receive buffers were added before the device was live, so it must be told about them now.
+        for rx_queue in self.rx_queues.iter() {
+            self.transport.notify(rx_queue.queue.queue.index());
+        }
+        if let Some(control_queue) = self.control_queue.as_mut() {
+            if num_queue_pairs > 1 {
+                set_queue_pairs(&mut self.transport, control_queue, num_queue_pairs as u16)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates the virtqueue with the given `index` and tells the device about it.
+    fn setup_queue(&mut self, index: u16) -> Result<Virtqueue, &'static str> {
+        let queue = Virtqueue::new(index, self.transport.preferred_queue_size(index))?;
+        self.transport.setup_queue(&queue)?;
+        Ok(queue)
+    }
+
+    /// Initializes the interrupt handler and enables interrupts for this virtio-net NIC.
+    ///
+    /// The provided `interface` must be the network interface associated with this NIC.
+    /// This interface's worker task will be woken up by a deferred task upon an interrupt
+    /// being triggered for a received packet.
+    ///
+    /// If the NIC has no usable interrupt line, received packets are instead picked up
+    /// whenever the interface is polled.
+    pub fn init_interrupts(
+        &mut self,
+        interface: Arc<net::NetworkInterface>,
+    ) -> Result<(), &'static str> {
+        let Some(interrupt_num) = self.interrupt_num else {
+            warn!("virtio-net NIC has no interrupt line; received packets will only be handled when polled.");
+            return Ok(());
+        };
+        let deferred_task = deferred_interrupt_tasks::register_interrupt_handler(
+            interrupt_num,
+            virtio_net_handler,
+            wake_interface_worker,
+            interface,
+            Some(format!("virtio_net_deferred_task_irq_{:#X}", interrupt_num)),
+        )
+        .map_err(|error| {
+            error!("error registering virtio-net handler: {:?}", error);
+            "virtio-net interrupt number was already in use! Sharing IRQs is currently unsupported."
+        })?;
+        self.deferred_task = Some(deferred_task);
+        Ok(())
+    }
+
+    pub fn spoof_mac(&mut self, spoofed_mac_addr: [u8; 6]) {
+        self.mac_spoofed = Some(spoofed_mac_addr);
+    }
+
+    /// Returns the number of receive/transmit queue pairs in use.
+    pub fn num_queue_pairs(&self) -> usize {
+        self.rx_queues.len()
+    }
+
+    /// Returns whether the link is up.
+    ///
+    /// Devices that don't report their link status are assumed to always be up.
+    pub fn link_up(&self) -> bool {
+        !self.has_link_status
+            || self.transport.read_config_u16(CONFIG_STATUS) & VIRTIO_NET_S_LINK_UP != 0
+    }
+
+    /// The main interrupt handling routine for the virtio-net NIC.
+    /// This should be invoked from the actual interrupt handler entry point.
+    fn handle_interrupt(&mut self) -> Result<(), &'static str> {
+        // Reading the ISR status acknowledges the interrupt, which deasserts the interrupt line.
+        let status = self.transport.read_isr();
+
+        if status & virtio::ISR_CONFIG != 0 {
+            debug!("virtio-net::handle_interrupt(): configuration changed, link up: {}", self.link_up());
+        }
+
+        if status & virtio::ISR_QUEUE != 0 {
+            for tx_queue in self.tx_queues.iter_mut() {
+                tx_queue.reclaim();
+            }
+            self.poll_receive()?;
+            if let Some(ref deferred_task) = self.deferred_task {
+                let _ = deferred_task
+                    .unblock()
+                    .expect("BUG: virtio_net::handle_interrupt(): couldn't unblock deferred task");
+            } else {
+                error!("virtio_net::handle_interrupt(): no deferred task");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl net::NetworkDevice for VirtioNetNic {
+    fn send(&mut self, buf: &[u8]) -> net::Result<()> {
+        let mut transmit_buffer = TransmitBuffer::new(buf.len() as u16).map_err(|_| net::Error::Exhausted)?;
+        transmit_buffer.copy_from_slice(buf);
+        self.send_packet(transmit_buffer).map_err(|_| net::Error::Unknown)
+    }
+
+    fn receive(&mut self) -> Option<ReceivedFrame> {
+        if let Some(frame) = self.get_received_frame() {
+            return Some(frame);
+        }
+        // Without interrupts, nothing else moves received packets into the receive queues.
+        if let Err(e) = self.poll_receive() {
+            error!("virtio_net: failed to poll for received packets: {}", e);
+        }
+        self.get_received_frame()
+    }
+
+    /// Returns the MAC address.
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac_spoofed.unwrap_or(self.mac_hardware)
+    }
+}
+
+/// Instructs the device to use the given number of queue pairs,
+/// which is sent as a command on the `control_queue`.
+///
+/// Returns an error if the device doesn't answer the command within `CONTROL_TIMEOUT_POLLS` polls.
+fn set_queue_pairs(transport: &mut Transport, control_queue: &mut Virtqueue, num_queue_pairs: u16) -> Result<(), &'static str> {
+    // The command consists of a class/command header, the number of pairs, and an ack byte written by the device.
+    const CLASS_OFFSET: usize = 0;
+    const DATA_OFFSET:  usize = 2;
+    const ACK_OFFSET:   usize = 4;
+
+    let (mut command, command_phys) = create_contiguous_mapping(ACK_OFFSET + 1, VIRTIO_MAPPING_FLAGS)?;
+    command.as_type_mut::<Volatile<u8>>(CLASS_OFFSET)?.write(VIRTIO_NET_CTRL_MQ);
+    command.as_type_mut::<Volatile<u8>>(CLASS_OFFSET + 1)?.write(VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET);
+    command.as_type_mut::<Volatile<u16>>(DATA_OFFSET)?.write(num_queue_pairs);
+    command.as_type_mut::<Volatile<u8>>(ACK_OFFSET)?.write(0xFF);
+
+    control_queue.add(&[
+        VirtqueueBuffer { phys_addr: command_phys + CLASS_OFFSET, len: 2, device_writable: false },
+        VirtqueueBuffer { phys_addr: command_phys + DATA_OFFSET,  len: 2, device_writable: false },
+        VirtqueueBuffer { phys_addr: command_phys + ACK_OFFSET,   len: 1, device_writable: true },
+    ])?;
+    transport.notify(control_queue.index());
+    let mut answered = false;
+    for _ in 0 .. CONTROL_TIMEOUT_POLLS {
+        if control_queue.pop_used().is_some() {
+            answered = true;
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    if !answered {
+        // The device may still write the ack byte later, so the command's memory must never be reused.
+        core::mem::forget(command);
+        return Err("virtio-net device didn't answer the command to set the number of queue pairs");
+    }
+
+    if command.as_type::<Volatile<u8>>(ACK_OFFSET)?.read() == VIRTIO_NET_OK {
+        Ok(())
+    } else {
+        Err("virtio-net device rejected the number of queue pairs")
+    }
+}
+
+extern "x86-interrupt" fn virtio_net_handler(_stack_frame: InterruptStackFrame) {
+    if let Some(nic_ref) = VIRTIO_NET_NIC.get() {
+        let mut nic = nic_ref.lock();
+        if let Err(e) = nic.handle_interrupt() {
+            error!("virtio_net_handler(): error handling interrupt: {:?}", e);
+        }
+        eoi(nic.interrupt_num);
+    } else {
+        error!("BUG: virtio_net_handler(): virtio-net NIC hasn't yet been initialized!");
+    }
+}
+
+/// This function is used as a deferred interrupt task.
+///
+/// After processing the interrupt, the worker task of the network interface associated with the virtio-net NIC
+/// is woken up to process the received data.
+fn wake_interface_worker(interface: &Arc<net::NetworkInterface>) -> Result<(), net::Error> {
+    interface.wake_worker();
+    Ok(())
+}
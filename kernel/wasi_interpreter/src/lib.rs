@@ -29,6 +29,7 @@ extern crate core2;
 extern crate fs_node;
 extern crate root;
 extern crate task;
+extern crate time;
 extern crate wasmi;
 
 use alloc::string::String;
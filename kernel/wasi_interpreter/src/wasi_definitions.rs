@@ -73,6 +73,7 @@ pub enum SystemCall {
     ArgsSizesGet,
     ArgsGet,
     ClockTimeGet,
+    FdFilestatGet,
 }
 
 impl FromStr for SystemCall {
@@ -102,6 +103,7 @@ impl FromStr for SystemCall {
             "args_sizes_get" => Ok(SystemCall::ArgsSizesGet),
             "args_get" => Ok(SystemCall::ArgsGet),
             "clock_time_get" => Ok(SystemCall::ClockTimeGet),
+            "fd_filestat_get" => Ok(SystemCall::FdFilestatGet),
             _ => Err("Unknown WASI system call."),
         }
     }
@@ -134,6 +136,7 @@ impl TryFrom<usize> for SystemCall {
             12 => Ok(SystemCall::ArgsSizesGet),
             13 => Ok(SystemCall::ArgsGet),
             14 => Ok(SystemCall::ClockTimeGet),
+            15 => Ok(SystemCall::FdFilestatGet),
             _ => Err("Unknown WASI system call."),
         }
     }
@@ -161,6 +164,7 @@ impl From<SystemCall> for usize {
             SystemCall::ArgsSizesGet => 12,
             SystemCall::ArgsGet => 13,
             SystemCall::ClockTimeGet => 14,
+            SystemCall::FdFilestatGet => 15,
         }
     }
 }
@@ -187,6 +191,7 @@ impl From<SystemCall> for Signature {
             SystemCall::ArgsSizesGet => sig!((I32,I32)->I32),
             SystemCall::ArgsGet => sig!((I32,I32)->I32),
             SystemCall::ClockTimeGet => sig!((I32,I64,I32)->I32),
+            SystemCall::FdFilestatGet => sig!((I32,I32)->I32),
         }
     }
 }
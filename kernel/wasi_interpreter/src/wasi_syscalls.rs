@@ -21,7 +21,7 @@ use crate::HostExternals;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::convert::TryFrom as _;
-use fs_node::{DirRef, FileOrDir};
+use fs_node::{DirRef, FileOrDir, NodeType};
 use wasmi::{MemoryRef, RuntimeArgs, RuntimeValue, Trap};
 
 /// Helper function to support retrieving args/env sizes.
@@ -670,14 +670,14 @@ pub fn execute_system_call(
             let clock_id: wasi::Clockid = wasmi_args.nth_checked(0).unwrap();
             let _precision: wasi::Timestamp = wasmi_args.nth_checked(1).unwrap();
 
-            // TODO: use rtc value converted to unix timestamp.
-            let unix_timestamp = 0;
-
             // fetch time.
             let timestamp: wasi::Timestamp = match clock_id {
                 wasi::CLOCKID_MONOTONIC => unimplemented!(),
                 wasi::CLOCKID_PROCESS_CPUTIME_ID => unimplemented!(),
-                wasi::CLOCKID_REALTIME => unix_timestamp,
+                wasi::CLOCKID_REALTIME => match time::approximate_wall_time() {
+                    Some(wall_time) => wall_time.as_nanos() as wasi::Timestamp,
+                    None => return Ok(Some(RuntimeValue::I32(From::from(wasi::ERRNO_NOTSUP)))),
+                },
                 wasi::CLOCKID_THREAD_CPUTIME_ID => unimplemented!(),
                 _ => {
                     return Ok(Some(RuntimeValue::I32(From::from(wasi::ERRNO_NOTSUP))));
@@ -689,5 +689,67 @@ pub fn execute_system_call(
             memory.set(ret_ptr, &timestamp.to_le_bytes()).unwrap();
             Ok(Some(RuntimeValue::I32(From::from(wasi::ERRNO_SUCCESS))))
         }
+
+        // Get the attributes of an open file.
+        //
+        // # Arguments
+        // * `fd`: the file descriptor number to get attributes of.
+        // * `stat_buf`: the buffer to store the file's attributes.
+        //
+        // # Return
+        // A WASI errno.
+        SystemCall::FdFilestatGet => {
+            let fd: wasi::Fd = wasmi_args.nth_checked(0).unwrap();
+            let metadata = match fd_table.get_posix_node(fd) {
+                Some(posix_node) => posix_node.theseus_file_or_dir.metadata(),
+                None => {
+                    return Ok(Some(RuntimeValue::I32(From::from(wasi::ERRNO_BADF))));
+                }
+            };
+
+            let filestat = wasi::Filestat {
+                dev: 0,
+                ino: 0,
+                filetype: match metadata.node_type {
+                    NodeType::Directory => wasi::FILETYPE_DIRECTORY,
+                    NodeType::Symlink => wasi::FILETYPE_SYMBOLIC_LINK,
+                    NodeType::File => wasi::FILETYPE_REGULAR_FILE,
+                },
+                nlink: 1,
+                size: metadata.size as wasi::Filesize,
+                atim: metadata.modified.as_nanos() as wasi::Timestamp,
+                mtim: metadata.modified.as_nanos() as wasi::Timestamp,
+                ctim: metadata.created.as_nanos() as wasi::Timestamp,
+            };
+
+            let stat_buf: u32 = wasmi_args.nth_checked(1).unwrap();
+
+            // write attributes to stat_buf, following the layout of `__wasi_filestat_t`.
+            memory.set(stat_buf, &[0; 64]).unwrap();
+            memory.set(stat_buf, &filestat.dev.to_le_bytes()).unwrap();
+            memory
+                .set(stat_buf.checked_add(8).unwrap(), &filestat.ino.to_le_bytes())
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(16).unwrap(), &[filestat.filetype])
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(24).unwrap(), &filestat.nlink.to_le_bytes())
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(32).unwrap(), &filestat.size.to_le_bytes())
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(40).unwrap(), &filestat.atim.to_le_bytes())
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(48).unwrap(), &filestat.mtim.to_le_bytes())
+                .unwrap();
+            memory
+                .set(stat_buf.checked_add(56).unwrap(), &filestat.ctim.to_le_bytes())
+                .unwrap();
+
+            Ok(Some(RuntimeValue::I32(From::from(wasi::ERRNO_SUCCESS))))
+        }
     }
 }
@@ -21,6 +21,7 @@ use crate::sys::time::SystemTime;
 use theseus_fs_node::{File as FileTrait, FileRef};
 use theseus_io::{ReaderWriter, LockableIo, KnownLength};
 use spin::Mutex;
+use alloc::string::String;
 
 /// This is a typedef for a Theseus-native `FileRef` (`Arc<Mutex<dyn File>>`)
 /// that is wrapped in a series of wrapper types, described below from inner to outer.
@@ -329,10 +330,15 @@ impl File {
                 .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
 
             let file_name = path.file_name().ok_or(io::Error::from(io::ErrorKind::NotFound))?;
-            let new_file = theseus_memfs::MemFile::create(
-                file_name.to_string_lossy().into(), 
+            let file_name: String = file_name.to_string_lossy().into();
+            theseus_memfs::MemFile::create(
+                file_name.clone(),
                 &containing_dir,
             ).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            // Re-obtain the new file from its containing directory, because some directories
+            // (e.g., those on a mounted disk) store their own copy of inserted files.
+            let new_file = containing_dir.lock().get_file(&file_name)
+                .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
 
             Ok(theseus_file_ref_to_file(new_file, opts.clone()))
         }
@@ -495,8 +501,26 @@ pub fn unlink(_p: &Path) -> io::Result<()> {
     unimplemented!()
 }
 
-pub fn rename(_old: &Path, _new: &Path) -> io::Result<()> {
-    unimplemented!()
+pub fn rename(old: &Path, new: &Path) -> io::Result<()> {
+    let curr_dir = crate::env::current_dir()?;
+    let (old_dir, old_name) = containing_dir_and_name(old, &curr_dir)?;
+    let (new_dir, new_name) = containing_dir_and_name(new, &curr_dir)?;
+    theseus_mount_table::move_node(&old_dir, &old_name, &new_dir, &new_name)
+        .map(|_replaced| ())
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Returns the directory that contains the node at the given `path`,
+/// which is relative to `curr_dir`, along with the name of that node.
+fn containing_dir_and_name(path: &Path, curr_dir: &theseus_fs_node::DirRef) -> io::Result<(theseus_fs_node::DirRef, String)> {
+    let parent_dir = path.parent()
+        .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+    let name = path.file_name()
+        .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+    let theseus_dir_path = theseus_path::Path::new(parent_dir.to_string_lossy().into());
+    let containing_dir = theseus_dir_path.get_dir(curr_dir)
+        .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+    Ok((containing_dir, name.to_string_lossy().into()))
 }
 
 pub fn set_perm(_p: &Path, _perm: FilePermissions) -> io::Result<()> {
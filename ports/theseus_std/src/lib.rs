@@ -2,6 +2,7 @@
 //! 
 //! Current ported modules include:
 //! * `fs`: basic filesystem access.
+//! * `net`: blocking TCP and UDP sockets on the default network interface.
 //! * `os_str`: platform-native string types.
 //!    * In Theseus, `OsString` = `String`, and `OsStr` = `str`.
 //! * `path`: basic path representations: `PathBuf` and `Path`.
@@ -16,6 +17,8 @@ extern crate alloc;
 mod env;
 pub mod fs;
 mod fs_imp;
+pub mod net;
+mod net_imp;
 pub mod os_str;
 mod os_str_imp;
 pub mod path;
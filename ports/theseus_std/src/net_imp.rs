@@ -0,0 +1,270 @@
+//! This module is equivalent to the Rust standard library's
+//! platform-specific "inner" net implementation.
+//!
+//! For example, for Unix-like systems, this module is implemented
+//! in the file [library/std/src/sys_common/net.rs].
+//!
+//! Theseus's blocking sockets from the `net` crate do most of the work,
+//! so this module only converts between their types and the std types.
+//!
+//! [library/std/src/sys_common/net.rs](https://github.com/rust-lang/rust/blob/master/library/std/src/sys_common/net.rs)
+
+use crate::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
+use core::{fmt, time::Duration};
+use core2::io;
+use spin::Mutex;
+use theseus_net::wire::{IpAddress, IpEndpoint, Ipv4Address, Ipv6Address};
+
+/// Converts an error from Theseus's `net` crate into an [`io::Error`].
+pub(crate) fn cvt_err(error: theseus_net::Error) -> io::Error {
+    use theseus_net::Error;
+    match error {
+        Error::TimedOut => io::ErrorKind::TimedOut.into(),
+        Error::ConnectionRefused => io::ErrorKind::ConnectionRefused.into(),
+        Error::ConnectionReset => io::ErrorKind::ConnectionReset.into(),
+        Error::NotConnected => io::ErrorKind::NotConnected.into(),
+        Error::Unaddressable => io::ErrorKind::AddrNotAvailable.into(),
+        Error::Illegal => io::Error::new(io::ErrorKind::InvalidInput, "illegal socket operation"),
+        Error::Exhausted => io::Error::new(io::ErrorKind::Other, "socket buffers exhausted"),
+        Error::Truncated => io::Error::new(io::ErrorKind::InvalidInput, "buffer too small for packet"),
+        Error::NotSupported => io::Error::new(io::ErrorKind::Uncategorized, "operation not supported"),
+        _ => io::Error::new(io::ErrorKind::Other, "network error"),
+    }
+}
+
+/// Converts a std socket address into a smoltcp endpoint.
+pub(crate) fn to_endpoint(addr: &SocketAddr) -> IpEndpoint {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => IpAddress::Ipv4(Ipv4Address(ip.octets())),
+        IpAddr::V6(ip) => IpAddress::Ipv6(Ipv6Address(ip.octets())),
+    };
+    IpEndpoint::new(ip, addr.port())
+}
+
+/// Converts a smoltcp endpoint into a std socket address.
+pub(crate) fn from_endpoint(endpoint: IpEndpoint) -> SocketAddr {
+    match endpoint.addr {
+        IpAddress::Ipv4(ip) => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(ip.0), endpoint.port)),
+        IpAddress::Ipv6(ip) => SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(ip.0), endpoint.port, 0, 0)),
+    }
+}
+
+/// Returns the unspecified IPv4 address with the given `port`,
+/// as sockets are bound to a port on all of their interface's addresses.
+fn unspecified_addr(port: u16) -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+}
+
+/// Returns the first address that `addr` resolves to.
+fn first_addr<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses"))
+}
+
+/// Returns an error if `timeout` is zero, which std doesn't allow as a timeout.
+fn check_timeout(timeout: Option<Duration>) -> io::Result<()> {
+    if timeout == Some(Duration::ZERO) {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot set a 0 duration timeout"))
+    } else {
+        Ok(())
+    }
+}
+
+pub struct TcpStream(theseus_net::TcpStream);
+
+impl TcpStream {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+        Self::connect_inner(&first_addr(addr)?, None)
+    }
+
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        check_timeout(Some(timeout))?;
+        Self::connect_inner(addr, Some(timeout))
+    }
+
+    fn connect_inner(addr: &SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        theseus_net::TcpStream::connect(to_endpoint(addr), timeout)
+            .map(TcpStream)
+            .map_err(cvt_err)
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(cvt_err)
+    }
+
+    pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(cvt_err)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.0.flush().map_err(cvt_err)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.remote_endpoint().map(from_endpoint).map_err(cvt_err)
+    }
+
+    pub fn socket_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_endpoint().map(from_endpoint).map_err(cvt_err)
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match how {
+            // smoltcp can't close only the receiving half of a connection,
+            // so reads keep returning data until the remote host closes its side.
+            Shutdown::Read => Ok(()),
+            Shutdown::Write | Shutdown::Both => {
+                self.0.shutdown();
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.0.set_read_timeout(timeout);
+        Ok(())
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.0.set_write_timeout(timeout);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.0.read_timeout())
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.0.write_timeout())
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut res = f.debug_struct("TcpStream");
+        if let Ok(addr) = self.socket_addr() {
+            res.field("addr", &addr);
+        }
+        if let Ok(peer) = self.peer_addr() {
+            res.field("peer", &peer);
+        }
+        res.finish()
+    }
+}
+
+pub struct TcpListener(theseus_net::TcpListener);
+
+impl TcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
+        let addr = first_addr(addr)?;
+        theseus_net::TcpListener::bind(to_endpoint(&addr))
+            .map(TcpListener)
+            .map_err(cvt_err)
+    }
+
+    pub fn socket_addr(&self) -> io::Result<SocketAddr> {
+        Ok(unspecified_addr(self.0.local_port()))
+    }
+
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.0
+            .accept()
+            .map(|(stream, remote)| (TcpStream(stream), from_endpoint(remote)))
+            .map_err(cvt_err)
+    }
+}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpListener")
+            .field("addr", &unspecified_addr(self.0.local_port()))
+            .finish()
+    }
+}
+
+pub struct UdpSocket {
+    inner: theseus_net::UdpSocket,
+    /// The default destination set by [`UdpSocket::connect`].
+    peer: Mutex<Option<SocketAddr>>,
+}
+
+impl UdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+        let addr = first_addr(addr)?;
+        theseus_net::UdpSocket::bind(to_endpoint(&addr))
+            .map(|inner| UdpSocket { inner, peer: Mutex::new(None) })
+            .map_err(cvt_err)
+    }
+
+    pub fn socket_addr(&self) -> io::Result<SocketAddr> {
+        Ok(unspecified_addr(self.inner.local_port()))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.peer.lock().ok_or(io::ErrorKind::NotConnected.into())
+    }
+
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        *self.peer.lock() = Some(first_addr(addr)?);
+        Ok(())
+    }
+
+    pub fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> io::Result<usize> {
+        let addr = first_addr(addr)?;
+        self.inner.send_to(buf, to_endpoint(&addr)).map_err(cvt_err)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner
+            .recv_from(buf)
+            .map(|(len, remote)| (len, from_endpoint(remote)))
+            .map_err(cvt_err)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let peer = self.peer_addr()?;
+        self.send_to(buf, peer)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let peer = self.peer_addr()?;
+        // A connected socket only receives datagrams from its peer.
+        loop {
+            let (len, addr) = self.recv_from(buf)?;
+            if addr == peer {
+                return Ok(len);
+            }
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.inner.set_read_timeout(timeout);
+        Ok(())
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.inner.set_write_timeout(timeout);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.inner.read_timeout())
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.inner.write_timeout())
+    }
+}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpSocket")
+            .field("addr", &unspecified_addr(self.inner.local_port()))
+            .finish()
+    }
+}
@@ -10,10 +10,13 @@ SCRIPTS_DIR="$(dirname "$(readlink -f "$0")")"
 THESEUS_BASE_DIR=$SCRIPTS_DIR/..
 TOOLS_DIR=$THESEUS_BASE_DIR/tools
 
-### This script requires rhash and python
+### This script requires rhash, openssl, and python
 if ! command -v rhash > /dev/null ; then 
   echo "The 'rhash' program is missing, please install it."
 fi
+if ! command -v openssl > /dev/null ; then 
+  echo "The 'openssl' program is missing, please install it."
+fi
 if ! command -v python > /dev/null ; then
   echo "The 'python' program is missing, please install it."
 fi
@@ -33,6 +36,14 @@ if [ -z $HTTP_ROOT ] ; then
 fi
 HTTP_ROOT=$(readlink -m $HTTP_ROOT)
 
+### required argument:  the Ed25519 private key (in PEM format) that the update build is signed with.
+### A key pair can be generated using 'make ota_keygen'.
+if [ ! -f "$OTA_SIGNING_KEY" ] ; then 
+	echo "Error: missing OTA_SIGNING_KEY var: the private key file used to sign update builds. Run 'make ota_keygen' to create one."
+	exit 1
+fi
+OTA_SIGNING_KEY=$(readlink -m $OTA_SIGNING_KEY)
+
 ### optional argument:  the name of the directory that will contain the new modules
 if [ -z $NEW_DIR_NAME ] ; then 
   NEW_DIR_NAME=$(date - u | sed '/s/ /_/g')
@@ -40,7 +51,7 @@ if [ -z $NEW_DIR_NAME ] ; then
 fi
 
 
-### create a directory to hold the newly-built module files and their checksums
+### create a directory to hold the newly-built module files and their manifest
 NEW_DIR=$(readlink -m $HTTP_ROOT/$NEW_DIR_NAME)
 rm -rf $NEW_DIR
 mkdir -p $NEW_DIR
@@ -52,13 +63,6 @@ cp $NEW_MODULES_DIR/*.o $NEW_DIR/
 # echo "NEW_DIR: $NEW_DIR"
 
 
-### calculate the checksums for each of the new module files
-mkdir -p $NEW_DIR/checksums
-cd $NEW_DIR/
-for f in *.o ; do
-  rhash --sha3-512 $f -o $NEW_DIR/checksums/$(basename $f).sha512
-done
-
 ### create a simple listing of all module files
 cd $NEW_DIR/
 ls *.o > $NEW_DIR/listing.txt
@@ -75,6 +79,19 @@ fi
 fi
 
 
+### Create a manifest with the name of the new update build and the hashes of all files in its directory, and sign it.
+### Theseus only applies update builds whose manifest signature matches its built-in public key,
+### and rejects a manifest that names a different update build than the one it requested.
+cd $NEW_DIR/
+MANIFEST_FILES="*.o"
+if [ -f diff.txt ] ; then
+  MANIFEST_FILES="$MANIFEST_FILES diff.txt"
+fi
+echo "build $NEW_DIR_NAME" > $NEW_DIR/manifest.txt
+rhash --sha3-512 $MANIFEST_FILES >> $NEW_DIR/manifest.txt
+openssl pkeyutl -sign -rawin -inkey $OTA_SIGNING_KEY -in $NEW_DIR/manifest.txt -out $NEW_DIR/manifest.txt.sig
+
+
 ### Update the root listing to reflect all available update directories.
 ### The root listing sorts directories in reverse chronological order (newest at top, oldest at bottom),
 ### without the trailing slash that usually is appended on directory names.
@@ -85,8 +102,14 @@ for d in $(ls -dt */ | sed 's/[/]//g') ; do
 done
 
 
-### Start up the actual HTTP server (after killing off any existing identical web server)
-WEBSERVER_CMD="python -m SimpleHTTPServer 8090"
+### Start up the actual HTTP server (after killing off any existing identical web server).
+### A server that supports range requests is preferred, so that interrupted downloads can be resumed.
+if python3 -c "import RangeHTTPServer" > /dev/null 2>&1 ; then
+  WEBSERVER_CMD="python3 -m RangeHTTPServer 8090"
+else
+  echo "Warning: the 'RangeHTTPServer' python module is missing (pip3 install rangehttpserver), interrupted downloads will restart from the beginning."
+  WEBSERVER_CMD="python -m SimpleHTTPServer 8090"
+fi
 pkill -f "$WEBSERVER_CMD" || true
 echo "Starting simple HTTP server at \"$HTTP_ROOT\" with new update \"$NEW_DIR_NAME\""
 cd $HTTP_ROOT && $($WEBSERVER_CMD) > /dev/null  2>&1  &